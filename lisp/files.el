@@ -803,36 +803,6 @@ The path separator is colon in GNU and GNU-like systems."
                     (lambda (f) (and (file-directory-p f) 'dir-ok)))
        (error "No such directory found via CDPATH environment variable"))))
 
-(defun directory-files-recursively (dir regexp &optional include-directories)
-  "Return list of all files under DIR that have file names matching REGEXP.
-This function works recursively.  Files are returned in \"depth first\"
-order, and files from each directory are sorted in alphabetical order.
-Each file name appears in the returned list in its absolute form.
-Optional argument INCLUDE-DIRECTORIES non-nil means also include in the
-output directories whose names match REGEXP."
-  (let ((result nil)
-	(files nil)
-	;; When DIR is "/", remote file names like "/method:" could
-	;; also be offered.  We shall suppress them.
-	(tramp-mode (and tramp-mode (file-remote-p (expand-file-name dir)))))
-    (dolist (file (sort (file-name-all-completions "" dir)
-			'string<))
-      (unless (member file '("./" "../"))
-	(if (directory-name-p file)
-	    (let* ((leaf (substring file 0 (1- (length file))))
-		   (full-file (expand-file-name leaf dir)))
-	      ;; Don't follow symlinks to other directories.
-	      (unless (file-symlink-p full-file)
-		(setq result
-		      (nconc result (directory-files-recursively
-				     full-file regexp include-directories))))
-	      (when (and include-directories
-			 (string-match regexp leaf))
-		(setq result (nconc result (list full-file)))))
-	  (when (string-match regexp file)
-	    (push (expand-file-name file dir) files)))))
-    (nconc result (nreverse files))))
-
 (defvar module-file-suffix)
 
 (defun load-file (file)
@@ -4299,32 +4269,6 @@ Interactively, confirmation is required unless you supply a prefix argument."
   ;; the one at the old location.
   (vc-refresh-state))
 
-(defun file-extended-attributes (filename)
-  "Return an alist of extended attributes of file FILENAME.
-
-Extended attributes are platform-specific metadata about the file,
-such as SELinux context, list of ACL entries, etc."
-  `((acl . ,(file-acl filename))
-    (selinux-context . ,(file-selinux-context filename))))
-
-(defun set-file-extended-attributes (filename attributes)
-  "Set extended attributes of file FILENAME to ATTRIBUTES.
-
-ATTRIBUTES must be an alist of file attributes as returned by
-`file-extended-attributes'.
-Value is t if the function succeeds in setting the attributes."
-  (let (result rv)
-    (dolist (elt attributes)
-      (let ((attr (car elt))
-	    (val (cdr elt)))
-	(cond ((eq attr 'acl)
-               (setq rv (set-file-acl filename val)))
-	      ((eq attr 'selinux-context)
-               (setq rv (set-file-selinux-context filename val))))
-        (setq result (or result rv))))
-
-    result))
-
 (defun backup-buffer ()
   "Make a backup of the disk file visited by the current buffer, if appropriate.
 This is normally done before saving the buffer the first time.
@@ -7436,16 +7380,44 @@ of the form (HIGH MIDDLE . LOW): first the high bits, then the
 middle 24 bits, and finally the low 16 bits."
   (nth 11 attributes))
 
+(defsubst file-attribute-birth-time (attributes)
+  "The birth time in ATTRIBUTES returned by `file-attributes'.
+This is only present when `file-attributes' was called with an
+`extended' ID-FORMAT.  It is nil if the file system does not
+record it, or a list of integers (HIGH LOW USEC PSEC) in the same
+style as (current-time)."
+  (nth 12 attributes))
+
+(defsubst file-attribute-mount-id (attributes)
+  "The mount ID in ATTRIBUTES returned by `file-attributes'.
+This is only present when `file-attributes' was called with an
+`extended' ID-FORMAT, and is nil if it is not known."
+  (nth 13 attributes))
+
+(defsubst file-attribute-blocks (attributes)
+  "The number of 512-byte blocks in ATTRIBUTES returned by `file-attributes'.
+This is only present when `file-attributes' was called with an
+`extended' ID-FORMAT."
+  (nth 14 attributes))
+
+(defsubst file-attribute-block-size (attributes)
+  "The preferred I/O block size in ATTRIBUTES returned by `file-attributes'.
+This is only present when `file-attributes' was called with an
+`extended' ID-FORMAT."
+  (nth 15 attributes))
+
 (defun file-attribute-collect (attributes &rest attr-names)
   "Return a sublist of ATTRIBUTES returned by `file-attributes'.
 ATTR-NAMES are symbols with the selected attribute names.
 
 Valid attribute names are: type, link-number, user-id, group-id,
 access-time, modification-time, status-change-time, size, modes,
-inode-number and device-number."
+inode-number and device-number, and for extended ATTRIBUTES also
+birth-time, mount-id, blocks and block-size."
   (let ((all '(type link-number user-id group-id access-time
                modification-time status-change-time
-               size modes inode-number device-number))
+               size modes inode-number device-number
+               birth-time mount-id blocks block-size))
         result)
     (while attr-names
       (let ((attr (pop attr-names)))
@@ -30,35 +30,39 @@ FORMAT is a string containing `format'-like specs like \"bash %u %k\",
 while SPECIFICATION is an alist mapping from format spec characters
 to values.  Any text properties on a %-spec itself are propagated to
 the text that it generates."
-  (with-temp-buffer
-    (insert format)
-    (goto-char (point-min))
-    (while (search-forward "%" nil t)
-      (cond
-       ;; Quoted percent sign.
-       ((eq (char-after) ?%)
-	(delete-char 1))
-       ;; Valid format spec.
-       ((looking-at "\\([-0-9.]*\\)\\([a-zA-Z]\\)")
-	(let* ((num (match-string 1))
-	       (spec (string-to-char (match-string 2)))
-	       (val (assq spec specification)))
-	  (unless val
-	    (error "Invalid format character: `%%%c'" spec))
-	  (setq val (cdr val))
-	  ;; Pad result to desired length.
-	  (let ((text (format (concat "%" num "s") val)))
-	    ;; Insert first, to preserve text properties.
-	    (insert-and-inherit text)
-	    ;; Delete the specifier body.
-            (delete-region (+ (match-beginning 0) (length text))
-                           (+ (match-end 0) (length text)))
-            ;; Delete the percent sign.
-            (delete-region (1- (match-beginning 0)) (match-beginning 0)))))
-       ;; Signal an error on bogus format strings.
-       (t
-	(error "Invalid format string"))))
-    (buffer-string)))
+  ;; Rewrite each %-spec as a named %s spec for `format-named'.
+  (let ((start 0)
+	(template nil)
+	(values nil))
+    (while (string-match
+	    "%\\(?:\\(%\\)\\|\\([-0-9.]*\\)\\([a-zA-Z]\\)\\)?" format start)
+      (let ((percent (match-beginning 0))
+	    (end (match-end 0)))
+	(push (substring format start percent) template)
+	(cond
+	 ;; Quoted percent sign.
+	 ((match-beginning 1)
+	  (push (substring format percent end) template))
+	 ;; Valid format spec.
+	 ((match-beginning 3)
+	  (let* ((num (match-string 2 format))
+		 (spec (string-to-char (match-string 3 format)))
+		 (name (string spec))
+		 (val (assq spec specification))
+		 (text (concat "%{" name "}" num "s")))
+	    (unless val
+	      (error "Invalid format character: `%%%c'" spec))
+	    (unless (assoc name values)
+	      (push (cons name (cdr val)) values))
+	    (set-text-properties 0 (length text)
+				 (text-properties-at percent format) text)
+	    (push text template)))
+	 ;; Signal an error on bogus format strings.
+	 (t
+	  (error "Invalid format string")))
+	(setq start end)))
+    (push (substring format start) template)
+    (format-named (apply #'concat (nreverse template)) (nreverse values))))
 
 (defun format-spec-make (&rest pairs)
   "Return an alist suitable for use in `format-spec' based on PAIRS.
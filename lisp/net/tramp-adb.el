@@ -108,6 +108,7 @@ It is used for TCP/IP devices."
     (directory-files . tramp-handle-directory-files)
     (directory-files-and-attributes
      . tramp-adb-handle-directory-files-and-attributes)
+    (directory-files-recursively . tramp-handle-directory-files-recursively)
     (dired-compress-file . ignore)
     (dired-uncache . tramp-handle-dired-uncache)
     (expand-file-name . tramp-adb-handle-expand-file-name)
@@ -119,6 +120,7 @@ It is used for TCP/IP devices."
     ;; FIXME: This is too sloppy.
     (file-executable-p . tramp-handle-file-exists-p)
     (file-exists-p . tramp-handle-file-exists-p)
+    (file-extended-attributes . tramp-handle-file-extended-attributes)
     (file-in-directory-p . tramp-handle-file-in-directory-p)
     (file-local-copy . tramp-adb-handle-file-local-copy)
     (file-modes . tramp-handle-file-modes)
@@ -142,6 +144,10 @@ It is used for TCP/IP devices."
     (file-system-info . tramp-adb-handle-file-system-info)
     (file-truename . tramp-adb-handle-file-truename)
     (file-writable-p . tramp-adb-handle-file-writable-p)
+    (file-xattr-get . ignore)
+    (file-xattr-list . ignore)
+    (file-xattr-remove . ignore)
+    (file-xattr-set . ignore)
     (find-backup-file-name . tramp-handle-find-backup-file-name)
     ;; `find-file-noselect' performed by default handler.
     ;; `get-file-buffer' performed by default handler.
@@ -156,6 +162,7 @@ It is used for TCP/IP devices."
     (process-file . tramp-adb-handle-process-file)
     (rename-file . tramp-adb-handle-rename-file)
     (set-file-acl . ignore)
+    (set-file-extended-attributes . tramp-handle-set-file-extended-attributes)
     (set-file-modes . tramp-adb-handle-set-file-modes)
     (set-file-selinux-context . ignore)
     (set-file-times . tramp-adb-handle-set-file-times)
@@ -218,6 +218,7 @@ It must be supported by libarchive(3).")
     (directory-files . tramp-handle-directory-files)
     (directory-files-and-attributes
      . tramp-handle-directory-files-and-attributes)
+    (directory-files-recursively . tramp-handle-directory-files-recursively)
     (dired-compress-file . tramp-archive-handle-not-implemented)
     (dired-uncache . tramp-archive-handle-dired-uncache)
     ;; `expand-file-name' performed by default handler.
@@ -228,6 +229,7 @@ It must be supported by libarchive(3).")
     (file-equal-p . tramp-handle-file-equal-p)
     (file-executable-p . tramp-archive-handle-file-executable-p)
     (file-exists-p . tramp-handle-file-exists-p)
+    (file-extended-attributes . tramp-handle-file-extended-attributes)
     (file-in-directory-p . tramp-handle-file-in-directory-p)
     (file-local-copy . tramp-archive-handle-file-local-copy)
     (file-modes . tramp-handle-file-modes)
@@ -251,6 +253,10 @@ It must be supported by libarchive(3).")
     (file-system-info . tramp-archive-handle-file-system-info)
     (file-truename . tramp-archive-handle-file-truename)
     (file-writable-p . ignore)
+    (file-xattr-get . ignore)
+    (file-xattr-list . ignore)
+    (file-xattr-remove . ignore)
+    (file-xattr-set . ignore)
     (find-backup-file-name . ignore)
     ;; `find-file-noselect' performed by default handler.
     ;; `get-file-buffer' performed by default handler.
@@ -265,6 +271,7 @@ It must be supported by libarchive(3).")
     (process-file . ignore)
     (rename-file . tramp-archive-handle-not-implemented)
     (set-file-acl . ignore)
+    (set-file-extended-attributes . tramp-handle-set-file-extended-attributes)
     (set-file-modes . tramp-archive-handle-not-implemented)
     (set-file-selinux-context . ignore)
     (set-file-times . tramp-archive-handle-not-implemented)
@@ -673,6 +673,7 @@ Every entry is a list (NAME ADDRESS).")
     (directory-files . tramp-handle-directory-files)
     (directory-files-and-attributes
      . tramp-handle-directory-files-and-attributes)
+    (directory-files-recursively . tramp-handle-directory-files-recursively)
     (dired-compress-file . ignore)
     (dired-uncache . tramp-handle-dired-uncache)
     (expand-file-name . tramp-gvfs-handle-expand-file-name)
@@ -683,6 +684,7 @@ Every entry is a list (NAME ADDRESS).")
     (file-equal-p . tramp-handle-file-equal-p)
     (file-executable-p . tramp-gvfs-handle-file-executable-p)
     (file-exists-p . tramp-handle-file-exists-p)
+    (file-extended-attributes . tramp-handle-file-extended-attributes)
     (file-in-directory-p . tramp-handle-file-in-directory-p)
     (file-local-copy . tramp-gvfs-handle-file-local-copy)
     (file-modes . tramp-handle-file-modes)
@@ -706,6 +708,10 @@ Every entry is a list (NAME ADDRESS).")
     (file-system-info . tramp-gvfs-handle-file-system-info)
     (file-truename . tramp-handle-file-truename)
     (file-writable-p . tramp-gvfs-handle-file-writable-p)
+    (file-xattr-get . ignore)
+    (file-xattr-list . ignore)
+    (file-xattr-remove . ignore)
+    (file-xattr-set . ignore)
     (find-backup-file-name . tramp-handle-find-backup-file-name)
     ;; `find-file-noselect' performed by default handler.
     ;; `get-file-buffer' performed by default handler.
@@ -720,6 +726,7 @@ Every entry is a list (NAME ADDRESS).")
     (process-file . ignore)
     (rename-file . tramp-gvfs-handle-rename-file)
     (set-file-acl . ignore)
+    (set-file-extended-attributes . tramp-handle-set-file-extended-attributes)
     (set-file-modes . ignore)
     (set-file-selinux-context . ignore)
     (set-file-times . ignore)
@@ -997,6 +997,7 @@ of command line.")
     (directory-files . tramp-handle-directory-files)
     (directory-files-and-attributes
      . tramp-sh-handle-directory-files-and-attributes)
+    (directory-files-recursively . tramp-handle-directory-files-recursively)
     (dired-compress-file . tramp-sh-handle-dired-compress-file)
     (dired-uncache . tramp-handle-dired-uncache)
     (expand-file-name . tramp-sh-handle-expand-file-name)
@@ -1007,6 +1008,7 @@ of command line.")
     (file-equal-p . tramp-handle-file-equal-p)
     (file-executable-p . tramp-sh-handle-file-executable-p)
     (file-exists-p . tramp-sh-handle-file-exists-p)
+    (file-extended-attributes . tramp-handle-file-extended-attributes)
     (file-in-directory-p . tramp-handle-file-in-directory-p)
     (file-local-copy . tramp-sh-handle-file-local-copy)
     (file-modes . tramp-handle-file-modes)
@@ -1030,6 +1032,10 @@ of command line.")
     (file-system-info . tramp-sh-handle-file-system-info)
     (file-truename . tramp-sh-handle-file-truename)
     (file-writable-p . tramp-sh-handle-file-writable-p)
+    (file-xattr-get . tramp-sh-handle-file-xattr-get)
+    (file-xattr-list . tramp-sh-handle-file-xattr-list)
+    (file-xattr-remove . tramp-sh-handle-file-xattr-remove)
+    (file-xattr-set . tramp-sh-handle-file-xattr-set)
     (find-backup-file-name . tramp-handle-find-backup-file-name)
     ;; `find-file-noselect' performed by default handler.
     ;; `get-file-buffer' performed by default handler.
@@ -1044,6 +1050,7 @@ of command line.")
     (process-file . tramp-sh-handle-process-file)
     (rename-file . tramp-sh-handle-rename-file)
     (set-file-acl . tramp-sh-handle-set-file-acl)
+    (set-file-extended-attributes . tramp-handle-set-file-extended-attributes)
     (set-file-modes . tramp-sh-handle-set-file-modes)
     (set-file-selinux-context . tramp-sh-handle-set-file-selinux-context)
     (set-file-times . tramp-sh-handle-set-file-times)
@@ -1650,6 +1657,79 @@ be non-negative integers."
       (tramp-flush-file-property v localname "file-acl-string")
       nil)))
 
+(defun tramp-remote-xattr-p (vec)
+  "Check, whether extended attributes are supported on the remote host."
+  (with-tramp-connection-property (tramp-get-connection-process vec) "xattr-p"
+    (tramp-send-command-and-check vec "getfattr --version")))
+
+(defun tramp-sh-handle-file-xattr-list (filename &optional nofollow)
+  "Like `file-xattr-list' for Tramp files."
+  (with-parsed-tramp-file-name filename nil
+    (when (and (tramp-remote-xattr-p v)
+	       (tramp-send-command-and-check
+		v (format "getfattr --absolute-names -m - %s %s"
+			  (if nofollow "-h" "")
+			  (tramp-shell-quote-argument localname))))
+      (with-current-buffer (tramp-get-connection-buffer v)
+	(goto-char (point-min))
+	(let (result)
+	  ;; The names follow a "# file:" header line.
+	  (while (re-search-forward "^\\([^#\n].*\\)$" nil t)
+	    (push (match-string 1) result))
+	  (nreverse result))))))
+
+(defun tramp-sh-handle-file-xattr-get (filename name &optional nofollow)
+  "Like `file-xattr-get' for Tramp files."
+  (with-parsed-tramp-file-name filename nil
+    (when (and (tramp-remote-xattr-p v)
+	       (tramp-send-command-and-check
+		v (format "getfattr --absolute-names -e hex -n %s %s %s"
+			  (tramp-shell-quote-argument name)
+			  (if nofollow "-h" "")
+			  (tramp-shell-quote-argument localname))))
+      (with-current-buffer (tramp-get-connection-buffer v)
+	(goto-char (point-min))
+	(when (re-search-forward
+	       "^[^#\n][^=\n]*\\(?:=\\(?:0x\\([[:xdigit:]]*\\)\\|\"\"\\)\\)?$"
+	       nil t)
+	  (let ((hex (or (match-string 1) "")))
+	    (apply #'unibyte-string
+		   (mapcar (lambda (i)
+			     (string-to-number (substring hex i (+ i 2)) 16))
+			   (number-sequence 0 (1- (length hex)) 2)))))))))
+
+(defun tramp-sh-handle-file-xattr-set (filename name value &optional nofollow)
+  "Like `file-xattr-set' for Tramp files."
+  (with-parsed-tramp-file-name filename nil
+    (let ((value (if (multibyte-string-p value)
+		     (encode-coding-string value 'utf-8-emacs)
+		   value)))
+      (unless (and (tramp-remote-xattr-p v)
+		   (tramp-send-command-and-check
+		    v (format "setfattr %s -n %s %s %s"
+			      (if nofollow "-h" "")
+			      (tramp-shell-quote-argument name)
+			      (if (zerop (length value))
+				  ""
+				(concat "-v 0x"
+					(mapconcat (lambda (byte)
+						     (format "%02x" byte))
+						   value "")))
+			      (tramp-shell-quote-argument localname))))
+	(tramp-error
+	 v 'file-error "Setting extended attribute %s of %s" name filename)))
+    nil))
+
+(defun tramp-sh-handle-file-xattr-remove (filename name &optional nofollow)
+  "Like `file-xattr-remove' for Tramp files."
+  (with-parsed-tramp-file-name filename nil
+    (and (tramp-remote-xattr-p v)
+	 (tramp-send-command-and-check
+	  v (format "setfattr %s -x %s %s"
+		    (if nofollow "-h" "")
+		    (tramp-shell-quote-argument name)
+		    (tramp-shell-quote-argument localname))))))
+
 ;; Simple functions using the `test' command.
 
 (defun tramp-sh-handle-file-executable-p (filename)
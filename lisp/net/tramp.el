@@ -2115,7 +2115,13 @@ ARGS are the arguments OPERATION has been called with."
 	      ;; Emacs 26+ only.
 	      file-name-case-insensitive-p
 	      ;; Emacs 27+ only.
-	      file-system-info))
+	      file-system-info
+	      ;; Remacs only.
+	      directory-files-recursively
+	      file-disk-usage file-extended-attributes
+	      set-file-extended-attributes
+	      file-xattr-get file-xattr-list file-xattr-remove
+	      file-xattr-set))
     (if (file-name-absolute-p (nth 0 args))
 	(nth 0 args)
       default-directory))
@@ -3002,6 +3008,54 @@ User is always nil."
 	      (if full x (expand-file-name x directory)) id-format)))
    (directory-files directory full match nosort)))
 
+(defun tramp-handle-directory-files-recursively
+    (directory regexp &optional include-directories max-depth
+	       follow-symlinks exclude respect-ignore-files)
+  "Like `directory-files-recursively' for Tramp files.
+If RESPECT-IGNORE-FILES is non-nil, `.git' directories are skipped,
+but `.gitignore' and `.ignore' files are not read."
+  (tramp--directory-files-recursively
+   (directory-file-name (expand-file-name directory))
+   regexp include-directories max-depth follow-symlinks exclude
+   respect-ignore-files 0 nil))
+
+(defun tramp--directory-files-recursively
+    (directory regexp include-directories max-depth follow-symlinks
+	       exclude respect-ignore-files depth active)
+  "Do the work of `tramp-handle-directory-files-recursively'.
+DIRECTORY is DEPTH levels below the directory being listed, and
+ACTIVE lists the true names of the directories being walked, so
+that following symbolic links cannot loop forever."
+  (let ((truename (file-truename directory))
+	result files)
+    (unless (member truename active)
+      (push truename active)
+      (dolist (file (sort (delete "./" (delete "../" (file-name-all-completions
+						      "" directory)))
+			  (lambda (a b)
+			    (string< (directory-file-name a)
+				     (directory-file-name b)))))
+	(let* ((dirp (directory-name-p file))
+	       (leaf (directory-file-name file))
+	       (full-file (expand-file-name leaf directory)))
+	  (cond
+	   ((and exclude (string-match-p exclude leaf)))
+	   ((and dirp respect-ignore-files (string-equal leaf ".git")))
+	   (dirp
+	    (when (and (or follow-symlinks (not (file-symlink-p full-file)))
+		       (or (null max-depth) (< depth max-depth)))
+	      (setq result
+		    (nconc result
+			   (tramp--directory-files-recursively
+			    full-file regexp include-directories max-depth
+			    follow-symlinks exclude respect-ignore-files
+			    (1+ depth) active))))
+	    (when (and include-directories (string-match-p regexp leaf))
+	      (setq result (nconc result (list full-file)))))
+	   ((string-match-p regexp leaf)
+	    (push full-file files))))))
+    (nconc result (nreverse files))))
+
 (defun tramp-handle-dired-uncache (dir)
   "Like `dired-uncache' for Tramp files."
   (with-parsed-tramp-file-name
@@ -3033,6 +3087,20 @@ User is always nil."
   "Like `file-exists-p' for Tramp files."
   (not (null (file-attributes filename))))
 
+(defun tramp-handle-file-extended-attributes (filename)
+  "Like `file-extended-attributes' for Tramp files."
+  (list (cons 'acl (file-acl filename))
+	(cons 'selinux-context (file-selinux-context filename))
+	(cons 'xattrs
+	      (delq nil
+		    (mapcar
+		     (lambda (name)
+		       (and (string-prefix-p "user." name)
+			    (let ((value (tramp-compat-funcall
+					  'file-xattr-get filename name)))
+			      (and value (cons name value)))))
+		     (tramp-compat-funcall 'file-xattr-list filename))))))
+
 (defun tramp-handle-file-in-directory-p (filename directory)
   "Like `file-in-directory-p' for Tramp files."
   ;; Native `file-in-directory-p' calls `file-truename', which
@@ -3571,6 +3639,24 @@ support symbolic links."
 	  (concat filename "/")
 	filename))))
 
+(defun tramp-handle-set-file-extended-attributes (filename attributes)
+  "Like `set-file-extended-attributes' for Tramp files."
+  (let (result)
+    (dolist (elt attributes result)
+      (let ((attr (car elt))
+	    (val (cdr elt)))
+	(when (cond
+	       ((eq attr 'acl) (set-file-acl filename val))
+	       ((eq attr 'selinux-context)
+		(set-file-selinux-context filename val))
+	       ((eq attr 'xattrs)
+		(if (fboundp 'file-xattr-set)
+		    (ignore-errors
+		      (dolist (xattr val t)
+			(file-xattr-set filename (car xattr) (cdr xattr))))
+		  (null val))))
+	  (setq result t))))))
+
 (defun tramp-handle-set-visited-file-modtime (&optional time-list)
   "Like `set-visited-file-modtime' for Tramp files."
   (unless (buffer-file-name)
@@ -3649,23 +3649,6 @@ the search/match was performed in."
 					(match-end num)))))
 
 
-(defun match-substitute-replacement (replacement
-				     &optional fixedcase literal string subexp)
-  "Return REPLACEMENT as it will be inserted by `replace-match'.
-In other words, all back-references in the form `\\&' and `\\N'
-are substituted with actual strings matched by the last search.
-Optional FIXEDCASE, LITERAL, STRING and SUBEXP have the same
-meaning as for `replace-match'."
-  (let ((match (match-string 0 string)))
-    (save-match-data
-      (set-match-data (mapcar (lambda (x)
-				(if (numberp x)
-				    (- x (match-beginning 0))
-				  x))
-			      (match-data t)))
-      (replace-match replacement fixedcase literal match subexp))))
-
-
 (defun looking-back (regexp &optional limit greedy)
   "Return non-nil if text before point matches regular expression REGEXP.
 Like `looking-at' except matches before point, and is slower.
@@ -363,6 +363,9 @@ fn build_ignored_paths() -> Vec<&'static str> {
     #[cfg(not(feature = "window-system-x11"))]
     ignored_paths.push("xsettings.rs");
 
+    #[cfg(not(any(feature = "inotify", feature = "notify-polling")))]
+    ignored_paths.push("inotify.rs");
+
     ignored_paths
 }
 
@@ -45,7 +45,8 @@ pub fn make_temporary_file(template: String, flags: i32) -> Result<(i32, String)
         let attempt = CString::new(validated_template.clone()).map_err(|_| EEXIST)?;
         let file_handle = match open_temporary_file(&attempt, flags) {
             Ok(file) => file,
-            Err(_) => continue,
+            Err(ref err) if err.raw_os_error() == Some(EEXIST) => continue,
+            Err(err) => return Err(err.raw_os_error().unwrap_or(EINVAL)),
         };
 
         return Ok((file_handle, validated_template));
@@ -178,3 +179,15 @@ fn test_rust_make_temp_error_inval() {
     let error = errno::errno();
     assert!(file_handle == -1 && error == errno::Errno(EINVAL));
 }
+
+#[test]
+fn test_rust_make_temp_error_noent() {
+    let mut tmpdir = env::temp_dir();
+    tmpdir.push("no-such-directory/.emacs-XXXXXX");
+    let fullpath = tmpdir.to_string_lossy().into_owned();
+    let name = CString::new(fullpath).unwrap();
+    let raw_ptr = name.into_raw();
+    let file_handle = unsafe { rust_make_temp(raw_ptr, 0) };
+    let error = errno::errno();
+    assert!(file_handle == -1 && error == errno::Errno(libc::ENOENT));
+}
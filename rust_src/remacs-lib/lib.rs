@@ -22,7 +22,7 @@ pub use crate::{
     // Used by make-docfile
     docfile::scan_rust_file,
     // Used for creating temporary files in emacs
-    files::rust_make_temp,
+    files::{make_temporary_file, rust_make_temp},
 
     math::{rust_count_one_bits, rust_count_trailing_zero_bits},
 
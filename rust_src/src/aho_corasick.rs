@@ -0,0 +1,255 @@
+//! Search for many literal strings at once, with the Aho-Corasick
+//! algorithm.
+//!
+//! The automaton works on characters rather than bytes, so that the
+//! caller can fold case with the buffer's case tables and read the
+//! text from both sides of the gap.
+
+use std::{cmp::Reverse, collections::VecDeque};
+
+use crate::multibyte::Codepoint;
+
+struct State {
+    /// Transitions on the next character, sorted by character.
+    transitions: Vec<(Codepoint, usize)>,
+    /// The state for the longest proper suffix of this state's string
+    /// that is a prefix of some pattern.
+    fail: usize,
+    /// The length of this state's string.
+    depth: usize,
+    /// The patterns that end here, including those that end in
+    /// states reachable through `fail'.
+    outputs: Vec<usize>,
+}
+
+impl State {
+    fn new(depth: usize) -> Self {
+        Self {
+            transitions: Vec::new(),
+            fail: 0,
+            depth,
+            outputs: Vec::new(),
+        }
+    }
+
+    fn transition(&self, c: Codepoint) -> Option<usize> {
+        self.transitions
+            .binary_search_by_key(&c, |&(k, _)| k)
+            .ok()
+            .map(|i| self.transitions[i].1)
+    }
+}
+
+/// An occurrence of a pattern, as byte positions in the text.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Match {
+    /// The index of the pattern that was found.
+    pub pattern: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+pub struct Automaton {
+    states: Vec<State>,
+    /// The length in characters of each pattern.
+    lengths: Vec<usize>,
+}
+
+impl Automaton {
+    pub fn new(patterns: &[Vec<Codepoint>]) -> Self {
+        let mut states = vec![State::new(0)];
+        for (index, pattern) in patterns.iter().enumerate() {
+            let mut state = 0;
+            for &c in pattern {
+                state = match states[state].transition(c) {
+                    Some(next) => next,
+                    None => {
+                        let next = states.len();
+                        states.push(State::new(states[state].depth + 1));
+                        let transitions = &mut states[state].transitions;
+                        let i = transitions
+                            .binary_search_by_key(&c, |&(k, _)| k)
+                            .unwrap_err();
+                        transitions.insert(i, (c, next));
+                        next
+                    }
+                };
+            }
+            states[state].outputs.push(index);
+        }
+
+        // Compute the failure links breadth first, so that the link of
+        // a state's parent is known before the state's own.
+        let mut queue: VecDeque<usize> = states[0].transitions.iter().map(|&(_, s)| s).collect();
+        while let Some(parent) = queue.pop_front() {
+            for i in 0..states[parent].transitions.len() {
+                let (c, child) = states[parent].transitions[i];
+                let mut fail = states[parent].fail;
+                let link = loop {
+                    if let Some(next) = states[fail].transition(c) {
+                        break next;
+                    }
+                    if fail == 0 {
+                        break 0;
+                    }
+                    fail = states[fail].fail;
+                };
+                states[child].fail = link;
+                let inherited = states[link].outputs.clone();
+                states[child].outputs.extend(inherited);
+                queue.push_back(child);
+            }
+        }
+
+        Self {
+            states,
+            lengths: patterns.iter().map(Vec::len).collect(),
+        }
+    }
+
+    fn next_state(&self, mut state: usize, c: Codepoint) -> usize {
+        loop {
+            if let Some(next) = self.states[state].transition(c) {
+                return next;
+            }
+            if state == 0 {
+                return 0;
+            }
+            state = self.states[state].fail;
+        }
+    }
+
+    /// Find the occurrence that starts first in the text made of CHARS,
+    /// each given with its length in bytes, which begins at byte
+    /// position START.  Of the occurrences that start at the same
+    /// place, the longest wins, then the one whose pattern comes first.
+    pub fn find<I>(&self, start: usize, chars: I) -> Option<Match>
+    where
+        I: IntoIterator<Item = (Codepoint, usize)>,
+    {
+        let longest = self.lengths.iter().copied().max()?;
+        // The byte positions of the last LONGEST character boundaries,
+        // the most recent last.
+        let mut boundaries = VecDeque::with_capacity(longest + 1);
+        boundaries.push_back(start);
+        let mut best: Option<(usize, Match)> = None;
+        let mut state = 0;
+        self.record(state, 0, &boundaries, &mut best);
+
+        for (count, (c, len)) in chars.into_iter().enumerate() {
+            // Later occurrences cannot start before the string of the
+            // current state does.
+            if let Some((first, _)) = best {
+                if count - self.states[state].depth > first {
+                    break;
+                }
+            }
+            state = self.next_state(state, c);
+            let end = boundaries.back().unwrap() + len;
+            if boundaries.len() > longest {
+                boundaries.pop_front();
+            }
+            boundaries.push_back(end);
+            self.record(state, count + 1, &boundaries, &mut best);
+        }
+        best.map(|(_, found)| found)
+    }
+
+    /// Update BEST, the best occurrence so far and the number of the
+    /// character it starts at, with the patterns that end in STATE after
+    /// COUNT characters.
+    fn record(
+        &self,
+        state: usize,
+        count: usize,
+        boundaries: &VecDeque<usize>,
+        best: &mut Option<(usize, Match)>,
+    ) {
+        let end = *boundaries.back().unwrap();
+        for &pattern in &self.states[state].outputs {
+            let length = self.lengths[pattern];
+            let first = count - length;
+            let found = Match {
+                pattern,
+                start: boundaries[boundaries.len() - 1 - length],
+                end,
+            };
+            let better = best.map_or(true, |(best_first, best_match)| {
+                (first, Reverse(end), pattern)
+                    < (best_first, Reverse(best_match.end), best_match.pattern)
+            });
+            if better {
+                *best = Some((first, found));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codepoints(s: &str) -> Vec<Codepoint> {
+        s.chars().map(|c| Codepoint::from(c as u32)).collect()
+    }
+
+    fn find<'a>(patterns: &[&str], text: &'a str) -> Option<(usize, &'a str)> {
+        let patterns: Vec<_> = patterns.iter().map(|p| codepoints(p)).collect();
+        let automaton = Automaton::new(&patterns);
+        let chars = text
+            .chars()
+            .map(|c| (Codepoint::from(c as u32), c.len_utf8()));
+        automaton
+            .find(0, chars)
+            .map(|m| (m.pattern, &text[m.start..m.end]))
+    }
+
+    #[test]
+    fn test_earliest_match() {
+        assert_eq!(
+            find(&["fox", "dog"], "the dog and the fox"),
+            Some((1, "dog"))
+        );
+        assert_eq!(find(&["fox", "dog"], "a cat"), None);
+        assert_eq!(find(&[], "a cat"), None);
+    }
+
+    #[test]
+    fn test_longest_at_same_start() {
+        assert_eq!(find(&["he", "hers", "her"], "ushers"), Some((1, "hers")));
+        // "he" ends first, but "she" starts before it.
+        assert_eq!(find(&["he", "she"], "ushers"), Some((1, "she")));
+        assert_eq!(find(&["b", "abcd", "bc"], "xabcd"), Some((1, "abcd")));
+    }
+
+    #[test]
+    fn test_duplicates_and_empty_patterns() {
+        assert_eq!(find(&["ab", "ab"], "xab"), Some((0, "ab")));
+        assert_eq!(find(&["", "x"], "xab"), Some((1, "x")));
+        assert_eq!(find(&["", "y"], "xab"), Some((0, "")));
+    }
+
+    #[test]
+    fn test_failure_links() {
+        assert_eq!(find(&["abcx", "bcd"], "abcd"), Some((1, "bcd")));
+        assert_eq!(find(&["aab"], "aaab"), Some((0, "aab")));
+    }
+
+    #[test]
+    fn test_multibyte_positions() {
+        let patterns = vec![codepoints("été"), codepoints("€")];
+        let automaton = Automaton::new(&patterns);
+        let text = "l'été 5€";
+        let chars = text
+            .chars()
+            .map(|c| (Codepoint::from(c as u32), c.len_utf8()));
+        assert_eq!(
+            automaton.find(10, chars),
+            Some(Match {
+                pattern: 0,
+                start: 12,
+                end: 17
+            })
+        );
+    }
+}
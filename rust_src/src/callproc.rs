@@ -1,24 +1,132 @@
 //! Synchronous subprocess invocation for GNU Emacs.
 
+use std::collections::HashSet;
+use std::ffi::{OsStr, OsString};
+use std::process::{Command, Output, Stdio};
+
+#[cfg(unix)]
+use std::os::unix::{ffi::OsStrExt, process::ExitStatusExt};
+
 use libc::O_RDONLY;
 
 use crate::{
     buffers,
-    coding::encode_file_name,
+    coding::{decode_coding_string, encode_coding_string, encode_file_name},
     eval::{record_unwind_protect_int, unbind_to},
     fileio::expand_file_name,
+    fns::{append, concat},
     lisp::LispObject,
+    lists::{list, LispConsCircularChecks, LispConsEndChecks},
+    multibyte::LispStringRef,
     remacs_macros::lisp_fn,
-    remacs_sys::Fdelete_region,
-    remacs_sys::Qnil,
     remacs_sys::NULL_DEVICE,
     remacs_sys::{
         build_string, call_process, close_file_unwind, create_temp_file, emacs_open,
-        report_file_error,
+        encode_directory, globals, make_specified_string, make_unibyte_string, openp,
+        remove_slash_colon, report_file_error,
     },
+    remacs_sys::{Fdelete_region, Ffile_name_as_directory, Ffind_operation_coding_system},
+    remacs_sys::{QCdirectory, QCenvironment, QCinherit_environment, Qcall_process, Qnil, Qt},
+    symbols::keywordp,
     threads::{c_specpdl_index, ThreadState},
 };
 
+/// Options that can be given to the synchronous process primitives as
+/// leading `:KEYWORD VALUE' pairs, before PROGRAM (or START for
+/// `call-process-region').
+struct ProcessOptions {
+    /// The value of `:environment', if given.
+    environment: Option<LispObject>,
+    /// Whether `:environment' is added to `process-environment' rather
+    /// than replacing it.
+    inherit_environment: bool,
+    /// The value of `:directory', if given.
+    directory: Option<LispStringRef>,
+}
+
+impl ProcessOptions {
+    /// Strip the leading keyword arguments off ARGS, returning the parsed
+    /// options and the remaining arguments.
+    fn parse(args: &mut [LispObject]) -> (Self, &mut [LispObject]) {
+        let mut options = Self {
+            environment: None,
+            inherit_environment: true,
+            directory: None,
+        };
+
+        let mut idx = 0;
+        while idx < args.len() && keywordp(args[idx]) {
+            let key = args[idx];
+            let value = match args.get(idx + 1) {
+                Some(&value) => value,
+                None => error!("Missing value for keyword argument {}", key),
+            };
+
+            if key == QCenvironment {
+                options.environment = Some(value);
+            } else if key == QCinherit_environment {
+                options.inherit_environment = value.is_not_nil();
+            } else if key == QCdirectory {
+                options.directory = Some(value.into());
+            } else {
+                error!("Unknown keyword argument {}", key);
+            }
+            idx += 2;
+        }
+
+        (options, &mut args[idx..])
+    }
+
+    /// Return the directory the process should run in: `:directory',
+    /// expanded against the current buffer's `default-directory', or
+    /// that directory itself.
+    fn directory(&self) -> LispObject {
+        let current = ThreadState::current_buffer_unchecked().directory_;
+        match self.directory {
+            Some(directory) => unsafe {
+                Ffile_name_as_directory(expand_file_name(directory, current.into()).into())
+            },
+            None => current,
+        }
+    }
+
+    /// Return the environment the process should get, in the format of
+    /// `process-environment'.
+    fn environment(&self) -> LispObject {
+        let inherited = unsafe { globals.Vprocess_environment };
+        match self.environment {
+            Some(environment) if self.inherit_environment => {
+                append(&mut [environment_strings(environment), inherited])
+            }
+            Some(environment) => environment_strings(environment),
+            None => inherited,
+        }
+    }
+}
+
+/// Convert ENV, a list of strings `NAME=VALUE' or an alist of
+/// `(NAME . VALUE)' pairs, into a list in the format of
+/// `process-environment'.  A nil VALUE unsets NAME.
+fn environment_strings(env: LispObject) -> LispObject {
+    let entries: Vec<LispObject> = env
+        .iter_cars(LispConsEndChecks::on, LispConsCircularChecks::on)
+        .map(|entry| match entry.as_cons() {
+            Some(cons) => {
+                let (name, value) = cons.into();
+                let name: LispStringRef = name.as_symbol_or_string().into();
+                if value.is_nil() {
+                    name.into()
+                } else {
+                    concat(&mut [name.into(), "=".into(), LispStringRef::from(value).into()])
+                }
+            }
+            None => LispStringRef::from(entry).into(),
+        })
+        .collect();
+
+    list(&entries)
+}
+
 /// Call PROGRAM synchronously in separate process.
 /// The remaining arguments are optional.
 /// The program's input comes from file INFILE (nil means `/dev/null').
@@ -48,16 +156,33 @@ use crate::{
 /// determined by `unhandled-file-name-directory'), or "~" otherwise.  If
 /// you want to run a process in a remote directory use `process-file'.
 ///
-/// usage: (call-process PROGRAM &optional INFILE DESTINATION DISPLAY &rest ARGS)
+/// PROGRAM may be preceded by keyword arguments:
+///
+/// :directory DIR -- run the process in DIR instead of `default-directory'.
+/// INFILE is also expanded relative to DIR.
+///
+/// :environment ENV -- ENV is a list of strings of the form "NAME=VALUE",
+/// or an alist of (NAME . VALUE) pairs, where a nil VALUE removes NAME
+/// from the environment.  The entries take precedence over those in
+/// `process-environment'.
+///
+/// :inherit-environment FLAG -- if FLAG is nil, the process gets only the
+/// variables given by :environment instead of adding them to
+/// `process-environment'.  The default is t.
+///
+/// usage: (call-process [KEYWORD VALUE]... PROGRAM &optional INFILE DESTINATION DISPLAY &rest ARGS)
 #[lisp_fn(min = "1", name = "call-process", c_name = "call_process")]
 pub fn call_process_lisp(args: &mut [LispObject]) -> LispObject {
     let count = c_specpdl_index();
 
+    let (options, args) = ProcessOptions::parse(args);
+    if args.is_empty() {
+        error!("No program given to call-process");
+    }
+    let directory = options.directory();
+
     let infile = if args.len() >= 2 && args[1].is_not_nil() {
-        expand_file_name(
-            args[1].into(),
-            ThreadState::current_buffer_unchecked().directory_.into(),
-        )
+        expand_file_name(args[1].into(), directory.into())
     } else {
         unsafe { build_string(NULL_DEVICE.as_ptr() as *const libc::c_char) }.into()
     };
@@ -89,6 +214,8 @@ pub fn call_process_lisp(args: &mut [LispObject]) -> LispObject {
             args.as_mut_ptr() as *mut LispObject,
             filefd,
             -1,
+            directory,
+            options.environment(),
         )
     })
 }
@@ -122,9 +249,17 @@ pub fn call_process_lisp(args: &mut [LispObject]) -> LispObject {
 /// and returns a numeric exit status or a signal description string.
 /// If you quit, the process is killed with SIGINT, or SIGKILL if you quit again.
 ///
-/// usage: (call-process-region START END PROGRAM &optional DELETE BUFFER DISPLAY &rest ARGS)
+/// START may be preceded by the keyword arguments :directory, :environment
+/// and :inherit-environment, as described in `call-process'.
+///
+/// usage: (call-process-region [KEYWORD VALUE]... START END PROGRAM &optional DELETE BUFFER DISPLAY &rest ARGS)
 #[lisp_fn(min = "3")]
 pub fn call_process_region(args: &mut [LispObject]) -> LispObject {
+    let (options, args) = ProcessOptions::parse(args);
+    if args.len() < 3 {
+        error!("Not enough arguments to call-process-region");
+    }
+
     let mut start = args[0];
     let mut end = args[1];
     let mut infile = Qnil;
@@ -171,8 +306,275 @@ pub fn call_process_region(args: &mut [LispObject]) -> LispObject {
     args[1] = infile;
 
     let count = if empty_input { -1 } else { spec };
-    let exit = unsafe { call_process(args.len() as isize, args.as_mut_ptr(), fd, count) };
+    let exit = unsafe {
+        call_process(
+            args.len() as isize,
+            args.as_mut_ptr(),
+            fd,
+            count,
+            options.directory(),
+            options.environment(),
+        )
+    };
     unbind_to(spec, exit)
 }
 
+/// Convert BYTES, encoded text, to an `OsString` suitable for passing
+/// to the operating system.
+#[cfg(unix)]
+fn bytes_to_os(bytes: &[u8]) -> OsString {
+    OsStr::from_bytes(bytes).to_os_string()
+}
+
+#[cfg(windows)]
+fn bytes_to_os(bytes: &[u8]) -> OsString {
+    OsString::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// Convert the encoded Lisp string S to an `OsString` suitable for
+/// passing to the operating system.
+fn lisp_string_to_os(s: LispStringRef) -> OsString {
+    bytes_to_os(s.as_slice())
+}
+
+/// Split each string of ENV, which should look like `process-environment',
+/// into a name and a value.  As in `getenv-internal', earlier entries take
+/// precedence over later ones, and an entry without `=' removes the
+/// variable from the environment.
+fn environment_pairs(env: LispObject) -> Vec<(OsString, OsString)> {
+    let mut seen = HashSet::new();
+    let mut pairs = Vec::new();
+
+    for entry in env.iter_cars(LispConsEndChecks::off, LispConsCircularChecks::safe) {
+        let entry = match entry.as_string() {
+            Some(s) => s,
+            None => continue,
+        };
+        let bytes = entry.as_slice();
+        let (name, value) = match bytes.iter().position(|&b| b == b'=') {
+            Some(idx) => (&bytes[..idx], Some(&bytes[idx + 1..])),
+            None => (bytes, None),
+        };
+
+        if !seen.insert(name.to_vec()) {
+            continue;
+        }
+        if let Some(value) = value {
+            pairs.push((bytes_to_os(name), bytes_to_os(value)));
+        }
+    }
+
+    pairs
+}
+
+/// Search `exec-path' for PROGRAM, signalling a `file-missing' error if
+/// it can't be found, and return its encoded absolute file name.
+fn find_program(program: LispStringRef) -> LispStringRef {
+    let mut path = Qnil;
+    let found = unsafe {
+        openp(
+            globals.Vexec_path,
+            program.into(),
+            globals.Vexec_suffixes,
+            &mut path,
+            LispObject::from(libc::X_OK),
+            false,
+        )
+    };
+    if found < 0 {
+        unsafe {
+            report_file_error(
+                "Searching for program\0".as_ptr() as *const libc::c_char,
+                program.into(),
+            )
+        };
+    }
+
+    encode_file_name(unsafe { remove_slash_colon(path) }.into())
+}
+
+/// Encode ARG for use as a command line argument, the way `call-process'
+/// does.
+fn encode_argument(arg: LispObject) -> OsString {
+    let arg: LispStringRef = arg.into();
+    let coding = unsafe { globals.Vlocale_coding_system };
+    let encoded = if arg.is_multibyte() && coding.is_not_nil() {
+        encode_coding_string(arg.into(), coding, Qt, Qnil).into()
+    } else {
+        arg
+    };
+    lisp_string_to_os(encoded)
+}
+
+/// Build a `Command' that runs PROGRAM with ARGS in the directory and
+/// with the environment given by OPTIONS.
+fn build_command(program: LispStringRef, args: &[LispObject], options: &ProcessOptions) -> Command {
+    let path = find_program(program);
+    let directory: LispStringRef = unsafe { encode_directory(options.directory()) }.into();
+
+    let mut command = Command::new(lisp_string_to_os(path));
+    command
+        .args(args.iter().map(|&arg| encode_argument(arg)))
+        .current_dir(lisp_string_to_os(directory))
+        .env_clear()
+        .envs(environment_pairs(options.environment()))
+        .stdin(Stdio::null());
+
+    command
+}
+
+/// Run COMMAND to completion, capturing its standard output and
+/// standard error.  Signal an error mentioning PROGRAM and the contents
+/// of standard error if it does not exit successfully.
+fn run_command(program: LispStringRef, mut command: Command) -> Output {
+    let output = match command.output() {
+        Ok(output) => output,
+        Err(err) => error!("Spawning child process {}: {}", program, err),
+    };
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stderr = stderr.trim_end();
+        match output.status.code() {
+            Some(code) => error!("{} exited with status {}: {}", program, code, stderr),
+            None => {
+                #[cfg(unix)]
+                let signal = output.status.signal().unwrap_or(0);
+                #[cfg(not(unix))]
+                let signal = 0;
+                error!("{} terminated by signal {}: {}", program, signal, stderr)
+            }
+        }
+    }
+
+    output
+}
+
+/// Decode BYTES, the output of PROGRAM, with CODING_SYSTEM.  If that
+/// is nil, use `coding-system-for-read' or, if that is nil too, the
+/// coding system `call-process' would use to decode the output of
+/// PROGRAM.
+fn decode_process_output(
+    bytes: &[u8],
+    coding_system: LispObject,
+    program: LispStringRef,
+) -> LispStringRef {
+    let raw =
+        unsafe { make_unibyte_string(bytes.as_ptr() as *const libc::c_char, bytes.len() as isize) };
+
+    let coding_system = if coding_system.is_not_nil() {
+        coding_system
+    } else if unsafe { globals.Vcoding_system_for_read }.is_not_nil() {
+        unsafe { globals.Vcoding_system_for_read }
+    } else {
+        let coding_systems =
+            callN_raw!(Ffind_operation_coding_system, Qcall_process, program.into());
+        match coding_systems.as_cons() {
+            Some(cons) => cons.car(),
+            None => unsafe { globals.Vdefault_process_coding_system }
+                .as_cons()
+                .map_or(Qnil, |cons| cons.car()),
+        }
+    };
+
+    if coding_system.is_nil() {
+        raw.into()
+    } else {
+        decode_coding_string(raw, coding_system, Qt, Qnil).into()
+    }
+}
+
+/// Split the decoded STRING into a list of lines, dropping the empty
+/// line after a final newline.
+fn split_lines(string: LispStringRef) -> LispObject {
+    let bytes = string.as_slice();
+    let multibyte = string.is_multibyte();
+    let mut lines = Vec::new();
+    let mut start = 0;
+
+    while start < bytes.len() {
+        let end = bytes[start..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(bytes.len(), |idx| start + idx);
+        let line = &bytes[start..end];
+        lines.push(unsafe {
+            make_specified_string(
+                line.as_ptr() as *const libc::c_char,
+                -1,
+                line.len() as isize,
+                multibyte,
+            )
+        });
+        start = end + 1;
+    }
+
+    list(&lines)
+}
+
+/// Run PROGRAM synchronously and return its standard output as a string.
+///
+/// Unlike `call-process', the output is collected directly into a string
+/// without going through a buffer.  Remaining arguments ARGS are strings
+/// passed as command arguments to PROGRAM.  The program's input comes
+/// from the null device, and it runs in `default-directory' with the
+/// environment given by `process-environment'.
+///
+/// The output is decoded with CODING-SYSTEM.  If that is nil, use
+/// `coding-system-for-read' if non-nil, and otherwise the coding system
+/// `call-process' would use for PROGRAM's output, as given by
+/// `process-coding-system-alist' or `default-process-coding-system'.
+///
+/// If PROGRAM exits with a non-zero status or is killed by a signal,
+/// signal an error whose message includes the program's standard error
+/// output.  Standard error is discarded otherwise.
+///
+/// PROGRAM may be preceded by the keyword arguments :directory,
+/// :environment and :inherit-environment, as described in `call-process'.
+///
+/// usage: (call-process-to-string [KEYWORD VALUE]... PROGRAM &optional CODING-SYSTEM &rest ARGS)
+#[lisp_fn(min = "1")]
+pub fn call_process_to_string(args: &mut [LispObject]) -> LispObject {
+    let (options, args) = ProcessOptions::parse(args);
+    if args.is_empty() {
+        error!("No program given to call-process-to-string");
+    }
+
+    let program: LispStringRef = args[0].into();
+    let coding_system = args.get(1).copied().unwrap_or(Qnil);
+    let rest = if args.len() > 2 { &args[2..] } else { &[] };
+    let output = run_command(program, build_command(program, rest, &options));
+    decode_process_output(&output.stdout, coding_system, program).into()
+}
+
+/// Run PROGRAM synchronously and return its output as a list of lines.
+///
+/// This is like `call-process-to-string', which see, except that the
+/// output is split at newlines.  A trailing newline does not produce an
+/// empty last element.  The output is decoded with CODING-SYSTEM, or
+/// its fallbacks, before it is split.
+///
+/// usage: (call-process-lines [KEYWORD VALUE]... PROGRAM &optional CODING-SYSTEM &rest ARGS)
+#[lisp_fn(min = "1")]
+pub fn call_process_lines(args: &mut [LispObject]) -> LispObject {
+    let (options, args) = ProcessOptions::parse(args);
+    if args.is_empty() {
+        error!("No program given to call-process-lines");
+    }
+
+    let program: LispStringRef = args[0].into();
+    let coding_system = args.get(1).copied().unwrap_or(Qnil);
+    let rest = if args.len() > 2 { &args[2..] } else { &[] };
+    let output = run_command(program, build_command(program, rest, &options));
+    split_lines(decode_process_output(
+        &output.stdout,
+        coding_system,
+        program,
+    ))
+}
+
+def_lisp_sym!(QCdirectory, ":directory");
+def_lisp_sym!(QCenvironment, ":environment");
+def_lisp_sym!(QCinherit_environment, ":inherit-environment");
+
 include!(concat!(env!("OUT_DIR"), "/callproc_exports.rs"));
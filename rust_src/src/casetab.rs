@@ -9,6 +9,7 @@ use crate::{
     fns::copy_sequence,
     lisp::LispObject,
     lists::put,
+    multibyte::Codepoint,
     objects::eq,
     remacs_sys::EmacsInt,
     remacs_sys::{
@@ -41,6 +42,29 @@ impl LispCaseTable {
     }
 }
 
+/// Return the table used to canonicalize characters in the current
+/// buffer when `case-fold-search' is non-nil there, and None
+/// otherwise.
+pub fn case_fold_canon_table() -> Option<LispCharTableRef> {
+    let buffer = ThreadState::current_buffer_unchecked();
+    if buffer.case_fold_search().is_nil() {
+        None
+    } else {
+        buffer.case_canon_table_.as_char_table()
+    }
+}
+
+/// Translate character C through the char-table TABLE.  Characters
+/// that TABLE does not map to a character are returned unchanged.
+pub fn translate_char(table: LispCharTableRef, c: Codepoint) -> Codepoint {
+    let val = table.get(c.val() as isize);
+    if val.is_character() {
+        Codepoint::from(val)
+    } else {
+        c
+    }
+}
+
 impl LispObject {
     pub fn force_case_table(self) -> LispCaseTable {
         LispCaseTable(self.force_char_table())
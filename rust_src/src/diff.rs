@@ -0,0 +1,583 @@
+//! Shortest edit scripts between two sequences.
+//!
+//! This is a port of the linear-space variant of Myers' O(ND)
+//! algorithm found in gnulib's diffseq.h, including its heuristics for
+//! bounding the cost of large comparisons.  It is used by
+//! `replace-buffer-contents' to find the parts of a buffer that have
+//! to change, and by `string-diff'.
+
+use std::ops::Range;
+
+/// Snakes bigger than this are considered "big".
+const SNAKE_LIMIT: isize = 20;
+
+/// A run of elements in the old sequence that is replaced by a run of
+/// elements in the new sequence.  Either range may be empty, but not
+/// both.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hunk {
+    pub old: Range<usize>,
+    pub new: Range<usize>,
+}
+
+struct Partition {
+    // Midpoints of this partition.
+    xmid: isize,
+    ymid: isize,
+    // True if low half will be analyzed minimally.
+    lo_minimal: bool,
+    // Likewise for high half.
+    hi_minimal: bool,
+}
+
+struct Context<'a, T, F> {
+    xv: &'a [T],
+    yv: &'a [T],
+    // Vectors, indexed by diagonal + OFFSET, containing the X
+    // coordinate of the point furthest along the given diagonal in the
+    // forward and backward searches of the edit matrix.
+    fd: Vec<isize>,
+    bd: Vec<isize>,
+    offset: isize,
+    // This corresponds to the diff --speed-large-files flag.
+    heuristic: bool,
+    // Edit scripts longer than this are too expensive to compute.
+    too_expensive: isize,
+    abort: F,
+    deletions: Vec<bool>,
+    insertions: Vec<bool>,
+}
+
+impl<'a, T: PartialEq, F: FnMut() -> bool> Context<'a, T, F> {
+    fn eq(&self, x: isize, y: isize) -> bool {
+        self.xv[x as usize] == self.yv[y as usize]
+    }
+
+    fn fd(&self, d: isize) -> isize {
+        self.fd[(d + self.offset) as usize]
+    }
+
+    fn set_fd(&mut self, d: isize, x: isize) {
+        self.fd[(d + self.offset) as usize] = x;
+    }
+
+    fn bd(&self, d: isize) -> isize {
+        self.bd[(d + self.offset) as usize]
+    }
+
+    fn set_bd(&mut self, d: isize, x: isize) {
+        self.bd[(d + self.offset) as usize] = x;
+    }
+
+    /// Find the midpoint of the shortest edit script for the portion
+    /// [XOFF, XLIM) x [YOFF, YLIM), scanning from both ends at once.
+    /// The first elements of the portions must not match, and neither
+    /// must the last.  Return None if the comparison was aborted.
+    fn diag(
+        &mut self,
+        xoff: isize,
+        xlim: isize,
+        yoff: isize,
+        ylim: isize,
+        find_minimal: bool,
+    ) -> Option<Partition> {
+        let dmin = xoff - ylim; // Minimum valid diagonal.
+        let dmax = xlim - yoff; // Maximum valid diagonal.
+        let fmid = xoff - yoff; // Center diagonal of top-down search.
+        let bmid = xlim - ylim; // Center diagonal of bottom-up search.
+        let (mut fmin, mut fmax) = (fmid, fmid);
+        let (mut bmin, mut bmax) = (bmid, bmid);
+        // True if southeast corner is on an odd diagonal with respect
+        // to the northwest.
+        let odd = (fmid - bmid) & 1 != 0;
+
+        self.set_fd(fmid, xoff);
+        self.set_bd(bmid, xlim);
+
+        let mut c: isize = 1;
+        loop {
+            if (self.abort)() {
+                return None;
+            }
+            let mut big_snake = false;
+
+            // Extend the top-down search by an edit step in each diagonal.
+            if fmin > dmin {
+                fmin -= 1;
+                self.set_fd(fmin - 1, -1);
+            } else {
+                fmin += 1;
+            }
+            if fmax < dmax {
+                fmax += 1;
+                self.set_fd(fmax + 1, -1);
+            } else {
+                fmax -= 1;
+            }
+            let mut d = fmax;
+            while d >= fmin {
+                let tlo = self.fd(d - 1);
+                let thi = self.fd(d + 1);
+                let x0 = if tlo < thi { thi } else { tlo + 1 };
+                let (mut x, mut y) = (x0, x0 - d);
+                while x < xlim && y < ylim && self.eq(x, y) {
+                    x += 1;
+                    y += 1;
+                }
+                if x - x0 > SNAKE_LIMIT {
+                    big_snake = true;
+                }
+                self.set_fd(d, x);
+                if odd && bmin <= d && d <= bmax && self.bd(d) <= x {
+                    return Some(Partition {
+                        xmid: x,
+                        ymid: y,
+                        lo_minimal: true,
+                        hi_minimal: true,
+                    });
+                }
+                d -= 2;
+            }
+
+            // Similarly extend the bottom-up search.
+            if bmin > dmin {
+                bmin -= 1;
+                self.set_bd(bmin - 1, isize::max_value());
+            } else {
+                bmin += 1;
+            }
+            if bmax < dmax {
+                bmax += 1;
+                self.set_bd(bmax + 1, isize::max_value());
+            } else {
+                bmax -= 1;
+            }
+            let mut d = bmax;
+            while d >= bmin {
+                let tlo = self.bd(d - 1);
+                let thi = self.bd(d + 1);
+                let x0 = if tlo < thi { tlo } else { thi - 1 };
+                let (mut x, mut y) = (x0, x0 - d);
+                while xoff < x && yoff < y && self.eq(x - 1, y - 1) {
+                    x -= 1;
+                    y -= 1;
+                }
+                if x0 - x > SNAKE_LIMIT {
+                    big_snake = true;
+                }
+                self.set_bd(d, x);
+                if !odd && fmin <= d && d <= fmax && x <= self.fd(d) {
+                    return Some(Partition {
+                        xmid: x,
+                        ymid: y,
+                        lo_minimal: true,
+                        hi_minimal: true,
+                    });
+                }
+                d -= 2;
+            }
+
+            if find_minimal {
+                c += 1;
+                continue;
+            }
+
+            // Heuristic: check occasionally for a diagonal that has made
+            // lots of progress compared with the edit distance.  If we
+            // have any such, find the one that has made the most
+            // progress and return it as if it had succeeded.
+            if 200 < c && big_snake && self.heuristic {
+                if let Some((xmid, ymid)) =
+                    self.best_forward_snake(xoff, xlim, yoff, ylim, fmin, fmax, c)
+                {
+                    return Some(Partition {
+                        xmid,
+                        ymid,
+                        lo_minimal: true,
+                        hi_minimal: false,
+                    });
+                }
+                if let Some((xmid, ymid)) =
+                    self.best_backward_snake(xoff, xlim, yoff, ylim, bmin, bmax, c)
+                {
+                    return Some(Partition {
+                        xmid,
+                        ymid,
+                        lo_minimal: false,
+                        hi_minimal: true,
+                    });
+                }
+            }
+
+            // Heuristic: if we've gone well beyond the call of duty,
+            // give up and report halfway between our best results so far.
+            if c >= self.too_expensive {
+                // Find forward diagonal that maximizes X + Y.
+                let (mut fxybest, mut fxbest) = (-1, 0);
+                let mut d = fmax;
+                while d >= fmin {
+                    let mut x = self.fd(d).min(xlim);
+                    let mut y = x - d;
+                    if ylim < y {
+                        x = ylim + d;
+                        y = ylim;
+                    }
+                    if fxybest < x + y {
+                        fxybest = x + y;
+                        fxbest = x;
+                    }
+                    d -= 2;
+                }
+                // Find backward diagonal that minimizes X + Y.
+                let (mut bxybest, mut bxbest) = (isize::max_value(), 0);
+                let mut d = bmax;
+                while d >= bmin {
+                    let mut x = self.bd(d).max(xoff);
+                    let mut y = x - d;
+                    if y < yoff {
+                        x = yoff + d;
+                        y = yoff;
+                    }
+                    if x + y < bxybest {
+                        bxybest = x + y;
+                        bxbest = x;
+                    }
+                    d -= 2;
+                }
+                // Use the better of the two diagonals.
+                return Some(if (xlim + ylim) - bxybest < fxybest - (xoff + yoff) {
+                    Partition {
+                        xmid: fxbest,
+                        ymid: fxybest - fxbest,
+                        lo_minimal: true,
+                        hi_minimal: false,
+                    }
+                } else {
+                    Partition {
+                        xmid: bxbest,
+                        ymid: bxybest - bxbest,
+                        lo_minimal: false,
+                        hi_minimal: true,
+                    }
+                });
+            }
+            c += 1;
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn best_forward_snake(
+        &self,
+        xoff: isize,
+        xlim: isize,
+        yoff: isize,
+        ylim: isize,
+        fmin: isize,
+        fmax: isize,
+        cost: isize,
+    ) -> Option<(isize, isize)> {
+        let fmid = xoff - yoff;
+        let mut best = 0;
+        let mut found = None;
+        let mut d = fmax;
+        while d >= fmin {
+            let dd = d - fmid;
+            let x = self.fd(d);
+            let y = x - d;
+            let v = (x - xoff) * 2 - dd;
+            if v > 12 * (cost + dd.abs())
+                && v > best
+                && xoff + SNAKE_LIMIT <= x
+                && x < xlim
+                && yoff + SNAKE_LIMIT <= y
+                && y < ylim
+            {
+                // We have a good enough best diagonal; now insist that
+                // it end with a significant snake.
+                if (1..=SNAKE_LIMIT).all(|k| self.eq(x - k, y - k)) {
+                    best = v;
+                    found = Some((x, y));
+                }
+            }
+            d -= 2;
+        }
+        found
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn best_backward_snake(
+        &self,
+        xoff: isize,
+        xlim: isize,
+        yoff: isize,
+        ylim: isize,
+        bmin: isize,
+        bmax: isize,
+        cost: isize,
+    ) -> Option<(isize, isize)> {
+        let bmid = xlim - ylim;
+        let mut best = 0;
+        let mut found = None;
+        let mut d = bmax;
+        while d >= bmin {
+            let dd = d - bmid;
+            let x = self.bd(d);
+            let y = x - d;
+            let v = (xlim - x) * 2 + dd;
+            if v > 12 * (cost + dd.abs())
+                && v > best
+                && xoff < x
+                && x <= xlim - SNAKE_LIMIT
+                && yoff < y
+                && y <= ylim - SNAKE_LIMIT
+                && (0..SNAKE_LIMIT).all(|k| self.eq(x + k, y + k))
+            {
+                best = v;
+                found = Some((x, y));
+            }
+            d -= 2;
+        }
+        found
+    }
+
+    /// Compare in detail the portions [XOFF, XLIM) and [YOFF, YLIM),
+    /// recording the elements to delete and insert.  Return false if
+    /// the comparison was aborted.
+    fn compareseq(
+        &mut self,
+        mut xoff: isize,
+        mut xlim: isize,
+        mut yoff: isize,
+        mut ylim: isize,
+        find_minimal: bool,
+    ) -> bool {
+        // Slide down the bottom initial diagonal.
+        while xoff < xlim && yoff < ylim && self.eq(xoff, yoff) {
+            xoff += 1;
+            yoff += 1;
+        }
+        // Slide up the top initial diagonal.
+        while xoff < xlim && yoff < ylim && self.eq(xlim - 1, ylim - 1) {
+            xlim -= 1;
+            ylim -= 1;
+        }
+
+        if xoff == xlim {
+            for y in yoff..ylim {
+                self.insertions[y as usize] = true;
+            }
+        } else if yoff == ylim {
+            for x in xoff..xlim {
+                self.deletions[x as usize] = true;
+            }
+        } else {
+            // Find a point of correspondence in the middle of the
+            // sequences and use it to split the problem.
+            let part = match self.diag(xoff, xlim, yoff, ylim, find_minimal) {
+                Some(part) => part,
+                None => return false,
+            };
+            if !self.compareseq(xoff, part.xmid, yoff, part.ymid, part.lo_minimal)
+                || !self.compareseq(part.xmid, xlim, part.ymid, ylim, part.hi_minimal)
+            {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Compute the hunks that turn OLD into NEW, in ascending order.
+///
+/// If TOO_EXPENSIVE is None, the result is a shortest edit script.
+/// Otherwise heuristics are used to keep the search roughly within
+/// that many edit steps per partition, at the price of a possibly
+/// suboptimal result.  ABORT is polled while comparing; if it ever
+/// returns true, the comparison stops and None is returned.
+pub fn diff<T, F>(old: &[T], new: &[T], too_expensive: Option<usize>, abort: F) -> Option<Vec<Hunk>>
+where
+    T: PartialEq,
+    F: FnMut() -> bool,
+{
+    let (xlen, ylen) = (old.len(), new.len());
+    let diags = xlen + ylen + 3;
+    let mut ctx = Context {
+        xv: old,
+        yv: new,
+        fd: vec![0; diags],
+        bd: vec![0; diags],
+        offset: ylen as isize + 1,
+        heuristic: too_expensive.is_some(),
+        too_expensive: too_expensive.map_or(isize::max_value(), |n| n.max(1) as isize),
+        abort,
+        deletions: vec![false; xlen],
+        insertions: vec![false; ylen],
+    };
+
+    if !ctx.compareseq(0, xlen as isize, 0, ylen as isize, too_expensive.is_none()) {
+        return None;
+    }
+
+    let mut hunks = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < xlen || j < ylen {
+        if (i < xlen && ctx.deletions[i]) || (j < ylen && ctx.insertions[j]) {
+            let (old_start, new_start) = (i, j);
+            while i < xlen && ctx.deletions[i] {
+                i += 1;
+            }
+            while j < ylen && ctx.insertions[j] {
+                j += 1;
+            }
+            hunks.push(Hunk {
+                old: old_start..i,
+                new: new_start..j,
+            });
+        } else {
+            i += 1;
+            j += 1;
+        }
+    }
+    Some(hunks)
+}
+
+/// Return the offsets at which the lines of SEQ start, followed by the
+/// length of SEQ.  A line includes its terminating NEWLINE.
+fn line_starts<T: PartialEq>(seq: &[T], newline: &T) -> Vec<usize> {
+    let mut starts = vec![0];
+    starts.extend(
+        seq.iter()
+            .enumerate()
+            .filter(|&(i, elt)| elt == newline && i + 1 < seq.len())
+            .map(|(i, _)| i + 1),
+    );
+    if !seq.is_empty() {
+        starts.push(seq.len());
+    }
+    starts
+}
+
+/// Like `diff', but compare OLD and NEW line by line, where a line
+/// ends after each NEWLINE element.  The hunks are expressed in
+/// elements, not lines, and always span whole lines.  The result is a
+/// shortest edit script in terms of lines.
+pub fn diff_lines<T, F>(old: &[T], new: &[T], newline: &T, abort: F) -> Option<Vec<Hunk>>
+where
+    T: PartialEq,
+    F: FnMut() -> bool,
+{
+    let old_starts = line_starts(old, newline);
+    let new_starts = line_starts(new, newline);
+    let old_lines: Vec<&[T]> = old_starts.windows(2).map(|w| &old[w[0]..w[1]]).collect();
+    let new_lines: Vec<&[T]> = new_starts.windows(2).map(|w| &new[w[0]..w[1]]).collect();
+
+    let hunks = diff(&old_lines, &new_lines, None, abort)?;
+    Some(
+        hunks
+            .into_iter()
+            .map(|hunk| Hunk {
+                old: old_starts[hunk.old.start]..old_starts[hunk.old.end],
+                new: new_starts[hunk.new.start]..new_starts[hunk.new.end],
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(old: &[u8], new: &[u8], hunks: &[Hunk]) -> Vec<u8> {
+        let mut result = old.to_vec();
+        for hunk in hunks.iter().rev() {
+            result.splice(hunk.old.clone(), new[hunk.new.clone()].iter().cloned());
+        }
+        result
+    }
+
+    fn cost(hunks: &[Hunk]) -> usize {
+        hunks.iter().map(|h| h.old.len() + h.new.len()).sum()
+    }
+
+    #[test]
+    fn test_diff_minimal() {
+        let cases: &[(&str, &str, usize)] = &[
+            ("", "", 0),
+            ("abc", "abc", 0),
+            ("", "abc", 3),
+            ("abc", "", 3),
+            ("abcabba", "cbabac", 5),
+            ("foo BAR baz", "foo bar baz", 6),
+            ("before dest after", "before source after", 8),
+        ];
+        for &(old, new, expected) in cases {
+            let hunks = diff(old.as_bytes(), new.as_bytes(), None, || false).unwrap();
+            assert_eq!(
+                apply(old.as_bytes(), new.as_bytes(), &hunks),
+                new.as_bytes()
+            );
+            assert_eq!(cost(&hunks), expected, "{:?} -> {:?}", old, new);
+        }
+    }
+
+    #[test]
+    fn test_diff_hunks() {
+        let hunks = diff(b"foo BAR baz", b"foo bar baz qux", None, || false).unwrap();
+        assert_eq!(
+            hunks,
+            vec![
+                Hunk {
+                    old: 4..7,
+                    new: 4..7
+                },
+                Hunk {
+                    old: 11..11,
+                    new: 11..15
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_too_expensive() {
+        let old: Vec<u8> = (0..2000).map(|i| (i * 7 % 13) as u8).collect();
+        let new: Vec<u8> = (0..2100).map(|i| (i * 5 % 11) as u8).collect();
+        let hunks = diff(&old, &new, Some(4), || false).unwrap();
+        assert_eq!(apply(&old, &new, &hunks), new);
+    }
+
+    #[test]
+    fn test_diff_abort() {
+        assert_eq!(diff(b"abc", b"abd", None, || true), None);
+        assert!(diff(b"abc", b"abc", None, || true).is_some());
+    }
+
+    #[test]
+    fn test_diff_lines() {
+        let old = b"one\ntwo\nthree\n";
+        let new = b"one\n2\nthree\nfour";
+        let hunks = diff_lines(old, new, &b'\n', || false).unwrap();
+        assert_eq!(
+            hunks,
+            vec![
+                Hunk {
+                    old: 4..8,
+                    new: 4..6
+                },
+                Hunk {
+                    old: 14..14,
+                    new: 12..16
+                },
+            ]
+        );
+        assert_eq!(apply(old, new, &hunks), new);
+        assert_eq!(diff_lines(b"", b"", &b'\n', || false), Some(vec![]));
+        assert_eq!(
+            diff_lines(b"a", b"a\n", &b'\n', || false),
+            Some(vec![Hunk {
+                old: 0..1,
+                new: 0..2
+            }])
+        );
+    }
+}
@@ -4,34 +4,53 @@ use remacs_macros::lisp_fn;
 
 #[cfg(unix)]
 use crate::dired_unix::{
-    directory_files_and_attributes_intro, directory_files_intro, file_attributes_intro, get_groups,
-    get_users,
+    directory_files_and_attributes_intro, directory_files_intro, directory_files_recursively_intro,
+    file_attributes_intro, file_disk_usage_intro, file_system_info_intro, get_groups, get_users,
 };
 #[cfg(windows)]
 use dired_windows::{file_attributes_intro, get_groups, get_users};
 
-use crate::{lisp::LispObject, lists::car, multibyte::LispStringRef, strings::string_lessp};
+use crate::{
+    lisp::LispObject, lists::car, multibyte::LispStringRef, remacs_sys::EmacsUint,
+    strings::string_lessp,
+};
 
 /// Return a list of names of files in DIRECTORY.
-/// There are three optional arguments:
+/// There are four optional arguments:
 /// If FULL is non-nil, return absolute file names.  Otherwise return names
 ///  that are relative to the specified directory.
 /// If MATCH is non-nil, mention only file names that match the regexp MATCH.
 /// If NOSORT is non-nil, the list is not sorted--its order is unpredictable.
 ///  Otherwise, the list returned is sorted with `string-lessp'.
 ///  NOSORT is useful if you plan to sort the result yourself.
+/// If COUNT is non-nil and a natural number, the function will return
+///  COUNT number of file names (if so many are present).
 #[lisp_fn(min = "1")]
 pub fn directory_files(
     directory: LispStringRef,
     full: LispObject,
     match_re: LispObject,
     nosort: LispObject,
+    count: Option<EmacsUint>,
 ) -> LispObject {
-    directory_files_intro(directory, full, match_re, nosort)
+    directory_files_intro(
+        directory,
+        full,
+        match_re,
+        nosort,
+        count.map(|count| count as usize),
+    )
 }
 
 /// Return a list of names of files and their attributes in DIRECTORY.
-/// There are four optional arguments:
+/// Value is a list of the form:
+///
+///   ((FILE1 . FILE1-ATTRS) (FILE2 . FILE2-ATTRS) ...)
+///
+/// where each FILEn-ATTRS is the attributes of FILEn as returned
+/// by `file-attributes'.
+///
+/// This function accepts five optional arguments:
 /// If FULL is non-nil, return absolute file names.  Otherwise return names
 ///  that are relative to the specified directory.
 /// If MATCH is non-nil, mention only file names that match the regexp MATCH.
@@ -39,6 +58,8 @@ pub fn directory_files(
 ///  NOSORT is useful if you plan to sort the result yourself.
 /// ID-FORMAT specifies the preferred format of attributes uid and gid, see
 /// `file-attributes' for further documentation.
+/// If COUNT is non-nil and a natural number, the function will return
+///  COUNT number of file names (if so many are present).
 /// On MS-Windows, performance depends on `w32-get-true-file-attributes',
 /// which see.
 #[lisp_fn(min = "1")]
@@ -48,8 +69,53 @@ pub fn directory_files_and_attributes(
     match_re: LispObject,
     nosort: LispObject,
     id_format: LispObject,
+    count: Option<EmacsUint>,
 ) -> LispObject {
-    directory_files_and_attributes_intro(directory, full, match_re, nosort, id_format)
+    directory_files_and_attributes_intro(
+        directory,
+        full,
+        match_re,
+        nosort,
+        id_format,
+        count.map(|count| count as usize),
+    )
+}
+
+/// Return list of all files under DIRECTORY whose names match REGEXP.
+/// This function works recursively.  Files are returned in "depth first"
+/// order, and files from each directory are sorted in alphabetical order.
+/// Each file name appears in the returned list in its absolute form.
+/// There are five optional arguments:
+/// If INCLUDE-DIRECTORIES is non-nil, also include in the output
+///  directories whose names match REGEXP.
+/// If MAX-DEPTH is non-nil, it is the number of levels of subdirectories
+///  to descend into; 0 means to list only the files directly in DIRECTORY.
+/// If FOLLOW-SYMLINKS is non-nil, descend into symbolic links to
+///  directories.  A directory is never walked twice on the same path.
+/// If EXCLUDE is non-nil, it is a regexp; files and directories whose
+///  names match it are left out, and such directories are not descended.
+/// If RESPECT-IGNORE-FILES is non-nil, skip `.git' directories and the
+///  files excluded by the `.gitignore' and `.ignore' files found along
+///  the way.
+#[lisp_fn(min = "2")]
+pub fn directory_files_recursively(
+    directory: LispStringRef,
+    regexp: LispStringRef,
+    include_directories: LispObject,
+    max_depth: Option<EmacsUint>,
+    follow_symlinks: LispObject,
+    exclude: LispObject,
+    respect_ignore_files: LispObject,
+) -> LispObject {
+    directory_files_recursively_intro(
+        directory,
+        regexp,
+        include_directories,
+        max_depth.map(|depth| depth as usize),
+        follow_symlinks,
+        exclude,
+        respect_ignore_files,
+    )
 }
 
 /// Return a list of attributes of file FILENAME.
@@ -60,6 +126,9 @@ pub fn directory_files_and_attributes(
 /// default, but we plan to change that, so you should specify a non-nil value
 /// for ID-FORMAT if you use the returned uid or gid.
 ///
+/// ID-FORMAT can also be `extended', or a list such as (string extended),
+/// to get the extended attributes 12 to 15 described below as well.
+///
 /// To access the elements returned, the following access functions are
 /// provided: `file-attribute-type', `file-attribute-link-number',
 /// `file-attribute-user-id', `file-attribute-group-id',
@@ -93,6 +162,16 @@ pub fn directory_files_and_attributes(
 /// 11. Filesystem device number.  If it is larger than what the Emacs
 ///   integer can hold, this is a cons cell, similar to the inode number.
 ///
+/// The extended attributes are:
+/// 12. Birth time, like the other times, or nil if the filesystem does not
+///   record it.
+/// 13. ID of the mount containing the file, or nil if it is not known.
+/// 14. Number of 512-byte blocks allocated to the file.
+/// 15. Preferred block size for file system I/O.
+///
+/// Symbolic links are not followed: the attributes of a link are those of
+/// the link itself.
+///
 /// On most filesystems, the combination of the inode and the device
 /// number uniquely identifies the file.
 ///
@@ -106,6 +185,38 @@ pub fn file_attributes(filename: LispStringRef, id_format: LispObject) -> LispOb
     file_attributes_intro(filename, id_format)
 }
 
+/// Return storage information about the file system FILENAME is on.
+/// Value is a list of numbers (TOTAL FREE AVAIL), where TOTAL is the total
+/// storage of the file system, FREE is the free storage, and AVAIL is the
+/// storage available to a non-superuser.  All 3 numbers are in bytes.
+/// If the underlying system call fails, value is nil.
+#[cfg(unix)]
+#[lisp_fn]
+pub fn file_system_info(filename: LispStringRef) -> LispObject {
+    file_system_info_intro(filename)
+}
+
+/// Return the number of bytes of disk space used by file FILENAME.
+/// If FILENAME is a directory, this includes the files in it, recursively,
+/// like the `du' command.  Symbolic links are not followed, and files with
+/// several names are counted once.  Files that cannot be read are skipped.
+///
+/// If APPARENT is non-nil, add up the sizes of the files instead of the
+/// space allocated to them, which differs for sparse and compressed files.
+/// If ONE-FILE-SYSTEM is non-nil, skip directories on other file systems
+/// than FILENAME.
+///
+/// Value is an integer, or a float if it is too large for an integer.
+#[cfg(unix)]
+#[lisp_fn(min = "1")]
+pub fn file_disk_usage(
+    filename: LispStringRef,
+    apparent: bool,
+    one_file_system: bool,
+) -> LispObject {
+    file_disk_usage_intro(filename, apparent, one_file_system)
+}
+
 /// Return t if first arg file attributes list is less than second.
 /// Comparison is in lexicographic order and case is significant.
 #[lisp_fn]
@@ -128,4 +239,7 @@ pub fn system_groups() -> LispObject {
     get_groups()
 }
 
+def_lisp_sym!(Qdirectory_files_recursively, "directory-files-recursively");
+def_lisp_sym!(Qfile_disk_usage, "file-disk-usage");
+
 include!(concat!(env!("OUT_DIR"), "/dired_exports.rs"));
@@ -3,28 +3,38 @@ use libc::{
     size_t, ssize_t, timespec as c_timespec,
 };
 
+use std::collections::HashSet;
 use std::ffi::{CStr, CString, OsStr};
 use std::fs;
 use std::io;
+use std::mem;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
 use std::os::unix::fs::MetadataExt;
 use std::path::Path;
 use std::ptr::null_mut;
 use std::slice;
+use std::thread;
+use std::time::UNIX_EPOCH;
 
 use crate::{
-    coding::decode_system,
+    coding::{decode_system, encode_file_name},
     fileio::{expand_file_name, find_file_name_handler},
+    fns::append,
+    gitignore::IgnoreRules,
     lisp::LispObject,
-    lists::list,
+    lists::{list, LispConsCircularChecks, LispConsEndChecks},
     multibyte::LispStringRef,
+    numbers::MOST_POSITIVE_FIXNUM,
     remacs_sys::{
-        build_string, compile_pattern, decode_file_name, file_attributes_c_internal,
-        filemode_string, globals, re_pattern_buffer, re_search,
+        build_string, compile_pattern, decode_file_name, filemode_string, globals, make_string,
+        make_unibyte_string, maybe_quit, re_pattern_buffer, re_search, report_file_errno,
+        EmacsDouble, EmacsUint,
     },
     remacs_sys::{
-        Qdirectory_files, Qdirectory_files_and_attributes, Qfile_attributes, Qfile_missing, Qnil,
-        Qt,
+        Qdirectory_files, Qdirectory_files_and_attributes, Qdirectory_files_recursively,
+        Qfile_attributes, Qfile_disk_usage, Qfile_missing, Qfile_system_info, Qnil, Qt,
     },
+    threads::without_global_lock,
     time::make_lisp_time,
 };
 
@@ -100,20 +110,10 @@ impl StringExt for String {
 }
 
 trait LispObjectExt {
-    fn to_idfstring(&self) -> String;
     fn to_stdstring(&self) -> String;
 }
 
 impl LispObjectExt for LispObject {
-    fn to_idfstring(&self) -> String {
-        if self.is_nil() {
-            "NOTstring".to_string()
-        } else {
-            let idf_sym_s: LispStringRef = self.as_symbol_or_string().into();
-            idf_sym_s.to_string().to_lowercase()
-        }
-    }
-
     fn to_stdstring(&self) -> String {
         let s = self.as_string().unwrap(); //LispObject String
         let slice = unsafe { slice::from_raw_parts(s.const_data_ptr(), s.len_bytes() as usize) };
@@ -128,6 +128,7 @@ struct DirReq {
     match_re: Option<LispObject>, // filter regexp
     sortmemaybe: SortFNames,
     id_format: LispObject, // integer uid (default) or string username
+    count: Option<usize>,  // maximum number of files to return
 }
 
 impl DirReq {
@@ -137,6 +138,7 @@ impl DirReq {
         match_re: Option<LispObject>,
         sortmemaybe: SortFNames,
         id_format: LispObject,
+        count: Option<usize>,
     ) -> Self {
         Self {
             dname,
@@ -144,6 +146,7 @@ impl DirReq {
             match_re,
             sortmemaybe,
             id_format,
+            count,
         }
     }
 }
@@ -173,7 +176,7 @@ impl DirData {
     fn load(&mut self, dr: &DirReq) {
         match *self {
             DirData::Files { ref mut fnames } => {
-                fnames_from_os(fnames, &dr.dname, dr.match_re);
+                fnames_from_os(fnames, &dr.dname, dr.match_re, dr.count);
                 if let SortFNames::Yes = dr.sortmemaybe {
                     fnames.sort();
                 }
@@ -182,7 +185,7 @@ impl DirData {
                 ref mut fnames,
                 ref mut fattrs,
             } => {
-                fnames_from_os(fnames, &dr.dname, dr.match_re);
+                fnames_from_os(fnames, &dr.dname, dr.match_re, dr.count);
                 if let SortFNames::Yes = dr.sortmemaybe {
                     fnames.sort();
                 }
@@ -209,14 +212,92 @@ fn fattrs_from_os(
     dname: &str,
     id_format: LispObject,
 ) {
-    for f in fnames {
-        let fa = file_attributes_core(f.to_full(dname.to_owned()).as_str().into(), id_format);
-        fattrs.push(fa);
+    let fpaths = fnames
+        .iter()
+        .map(|f| f.to_full(dname.to_owned()))
+        .collect::<Vec<_>>();
+
+    // Only the stat phase runs in parallel; the user and group name
+    // lookups are not thread-safe and the Lisp objects must be built
+    // while holding the global lock.
+    for attrs in stat_files(fpaths, IdFormat::new(id_format)) {
+        fattrs.push(match attrs {
+            Some(mut attrs) => {
+                attrs.lookup_ids();
+                attrs.to_list()
+            }
+            None => Qnil,
+        });
     }
 }
 
-fn fnames_from_os(fnames: &mut Vec<String>, dname: &str, match_re: Option<LispObject>) {
-    match read_dir(dname, fnames, match_re) {
+// Directories with fewer entries than this are stat'ed on the calling
+// thread, as spawning threads would cost more than it saves.
+const PARALLEL_STAT_MIN_FILES: usize = 256;
+// Each stat thread gets at least this many files.
+const PARALLEL_STAT_MIN_CHUNK: usize = 64;
+const PARALLEL_STAT_MAX_THREADS: usize = 16;
+
+// Stat FPATHS, returning their attributes in the same order, with None
+// for the files that could not be stat'ed.  Large batches are split
+// across threads, and the global lock is released meanwhile.
+fn stat_files(fpaths: Vec<String>, id_format: IdFormat) -> Vec<Option<FileAttrs>> {
+    let stat = |fpath: String, id_format: IdFormat| {
+        let mut attrs = FileAttrs::new(fpath, id_format);
+        attrs.get().ok().map(|_| attrs)
+    };
+
+    let ncpus = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) };
+    let nthreads = (ncpus.max(1) as usize)
+        .min(fpaths.len() / PARALLEL_STAT_MIN_CHUNK)
+        .min(PARALLEL_STAT_MAX_THREADS);
+    if fpaths.len() < PARALLEL_STAT_MIN_FILES || nthreads < 2 {
+        return fpaths
+            .into_iter()
+            .map(|fpath| stat(fpath, id_format))
+            .collect();
+    }
+
+    let chunk_len = (fpaths.len() + nthreads - 1) / nthreads;
+    let mut results = Vec::with_capacity(fpaths.len());
+    without_global_lock(|| {
+        let mut fpaths = fpaths.into_iter();
+        let mut workers = Vec::with_capacity(nthreads);
+        loop {
+            let chunk = fpaths.by_ref().take(chunk_len).collect::<Vec<_>>();
+            if chunk.is_empty() {
+                break;
+            }
+            let len = chunk.len();
+            let worker = thread::Builder::new().spawn(move || {
+                chunk
+                    .into_iter()
+                    .map(|fpath| stat(fpath, id_format))
+                    .collect::<Vec<_>>()
+            });
+            workers.push((len, worker));
+        }
+
+        for (len, worker) in workers {
+            match worker.map(thread::JoinHandle::join) {
+                Ok(Ok(chunk)) => results.extend(chunk),
+                // A thread that could not be spawned or that panicked
+                // leaves its files without attributes.
+                _ => results.extend((0..len).map(|_| None)),
+            }
+        }
+    });
+
+    results
+}
+
+fn fnames_from_os(
+    fnames: &mut Vec<String>,
+    dname: &str,
+    match_re: Option<LispObject>,
+    count: Option<usize>,
+) {
+    match read_dir(dname, fnames, match_re, count) {
         Ok(_) => {}
         Err(err) => {
             xsignal!(
@@ -228,7 +309,12 @@ fn fnames_from_os(fnames: &mut Vec<String>, dname: &str, match_re: Option<LispOb
     }
 }
 
-fn read_dir(dname: &str, fnames: &mut Vec<String>, match_re: Option<LispObject>) -> io::Result<()> {
+fn read_dir(
+    dname: &str,
+    fnames: &mut Vec<String>,
+    match_re: Option<LispObject>,
+    count: Option<usize>,
+) -> io::Result<()> {
     let dir_p = Path::new(dname);
 
     let re = match match_re {
@@ -236,6 +322,11 @@ fn read_dir(dname: &str, fnames: &mut Vec<String>, match_re: Option<LispObject>)
         None => None,
     };
 
+    let is_full = |fnames: &Vec<String>| count.map_or(false, |count| fnames.len() >= count);
+    if is_full(fnames) {
+        return Ok(());
+    }
+
     let dot = String::from(".");
     if match_re_maybe(dot.to_owned(), &re).is_some() {
         fnames.push(dot);
@@ -246,6 +337,9 @@ fn read_dir(dname: &str, fnames: &mut Vec<String>, match_re: Option<LispObject>)
     }
 
     for fname in fs::read_dir(dir_p)? {
+        if is_full(fnames) {
+            break;
+        }
         let fname = fname?;
         let f_enc = match fname.file_name().into_string() {
             Ok(file_name) => file_name,
@@ -331,19 +425,31 @@ pub fn directory_files_intro(
     full: LispObject,
     match_re: LispObject,
     nosort: LispObject,
+    count: Option<usize>,
 ) -> LispObject {
     let dnexp = expand_file_name(directory, None);
 
     let handler = find_file_name_handler(dnexp, Qdirectory_files);
     if handler.is_not_nil() {
-        return call!(
-            handler,
-            Qdirectory_files,
-            dnexp.into(),
-            full,
-            match_re,
-            nosort
-        );
+        return match count {
+            Some(count) => call!(
+                handler,
+                Qdirectory_files,
+                dnexp.into(),
+                full,
+                match_re,
+                nosort,
+                LispObject::from_natnum(count as u64)
+            ),
+            None => call!(
+                handler,
+                Qdirectory_files,
+                dnexp.into(),
+                full,
+                match_re,
+                nosort
+            ),
+        };
     }
 
     let dr = DirReq::new(
@@ -364,6 +470,7 @@ pub fn directory_files_intro(
             SortFNames::No
         },
         Qnil,
+        count,
     );
     let mut dd = DirData::Files { fnames: Vec::new() };
 
@@ -376,20 +483,33 @@ pub fn directory_files_and_attributes_intro(
     match_re: LispObject,
     nosort: LispObject,
     id_format: LispObject,
+    count: Option<usize>,
 ) -> LispObject {
     let dnexp = expand_file_name(directory, None);
 
     let handler = find_file_name_handler(dnexp, Qdirectory_files_and_attributes);
     if handler.is_not_nil() {
-        return call!(
-            handler,
-            Qdirectory_files_and_attributes,
-            dnexp.into(),
-            full,
-            match_re,
-            nosort,
-            id_format
-        );
+        return match count {
+            Some(count) => call!(
+                handler,
+                Qdirectory_files_and_attributes,
+                dnexp.into(),
+                full,
+                match_re,
+                nosort,
+                id_format,
+                LispObject::from_natnum(count as u64)
+            ),
+            None => call!(
+                handler,
+                Qdirectory_files_and_attributes,
+                dnexp.into(),
+                full,
+                match_re,
+                nosort,
+                id_format
+            ),
+        };
     }
 
     let dr = DirReq::new(
@@ -410,6 +530,7 @@ pub fn directory_files_and_attributes_intro(
             SortFNames::No
         },
         id_format,
+        count,
     );
     let mut dd = DirData::FilesAttrs {
         fnames: Vec::new(),
@@ -447,6 +568,7 @@ pub extern "C" fn directory_files_internal(
             SortFNames::No
         },
         id_format,
+        None,
     );
 
     let mut dd = if attrs {
@@ -461,6 +583,323 @@ pub extern "C" fn directory_files_internal(
     directory_files_core(&dr, &mut dd)
 }
 
+// Recursive directory walk request input
+struct WalkReq {
+    match_re: RegEx,
+    exclude_re: Option<RegEx>,
+    include_dirs: bool,
+    max_depth: Option<usize>,
+    follow_symlinks: bool,
+    respect_ignore: bool,
+}
+
+// Mutable state carried through a recursive directory walk
+struct WalkState {
+    ignores: IgnoreRules,
+    // (device, inode) of the directories currently being walked,
+    // so that following symlinks cannot loop forever.
+    active: HashSet<(u64, u64)>,
+    // Decoded file names, in Emacs' internal encoding.
+    results: Vec<Vec<u8>>,
+}
+
+// Decode the encoded file name F_ENC with the file name coding system,
+// returning the bytes of the result in Emacs' internal encoding.
+fn decode_fname_bytes(f_enc: &[u8]) -> Vec<u8> {
+    let f_enc_lo =
+        unsafe { make_unibyte_string(f_enc.as_ptr() as *const c_char, f_enc.len() as isize) }; // encoded
+    let f_dec_lo: LispStringRef = unsafe { decode_file_name(f_enc_lo) }.into(); // decoded
+    f_dec_lo.as_slice().to_vec()
+}
+
+// Join the file name F onto the directory name DIR with a single '/'.
+fn join_fname(dir: &[u8], f: &[u8]) -> Vec<u8> {
+    let mut full = dir.to_vec();
+    if full.last() != Some(&b'/') {
+        full.push(b'/');
+    }
+    full.extend_from_slice(f);
+    full
+}
+
+impl WalkReq {
+    // Walk DNAME, the encoded name of a directory whose decoded name is
+    // DNAME_DEC, and which is REL relative to the root of the walk and
+    // DEPTH levels below it.  Append matches to the state's results
+    // in the same order as the Lisp implementation of
+    // directory-files-recursively: the contents of each subdirectory
+    // (followed by the subdirectory itself) first, then the files.
+    fn walk(&self, dname: &[u8], dname_dec: &[u8], rel: &str, depth: usize, state: &mut WalkState) {
+        unsafe { maybe_quit() };
+
+        let dpath = Path::new(OsStr::from_bytes(dname));
+        let md = match fs::metadata(dpath) {
+            Ok(md) => md,
+            Err(_) => return,
+        };
+        if !state.active.insert((md.dev(), md.ino())) {
+            return;
+        }
+        let pushed = self.respect_ignore && state.ignores.push_dir(dpath, rel);
+
+        let mut entries = Vec::new();
+        if let Ok(dir) = fs::read_dir(dpath) {
+            for entry in dir.filter_map(Result::ok) {
+                let f_enc = entry.file_name().as_bytes().to_vec();
+                let ftype = match entry.file_type() {
+                    Ok(ftype) => ftype,
+                    Err(_) => continue,
+                };
+                let is_link_to_dir = ftype.is_symlink()
+                    && fs::metadata(entry.path()).map_or(false, |md| md.is_dir());
+                entries.push((
+                    decode_fname_bytes(&f_enc),
+                    f_enc,
+                    ftype.is_dir() || is_link_to_dir,
+                    // Symlinks to directories are only descended into on request.
+                    ftype.is_dir() || (is_link_to_dir && self.follow_symlinks),
+                ));
+            }
+        }
+        entries.sort();
+
+        let mut files = Vec::new();
+        for (f, f_enc, is_dir, descend) in entries {
+            if self.respect_ignore && is_dir && f == b".git" {
+                continue;
+            }
+            if let Some(ref exclude) = self.exclude_re {
+                if exclude.is_match(&f) {
+                    continue;
+                }
+            }
+            let f_enc_str = String::from_utf8_lossy(&f_enc);
+            let f_rel = if rel.is_empty() {
+                f_enc_str.into_owned()
+            } else {
+                format!("{}/{}", rel, f_enc_str)
+            };
+            if self.respect_ignore && state.ignores.is_ignored(&f_rel, is_dir) {
+                continue;
+            }
+
+            let full = join_fname(dname_dec, &f);
+            if is_dir {
+                if descend && self.max_depth.map_or(true, |max| depth < max) {
+                    self.walk(&join_fname(dname, &f_enc), &full, &f_rel, depth + 1, state);
+                }
+                if self.include_dirs && self.match_re.is_match(&f) {
+                    state.results.push(full);
+                }
+            } else if self.match_re.is_match(&f) {
+                files.push(full);
+            }
+        }
+        state.results.append(&mut files);
+
+        if pushed {
+            state.ignores.pop();
+        }
+        state.active.remove(&(md.dev(), md.ino()));
+    }
+}
+
+pub fn directory_files_recursively_intro(
+    directory: LispStringRef,
+    regexp: LispStringRef,
+    include_directories: LispObject,
+    max_depth: Option<usize>,
+    follow_symlinks: LispObject,
+    exclude: LispObject,
+    respect_ignore_files: LispObject,
+) -> LispObject {
+    let dnexp = expand_file_name(directory, None);
+
+    let handler = find_file_name_handler(dnexp, Qdirectory_files_recursively);
+    if handler.is_not_nil() {
+        return call!(
+            handler,
+            Qdirectory_files_recursively,
+            dnexp.into(),
+            regexp.into(),
+            include_directories,
+            max_depth.map_or(Qnil, LispObject::from),
+            follow_symlinks,
+            exclude,
+            respect_ignore_files
+        );
+    }
+
+    let req = WalkReq {
+        match_re: RegEx::new(regexp.into()),
+        exclude_re: if exclude.is_nil() {
+            None
+        } else {
+            Some(RegEx::new(exclude))
+        },
+        include_dirs: include_directories.is_not_nil(),
+        max_depth,
+        follow_symlinks: follow_symlinks.is_not_nil(),
+        respect_ignore: respect_ignore_files.is_not_nil(),
+    };
+    let mut state = WalkState {
+        ignores: IgnoreRules::new(),
+        active: HashSet::new(),
+        results: Vec::new(),
+    };
+
+    let dname = encode_file_name(dnexp);
+    req.walk(
+        trim_dir_slashes(dname.as_slice()),
+        trim_dir_slashes(dnexp.as_slice()),
+        "",
+        0,
+        &mut state,
+    );
+
+    list(
+        &state
+            .results
+            .iter()
+            .map(|f| unsafe { make_string(f.as_ptr() as *const c_char, f.len() as isize) })
+            .collect::<Vec<_>>(),
+    )
+}
+
+// Strip the trailing slashes from the directory name DNAME, leaving "/"
+// alone.
+fn trim_dir_slashes(dname: &[u8]) -> &[u8] {
+    match dname.iter().rposition(|&c| c != b'/') {
+        Some(last) => &dname[..=last],
+        None => &dname[..dname.len().min(1)],
+    }
+}
+
+// Convert a byte count to a Lisp integer, or to a float if it is too
+// large for a fixnum.
+fn bytes_to_lisp(bytes: u64) -> LispObject {
+    if bytes <= MOST_POSITIVE_FIXNUM as u64 {
+        LispObject::from_natnum(bytes as EmacsUint)
+    } else {
+        LispObject::from_float(bytes as EmacsDouble)
+    }
+}
+
+pub fn file_system_info_intro(filename: LispStringRef) -> LispObject {
+    let fnexp = expand_file_name(filename, None);
+
+    let handler = find_file_name_handler(fnexp, Qfile_system_info);
+    if handler.is_not_nil() {
+        let result = call!(handler, Qfile_system_info, fnexp.into());
+        if result.is_cons() || result.is_nil() {
+            return result;
+        }
+        error!("Invalid handler in `file-name-handler-alist'");
+    }
+
+    let encoded = encode_file_name(fnexp);
+    let mut st: libc::statvfs = unsafe { mem::zeroed() };
+    if unsafe { libc::statvfs(encoded.const_sdata_ptr(), &mut st) } != 0 {
+        return Qnil;
+    }
+
+    // Like the C implementation, return floats.  Block sizes are powers
+    // of 2, so the products are accurate to 53 bits.
+    let blocksize = st.f_frsize as EmacsDouble;
+    list(&[
+        LispObject::from_float(blocksize * st.f_blocks as EmacsDouble),
+        LispObject::from_float(blocksize * st.f_bfree as EmacsDouble),
+        LispObject::from_float(blocksize * st.f_bavail as EmacsDouble),
+    ])
+}
+
+// State of a disk usage count
+struct DiskUsage {
+    apparent: bool,
+    // The device of the top-level file, if not crossing file systems
+    dev: Option<u64>,
+    // (device, inode) of the files with several names counted so far
+    seen: HashSet<(u64, u64)>,
+    total: u64,
+}
+
+impl DiskUsage {
+    // Add the file PATH with status MD and, if it is a directory, the
+    // files in it.  Files that cannot be read are skipped, like du does
+    // after complaining about them.
+    fn count(&mut self, path: &Path, md: &fs::Metadata) {
+        if md.nlink() > 1 && !md.is_dir() && !self.seen.insert((md.dev(), md.ino())) {
+            return;
+        }
+        self.total += if self.apparent {
+            md.size()
+        } else {
+            md.blocks() * 512
+        };
+
+        if !md.is_dir() || self.dev.map_or(false, |dev| dev != md.dev()) {
+            return;
+        }
+        unsafe { maybe_quit() };
+        if let Ok(dir) = fs::read_dir(path) {
+            for entry in dir.filter_map(Result::ok) {
+                let path = entry.path();
+                if let Ok(md) = fs::symlink_metadata(&path) {
+                    self.count(&path, &md);
+                }
+            }
+        }
+    }
+}
+
+pub fn file_disk_usage_intro(
+    filename: LispStringRef,
+    apparent: bool,
+    one_file_system: bool,
+) -> LispObject {
+    let fnexp = expand_file_name(filename, None);
+
+    let handler = find_file_name_handler(fnexp, Qfile_disk_usage);
+    if handler.is_not_nil() {
+        return call!(
+            handler,
+            Qfile_disk_usage,
+            fnexp.into(),
+            apparent.into(),
+            one_file_system.into()
+        );
+    }
+
+    let encoded = encode_file_name(fnexp);
+    let path = Path::new(OsStr::from_bytes(encoded.as_slice()));
+    let md = match fs::symlink_metadata(path) {
+        Ok(md) => md,
+        Err(err) => {
+            unsafe {
+                report_file_errno(
+                    "Getting disk usage\0".as_ptr() as *const c_char,
+                    fnexp.into(),
+                    err.raw_os_error().unwrap_or(0),
+                )
+            };
+            unreachable!();
+        }
+    };
+
+    let mut usage = DiskUsage {
+        apparent,
+        dev: if one_file_system {
+            Some(md.dev())
+        } else {
+            None
+        },
+        seen: HashSet::new(),
+        total: 0,
+    };
+    usage.count(path, &md);
+    bytes_to_lisp(usage.total)
+}
+
 struct RegEx {
     recomp: *mut re_pattern_buffer,
 }
@@ -475,7 +914,8 @@ impl RegEx {
         }
     }
 
-    fn is_match(&self, s: &str) -> bool {
+    fn is_match<S: AsRef<[u8]> + ?Sized>(&self, s: &S) -> bool {
+        let s = s.as_ref();
         unsafe {
             re_search(
                 self.recomp,
@@ -489,152 +929,248 @@ impl RegEx {
     }
 }
 
+// How file-attributes reports the owner of a file and which attributes
+// it returns, as parsed from its ID-FORMAT argument.  ID-FORMAT is
+// either a symbol or a list of them: `string' asks for user and group
+// names, and `extended' for the statx fields after the device number.
+#[derive(Clone, Copy)]
+struct IdFormat {
+    names: bool,
+    extended: bool,
+}
+
+impl IdFormat {
+    fn new(id_format: LispObject) -> Self {
+        let mut idf = Self {
+            names: false,
+            extended: false,
+        };
+        let mut add = |elt: LispObject| {
+            let name: LispStringRef = elt.as_symbol_or_string().into();
+            match name.to_string().to_lowercase().as_str() {
+                "string" => idf.names = true,
+                "extended" => idf.extended = true,
+                _ => {}
+            }
+        };
+
+        if id_format.is_cons() {
+            id_format
+                .iter_cars(LispConsEndChecks::on, LispConsCircularChecks::on)
+                .for_each(add);
+        } else if id_format.is_not_nil() {
+            add(id_format);
+        }
+
+        idf
+    }
+}
+
+// Seconds and nanoseconds since the epoch.
+#[derive(Clone, Copy, Default)]
+struct FileTime {
+    sec: i64,
+    nsec: c_long,
+}
+
+impl FileTime {
+    fn to_lisp(self) -> LispObject {
+        make_lisp_time(c_timespec {
+            tv_sec: self.sec,
+            tv_nsec: self.nsec,
+        })
+    }
+}
+
 struct FileAttrs {
-    use_c_internal: bool, // escape hatch
     fpath: String,
     ftype_is_sym: bool,
-    ftype_sym_path: String,
+    ftype_sym_path: Vec<u8>, // encoded
     ftype_is_dir: bool,
     nlinks: u64,
-    id_format: String,
-    idf_is_int: bool,
+    id_format: IdFormat,
     idf_u_is_int: bool, // fallback if pw lookup fails
     idf_g_is_int: bool, // fallback if gr lookup fails
     idf_uid: u32,
     idf_gid: u32,
     idf_uname: String,
     idf_gname: String,
-    atime_s: i64,
-    atime_ns: c_long,
-    mtime_s: i64,
-    mtime_ns: c_long,
-    ctime_s: i64,
-    ctime_ns: c_long,
+    atime: FileTime,
+    mtime: FileTime,
+    ctime: FileTime,
+    btime: Option<FileTime>, // not every filesystem records it
     size: u64,
     //file_mode: String,
     ino: u64,
     dev: u64,
+    mnt_id: Option<u64>, // Linux 5.8 and later
+    blocks: u64,
+    blksize: u64,
 }
 
 impl FileAttrs {
-    fn new(fpath: String, id_format: String) -> Self {
+    fn new(fpath: String, id_format: IdFormat) -> Self {
         Self {
-            use_c_internal: false,
             fpath,
             ftype_is_sym: false,
-            ftype_sym_path: "deadbeef".to_string(),
+            ftype_sym_path: Vec::new(),
             ftype_is_dir: false,
             nlinks: 0,
             id_format,
-            idf_is_int: true,
             idf_u_is_int: false,
             idf_g_is_int: false,
             idf_uid: 65534, // 'nobody' on Ubuntu
             idf_gid: 65534, // 'nogroup' on Ubuntu
             idf_uname: "deadbeef".to_string(),
             idf_gname: "deadbeef".to_string(),
-            atime_s: 0,
-            atime_ns: 0,
-            mtime_s: 0,
-            mtime_ns: 0,
-            ctime_s: 0,
-            ctime_ns: 0,
+            atime: FileTime::default(),
+            mtime: FileTime::default(),
+            ctime: FileTime::default(),
+            btime: None,
             size: 0,
             //file_mode,
             ino: 0,
             dev: 0,
+            mnt_id: None,
+            blocks: 0,
+            blksize: 0,
         }
     }
 
+    // Like lstat, symbolic links are not followed: their attributes are
+    // those of the link itself.
     fn get(&mut self) -> io::Result<()> {
-        let md = fs::metadata(self.fpath.clone())?;
-
-        // file_type
-        let ft = md.file_type();
-        // is_symlink() appears to be always -- somewhat counter intuitively --
-        // false cuz the link is followed first.
-        if let Ok(symmemaybe) = fs::read_link(self.fpath.clone()) {
-            self.ftype_is_sym = true;
-            self.ftype_sym_path = symmemaybe.to_str().unwrap().to_string();
-
-            // Punt back to C for symlinks as the Rust trait
-            // std::os::fs::symlink_metadata does not provide most
-            // of the fields needed.
-            // std::os::fs::metadata does not work cuz it follows the link first.
-            self.use_c_internal = true;
-
-            return Ok(());
-        } else if ft.is_dir() {
-            self.ftype_is_dir = true;
-        } else {
-            self.ftype_is_dir = false;
+        #[cfg(target_os = "linux")]
+        {
+            if let Some(stx) = statx::statx(&self.fpath) {
+                self.get_from_statx(&stx?);
+                return self.get_link_target();
+            }
         }
 
-        self.nlinks = md.nlink();
+        let md = fs::symlink_metadata(&self.fpath)?;
+        self.get_from_metadata(&md);
+        self.get_link_target()
+    }
 
-        //  2. File uid as a string or a number.  If a string value cannot be
-        //  looked up, a numeric value, either an integer or a float, is returned.
-        self.idf_is_int = "string" != self.id_format;
-        if self.idf_is_int {
-            self.idf_uid = md.uid();
-            self.idf_gid = md.gid();
-        } else {
-            let pw: *mut passwd = unsafe { getpwuid(md.uid()) };
-            if pw.is_null() {
-                self.idf_u_is_int = true;
-                self.idf_uid = md.uid();
-            } else {
-                let c_buf: *const c_char = unsafe { (*pw).pw_name };
-                let c_str: &CStr = unsafe { CStr::from_ptr(c_buf) };
-                let str_slice: &str = c_str.to_str().unwrap();
-                self.idf_uname = str_slice.to_owned();
-            }
+    #[cfg(target_os = "linux")]
+    fn get_from_statx(&mut self, stx: &statx::Statx) {
+        let mode = u32::from(stx.stx_mode) & libc::S_IFMT;
+        self.ftype_is_sym = mode == libc::S_IFLNK;
+        self.ftype_is_dir = mode == libc::S_IFDIR;
 
-            let gr: *mut group = unsafe { getgrgid(md.gid()) };
-            if gr.is_null() {
-                self.idf_gid = md.gid();
-                self.idf_g_is_int = true;
-            } else {
-                let c_buf: *const c_char = unsafe { (*gr).gr_name };
-                let c_str: &CStr = unsafe { CStr::from_ptr(c_buf) };
-                let str_slice: &str = c_str.to_str().unwrap();
-                self.idf_gname = str_slice.to_owned();
-            }
+        self.nlinks = u64::from(stx.stx_nlink);
+
+        //  The names are looked up later by lookup_ids, as getpwuid and
+        //  getgrgid are not thread-safe.
+        self.idf_uid = stx.stx_uid;
+        self.idf_gid = stx.stx_gid;
+
+        self.atime = stx.stx_atime.into();
+        self.mtime = stx.stx_mtime.into();
+        self.ctime = stx.stx_ctime.into();
+        if stx.stx_mask & statx::STATX_BTIME != 0 {
+            self.btime = Some(stx.stx_btime.into());
+        }
+
+        self.size = stx.stx_size;
+
+        self.ino = stx.stx_ino;
+        self.dev = statx::makedev(stx.stx_dev_major, stx.stx_dev_minor);
+        if stx.stx_mask & statx::STATX_MNT_ID != 0 {
+            self.mnt_id = Some(stx.stx_mnt_id);
         }
 
-        self.atime_s = md.atime();
-        self.atime_ns = md.atime_nsec();
-        self.mtime_s = md.mtime();
-        self.mtime_ns = md.mtime_nsec();
-        self.ctime_s = md.ctime();
-        self.ctime_ns = md.ctime_nsec();
+        self.blocks = stx.stx_blocks;
+        self.blksize = u64::from(stx.stx_blksize);
+    }
+
+    fn get_from_metadata(&mut self, md: &fs::Metadata) {
+        let ft = md.file_type();
+        self.ftype_is_sym = ft.is_symlink();
+        self.ftype_is_dir = ft.is_dir();
+
+        self.nlinks = md.nlink();
+
+        //  The names are looked up later by lookup_ids, as getpwuid and
+        //  getgrgid are not thread-safe.
+        self.idf_uid = md.uid();
+        self.idf_gid = md.gid();
+
+        self.atime = FileTime {
+            sec: md.atime(),
+            nsec: md.atime_nsec(),
+        };
+        self.mtime = FileTime {
+            sec: md.mtime(),
+            nsec: md.mtime_nsec(),
+        };
+        self.ctime = FileTime {
+            sec: md.ctime(),
+            nsec: md.ctime_nsec(),
+        };
+        self.btime = md
+            .created()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| FileTime {
+                sec: d.as_secs() as i64,
+                nsec: c_long::from(d.subsec_nanos()),
+            });
 
         self.size = md.size();
 
         self.ino = md.ino();
         self.dev = md.dev();
 
+        self.blocks = md.blocks();
+        self.blksize = md.blksize();
+    }
+
+    fn get_link_target(&mut self) -> io::Result<()> {
+        if self.ftype_is_sym {
+            self.ftype_sym_path = fs::read_link(&self.fpath)?.into_os_string().into_vec();
+        }
         Ok(())
     }
 
-    // FileAttrs -> LispObject list
-    fn to_list(&self) -> LispObject {
-        if self.use_c_internal {
-            let (dir, f) = self.fpath.to_dir_f();
-            let name = CString::new(self.fpath.clone().as_str()).unwrap();
-            return unsafe {
-                file_attributes_c_internal(
-                    name.as_ptr(),
-                    dir.as_str().into(),
-                    f.as_str().into(),
-                    self.id_format.as_str().into(),
-                )
-            };
+    // Resolve the uid and gid found by get to names, if ID-FORMAT asked
+    // for strings.  Unlike get, this must run on the main thread.
+    fn lookup_ids(&mut self) {
+        if !self.id_format.names {
+            return;
         }
 
+        let pw: *mut passwd = unsafe { getpwuid(self.idf_uid) };
+        if pw.is_null() {
+            self.idf_u_is_int = true;
+        } else {
+            let c_buf: *const c_char = unsafe { (*pw).pw_name };
+            let c_str: &CStr = unsafe { CStr::from_ptr(c_buf) };
+            let str_slice: &str = c_str.to_str().unwrap();
+            self.idf_uname = str_slice.to_owned();
+        }
+
+        let gr: *mut group = unsafe { getgrgid(self.idf_gid) };
+        if gr.is_null() {
+            self.idf_g_is_int = true;
+        } else {
+            let c_buf: *const c_char = unsafe { (*gr).gr_name };
+            let c_str: &CStr = unsafe { CStr::from_ptr(c_buf) };
+            let str_slice: &str = c_str.to_str().unwrap();
+            self.idf_gname = str_slice.to_owned();
+        }
+    }
+
+    // FileAttrs -> LispObject list
+    fn to_list(&self) -> LispObject {
         //  0. t for directory, string (name linked to) for symbolic link, or nil.
         let ftype = if self.ftype_is_sym {
-            self.ftype_sym_path.to_owned().to_bstring()
+            let target = &self.ftype_sym_path;
+            let target_lo = unsafe {
+                make_unibyte_string(target.as_ptr() as *const c_char, target.len() as isize)
+            }; // encoded
+            unsafe { decode_file_name(target_lo) } // decoded
         } else if self.ftype_is_dir {
             Qt
         } else {
@@ -647,13 +1183,13 @@ impl FileAttrs {
         //  2. File uid as a string or a number.  If a string value cannot be
         //     looked up, a numeric value, either an integer or a float, is returned.
         //  3. File gid, likewise.
-        let uid = if self.idf_is_int || self.idf_u_is_int {
+        let uid = if !self.id_format.names || self.idf_u_is_int {
             LispObject::from_natnum(u64::from(self.idf_uid))
         } else {
             self.idf_uname.to_owned().to_bstring()
         };
 
-        let gid = if self.idf_is_int || self.idf_g_is_int {
+        let gid = if !self.id_format.names || self.idf_g_is_int {
             LispObject::from_natnum(u64::from(self.idf_gid))
         } else {
             self.idf_gname.to_owned().to_bstring()
@@ -666,22 +1202,13 @@ impl FileAttrs {
         //     change to the file's contents.
         //  6. Last status change time, likewise.  This is the time of last change
         //     to the file's attributes: owner and group, access mode bits, etc.
-        let atime = make_lisp_time(c_timespec {
-            tv_sec: self.atime_s,
-            tv_nsec: self.atime_ns, //tv_nsec: self.atime_ns
-        });
-        let mtime = make_lisp_time(c_timespec {
-            tv_sec: self.mtime_s,
-            tv_nsec: self.mtime_ns,
-        });
-        let ctime = make_lisp_time(c_timespec {
-            tv_sec: self.ctime_s,
-            tv_nsec: self.ctime_ns,
-        });
+        let atime = self.atime.to_lisp();
+        let mtime = self.mtime.to_lisp();
+        let ctime = self.ctime.to_lisp();
 
         //  7. Size in bytes.
         //     This is a floating point number if the size is too large for an integer.
-        //     remacs: symlink size is of file linked to? (or size of path str?)
+        //     For a symbolic link, this is the length of the name linked to.
         let size = LispObject::from_natnum(self.size);
 
         //  8. File modes, as a string of ten letters or dashes as in ls -l.
@@ -702,9 +1229,143 @@ impl FileAttrs {
         //     integer can hold, this is a cons cell, similar to the inode number.
         let devno = LispObject::from_natnum(self.dev);
 
-        list(&[
+        let attrs = list(&[
             ftype, nlinks, uid, gid, atime, mtime, ctime, size, modes, undef, inode, devno,
-        ])
+        ]);
+        if !self.id_format.extended {
+            return attrs;
+        }
+
+        // 12. Birth time, like the other times, or nil if unknown.
+        // 13. Mount ID, or nil if unknown.
+        // 14. Number of 512-byte blocks allocated to the file.
+        // 15. Preferred block size for I/O on the file.
+        let btime = self.btime.map_or(Qnil, FileTime::to_lisp);
+        let mnt_id = self.mnt_id.map_or(Qnil, LispObject::from_natnum);
+        let blocks = LispObject::from_natnum(self.blocks);
+        let blksize = LispObject::from_natnum(self.blksize);
+
+        append(&mut [attrs, list(&[btime, mnt_id, blocks, blksize])])
+    }
+}
+
+// Raw statx(2), which libc does not wrap for us yet.  It is the only
+// way to get birth times and mount IDs on Linux.
+#[cfg(target_os = "linux")]
+mod statx {
+    use libc::{c_int, c_long, c_uint, AT_FDCWD, AT_SYMLINK_NOFOLLOW};
+    use std::ffi::CString;
+    use std::io;
+    use std::mem;
+
+    use super::FileTime;
+
+    #[cfg(target_arch = "x86_64")]
+    const SYS_STATX: Option<c_long> = Some(332);
+    #[cfg(target_arch = "x86")]
+    const SYS_STATX: Option<c_long> = Some(383);
+    #[cfg(target_arch = "aarch64")]
+    const SYS_STATX: Option<c_long> = Some(291);
+    #[cfg(target_arch = "arm")]
+    const SYS_STATX: Option<c_long> = Some(397);
+    #[cfg(not(any(
+        target_arch = "x86_64",
+        target_arch = "x86",
+        target_arch = "aarch64",
+        target_arch = "arm"
+    )))]
+    const SYS_STATX: Option<c_long> = None;
+
+    const AT_STATX_SYNC_AS_STAT: c_int = 0;
+    const STATX_BASIC_STATS: c_uint = 0x7ff;
+    pub const STATX_BTIME: u32 = 0x800;
+    pub const STATX_MNT_ID: u32 = 0x1000;
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    pub struct StatxTimestamp {
+        pub tv_sec: i64,
+        pub tv_nsec: u32,
+        reserved: i32,
+    }
+
+    impl From<StatxTimestamp> for FileTime {
+        fn from(ts: StatxTimestamp) -> Self {
+            Self {
+                sec: ts.tv_sec,
+                nsec: c_long::from(ts.tv_nsec),
+            }
+        }
+    }
+
+    // struct statx from <linux/stat.h>.
+    #[repr(C)]
+    pub struct Statx {
+        pub stx_mask: u32,
+        pub stx_blksize: u32,
+        pub stx_attributes: u64,
+        pub stx_nlink: u32,
+        pub stx_uid: u32,
+        pub stx_gid: u32,
+        pub stx_mode: u16,
+        spare0: u16,
+        pub stx_ino: u64,
+        pub stx_size: u64,
+        pub stx_blocks: u64,
+        pub stx_attributes_mask: u64,
+        pub stx_atime: StatxTimestamp,
+        pub stx_btime: StatxTimestamp,
+        pub stx_ctime: StatxTimestamp,
+        pub stx_mtime: StatxTimestamp,
+        pub stx_rdev_major: u32,
+        pub stx_rdev_minor: u32,
+        pub stx_dev_major: u32,
+        pub stx_dev_minor: u32,
+        pub stx_mnt_id: u64,
+        spare2: u64,
+        spare3: [u64; 12],
+    }
+
+    // Stat PATH without following a final symbolic link.  Returns None
+    // if statx is not available, so that the caller can fall back on
+    // lstat.
+    pub fn statx(path: &str) -> Option<io::Result<Statx>> {
+        let nr = SYS_STATX?;
+        let cpath = match CString::new(path) {
+            Ok(cpath) => cpath,
+            Err(err) => return Some(Err(err.into())),
+        };
+
+        let mut stx: Statx = unsafe { mem::zeroed() };
+        let ret = unsafe {
+            libc::syscall(
+                nr,
+                AT_FDCWD,
+                cpath.as_ptr(),
+                AT_SYMLINK_NOFOLLOW | AT_STATX_SYNC_AS_STAT,
+                STATX_BASIC_STATS | STATX_BTIME | STATX_MNT_ID,
+                &mut stx as *mut Statx,
+            )
+        };
+        if ret == 0 {
+            return Some(Ok(stx));
+        }
+
+        let err = io::Error::last_os_error();
+        match err.raw_os_error() {
+            // Old kernels lack statx, and some sandboxes forbid it.
+            Some(libc::ENOSYS) | Some(libc::EPERM) => None,
+            _ => Some(Err(err)),
+        }
+    }
+
+    // The glibc encoding of a device number.
+    pub fn makedev(major: u32, minor: u32) -> u64 {
+        let (major, minor) = (u64::from(major), u64::from(minor));
+        ((major & 0xffff_f000) << 32)
+            | ((major & 0x0000_0fff) << 8)
+            | ((minor & 0xffff_ff00) << 12)
+            | (minor & 0x0000_00ff)
     }
 }
 
@@ -734,11 +1395,12 @@ pub extern "C" fn file_attributes_rust_internal(
 }
 
 fn file_attributes_core(fpath: LispObject, id_format: LispObject) -> LispObject {
-    let mut attrs = FileAttrs::new(fpath.to_stdstring(), id_format.to_idfstring());
+    let mut attrs = FileAttrs::new(fpath.to_stdstring(), IdFormat::new(id_format));
     let res = attrs.get();
     if res.is_err() {
         Qnil
     } else {
+        attrs.lookup_ids();
         attrs.to_list()
     }
 }
@@ -3,44 +3,57 @@
 use std::cmp::max;
 use std::ops::{Add, Sub};
 use std::ptr;
+use std::slice;
+use std::time::{Duration, Instant};
 
 use libc::{c_char, c_int, c_uchar, ptrdiff_t};
 
 use remacs_macros::lisp_fn;
 
 use crate::{
-    buffers::{current_buffer, validate_region_rust},
+    buffers::{current_buffer, get_buffer, nsberror, validate_region_rust, BEG_BYTE},
     buffers::{LispBufferOrCurrent, LispBufferOrName, LispBufferRef, BUF_BYTES_MAX},
+    casetab::{case_fold_canon_table, translate_char},
     character::{char_head_p, dec_pos},
+    diff::{diff, diff_lines},
     eval::{progn, record_unwind_protect, unbind_to},
-    fns::copy_sequence,
+    fns::{concat, copy_sequence},
+    format::{named_format, styled_format},
     indent::invalidate_current_column,
     lisp::LispObject,
+    lists::{list, LispConsCircularChecks, LispConsEndChecks},
     marker::{marker_position_lisp, point_marker, set_point_from_marker},
-    multibyte::MAX_MULTIBYTE_LENGTH,
-    multibyte::{multibyte_char_at, Codepoint, LispStringRef},
+    multibyte::{multibyte_char_at, multibyte_length_by_head, Codepoint, LispStringRef},
+    multibyte::{MAX_CHAR, MAX_MULTIBYTE_LENGTH},
     numbers::{check_range, LispNumber},
     remacs_sys::EmacsInt,
     remacs_sys::{
-        buffer_overflow, build_string, chars_in_text, current_message, del_range, del_range_1,
-        downcase, find_before_next_newline, find_newline, get_char_property_and_overlay, globals,
-        insert_1_both, insert_from_buffer, insert_from_string_1, make_buffer_string,
-        make_buffer_string_both, make_save_obj_obj_obj_obj, make_string_from_bytes, maybe_quit,
-        message1, message3, record_unwind_current_buffer, save_excursion_restore,
-        save_restriction_restore, save_restriction_save, scan_newline_from_point,
-        set_buffer_internal_1, set_point, set_point_both, signal_after_change, styled_format,
-        update_buffer_properties, update_compositions, CHECK_BORDER, STRING_BYTES,
+        adjust_markers_bytepos, buffer_overflow, build_string, chars_in_text, copy_intervals,
+        current_message, del_range, del_range_1, del_range_both, downcase,
+        find_before_next_newline, find_newline, fix_start_end_in_overlays,
+        get_char_property_and_overlay, globals, graft_intervals_into_buffer, insert_1_both,
+        insert_from_buffer, insert_from_string_1, lisp_time_argument, make_buffer_string,
+        make_buffer_string_both, make_multibyte_string, make_save_obj_obj_obj_obj,
+        make_string_from_bytes, maybe_quit, message1, message3, modify_text, move_gap_both,
+        prepare_to_modify_buffer, record_change, record_unwind_current_buffer, replace_range,
+        save_excursion_restore, save_restriction_restore, save_restriction_save,
+        scan_newline_from_point, set_buffer_internal_1, set_point, set_point_both,
+        set_text_properties_1, signal_after_change, specbind, string_char_to_byte,
+        string_make_unibyte, update_buffer_properties, update_compositions,
+        validate_interval_range, CHECK_ALL, CHECK_BORDER, CHECK_INSIDE, CHECK_TAIL, STRING_BYTES,
     },
     remacs_sys::{
-        Fadd_text_properties, Fget_pos_property, Fnext_single_char_property_change,
-        Fprevious_single_char_property_change, Fsystem_name, Fx_popup_dialog,
+        Fadd_text_properties, Fget_pos_property, Fmake_string, Fnext_single_char_property_change,
+        Fprevious_single_char_property_change, Fsystem_name, Fundo_boundary, Fx_popup_dialog,
     },
     remacs_sys::{
-        Qboundary, Qchar_or_string_p, Qfield, Qinteger_or_marker_p, Qmark_inactive, Qnil, Qt,
+        Qboundary, Qbuffer_or_string_p, Qchange, Qchar, Qchar_or_string_p, Qdelete, Qfield,
+        Qinhibit_modification_hooks, Qinteger_or_marker_p, Qmark_inactive, Qnil, Qplistp, Qsymbolp,
+        Qt, Qtranslation_table,
     },
     textprop::get_char_property,
     threads::{c_specpdl_index, ThreadState},
-    time::{lisp_time_struct, time_overflow, LispTime},
+    time::{lisp_time_struct, time_overflow, LispTime, TicksTime},
     util::clip_to_bounds,
     windows::{selected_window, LispWindowRef},
 };
@@ -861,6 +874,368 @@ pub fn insert_buffer_substring(
     };
 }
 
+/// An iterator over the characters of a buffer region.  The
+/// characters of a unibyte buffer are converted to multibyte, so that
+/// buffers of either kind can be compared.
+struct BufferChars {
+    buffer: LispBufferRef,
+    pos_byte: isize,
+    end_byte: isize,
+}
+
+impl BufferChars {
+    fn new(buffer: LispBufferRef, start: isize, end: isize) -> Self {
+        Self {
+            buffer,
+            pos_byte: buffer.charpos_to_bytepos(start),
+            end_byte: buffer.charpos_to_bytepos(end),
+        }
+    }
+}
+
+impl Iterator for BufferChars {
+    type Item = Codepoint;
+
+    fn next(&mut self) -> Option<Codepoint> {
+        if self.pos_byte >= self.end_byte {
+            return None;
+        }
+        let c = if self.buffer.multibyte_characters_enabled() {
+            let c = self.buffer.fetch_multibyte_char(self.pos_byte);
+            self.pos_byte = self.buffer.inc_pos(self.pos_byte);
+            c
+        } else {
+            let c = Codepoint::from(self.buffer.fetch_byte(self.pos_byte)).unibyte_to_char();
+            self.pos_byte += 1;
+            c
+        };
+        Some(c)
+    }
+}
+
+/// Return the live buffer designated by BUFFER, which may be a buffer,
+/// the name of one, or nil for the current buffer.
+fn live_buffer_or_current(buffer: LispObject) -> LispBufferRef {
+    if buffer.is_nil() {
+        return ThreadState::current_buffer_unchecked();
+    }
+    let buf = get_buffer(buffer.into()).unwrap_or_else(|| nsberror(buffer));
+    if !buf.is_live() {
+        error!("Selecting deleted buffer");
+    }
+    buf
+}
+
+/// Return the bounds of the region of BUFFER between START and END,
+/// which default to those of its accessible portion.
+fn buffer_region(buffer: LispBufferRef, start: LispObject, end: LispObject) -> (isize, isize) {
+    let mut beg = if start.is_nil() {
+        buffer.begv
+    } else {
+        start.as_fixnum_coerce_marker_or_error() as isize
+    };
+    let mut fin = if end.is_nil() {
+        buffer.zv
+    } else {
+        end.as_fixnum_coerce_marker_or_error() as isize
+    };
+
+    if beg > fin {
+        std::mem::swap(&mut beg, &mut fin);
+    }
+    if !(buffer.begv <= beg && fin <= buffer.zv) {
+        args_out_of_range!(start, end);
+    }
+    (beg, fin)
+}
+
+/// Compare two substrings of two buffers; return result as number.
+/// Return -N if first string is less after N-1 chars, +N if first string is
+/// greater after N-1 chars, or 0 if strings match.
+/// The first substring is in BUFFER1 from START1 to END1 and the second
+/// is in BUFFER2 from START2 to END2.
+/// All arguments may be nil.  If BUFFER1 or BUFFER2 is nil, the current
+/// buffer is used.  If START1 or START2 is nil, the value of `point-min'
+/// in the respective buffers is used.  If END1 or END2 is nil, the value
+/// of `point-max' in the respective buffers is used.
+/// The value of `case-fold-search' in the current buffer
+/// determines whether case is significant or ignored.
+#[lisp_fn]
+pub fn compare_buffer_substrings(
+    buffer1: LispObject,
+    start1: LispObject,
+    end1: LispObject,
+    buffer2: LispObject,
+    start2: LispObject,
+    end2: LispObject,
+) -> EmacsInt {
+    let trt = case_fold_canon_table();
+
+    let bp1 = live_buffer_or_current(buffer1);
+    let (begp1, endp1) = buffer_region(bp1, start1, end1);
+    let bp2 = live_buffer_or_current(buffer2);
+    let (begp2, endp2) = buffer_region(bp2, start2, end2);
+
+    let mut chars: EmacsInt = 0;
+    for (mut c1, mut c2) in
+        BufferChars::new(bp1, begp1, endp1).zip(BufferChars::new(bp2, begp2, endp2))
+    {
+        if let Some(trt) = trt {
+            c1 = translate_char(trt, c1);
+            c2 = translate_char(trt, c2);
+        }
+        if c1 != c2 {
+            return if c1 < c2 { -1 - chars } else { chars + 1 };
+        }
+        chars += 1;
+        if chars % 0x10000 == 0 {
+            unsafe { maybe_quit() };
+        }
+    }
+
+    // The strings match as far as they go.  If one is shorter, that one
+    // is less.
+    if chars < (endp1 - begp1) as EmacsInt {
+        chars + 1
+    } else if chars < (endp2 - begp2) as EmacsInt {
+        -chars - 1
+    } else {
+        0
+    }
+}
+
+/// Return the characters of OPERAND, an argument of `string-diff', and
+/// the position of the first of them.
+fn diff_operand(operand: LispObject) -> (Vec<Codepoint>, EmacsInt) {
+    if let Some(string) = operand.as_string() {
+        let multibyte = string.is_multibyte();
+        let chars = string
+            .chars()
+            .map(|c| if multibyte { c } else { c.unibyte_to_char() })
+            .collect();
+        return (chars, 0);
+    }
+
+    let (buffer, start, end) = if operand.is_buffer() {
+        (operand, Qnil, Qnil)
+    } else if !operand.is_cons() {
+        wrong_type!(Qbuffer_or_string_p, operand)
+    } else {
+        let items: Vec<LispObject> = operand
+            .iter_cars(LispConsEndChecks::on, LispConsCircularChecks::on)
+            .collect();
+        match items.as_slice() {
+            &[buffer, start, end] => (buffer, start, end),
+            _ => wrong_type!(Qbuffer_or_string_p, operand),
+        }
+    };
+    let buffer = live_buffer_or_current(buffer);
+    let (beg, fin) = buffer_region(buffer, start, end);
+    (
+        BufferChars::new(buffer, beg, fin).collect(),
+        beg as EmacsInt,
+    )
+}
+
+/// Return the edit operations that turn A into B.
+/// A and B can each be a string, a buffer, or a list (BUFFER START END)
+/// designating a region of BUFFER; a buffer stands for its accessible
+/// portion.
+///
+/// The value is a list of elements (OP START-A END-A START-B END-B), in
+/// ascending order, saying that the text of A from START-A to END-A is
+/// replaced by the text of B from START-B to END-B.  OP is `delete' if
+/// the text of B is empty, `insert' if the text of A is empty, and
+/// `change' otherwise.  Positions in strings count from zero; positions
+/// in buffers are buffer positions.  Parts of A and B not covered by
+/// any operation are equal.
+///
+/// GRANULARITY nil or `char' compares A and B character by character.
+/// `line' compares them line by line, so that each operation spans
+/// whole lines; a line ends after each newline.
+///
+/// The operations form a shortest edit script at the given granularity.
+/// Text properties and `case-fold-search' are ignored.
+#[lisp_fn(min = "2")]
+pub fn string_diff(a: LispObject, b: LispObject, granularity: LispObject) -> LispObject {
+    let (chars_a, beg_a) = diff_operand(a);
+    let (chars_b, beg_b) = diff_operand(b);
+    let abort = || {
+        unsafe { maybe_quit() };
+        false
+    };
+
+    let hunks = if granularity.is_nil() || granularity == Qchar {
+        diff(&chars_a, &chars_b, None, abort)
+    } else if granularity == Qline {
+        diff_lines(&chars_a, &chars_b, &Codepoint::from('\n'), abort)
+    } else {
+        error!("Invalid granularity {}", granularity)
+    };
+
+    let ops: Vec<LispObject> = hunks
+        .unwrap_or_default()
+        .into_iter()
+        .map(|hunk| {
+            let op = if hunk.new.is_empty() {
+                Qdelete
+            } else if hunk.old.is_empty() {
+                Qinsert
+            } else {
+                Qchange
+            };
+            list!(
+                op,
+                beg_a + hunk.old.start as EmacsInt,
+                beg_a + hunk.old.end as EmacsInt,
+                beg_b + hunk.new.start as EmacsInt,
+                beg_b + hunk.new.end as EmacsInt
+            )
+        })
+        .collect();
+    list(&ops)
+}
+
+/// Replace accessible portion of current buffer with that of SOURCE.
+/// SOURCE can be a buffer or a string that names a buffer.
+/// Interactively, prompt for SOURCE.
+///
+/// As far as possible the replacement is non-destructive, i.e. existing
+/// buffer contents, markers, properties, and overlays in the current
+/// buffer stay intact.
+///
+/// Because this function can be very slow if there is a large number of
+/// differences between the two buffers, there are two optional arguments
+/// mitigating this issue.
+///
+/// The MAX-SECS argument, if given, defines a hard limit on the time used
+/// for comparing the buffers.  If it takes longer than MAX-SECS, the
+/// function falls back to a plain `delete-region' and
+/// `insert-buffer-substring'.
+///
+/// The optional argument MAX-COSTS defines the quality of the difference
+/// computation.  If the actual costs exceed this limit, heuristics are
+/// used to provide a faster but suboptimal solution.  The default value
+/// is 1000000.
+///
+/// This function returns t if a non-destructive replacement could be
+/// performed.  Otherwise, i.e., if MAX-SECS was exceeded, it returns
+/// nil.
+#[lisp_fn(min = "1", intspec = "bSource buffer: ")]
+pub fn replace_buffer_contents(
+    source: LispBufferOrName,
+    max_secs: LispObject,
+    max_costs: Option<EmacsInt>,
+) -> bool {
+    let a = ThreadState::current_buffer_unchecked();
+    let b = LispBufferRef::from(source)
+        .as_live()
+        .unwrap_or_else(|| error!("Selecting deleted buffer"));
+    if a == b {
+        error!("Cannot replace a buffer with itself");
+    }
+
+    let deadline = if max_secs.is_nil() {
+        None
+    } else {
+        let t = unsafe { lisp_time_argument(max_secs) };
+        let limit = if t.tv_sec < 0 {
+            Duration::new(0, 0)
+        } else {
+            Duration::new(t.tv_sec as u64, t.tv_nsec as u32)
+        };
+        // A limit too far in the future is no limit at all.
+        Instant::now().checked_add(limit)
+    };
+    let too_expensive = max_costs.unwrap_or(1_000_000).max(1) as usize;
+
+    let min_a = a.begv;
+    let min_b = b.begv;
+    let size_a = a.zv - min_a;
+
+    // Handle trivial cases where at least one accessible portion is
+    // empty.
+    if size_a == 0 && b.zv == min_b {
+        return true;
+    }
+    if size_a == 0 {
+        insert_buffer_substring(b.into(), None, None);
+        return true;
+    }
+    if b.zv == min_b {
+        unsafe { del_range_both(a.begv, a.begv_byte, a.zv, a.zv_byte, true) };
+        return true;
+    }
+
+    let hunks = diff(
+        &BufferChars::new(a, a.begv, a.zv).collect::<Vec<_>>(),
+        &BufferChars::new(b, b.begv, b.zv).collect::<Vec<_>>(),
+        Some(too_expensive),
+        || {
+            unsafe { maybe_quit() };
+            deadline.map_or(false, |deadline| Instant::now() >= deadline)
+        },
+    );
+
+    let hunks = match hunks {
+        Some(hunks) => hunks,
+        None => {
+            unsafe { del_range(min_a, a.zv) };
+            insert_buffer_substring(b.into(), None, None);
+            return false;
+        }
+    };
+
+    unsafe { Fundo_boundary() };
+    let count = c_specpdl_index();
+    unsafe { record_unwind_protect(Some(save_excursion_restore), save_excursion_save()) };
+
+    // Run the modification hooks once for the whole region rather than
+    // once per hunk.
+    let run_mod_hooks = unsafe { !globals.inhibit_modification_hooks };
+    if run_mod_hooks {
+        unsafe {
+            prepare_to_modify_buffer(a.begv, a.zv, ptr::null_mut());
+            specbind(Qinhibit_modification_hooks, Qt);
+        }
+    }
+
+    // Walk backwards through the hunks, so that the positions of the
+    // earlier ones stay valid.
+    for hunk in hunks.iter().rev() {
+        let beg_a = min_a + hunk.old.start as isize;
+        let end_a = min_a + hunk.old.end as isize;
+        if beg_a < end_a {
+            unsafe { del_range(beg_a, end_a) };
+        }
+        if !hunk.new.is_empty() {
+            unsafe { set_point(beg_a) };
+            insert_buffer_substring(
+                b.into(),
+                Some(LispNumber::Fixnum(
+                    (min_b + hunk.new.start as isize) as EmacsInt,
+                )),
+                Some(LispNumber::Fixnum(
+                    (min_b + hunk.new.end as isize) as EmacsInt,
+                )),
+            );
+        }
+    }
+
+    // Unbind `inhibit-modification-hooks' first, or the after-change
+    // functions would not run.
+    unbind_to(count, Qnil);
+
+    if run_mod_hooks {
+        let cur = ThreadState::current_buffer_unchecked();
+        unsafe {
+            signal_after_change(cur.begv, size_a, cur.zv - cur.begv);
+            update_compositions(cur.begv, cur.zv, CHECK_INSIDE as i32);
+        }
+    }
+    true
+}
+
 /// Display a message at the bottom of the screen.
 /// The message also goes into the `*Messages*' buffer, if `message-log-max'
 /// is non-nil.  (In keyboard macros, that's all it does.)
@@ -1019,13 +1394,7 @@ pub fn lisp_current_message() -> LispObject {
 /// usage: (format STRING &rest OBJECTS)
 #[lisp_fn(min = "1")]
 pub fn format(args: &mut [LispObject]) -> LispObject {
-    unsafe {
-        styled_format(
-            args.len() as isize,
-            args.as_mut_ptr() as *mut LispObject,
-            false,
-        )
-    }
+    styled_format(args, false)
 }
 
 /// Format a string out of a format-string and arguments.
@@ -1040,15 +1409,72 @@ pub fn format(args: &mut [LispObject]) -> LispObject {
 /// usage: (format-message STRING &rest OBJECTS)
 #[lisp_fn(min = "1")]
 pub fn format_message(args: &mut [LispObject]) -> LispObject {
-    unsafe {
-        styled_format(
-            args.len() as isize,
-            args.as_mut_ptr() as *mut LispObject,
-            true,
-        )
+    styled_format(args, true)
+}
+
+/// The name by which `format-named' refers to the value with KEY: the
+/// name of a symbol without any leading colon, or a string.
+fn format_field_name(key: LispObject) -> Vec<u8> {
+    let name = match key.as_symbol() {
+        Some(symbol) => symbol.symbol_name(),
+        None if key.is_string() => key,
+        None => wrong_type!(Qsymbolp, key),
+    };
+    let name = name.force_string();
+    let bytes = name.as_slice();
+    if key.is_symbol() && bytes.first() == Some(&b':') {
+        bytes[1..].to_vec()
+    } else {
+        bytes.to_vec()
     }
 }
 
+/// Format a string out of TEMPLATE and the named VALUES.
+/// VALUES is either an alist of (NAME . VALUE) elements, or a plist of
+/// alternating names and values, such as (:name "foo" :count 3).  Each
+/// NAME is a symbol or a string; the colon of a keyword is not part of
+/// its name.
+///
+/// TEMPLATE is like the format string of `format', except that each
+/// %-sequence other than %% must name its value, as in
+///
+///   (format-named "%{name}s: %{count}5d" \\='((name . "foo") (count . 3)))
+///
+/// which returns "foo:     3".  The name in braces takes the place of
+/// the field number of `format', and is followed by the same flags,
+/// width, precision and conversion character.  If a name appears more
+/// than once in VALUES, the first value is used.  A value can be used
+/// any number of times, or not at all.
+///
+/// Text properties of TEMPLATE and of string values are copied to the
+/// result, as `format' does.
+#[lisp_fn]
+pub fn format_named(template: LispStringRef, values: LispObject) -> LispObject {
+    let items: Vec<LispObject> = values
+        .iter_cars(LispConsEndChecks::on, LispConsCircularChecks::on)
+        .collect();
+    let mut args = vec![LispObject::from(template)];
+    let mut names = Vec::new();
+    if items.first().map_or(false, |item| item.is_cons()) {
+        for item in items {
+            let binding: Option<(LispObject, LispObject)> = item.into();
+            if let Some((key, value)) = binding {
+                names.push(format_field_name(key));
+                args.push(value);
+            }
+        }
+    } else {
+        if items.len() % 2 != 0 {
+            wrong_type!(Qplistp, values);
+        }
+        for pair in items.chunks(2) {
+            names.push(format_field_name(pair[0]));
+            args.push(pair[1]);
+        }
+    }
+    named_format(&args, &names)
+}
+
 /// Return the contents of the current buffer as a string.
 /// If narrowing is in effect, this function returns only the visible part
 /// of the buffer.
@@ -1350,7 +1776,16 @@ fn time_arith(
     a: LispObject,
     b: LispObject,
     op: impl FnOnce(LispTime, LispTime) -> LispTime,
-) -> Vec<EmacsInt> {
+    exact_op: impl FnOnce(TicksTime, TicksTime) -> Option<TicksTime>,
+) -> LispObject {
+    // If either argument is (TICKS . HZ), compute exactly and return
+    // that form too.
+    if TicksTime::from_pair(a).is_some() || TicksTime::from_pair(b).is_some() {
+        let t = exact_op(TicksTime::from_lisp(a), TicksTime::from_lisp(b))
+            .unwrap_or_else(|| time_overflow());
+        return t.reduce_to_fit().into_lisp();
+    }
+
     let mut alen: c_int = 0;
     let mut blen: c_int = 0;
     let ta = unsafe { lisp_time_struct(a, &mut alen) };
@@ -1362,35 +1797,36 @@ fn time_arith(
 
     let maxlen = max(alen, blen) as usize;
 
-    t.into_vec(maxlen)
+    t.into_vec(maxlen).into()
 }
 
 /// Return the sum of two time values A and B, as a time value. A nil value for either argument
 /// stands for the current time. See `current-time-string' for the various forms of a time value.
+/// If either argument is a pair (TICKS . HZ) and `time-ticks-hz-pairs' is non-nil, the sum is
+/// exact and has that form too, with a clock frequency that is a multiple of both arguments'
+/// frequencies.
 #[lisp_fn(name = "time-add", c_name = "time_add")]
-pub fn time_add_lisp(a: LispObject, b: LispObject) -> Vec<EmacsInt> {
-    time_arith(a, b, LispTime::add)
+pub fn time_add_lisp(a: LispObject, b: LispObject) -> LispObject {
+    time_arith(a, b, LispTime::add, TicksTime::checked_add)
 }
 
 /// Return the difference between two time values A and B, as a time value. Use `float-time' to
 /// convert the difference into elapsed seconds.  A nil value for either argument stands for the
 /// current time.  See `current-time-string' for the various forms of a time value.
+/// If either argument is a pair (TICKS . HZ) and `time-ticks-hz-pairs' is non-nil, the difference
+/// is exact and has that form too, with a clock frequency that is a multiple of both arguments'
+/// frequencies.
 #[lisp_fn(name = "time-subtract", c_name = "time_subtract")]
-pub fn time_subtract_lisp(a: LispObject, b: LispObject) -> Vec<EmacsInt> {
-    time_arith(a, b, LispTime::sub)
+pub fn time_subtract_lisp(a: LispObject, b: LispObject) -> LispObject {
+    time_arith(a, b, LispTime::sub, TicksTime::checked_sub)
 }
 
 /// Return non-nil if time value T1 is earlier than time value T2.  A nil value for either
 /// argument stands for the current time.  See `current-time-string' for the various forms of a
-/// time value.
+/// time value.  The comparison is exact, whatever the forms of T1 and T2.
 #[lisp_fn]
 pub fn time_less_p(t1: LispObject, t2: LispObject) -> bool {
-    let mut t1len: c_int = 0;
-    let mut t2len: c_int = 0;
-    let a = unsafe { lisp_time_struct(t1, &mut t1len) };
-    let b = unsafe { lisp_time_struct(t2, &mut t2len) };
-
-    a < b
+    TicksTime::from_lisp(t1) < TicksTime::from_lisp(t2)
 }
 
 /// Remove restrictions (narrowing) from current buffer.
@@ -1695,4 +2131,852 @@ pub fn system_name() -> LispStringRef {
     unsafe { Fsystem_name() }.into()
 }
 
+/// Gap-aware access to the bytes of a buffer's text.  Positions are
+/// byte positions counted from `BEG_BYTE', and the gap is skipped.
+trait GapText {
+    /// Return the storage of the text, gap included.
+    fn storage(&self) -> &[u8];
+
+    fn storage_mut(&mut self) -> &mut [u8];
+
+    /// Return the byte position of the gap and its size in bytes.
+    fn gap(&self) -> (isize, isize);
+
+    fn is_multibyte_text(&self) -> bool;
+
+    /// Return the index into the storage of byte position POS.
+    fn offset(&self, pos: isize) -> usize {
+        let (gpt, gap_size) = self.gap();
+        let skip = if pos >= gpt { gap_size } else { 0 };
+        (pos - BEG_BYTE + skip) as usize
+    }
+
+    /// Return the byte at byte position POS.
+    fn byte_at(&self, pos: isize) -> u8 {
+        self.storage()[self.offset(pos)]
+    }
+
+    /// Return the character at byte position POS and its length in
+    /// bytes.
+    fn char_at(&self, pos: isize) -> (Codepoint, usize) {
+        let bytes = &self.storage()[self.offset(pos)..];
+        if self.is_multibyte_text() {
+            multibyte_char_at(bytes)
+        } else {
+            (Codepoint::from(bytes[0]), 1)
+        }
+    }
+
+    /// Return the bytes from byte position FROM to TO, which must not
+    /// straddle the gap.
+    fn bytes_mut(&mut self, from: isize, to: isize) -> &mut [u8] {
+        let (gpt, _) = self.gap();
+        assert!(to <= gpt || gpt <= from, "byte range straddles the gap");
+        let start = self.offset(from);
+        &mut self.storage_mut()[start..start + (to - from) as usize]
+    }
+}
+
+impl GapText for LispBufferRef {
+    fn storage(&self) -> &[u8] {
+        let len = self.z_byte() - BEG_BYTE + self.gap_size();
+        unsafe { slice::from_raw_parts(self.beg_addr(), len as usize) }
+    }
+
+    fn storage_mut(&mut self) -> &mut [u8] {
+        let len = self.z_byte() - BEG_BYTE + self.gap_size();
+        unsafe { slice::from_raw_parts_mut(self.beg_addr(), len as usize) }
+    }
+
+    fn gap(&self) -> (isize, isize) {
+        (self.gpt_byte(), self.gap_size())
+    }
+
+    fn is_multibyte_text(&self) -> bool {
+        self.multibyte_characters_enabled()
+    }
+}
+
+/// Return the character and byte positions of the first character at
+/// or after POS, whose byte position is POS_BYTE, and before END whose
+/// representation is exactly NEEDLE.
+fn find_char_bytes<T: GapText>(
+    text: &T,
+    mut pos: isize,
+    mut pos_byte: isize,
+    end: isize,
+    needle: &[u8],
+) -> Option<(isize, isize)> {
+    while pos < end {
+        let len = if text.is_multibyte_text() {
+            multibyte_length_by_head(text.byte_at(pos_byte))
+        } else {
+            1
+        };
+        if len == needle.len()
+            && needle
+                .iter()
+                .enumerate()
+                .all(|(i, &b)| text.byte_at(pos_byte + i as isize) == b)
+        {
+            return Some((pos, pos_byte));
+        }
+        pos += 1;
+        pos_byte += len as isize;
+    }
+    None
+}
+
+/// How a character stored by `subst-char-in-region' may combine with
+/// the bytes around it.
+#[derive(Clone, Copy, PartialEq)]
+enum ByteCombining {
+    No,
+    After,
+    Both,
+}
+
+unsafe extern "C" fn subst_char_in_region_unwind(undo_list: LispObject) {
+    ThreadState::current_buffer_unchecked().undo_list_ = undo_list;
+}
+
+unsafe extern "C" fn subst_char_in_region_unwind_1(filename: LispObject) {
+    ThreadState::current_buffer_unchecked().filename_ = filename;
+}
+
+/// From START to END, replace FROMCHAR with TOCHAR each time it occurs.
+/// If optional arg NOUNDO is non-nil, don't record this change for undo
+/// and don't mark the buffer as really changed.
+/// Both characters must have the same length of multi-byte form.
+#[lisp_fn(min = "4")]
+pub fn subst_char_in_region(
+    start: LispObject,
+    end: LispObject,
+    fromchar: Codepoint,
+    tochar: Codepoint,
+    noundo: bool,
+) {
+    let mut buffer = ThreadState::current_buffer_unchecked();
+    validate_region_rust(start, end);
+
+    let mut fromstr = [0; MAX_MULTIBYTE_LENGTH];
+    let mut tostr = [0; MAX_MULTIBYTE_LENGTH];
+    let (len, combining) = if buffer.multibyte_characters_enabled() {
+        let len = fromchar.write_to(&mut fromstr);
+        if tochar.write_to(&mut tostr) != len {
+            error!("Characters in `subst-char-in-region' have different byte-lengths");
+        }
+        // If *TOSTR is in the range 0x80..0x9F and TOCHAR is not a
+        // complete multibyte character, it may be combined with the
+        // after bytes.  If it is in the range 0xA0..0xFF, it may be
+        // combined with the before and after bytes.
+        let combining = if tostr[0] < 0x80 {
+            ByteCombining::No
+        } else if !char_head_p(tostr[0]) {
+            ByteCombining::Both
+        } else if multibyte_length_by_head(tostr[0]) > len {
+            ByteCombining::After
+        } else {
+            ByteCombining::No
+        };
+        (len, combining)
+    } else {
+        fromstr[0] = fromchar.val() as u8;
+        tostr[0] = tochar.val() as u8;
+        (1, ByteCombining::No)
+    };
+    let (fromstr, tostr) = (&fromstr[..len], &tostr[..len]);
+
+    // If we don't want undo, turn off putting stuff on the list.  That's
+    // faster than getting rid of things, and it prevents even the entry
+    // for a first change.  Also inhibit locking the file.
+    let count = c_specpdl_index();
+    if noundo {
+        unsafe {
+            record_unwind_protect(Some(subst_char_in_region_unwind), buffer.undo_list_);
+            record_unwind_protect(Some(subst_char_in_region_unwind_1), buffer.filename_);
+        }
+        buffer.undo_list_ = Qt;
+        buffer.filename_ = Qnil;
+    }
+
+    let mut prepared = false;
+    let mut changed: Option<isize> = None;
+    let mut last_changed = 0;
+    'restart: loop {
+        let (start, end) = validate_region_rust(start, end);
+        let mut pos = start;
+        let mut pos_byte = buffer.charpos_to_bytepos(start);
+
+        while let Some((found, found_byte)) = find_char_bytes(&buffer, pos, pos_byte, end, fromstr)
+        {
+            pos = found;
+            pos_byte = found_byte;
+
+            if !prepared {
+                prepared = true;
+                unsafe { modify_text(pos, end) };
+                if noundo {
+                    let text = unsafe { &mut *buffer.text };
+                    if text.modiff - 1 == text.save_modiff {
+                        text.save_modiff += 1;
+                    }
+                    if text.modiff - 1 == buffer.auto_save_modified {
+                        buffer.auto_save_modified += 1;
+                    }
+                }
+                // The before-change functions may have moved the gap or
+                // even modified the buffer, so start over.
+                continue 'restart;
+            }
+            changed.get_or_insert(pos);
+
+            // Take care of the case where the new character combines
+            // with neighboring bytes.
+            let mut pos_byte_next = pos_byte + len as isize;
+            let combines_after =
+                pos_byte_next < buffer.z_byte() && !char_head_p(buffer.byte_at(pos_byte_next));
+            let combines = match combining {
+                ByteCombining::No => false,
+                ByteCombining::After => combines_after,
+                ByteCombining::Both => {
+                    combines_after || (pos_byte > BEG_BYTE && buffer.byte_at(pos_byte - 1) >= 0x80)
+                }
+            };
+
+            if combines {
+                let undo_list = buffer.undo_list_;
+                // replace_range is less efficient, because it moves the
+                // gap, but it handles combining correctly.
+                unsafe {
+                    let string =
+                        make_multibyte_string(tostr.as_ptr() as *const c_char, 1, len as isize);
+                    replace_range(pos, pos + 1, string, false, false, true, false);
+                }
+                pos_byte_next = buffer.charpos_to_bytepos(pos);
+                if pos_byte_next > pos_byte {
+                    // Before combining happened.  We should not increment
+                    // POS.  So, to cancel the later increment of POS,
+                    // decrease it now.
+                    pos -= 1;
+                } else {
+                    pos_byte_next = buffer.inc_pos(pos_byte_next);
+                }
+                if noundo {
+                    buffer.undo_list_ = undo_list;
+                }
+            } else {
+                if !noundo {
+                    unsafe { record_change(pos, 1) };
+                }
+                buffer
+                    .bytes_mut(pos_byte, pos_byte_next)
+                    .copy_from_slice(tostr);
+            }
+            last_changed = pos + 1;
+            pos_byte = pos_byte_next;
+            pos += 1;
+        }
+        break;
+    }
+
+    if let Some(changed) = changed {
+        unsafe {
+            signal_after_change(changed, last_changed - changed, last_changed - changed);
+            update_compositions(changed, last_changed, CHECK_ALL as i32);
+        }
+    }
+
+    unbind_to(count, Qnil);
+}
+
+/// Check whether the characters at byte position POS_BYTE of TEXT, of
+/// which there are LIMIT, start with the characters of an element of
+/// VAL, a list (([FROM-CHAR ...] . TO) ...).  Return the first element
+/// that matches.
+fn check_translation<T: GapText>(
+    text: &T,
+    mut pos_byte: isize,
+    limit: usize,
+    val: LispObject,
+) -> Option<LispObject> {
+    let mut chars: Vec<Codepoint> = Vec::new();
+    for elt in val.iter_cars(LispConsEndChecks::off, LispConsCircularChecks::off) {
+        let from = match elt.as_cons().and_then(|cons| cons.car().as_vector()) {
+            Some(from) if from.len() <= limit => from,
+            _ => continue,
+        };
+        while chars.len() < from.len() {
+            let (c, len) = text.char_at(pos_byte);
+            chars.push(c);
+            pos_byte += len as isize;
+        }
+        if from
+            .iter()
+            .zip(&chars)
+            .all(|(f, c)| f.as_fixnum() == Some(EmacsInt::from(c.val())))
+        {
+            return Some(elt);
+        }
+    }
+    None
+}
+
+/// Internal use only.
+/// From START to END, translate characters according to TABLE.
+/// TABLE is a string or a char-table; the Nth character in it is the
+/// mapping for the character with code N.
+/// It returns the number of characters changed.
+#[lisp_fn]
+pub fn translate_region_internal(
+    start: LispObject,
+    end: LispObject,
+    table: LispObject,
+) -> EmacsInt {
+    let mut buffer = ThreadState::current_buffer_unchecked();
+    let multibyte = buffer.multibyte_characters_enabled();
+    let (start, end) = validate_region_rust(start, end);
+
+    let (char_table, string, size) = match table.as_char_table() {
+        Some(char_table) => {
+            if char_table.purpose != Qtranslation_table {
+                error!("Not a translation table");
+            }
+            (Some(char_table), None, MAX_CHAR as usize)
+        }
+        None => {
+            let mut string: LispStringRef = table.into();
+            if !multibyte && string.len_chars() < string.len_bytes() {
+                string = unsafe { string_make_unibyte(string.into()) }.into();
+            }
+            (None, Some(string), string.len_chars() as usize)
+        }
+    };
+
+    let mut pos = start;
+    let mut pos_byte = buffer.charpos_to_bytepos(pos);
+    let mut end_pos = end;
+    unsafe { modify_text(pos, end_pos) };
+
+    let mut cnt = 0;
+    while pos < end_pos {
+        let (oc, mut len) = buffer.char_at(pos_byte);
+        if (oc.val() as usize) < size {
+            let mut buf = [0; MAX_MULTIBYTE_LENGTH];
+            // The new character and the length of its representation in
+            // BUF, or None if VAL maps OC to a sequence.
+            let mut val = Qnil;
+            let replacement = if let Some(string) = string {
+                let data = string.as_slice();
+                if string.is_multibyte() {
+                    let idx =
+                        unsafe { string_char_to_byte(string.into(), oc.val() as isize) } as usize;
+                    let (nc, str_len) = multibyte_char_at(&data[idx..]);
+                    buf[..str_len].copy_from_slice(&data[idx..idx + str_len]);
+                    Some((nc, str_len))
+                } else {
+                    let byte = data[oc.val() as usize];
+                    let nc = Codepoint::from(byte);
+                    if !nc.is_ascii() && multibyte {
+                        Some((nc, Codepoint::from_raw(byte).write_to(&mut buf)))
+                    } else {
+                        buf[0] = byte;
+                        Some((nc, 1))
+                    }
+                }
+            } else {
+                val = char_table.unwrap().get(oc.val() as isize);
+                if val.is_character() {
+                    let nc = Codepoint::from(val);
+                    Some((nc, nc.write_to(&mut buf)))
+                } else if val.is_vector() || val.is_cons() {
+                    // VAL is [TO_CHAR ...] or (([FROM-CHAR ...] .  TO) ...)
+                    // where TO is TO-CHAR or [TO-CHAR ...].
+                    None
+                } else {
+                    Some((oc, len))
+                }
+            };
+
+            match replacement {
+                Some((nc, str_len)) if nc != oc => {
+                    // Simple one char to one char translation.
+                    if len != str_len {
+                        // This is less efficient, because it moves the
+                        // gap, but it should handle multibyte characters
+                        // correctly.
+                        unsafe {
+                            let string = make_multibyte_string(
+                                buf.as_ptr() as *const c_char,
+                                1,
+                                str_len as isize,
+                            );
+                            replace_range(pos, pos + 1, string, true, false, true, false);
+                        }
+                        len = str_len;
+                    } else {
+                        unsafe { record_change(pos, 1) };
+                        buffer
+                            .bytes_mut(pos_byte, pos_byte + len as isize)
+                            .copy_from_slice(&buf[..len]);
+                        unsafe {
+                            signal_after_change(pos, 1, 1);
+                            update_compositions(pos, pos + 1, CHECK_BORDER as i32);
+                        }
+                    }
+                    cnt += 1;
+                }
+                Some(_) => {}
+                None => {
+                    let (from_len, to) = if val.is_cons() {
+                        match check_translation(&buffer, pos_byte, (end_pos - pos) as usize, val) {
+                            Some(elt) => {
+                                // ELT is ([FROM-CHAR ...] . TO).
+                                let (from, to): (LispObject, LispObject) = elt.into();
+                                (from.force_vector().len() as isize, to)
+                            }
+                            None => {
+                                pos_byte += len as isize;
+                                pos += 1;
+                                continue;
+                            }
+                        }
+                    } else {
+                        (1, val)
+                    };
+
+                    let string: LispStringRef = if to.is_vector() {
+                        concat(&mut [to]).into()
+                    } else {
+                        unsafe { Fmake_string(1.into(), to, Qnil) }.into()
+                    };
+                    unsafe {
+                        replace_range(pos, pos + from_len, string.into(), true, false, true, false)
+                    };
+                    pos_byte += string.len_bytes();
+                    pos += string.len_chars();
+                    cnt += string.len_chars() as EmacsInt;
+                    end_pos += string.len_chars() - from_len;
+                    continue;
+                }
+            }
+        }
+        pos_byte += len as isize;
+        pos += 1;
+    }
+
+    cnt
+}
+
+/// Two regions START1..END1 and START2..END2 of a buffer, where END1 <=
+/// START2, that are being transposed.  The positions count either
+/// characters or bytes.
+#[derive(Clone, Copy, Debug)]
+struct Transposition {
+    start1: isize,
+    end1: isize,
+    start2: isize,
+    end2: isize,
+}
+
+impl Transposition {
+    /// Return where the text at position POS ends up.
+    fn position(self, pos: isize) -> isize {
+        let Self {
+            start1,
+            end1,
+            start2,
+            end2,
+        } = self;
+        if pos < start1 || pos >= end2 {
+            pos
+        } else if pos < end1 {
+            pos + (end2 - end1)
+        } else if pos < start2 {
+            pos + (end2 - start2) - (end1 - start1)
+        } else {
+            pos - (start2 - start1)
+        }
+    }
+
+    /// Transpose the regions in TEXT, whose byte positions these are.
+    /// The gap must not be between START1 and END2.
+    fn transpose_bytes<T: GapText>(self, text: &mut T) {
+        let bytes = text.bytes_mut(self.start1, self.end2);
+        // [1 mid 2] -> [2 1 mid] -> [2 mid 1]
+        bytes.rotate_right((self.end2 - self.start2) as usize);
+        bytes[(self.end2 - self.start2) as usize..]
+            .rotate_right((self.start2 - self.end1) as usize);
+    }
+}
+
+/// Transpose the markers, and point, in two regions of BUFFER, and
+/// adjust the ones between them if the regions differ in size.  CHARS
+/// and BYTES are the regions in character and byte positions.
+fn transpose_markers(mut buffer: LispBufferRef, chars: Transposition, bytes: Transposition) {
+    let pt = chars.position(buffer.pt);
+    let pt_byte = bytes.position(buffer.pt_byte);
+    buffer.set_pt_both(pt, pt_byte);
+
+    if let Some(markers) = buffer.markers() {
+        for mut marker in markers.iter() {
+            // Denormalized markers between the gap's position and its
+            // end are taken care of by `marker-position'.
+            marker.set_bytepos(bytes.position(marker.bytepos));
+            marker.set_charpos(chars.position(marker.charpos));
+        }
+    }
+}
+
+/// Remove the text properties of BUFFER between START and END.  Unlike
+/// `set-text-properties', this cannot cause a garbage collection.
+unsafe fn clear_text_properties(buffer: LispObject, start: isize, end: isize) {
+    let mut start: LispObject = start.into();
+    let mut end: LispObject = end.into();
+    let interval = validate_interval_range(buffer, &mut start, &mut end, false);
+    if !interval.is_null() {
+        set_text_properties_1(start, end, Qnil, buffer, interval);
+    }
+}
+
+/// Transpose region STARTR1 to ENDR1 with STARTR2 to ENDR2.
+/// The regions should not be overlapping, because the size of the buffer is
+/// never changed in a transposition.
+///
+/// Optional fifth arg LEAVE-MARKERS, if non-nil, means don't update
+/// any markers that happen to be located in the regions.
+///
+/// Transposing beyond buffer boundaries is an error.
+///
+/// Interactively, STARTR1 and ENDR1 are point and mark; STARTR2 and ENDR2
+/// are the last two marks pushed to the mark ring; LEAVE-MARKERS is nil.
+/// If a prefix argument N is given, STARTR2 and ENDR2 are the two
+/// successive marks N entries back in the mark ring.  A negative prefix
+/// argument instead counts forward from the oldest mark in the mark
+/// ring.
+#[lisp_fn(
+    min = "4",
+    intspec = "(if (< (length mark-ring) 2) (error \"Other region must be marked before transposing two regions\") (let* ((num (if current-prefix-arg (prefix-numeric-value current-prefix-arg) 0)) (ring-length (length mark-ring)) (eltnum (mod num ring-length)) (eltnum2 (mod (1+ num) ring-length))) (list (point) (mark) (elt mark-ring eltnum) (elt mark-ring eltnum2))))"
+)]
+pub fn transpose_regions(
+    startr1: LispObject,
+    endr1: LispObject,
+    startr2: LispObject,
+    endr2: LispObject,
+    leave_markers: bool,
+) {
+    let mut buffer = ThreadState::current_buffer_unchecked();
+    let (mut start1, mut end1) = validate_region_rust(startr1, endr1);
+    let (mut start2, mut end2) = validate_region_rust(startr2, endr2);
+
+    // Swap the regions if they're reversed.
+    if start2 < end1 {
+        std::mem::swap(&mut start1, &mut start2);
+        std::mem::swap(&mut end1, &mut end2);
+    }
+    let len1 = end1 - start1;
+    let len2 = end2 - start2;
+
+    if start2 < end1 {
+        error!("Transposed regions overlap");
+    }
+    // Nothing to change for adjacent regions with one being empty.
+    if (len1 == 0 || len2 == 0) && end1 == start2 {
+        return;
+    }
+
+    // Run the before-change functions first, as they may move the gap.
+    unsafe { modify_text(start1, end2) };
+
+    // Make sure the gap won't interfere, by moving it the minimum
+    // distance out of the text we will operate on.
+    let start1_byte = buffer.charpos_to_bytepos(start1);
+    let end2_byte = buffer.charpos_to_bytepos(end2);
+    let gap = buffer.gpt();
+    if start1 < gap && gap < end2 {
+        unsafe {
+            if gap - start1 < end2 - gap {
+                move_gap_both(start1, start1_byte);
+            } else {
+                move_gap_both(end2, end2_byte);
+            }
+        }
+    }
+
+    let chars = Transposition {
+        start1,
+        end1,
+        start2,
+        end2,
+    };
+    let bytes = Transposition {
+        start1: start1_byte,
+        end1: buffer.charpos_to_bytepos(end1),
+        start2: buffer.charpos_to_bytepos(start2),
+        end2: end2_byte,
+    };
+
+    // The text between the regions stays where it is only if the
+    // regions have the same size in both characters and bytes.
+    let adjacent = end1 == start2;
+    let same_size =
+        !adjacent && len1 == len2 && bytes.end1 - bytes.start1 == bytes.end2 - bytes.start2;
+
+    let buf: LispObject = buffer.into();
+    unsafe {
+        if same_size {
+            record_change(start1, len1);
+            record_change(start2, len2);
+        } else {
+            record_change(start1, end2 - start1);
+        }
+
+        let intervals = (*buffer.text).intervals;
+        let tmp_interval1 = copy_intervals(intervals, start1, len1);
+        let tmp_interval_mid = copy_intervals(intervals, end1, start2 - end1);
+        let tmp_interval2 = copy_intervals(intervals, start2, len2);
+        if same_size {
+            clear_text_properties(buf, start1, end1);
+            clear_text_properties(buf, start2, end2);
+        } else {
+            clear_text_properties(buf, start1, end2);
+        }
+
+        bytes.transpose_bytes(&mut buffer);
+
+        graft_intervals_into_buffer(tmp_interval1, end2 - len1, len1, buffer.as_mut(), false);
+        if !same_size && !adjacent {
+            graft_intervals_into_buffer(
+                tmp_interval_mid,
+                start1 + len2,
+                start2 - end1,
+                buffer.as_mut(),
+                false,
+            );
+        }
+        graft_intervals_into_buffer(tmp_interval2, start1, len2, buffer.as_mut(), false);
+
+        update_compositions(start1, start1 + len2, CHECK_BORDER as i32);
+        if adjacent {
+            update_compositions(start1 + len2, end2, CHECK_TAIL as i32);
+        } else {
+            update_compositions(end2 - len1, end2, CHECK_BORDER as i32);
+        }
+    }
+
+    if leave_markers {
+        // The character positions of the markers remain intact, but we
+        // still need to update their byte positions, because the
+        // transposed regions might include multibyte sequences which
+        // make some original byte positions of the markers invalid.
+        // The same goes for point.
+        unsafe { adjust_markers_bytepos(start1, start1_byte, end2, end2_byte, 0) };
+        let pt = buffer.pt;
+        if start1 < pt && pt < end2 {
+            let pt_byte = (start1..pt).fold(start1_byte, |pos_byte, _| buffer.inc_pos(pos_byte));
+            buffer.set_pt_both(pt, pt_byte);
+        }
+    } else {
+        transpose_markers(buffer, chars, bytes);
+        unsafe { fix_start_end_in_overlays(start1, end2) };
+    }
+
+    unsafe { signal_after_change(start1, end2 - start1, end2 - start1) };
+}
+
+def_lisp_sym!(Qchange, "change");
+def_lisp_sym!(Qchar, "char");
+def_lisp_sym!(Qdelete, "delete");
+def_lisp_sym!(Qinsert, "insert");
+def_lisp_sym!(Qline, "line");
+
 include!(concat!(env!("OUT_DIR"), "/editfns_exports.rs"));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct MockBuffer {
+        storage: Vec<u8>,
+        gpt: isize,
+        gap_size: isize,
+        multibyte: bool,
+    }
+
+    impl MockBuffer {
+        /// Make a buffer containing TEXT with a gap of 4 bytes before
+        /// byte offset GAP.
+        fn new(text: &[u8], gap: usize, multibyte: bool) -> Self {
+            let mut storage = text[..gap].to_vec();
+            storage.extend_from_slice(&[0; 4]);
+            storage.extend_from_slice(&text[gap..]);
+            Self {
+                storage,
+                gpt: gap as isize + BEG_BYTE,
+                gap_size: 4,
+                multibyte,
+            }
+        }
+
+        fn text(&self) -> Vec<u8> {
+            let gap = (self.gpt - BEG_BYTE) as usize;
+            let mut text = self.storage[..gap].to_vec();
+            text.extend_from_slice(&self.storage[gap + self.gap_size as usize..]);
+            text
+        }
+    }
+
+    impl GapText for MockBuffer {
+        fn storage(&self) -> &[u8] {
+            &self.storage
+        }
+
+        fn storage_mut(&mut self) -> &mut [u8] {
+            &mut self.storage
+        }
+
+        fn gap(&self) -> (isize, isize) {
+            (self.gpt, self.gap_size)
+        }
+
+        fn is_multibyte_text(&self) -> bool {
+            self.multibyte
+        }
+    }
+
+    fn encode(c: Codepoint) -> Vec<u8> {
+        let mut buf = [0; MAX_MULTIBYTE_LENGTH];
+        let len = c.write_to(&mut buf);
+        buf[..len].to_vec()
+    }
+
+    #[test]
+    fn test_gap_text_char_at() {
+        let buffer = MockBuffer::new("aé€b".as_bytes(), 3, true);
+        assert_eq!(buffer.byte_at(1), b'a');
+        assert_eq!(buffer.char_at(2), (Codepoint::from('é' as u32), 2));
+        assert_eq!(buffer.char_at(4), (Codepoint::from('€' as u32), 3));
+        assert_eq!(buffer.char_at(7), (Codepoint::from('b' as u32), 1));
+
+        let buffer = MockBuffer::new(b"a\xe9b", 1, false);
+        assert_eq!(buffer.char_at(2), (Codepoint::from(0xe9_u8), 1));
+    }
+
+    #[test]
+    fn test_find_char_bytes() {
+        let text = "€é xé".as_bytes();
+        let e_acute = encode(Codepoint::from('é' as u32));
+        for gap in &[0, 3, 5, 6, text.len()] {
+            let buffer = MockBuffer::new(text, *gap, true);
+            let end = 6;
+            assert_eq!(
+                find_char_bytes(&buffer, 1, 1, end, &e_acute),
+                Some((2, 4)),
+                "gap at {}",
+                gap
+            );
+            assert_eq!(find_char_bytes(&buffer, 3, 6, end, &e_acute), Some((5, 8)));
+            assert_eq!(find_char_bytes(&buffer, 3, 6, 5, &e_acute), None);
+            assert_eq!(find_char_bytes(&buffer, 1, 1, end, b"x"), Some((4, 7)));
+        }
+
+        // The trailing bytes of a character are never matched on their
+        // own.
+        let buffer = MockBuffer::new("€".as_bytes(), 0, true);
+        assert_eq!(find_char_bytes(&buffer, 1, 1, 2, &[0x82, 0xac]), None);
+
+        // Raw bytes are distinct from the characters with the same code.
+        let mut text = encode(Codepoint::from_raw(0xe9));
+        text.extend_from_slice(&e_acute);
+        let buffer = MockBuffer::new(&text, 2, true);
+        assert_eq!(find_char_bytes(&buffer, 1, 1, 3, &e_acute), Some((2, 3)));
+        assert_eq!(
+            find_char_bytes(&buffer, 1, 1, 3, &encode(Codepoint::from_raw(0xe9))),
+            Some((1, 1))
+        );
+
+        let buffer = MockBuffer::new(b"ab\xe9", 1, false);
+        assert_eq!(find_char_bytes(&buffer, 1, 1, 4, b"\xe9"), Some((3, 3)));
+    }
+
+    #[test]
+    fn test_transposition_position() {
+        // Regions 1..3 and 6..7, of different sizes.
+        let t = Transposition {
+            start1: 1,
+            end1: 3,
+            start2: 6,
+            end2: 7,
+        };
+        let moved: Vec<isize> = (0..9).map(|pos| t.position(pos)).collect();
+        assert_eq!(moved, vec![0, 5, 6, 2, 3, 4, 1, 7, 8]);
+
+        // Adjacent regions.
+        let t = Transposition {
+            start1: 1,
+            end1: 3,
+            start2: 3,
+            end2: 4,
+        };
+        let moved: Vec<isize> = (1..5).map(|pos| t.position(pos)).collect();
+        assert_eq!(moved, vec![2, 3, 1, 4]);
+    }
+
+    #[test]
+    fn test_transpose_bytes() {
+        let mut buffer = MockBuffer::new(b"abcdefg", 7, false);
+        Transposition {
+            start1: 2,
+            end1: 4,
+            start2: 5,
+            end2: 8,
+        }
+        .transpose_bytes(&mut buffer);
+        assert_eq!(buffer.text(), b"aefgdbc");
+
+        // Regions with the same number of characters but not of bytes.
+        let text = "xé-ab€y";
+        let mut buffer = MockBuffer::new(text.as_bytes(), 0, true);
+        let bytes = Transposition {
+            start1: 2,
+            end1: 4,
+            start2: 7,
+            end2: 10,
+        };
+        bytes.transpose_bytes(&mut buffer);
+        assert_eq!(buffer.text(), "x€-abéy".as_bytes());
+
+        // Every character boundary ends up on a character boundary.
+        let chars = Transposition {
+            start1: 2,
+            end1: 3,
+            start2: 6,
+            end2: 7,
+        };
+        let boundaries: Vec<(isize, isize)> = text
+            .char_indices()
+            .enumerate()
+            .map(|(i, (b, _))| (i as isize + 1, b as isize + BEG_BYTE))
+            .collect();
+        let new_text = String::from_utf8(buffer.text()).unwrap();
+        for (charpos, bytepos) in boundaries {
+            let (charpos, bytepos) = (chars.position(charpos), bytes.position(bytepos));
+            assert_eq!(
+                new_text
+                    .char_indices()
+                    .nth((charpos - 1) as usize)
+                    .unwrap()
+                    .0 as isize
+                    + BEG_BYTE,
+                bytepos
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_bytes_mut_across_gap() {
+        let mut buffer = MockBuffer::new(b"abcd", 2, false);
+        buffer.bytes_mut(1, 4);
+    }
+}
@@ -23,8 +23,7 @@ use crate::{
         backtrace_debug_on_exit, build_string, call_debugger, check_cons_list, do_debug_on_call,
         do_one_unbind, eval_sub, funcall_lambda, funcall_subr, globals, grow_specpdl,
         internal_catch, internal_lisp_condition_case, list2, maybe_gc, maybe_quit,
-        record_in_backtrace, record_unwind_save_match_data, signal_or_quit, specbind, COMPILEDP,
-        MODULE_FUNCTIONP,
+        record_in_backtrace, signal_or_quit, specbind, COMPILEDP, MODULE_FUNCTIONP,
     },
     remacs_sys::{pvec_type, EmacsInt, Lisp_Compiled, Set_Internal_Bind},
     remacs_sys::{Fapply, Fdefault_value, Fload},
@@ -34,6 +33,7 @@ use crate::{
         Qnil, Qrisky_local_variable, Qsetq, Qt, Qunbound, Qvariable_documentation, Qvoid_function,
     },
     remacs_sys::{Vautoload_queue, Vrun_hooks},
+    search::record_unwind_save_match_data,
     symbols::{fboundp, symbol_function, LispSymbolRef},
     threads::{c_specpdl_index, ThreadState},
     vectors::length,
@@ -1,28 +1,77 @@
 //! Functions to deal with files
 use errno::{set_errno, Errno};
 
+#[cfg(unix)]
+use std::{
+    ffi::{CString, OsStr},
+    fs::{self, DirBuilder, File},
+    io,
+    mem::{self, ManuallyDrop},
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{DirBuilderExt, MetadataExt},
+        io::{AsRawFd, FromRawFd},
+    },
+    path::Path,
+    ptr, str,
+};
+
 use std::path;
 
+use libc::c_char;
+#[cfg(unix)]
+use libc::{c_int, c_void};
+
+#[cfg(unix)]
+use remacs_lib::make_temporary_file;
 use remacs_macros::lisp_fn;
 
 use crate::{
     coding::encode_file_name,
     errno::errno,
     lisp::LispObject,
-    lists::LispCons,
+    lists::{LispCons, LispConsCircularChecks, LispConsEndChecks},
     math::{arithcompare, ArithComparison},
     multibyte::LispStringRef,
     remacs_sys::{
         check_executable, check_existing, empty_unibyte_string, expand_and_dir_to_file,
         file_directory_p, file_name_absolute_p, file_name_case_insensitive_p, report_file_errno,
     },
-    remacs_sys::{Fexpand_file_name, Ffind_file_name_handler, Fmake_temp_file_internal},
     remacs_sys::{
-        Qfile_directory_p, Qfile_executable_p, Qfile_exists_p, Qfile_name_case_insensitive_p, Qnil,
+        Fexpand_file_name, Ffile_acl, Ffile_selinux_context, Ffind_file_name_handler,
+        Fmake_temp_file_internal, Fset_file_acl, Fset_file_selinux_context,
+    },
+    remacs_sys::{
+        Qacl, Qfile_directory_p, Qfile_executable_p, Qfile_exists_p, Qfile_extended_attributes,
+        Qfile_name_case_insensitive_p, Qfile_xattr_get, Qfile_xattr_list, Qfile_xattr_remove,
+        Qfile_xattr_set, Qnil, Qselinux_context, Qset_file_extended_attributes, Qxattrs,
     },
     threads::ThreadState,
 };
 
+#[cfg(unix)]
+use crate::{
+    buffers::{validate_region, LispBufferRef},
+    eval::{record_unwind_protect, record_unwind_protect_int, unbind_to},
+    remacs_sys::{
+        auto_save_mode_bits, auto_saving, auto_saving_dir_umask, barf_or_query_if_file_exists,
+        clear_unwind_protect, close_file_unwind, coding_system, decode_file_name, emacs_close,
+        emacs_open, emacs_read_quit, emacs_readlinkat, emacs_write_quit, expand_cp_target,
+        fd_selinux_context, file_offset, globals, lock_file, make_timespec, make_unibyte_string,
+        maybe_quit, noninteractive, realmask, renameat_noreplace, set_fd_selinux_context, specbind,
+        timestamp_file_system, unlock_file, update_mode_lines, valid_timestamp_file_system,
+        write_region_annotate, write_region_contents, write_region_post_annotations,
+    },
+    remacs_sys::{Fdirectory_file_name, Ffile_symlink_p, Fmake_symbolic_link},
+    remacs_sys::{
+        Qcopy_directory, Qcopy_file, Qdelete_by_moving_to_trash, Qdelete_directory, Qdelete_file,
+        Qexcl, Qfile_date_error, Qfile_error, Qmake_directory_internal, Qmove_file_to_trash,
+        Qrename_file, Qt, Qwrite_region,
+    },
+    strings::string_equal,
+    threads::c_specpdl_index,
+};
+
 /// Signal a file-access failure that set errno.  STRING describes the
 /// failure, NAME the file involved.  When invoking this function, take
 /// care to not use arguments such as build_string ("foo") that involve
@@ -190,4 +239,1659 @@ pub fn make_temp_name(prefix: LispStringRef) -> LispObject {
     }
 }
 
+/// Signal a file error for ERR, which happened while doing what STRING
+/// describes to NAME.  STRING must be NUL-terminated.
+#[cfg(unix)]
+fn report_file_io_error(string: &str, name: LispObject, err: &io::Error) -> ! {
+    debug_assert!(string.ends_with('\0'));
+    unsafe {
+        report_file_errno(
+            string.as_ptr() as *const c_char,
+            name,
+            err.raw_os_error().unwrap_or(0),
+        )
+    };
+    unreachable!();
+}
+
+/// Signal a file error for the current errno.  STRING must be
+/// NUL-terminated.
+#[cfg(unix)]
+fn report_file_errno_now(string: &str, name: LispObject) -> ! {
+    report_file_io_error(string, name, &io::Error::last_os_error())
+}
+
+/// Return ENCODED, an encoded file name, as a path.
+#[cfg(unix)]
+fn encoded_path(encoded: &LispStringRef) -> &Path {
+    Path::new(OsStr::from_bytes(encoded.as_slice()))
+}
+
+/// Ways of copying a whole file faster than reading and writing it.
+#[cfg(target_os = "linux")]
+mod fast_copy {
+    use libc::{c_int, c_long, c_ulong};
+    use std::io;
+    use std::ptr;
+
+    /// _IOW (0x94, 9, int)
+    const FICLONE: c_ulong = 0x4004_9409;
+
+    #[cfg(target_arch = "x86_64")]
+    const SYS_COPY_FILE_RANGE: Option<c_long> = Some(326);
+    #[cfg(target_arch = "x86")]
+    const SYS_COPY_FILE_RANGE: Option<c_long> = Some(377);
+    #[cfg(target_arch = "aarch64")]
+    const SYS_COPY_FILE_RANGE: Option<c_long> = Some(285);
+    #[cfg(target_arch = "arm")]
+    const SYS_COPY_FILE_RANGE: Option<c_long> = Some(391);
+    #[cfg(not(any(
+        target_arch = "x86_64",
+        target_arch = "x86",
+        target_arch = "aarch64",
+        target_arch = "arm"
+    )))]
+    const SYS_COPY_FILE_RANGE: Option<c_long> = None;
+
+    /// Make DEST share the data of SOURCE, if the file system can.
+    pub fn clone(dest: c_int, source: c_int) -> bool {
+        unsafe { libc::ioctl(dest, FICLONE, source) == 0 }
+    }
+
+    /// Copy up to LEN bytes from the current offset of SOURCE to the
+    /// current offset of DEST inside the kernel.  Return the number of
+    /// bytes copied, 0 at end of file.
+    pub fn copy_range(source: c_int, dest: c_int, len: usize) -> io::Result<usize> {
+        let sys_copy_file_range = match SYS_COPY_FILE_RANGE {
+            Some(n) => n,
+            None => return Err(io::Error::from_raw_os_error(libc::ENOSYS)),
+        };
+        let n = unsafe {
+            libc::syscall(
+                sys_copy_file_range,
+                source,
+                ptr::null_mut::<libc::loff_t>(),
+                dest,
+                ptr::null_mut::<libc::loff_t>(),
+                len,
+                0,
+            )
+        };
+        if n < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(n as usize)
+        }
+    }
+
+    /// Whether ERR from `copy_range' just means that it cannot be used
+    /// for these files.
+    pub fn unsupported(err: &io::Error) -> bool {
+        match err.raw_os_error() {
+            Some(libc::ENOSYS)
+            | Some(libc::EXDEV)
+            | Some(libc::EINVAL)
+            | Some(libc::EOPNOTSUPP)
+            | Some(libc::EPERM)
+            | Some(libc::EBADF) => true,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+mod fast_copy {
+    use libc::c_int;
+    use std::io;
+
+    pub fn clone(_dest: c_int, _source: c_int) -> bool {
+        false
+    }
+
+    pub fn copy_range(_source: c_int, _dest: c_int, _len: usize) -> io::Result<usize> {
+        Err(io::Error::from_raw_os_error(libc::ENOSYS))
+    }
+
+    pub fn unsupported(_err: &io::Error) -> bool {
+        true
+    }
+}
+
+/// Create a file under a temporary name in the directory of the file
+/// whose encoded name is ENCODED.  Return its descriptor and encoded
+/// name, or the errno of the failure.
+#[cfg(unix)]
+fn create_temporary_sibling(encoded: LispStringRef) -> Result<(c_int, LispStringRef), c_int> {
+    let name = str::from_utf8(encoded.as_slice()).map_err(|_| libc::EINVAL)?;
+    let (fd, tempname) = make_temporary_file(format!("{}.XXXXXX", name), libc::O_CLOEXEC)?;
+    let tempname =
+        unsafe { make_unibyte_string(tempname.as_ptr() as *const c_char, tempname.len() as isize) };
+    Ok((fd, tempname.into()))
+}
+
+/// Unwind function deleting the temporary file named ENCODED.
+#[cfg(unix)]
+unsafe extern "C" fn delete_temporary_file_unwind(encoded: LispObject) {
+    libc::unlink(LispStringRef::from(encoded).const_sdata_ptr());
+}
+
+/// Rename the file named FROM to TO, both encoded.  Fail if TO exists,
+/// unless the system cannot check that atomically.
+#[cfg(unix)]
+fn rename_noreplace(from: LispStringRef, to: LispStringRef) -> io::Result<()> {
+    if unsafe {
+        renameat_noreplace(
+            libc::AT_FDCWD,
+            from.const_sdata_ptr(),
+            libc::AT_FDCWD,
+            to.const_sdata_ptr(),
+        )
+    } == 0
+    {
+        return Ok(());
+    }
+
+    let err = io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(libc::EINVAL) | Some(libc::ENOSYS) | Some(libc::ENOTSUP) => {
+            fs::rename(encoded_path(&from), encoded_path(&to))
+        }
+        _ => Err(err),
+    }
+}
+
+/// How much `copy_contents' copies between two checks for quit.
+#[cfg(unix)]
+const COPY_CHUNK_SIZE: usize = 16 * 1024 * 1024;
+
+/// The side of a copy on which an error happened.
+#[cfg(unix)]
+enum CopyError {
+    Read(io::Error),
+    Write(io::Error),
+    Both(io::Error),
+}
+
+/// Copy the rest of the file open as IFD to OFD.  Return the number of
+/// bytes copied.
+#[cfg(unix)]
+fn copy_contents(ifd: c_int, ofd: c_int) -> Result<u64, CopyError> {
+    let mut newsize: u64 = 0;
+
+    // `copy_file_range' returns 0 at once for files like those in /proc
+    // whose size is unknown, so go on with `read' afterwards in any case.
+    loop {
+        match fast_copy::copy_range(ifd, ofd, COPY_CHUNK_SIZE) {
+            Ok(0) => break,
+            Ok(n) => newsize += n as u64,
+            Err(ref err) if newsize == 0 && fast_copy::unsupported(err) => break,
+            Err(err) => return Err(CopyError::Both(err)),
+        }
+        unsafe { maybe_quit() };
+    }
+
+    let mut buf = vec![0_u8; 64 * 1024];
+    loop {
+        let n =
+            unsafe { emacs_read_quit(ifd, buf.as_mut_ptr() as *mut c_void, buf.len() as isize) };
+        if n < 0 {
+            return Err(CopyError::Read(io::Error::last_os_error()));
+        }
+        if n == 0 {
+            return Ok(newsize);
+        }
+        if unsafe { emacs_write_quit(ofd, buf.as_ptr() as *const c_void, n) } != n {
+            return Err(CopyError::Write(io::Error::last_os_error()));
+        }
+        newsize += n as u64;
+    }
+}
+
+/// Which file an error in `copy_xattrs' is about.
+#[cfg(unix)]
+enum XattrError {
+    From(io::Error),
+    To(io::Error),
+}
+
+/// Copy the extended attributes of the file open as IFD to OFD.  This
+/// includes ACL entries and the SELinux context.  Attributes the
+/// output file system does not support, or that only a privileged user
+/// may set, are skipped.
+#[cfg(target_os = "linux")]
+fn copy_xattrs(ifd: c_int, ofd: c_int) -> Result<(), XattrError> {
+    copy_xattrs_with(
+        |names, size| unsafe { libc::flistxattr(ifd, names, size) },
+        |name, value, size| unsafe { libc::fgetxattr(ifd, name, value, size) },
+        ofd,
+    )
+}
+
+/// Like `copy_xattrs', but copy the attributes of the file whose
+/// encoded name is ENCODED, without following symlinks.
+#[cfg(target_os = "linux")]
+fn copy_xattrs_from_name(encoded: LispStringRef, ofd: c_int) -> Result<(), XattrError> {
+    let name = encoded.const_sdata_ptr();
+    copy_xattrs_with(
+        |names, size| unsafe { libc::llistxattr(name, names, size) },
+        |attr, value, size| unsafe { libc::lgetxattr(name, attr, value, size) },
+        ofd,
+    )
+}
+
+/// Copy to OFD the extended attributes whose names LIST stores, and
+/// whose values GET reads, like `listxattr' and `getxattr' do.
+#[cfg(target_os = "linux")]
+fn copy_xattrs_with(
+    list: impl Fn(*mut c_char, usize) -> isize,
+    get: impl Fn(*const c_char, *mut c_void, usize) -> isize,
+    ofd: c_int,
+) -> Result<(), XattrError> {
+    let unsupported = |err: &io::Error| match err.raw_os_error() {
+        Some(libc::ENOTSUP) | Some(libc::ENOSYS) => true,
+        _ => false,
+    };
+
+    let size = list(ptr::null_mut(), 0);
+    if size < 0 {
+        let err = io::Error::last_os_error();
+        return if unsupported(&err) {
+            Ok(())
+        } else {
+            Err(XattrError::From(err))
+        };
+    }
+    let mut names = vec![0_u8; size as usize];
+    let size = list(names.as_mut_ptr() as *mut c_char, names.len());
+    if size < 0 {
+        return Err(XattrError::From(io::Error::last_os_error()));
+    }
+    names.truncate(size as usize);
+
+    for name in names.split(|&c| c == 0).filter(|name| !name.is_empty()) {
+        let cname = CString::new(name).unwrap();
+        let size = get(cname.as_ptr(), ptr::null_mut(), 0);
+        if size < 0 {
+            return Err(XattrError::From(io::Error::last_os_error()));
+        }
+        let mut value = vec![0_u8; size as usize];
+        let size = get(
+            cname.as_ptr(),
+            value.as_mut_ptr() as *mut c_void,
+            value.len(),
+        );
+        if size < 0 {
+            return Err(XattrError::From(io::Error::last_os_error()));
+        }
+
+        if unsafe {
+            libc::fsetxattr(
+                ofd,
+                cname.as_ptr(),
+                value.as_ptr() as *const c_void,
+                size as usize,
+                0,
+            )
+        } != 0
+        {
+            let err = io::Error::last_os_error();
+            let privileged = name.starts_with(b"trusted.") || name.starts_with(b"security.");
+            if !(unsupported(&err) || (privileged && err.raw_os_error() == Some(libc::EPERM))) {
+                return Err(XattrError::To(err));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn copy_xattrs(_ifd: c_int, _ofd: c_int) -> Result<(), XattrError> {
+    Ok(())
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn copy_xattrs_from_name(_encoded: LispStringRef, _ofd: c_int) -> Result<(), XattrError> {
+    Ok(())
+}
+
+/// Copy FILE to NEWNAME.  Both args must be strings.
+/// If NEWNAME is a directory name, copy FILE to a like-named file under
+/// NEWNAME.  For NEWNAME to be recognized as a directory name, it should
+/// end in a slash.
+///
+/// This function always sets the file modes of the output file to match
+/// the input file.
+///
+/// The optional third argument OK-IF-ALREADY-EXISTS specifies what to do
+/// if file NEWNAME already exists.  If OK-IF-ALREADY-EXISTS is nil,
+/// signal a `file-already-exists' error without overwriting.  If
+/// OK-IF-ALREADY-EXISTS is an integer, request confirmation from the user
+/// about overwriting; this is what happens in interactive use with M-x.
+/// Any other value for OK-IF-ALREADY-EXISTS means to overwrite the
+/// existing file.
+///
+/// Fourth arg KEEP-TIME non-nil means give the output file the same
+/// last-modified time as the old one.  (This works on only some systems.)
+///
+/// A prefix arg makes KEEP-TIME non-nil.
+///
+/// If PRESERVE-UID-GID is non-nil, try to transfer the uid and gid of
+/// FILE to NEWNAME.
+///
+/// If PRESERVE-PERMISSIONS is non-nil, copy permissions of FILE to NEWNAME;
+/// this includes the file modes, along with ACL entries, SELinux context
+/// and other extended attributes if present.  Otherwise, if NEWNAME is
+/// created its file permission bits are those of FILE, masked by the
+/// default file permissions.
+///
+/// Where the file system supports it, NEWNAME shares the data of FILE
+/// until either is modified, or the data is copied inside the kernel.
+#[cfg(unix)]
+#[lisp_fn(min = "2", intspec = "fCopy file: \nGCopy %s to file: \np\nP")]
+pub fn copy_file(
+    file: LispStringRef,
+    newname: LispStringRef,
+    ok_if_already_exists: LispObject,
+    keep_time: bool,
+    preserve_uid_gid: bool,
+    preserve_permissions: bool,
+) -> LispObject {
+    let file = expand_file_name(file, None);
+    let newname: LispStringRef = unsafe { expand_cp_target(file.into(), newname.into()) }.into();
+
+    // If the input file name has special constructs in it,
+    // call the corresponding file handler.
+    let mut handler = find_file_name_handler(file, Qcopy_file);
+    // Likewise for output file name.
+    if handler.is_nil() {
+        handler = find_file_name_handler(newname, Qcopy_file);
+    }
+    if handler.is_not_nil() {
+        return call!(
+            handler,
+            Qcopy_file,
+            file.into(),
+            newname.into(),
+            ok_if_already_exists,
+            keep_time.into(),
+            preserve_uid_gid.into(),
+            preserve_permissions.into()
+        );
+    }
+
+    let encoded_file = encode_file_name(file);
+    let encoded_newname = encode_file_name(newname);
+    let count = c_specpdl_index();
+
+    let ifd = unsafe { emacs_open(encoded_file.const_sdata_ptr(), libc::O_RDONLY, 0) };
+    if ifd < 0 {
+        report_file_errno_now("Opening input file\0", file.into());
+    }
+    unsafe { record_unwind_protect_int(Some(close_file_unwind), ifd) };
+
+    // The files are closed by the unwind protects, not when dropped.
+    let input = ManuallyDrop::new(unsafe { File::from_raw_fd(ifd) });
+    let st = input
+        .metadata()
+        .unwrap_or_else(|err| report_file_io_error("Input file status\0", file.into(), &err));
+
+    let selinux_context = if preserve_permissions {
+        unsafe { fd_selinux_context(ifd, file.into()) }
+    } else {
+        ptr::null_mut()
+    };
+
+    // We can copy only regular files.
+    if !st.is_file() {
+        let errno = if st.is_dir() {
+            libc::EISDIR
+        } else {
+            libc::EINVAL
+        };
+        report_file_io_error(
+            "Non-regular file\0",
+            file.into(),
+            &io::Error::from_raw_os_error(errno),
+        );
+    }
+
+    let new_mask = st.mode() & if preserve_uid_gid { 0o700 } else { 0o777 };
+    let mut already_exists = false;
+
+    // A new NEWNAME is written under a temporary name in the same
+    // directory and renamed when complete, so that it never exists
+    // partially written.  An existing NEWNAME is overwritten in place,
+    // which keeps its inode, its other names and its owner.
+    let mut tempname = None;
+    let mut temp_count = 0;
+    let mut ofd = -1;
+    if fs::symlink_metadata(encoded_path(&encoded_newname)).is_err() {
+        if let Ok((fd, name)) = create_temporary_sibling(encoded_newname) {
+            temp_count = c_specpdl_index();
+            unsafe { record_unwind_protect(Some(delete_temporary_file_unwind), name.into()) };
+            ofd = fd;
+            tempname = Some(name);
+        }
+    }
+    if tempname.is_none() {
+        ofd = unsafe {
+            emacs_open(
+                encoded_newname.const_sdata_ptr(),
+                libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL,
+                new_mask as c_int,
+            )
+        };
+    }
+    if ofd < 0 && errno().0 == libc::EEXIST {
+        if ok_if_already_exists.is_nil() || ok_if_already_exists.is_integer() {
+            unsafe {
+                barf_or_query_if_file_exists(
+                    newname.into(),
+                    true,
+                    "copy to it\0".as_ptr() as *const c_char,
+                    ok_if_already_exists.is_integer(),
+                    false,
+                )
+            };
+        }
+        already_exists = true;
+        ofd = unsafe { emacs_open(encoded_newname.const_sdata_ptr(), libc::O_WRONLY, 0) };
+    }
+    if ofd < 0 {
+        report_file_errno_now("Opening output file\0", newname.into());
+    }
+
+    let ofd_count = c_specpdl_index();
+    unsafe { record_unwind_protect_int(Some(close_file_unwind), ofd) };
+    let output = ManuallyDrop::new(unsafe { File::from_raw_fd(ofd) });
+
+    let mut oldsize = 0;
+    if already_exists {
+        let out_st = output.metadata().unwrap_or_else(|err| {
+            report_file_io_error("Output file status\0", newname.into(), &err)
+        });
+        if st.dev() == out_st.dev() && st.ino() == out_st.ino() {
+            unsafe {
+                report_file_errno(
+                    "Input and output files are the same\0".as_ptr() as *const c_char,
+                    list!(file, newname),
+                    0,
+                )
+            };
+        }
+        if out_st.is_file() {
+            oldsize = out_st.size();
+        }
+    }
+
+    unsafe { maybe_quit() };
+
+    let newsize = if fast_copy::clone(ofd, ifd) {
+        st.size()
+    } else {
+        match copy_contents(ifd, ofd) {
+            Ok(newsize) => newsize,
+            Err(CopyError::Read(err)) => report_file_io_error("Read error\0", file.into(), &err),
+            Err(CopyError::Write(err)) => {
+                report_file_io_error("Write error\0", newname.into(), &err)
+            }
+            Err(CopyError::Both(err)) => {
+                report_file_io_error("Copying file\0", list!(file, newname), &err)
+            }
+        }
+    };
+
+    // Truncate any existing output file after writing the data.  This
+    // is more likely to work than truncation before writing, if the
+    // file system is out of space or the user is over disk quota.
+    if newsize < oldsize {
+        if let Err(err) = output.set_len(newsize) {
+            report_file_io_error("Truncating output file\0", newname.into(), &err);
+        }
+    }
+
+    // Preserve the original file permissions, and if requested, also its
+    // owner and group.
+    let realmask = unsafe { realmask };
+    let mut preserved_permissions = st.mode() & 0o7777;
+    let mut default_permissions = st.mode() & 0o777 & !realmask;
+    if preserve_uid_gid {
+        // Attempt to change owner and group.  If that doesn't work
+        // attempt to change just the group, as that is sometimes allowed.
+        // Adjust the mode mask to eliminate setuid or setgid bits
+        // or group permissions bits that are inappropriate if the
+        // owner or group are wrong.
+        if unsafe { libc::fchown(ofd, st.uid(), st.gid()) } != 0 {
+            if unsafe { libc::fchown(ofd, !0, st.gid()) } == 0 {
+                preserved_permissions &= !0o4000;
+            } else {
+                preserved_permissions &= !0o6000;
+
+                // Copy the other bits to the group bits, since the
+                // group is wrong.
+                preserved_permissions &= !0o070;
+                preserved_permissions |= (preserved_permissions & 7) << 3;
+                default_permissions &= !0o070;
+                default_permissions |= (default_permissions & 7) << 3;
+            }
+        }
+    }
+
+    if preserve_permissions {
+        if unsafe { libc::fchmod(ofd, preserved_permissions) } != 0 {
+            report_file_errno_now("Copying permissions to\0", newname.into());
+        }
+        match copy_xattrs(ifd, ofd) {
+            Ok(()) => {}
+            Err(XattrError::From(err)) => {
+                report_file_io_error("Copying permissions from\0", file.into(), &err)
+            }
+            Err(XattrError::To(err)) => {
+                report_file_io_error("Copying permissions to\0", newname.into(), &err)
+            }
+        }
+        unsafe { set_fd_selinux_context(ofd, selinux_context, newname.into()) };
+    } else if !already_exists
+        && (tempname.is_some() || (new_mask & !realmask) != default_permissions)
+        && unsafe { libc::fchmod(ofd, default_permissions) } != 0
+    {
+        report_file_errno_now("Copying permissions to\0", newname.into());
+    }
+
+    if keep_time {
+        let times = [
+            libc::timespec {
+                tv_sec: st.atime() as libc::time_t,
+                tv_nsec: st.atime_nsec() as libc::c_long,
+            },
+            libc::timespec {
+                tv_sec: st.mtime() as libc::time_t,
+                tv_nsec: st.mtime_nsec() as libc::c_long,
+            },
+        ];
+        if unsafe { libc::futimens(ofd, times.as_ptr()) } != 0 {
+            xsignal!(
+                Qfile_date_error,
+                LispObject::from("Cannot set file date"),
+                newname
+            );
+        }
+    }
+
+    unsafe { clear_unwind_protect(ofd_count) };
+    if unsafe { emacs_close(ofd) } < 0 {
+        report_file_errno_now("Write error\0", newname.into());
+    }
+
+    if let Some(tempname) = tempname {
+        if let Err(err) = rename_noreplace(tempname, encoded_newname) {
+            report_file_io_error("Renaming\0", newname.into(), &err);
+        }
+        unsafe { clear_unwind_protect(temp_count) };
+    }
+
+    unbind_to(count, Qnil)
+}
+
+/// Create a new directory named DIRECTORY.
+#[cfg(unix)]
+#[lisp_fn]
+pub fn make_directory_internal(directory: LispStringRef) -> LispObject {
+    let directory = expand_file_name(directory, None);
+
+    let handler = find_file_name_handler(directory, Qmake_directory_internal);
+    if handler.is_not_nil() {
+        return call!(handler, Qmake_directory_internal, directory.into());
+    }
+
+    let encoded_dir = encode_file_name(directory);
+    let mode = 0o777 & !unsafe { auto_saving_dir_umask };
+    if let Err(err) = DirBuilder::new()
+        .mode(mode)
+        .create(encoded_path(&encoded_dir))
+    {
+        report_file_io_error("Creating directory\0", directory.into(), &err);
+    }
+
+    Qnil
+}
+
+/// Delete the directory named DIRECTORY.  Does not follow symlinks.
+#[cfg(unix)]
+#[lisp_fn]
+pub fn delete_directory_internal(directory: LispStringRef) -> LispObject {
+    let directory = unsafe { Fdirectory_file_name(expand_file_name(directory, None).into()) };
+    let encoded_dir = encode_file_name(directory.into());
+
+    if let Err(err) = fs::remove_dir(encoded_path(&encoded_dir)) {
+        report_file_io_error("Removing directory\0", directory, &err);
+    }
+
+    Qnil
+}
+
+/// Delete file named FILENAME.  If it is a symlink, remove the symlink.
+/// If file has multiple names, it continues to exist with the other names.
+/// TRASH non-nil means to trash the file instead of deleting, provided
+/// `delete-by-moving-to-trash' is non-nil.
+///
+/// When called interactively, TRASH is t if no prefix argument is given.
+/// With a prefix argument, TRASH is nil.
+#[cfg(unix)]
+#[lisp_fn(
+    min = "1",
+    intspec = "(list (read-file-name \
+                (if (and delete-by-moving-to-trash (null current-prefix-arg)) \
+                    \"Move file to trash: \" \"Delete file: \") \
+                nil default-directory (confirm-nonexistent-file-or-buffer)) \
+              (null current-prefix-arg))"
+)]
+pub fn delete_file(filename: LispStringRef, trash: bool) -> LispObject {
+    if file_directory_p_lisp(filename) && unsafe { Ffile_symlink_p(filename.into()) }.is_nil() {
+        xsignal!(
+            Qfile_error,
+            LispObject::from("Removing old name: is a directory"),
+            filename
+        );
+    }
+    let filename = expand_file_name(filename, None);
+
+    let handler = find_file_name_handler(filename, Qdelete_file);
+    if handler.is_not_nil() {
+        return call!(handler, Qdelete_file, filename.into(), trash.into());
+    }
+
+    if unsafe { globals.delete_by_moving_to_trash } && trash {
+        return call!(Qmove_file_to_trash, filename.into());
+    }
+
+    let encoded_file = encode_file_name(filename);
+    match fs::remove_file(encoded_path(&encoded_file)) {
+        Err(ref err) if err.kind() != io::ErrorKind::NotFound => {
+            report_file_io_error("Removing old name\0", filename.into(), err)
+        }
+        _ => Qnil,
+    }
+}
+
+/// Rename FILE as NEWNAME.  Both args must be strings.
+/// If file has names other than FILE, it continues to have those names.
+/// If NEWNAME is a directory name, rename FILE to a like-named file under
+/// NEWNAME.  For NEWNAME to be recognized as a directory name, it should
+/// end in a slash.
+///
+/// Signal a `file-already-exists' error if a file NEWNAME already exists
+/// unless optional third argument OK-IF-ALREADY-EXISTS is non-nil.
+/// An integer third arg means request confirmation if NEWNAME already exists.
+/// This is what happens in interactive use with M-x.
+#[cfg(unix)]
+#[lisp_fn(min = "2", intspec = "fRename file: \nGRename %s to file: \np")]
+pub fn rename_file(
+    file: LispStringRef,
+    newname: LispStringRef,
+    ok_if_already_exists: LispObject,
+) -> LispObject {
+    let file = expand_file_name(file, None);
+    let mut ok_if_already_exists = ok_if_already_exists;
+
+    // MS-Windows uses the C implementation, which treats a change of
+    // case only on a case-insensitive file system as a plain rename.
+    let newname: LispStringRef =
+        unsafe { expand_cp_target(Fdirectory_file_name(file.into()), newname.into()) }.into();
+
+    // If the file name has special constructs in it,
+    // call the corresponding file handler.
+    let mut handler = find_file_name_handler(file, Qrename_file);
+    if handler.is_nil() {
+        handler = find_file_name_handler(newname, Qrename_file);
+    }
+    if handler.is_not_nil() {
+        return call!(
+            handler,
+            Qrename_file,
+            file.into(),
+            newname.into(),
+            ok_if_already_exists
+        );
+    }
+
+    let encoded_file = encode_file_name(file);
+    let encoded_newname = encode_file_name(newname);
+
+    let mut plain_rename = ok_if_already_exists.is_not_nil() && !ok_if_already_exists.is_integer();
+    let mut rename_errno = 0;
+    if !plain_rename {
+        if unsafe {
+            renameat_noreplace(
+                libc::AT_FDCWD,
+                encoded_file.const_sdata_ptr(),
+                libc::AT_FDCWD,
+                encoded_newname.const_sdata_ptr(),
+            )
+        } == 0
+        {
+            return Qnil;
+        }
+
+        rename_errno = errno().0;
+        match rename_errno {
+            libc::EEXIST | libc::EINVAL | libc::ENOSYS | libc::ENOTSUP => {
+                unsafe {
+                    barf_or_query_if_file_exists(
+                        newname.into(),
+                        rename_errno == libc::EEXIST,
+                        "rename to it\0".as_ptr() as *const c_char,
+                        ok_if_already_exists.is_integer(),
+                        false,
+                    )
+                };
+                plain_rename = true;
+            }
+            _ => {}
+        }
+    }
+
+    if plain_rename {
+        match fs::rename(encoded_path(&encoded_file), encoded_path(&encoded_newname)) {
+            Ok(()) => return Qnil,
+            Err(err) => rename_errno = err.raw_os_error().unwrap_or(0),
+        }
+        // Don't prompt again.
+        ok_if_already_exists = Qt;
+    } else if ok_if_already_exists.is_not_nil() {
+        ok_if_already_exists = Qt;
+    }
+
+    if rename_errno != libc::EXDEV {
+        report_file_io_error(
+            "Renaming\0",
+            list!(file, newname),
+            &io::Error::from_raw_os_error(rename_errno),
+        );
+    }
+
+    // FILE is on another file system, so copy it and delete the original.
+    let file_st = if directory_name_p(file) {
+        None
+    } else {
+        match fs::symlink_metadata(encoded_path(&encoded_file)) {
+            Ok(st) => Some(st),
+            Err(err) => report_file_io_error("Renaming\0", list!(file, newname), &err),
+        }
+    };
+    let dirp = file_st.as_ref().map_or(true, fs::Metadata::is_dir);
+
+    if dirp {
+        call!(Qcopy_directory, file.into(), newname.into(), Qt, Qnil);
+    } else {
+        let is_symlink = file_st
+            .as_ref()
+            .map_or(false, |st| st.file_type().is_symlink());
+        let symlink_target = if is_symlink {
+            unsafe { emacs_readlinkat(libc::AT_FDCWD, encoded_file.const_sdata_ptr()) }
+        } else {
+            Qnil
+        };
+        if symlink_target.is_not_nil() {
+            unsafe { Fmake_symbolic_link(symlink_target, newname.into(), ok_if_already_exists) };
+        } else {
+            copy_file(file, newname, ok_if_already_exists, true, true, true);
+        }
+    }
+
+    let count = c_specpdl_index();
+    unsafe { specbind(Qdelete_by_moving_to_trash, Qnil) };
+    if dirp {
+        call!(Qdelete_directory, file.into(), Qt);
+    } else {
+        delete_file(file, false);
+    }
+    unbind_to(count, Qnil)
+}
+
+/// Transfer the data and metadata of the file open as FD to disk,
+/// retrying if interrupted.  fsync can report a write failure here,
+/// e.g., due to disk full under NFS.  But ignore EINVAL, which means
+/// fsync is not supported on this file.
+#[cfg(unix)]
+fn fsync_retrying(fd: c_int) -> io::Result<()> {
+    while unsafe { libc::fsync(fd) } != 0 {
+        let err = io::Error::last_os_error();
+        match err.raw_os_error() {
+            Some(libc::EINTR) => {}
+            Some(libc::EINVAL) => break,
+            _ => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+/// Transfer the directory entries of the directory containing the file
+/// whose encoded name is ENCODED to disk, so that a file renamed into
+/// it is still there after a crash.
+#[cfg(unix)]
+fn fsync_parent_directory(encoded: LispStringRef) -> io::Result<()> {
+    let dir = encoded_path(&encoded)
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    fsync_retrying(File::open(dir)?.as_raw_fd())
+}
+
+/// Whether `write-region' should write to a temporary file and rename
+/// it over the file whose status is ST, or that does not exist if ST is
+/// None.  See `write-region-in-place'.
+#[cfg(unix)]
+fn write_region_atomically(st: Option<&fs::Metadata>) -> bool {
+    let in_place = unsafe { globals.Vwrite_region_in_place };
+    match st {
+        _ if in_place.eq(Qt) => false,
+        None => true,
+        // A symlink is written through, and any other special file
+        // cannot be replaced by a regular one.
+        Some(st) => st.is_file() && !(st.nlink() > 1 && in_place.is_not_nil()),
+    }
+}
+
+/// Give the file open as FD the owner, group, file modes and extended
+/// attributes of the file whose status is ST and encoded name is
+/// ENCODED.  If ST is None, give it the default permissions of a new
+/// file instead.  Return false if that is not possible.
+#[cfg(unix)]
+fn inherit_file_attributes(fd: c_int, st: Option<&fs::Metadata>, encoded: LispStringRef) -> bool {
+    match st {
+        None => unsafe { libc::fchmod(fd, 0o666 & !realmask) == 0 },
+        // Change the owner first, as that clears the setuid and setgid
+        // bits.
+        Some(st) => unsafe {
+            libc::fchown(fd, st.uid(), st.gid()) == 0
+                && libc::fchmod(fd, st.mode() & 0o7777) == 0
+                && copy_xattrs_from_name(encoded, fd).is_ok()
+        },
+    }
+}
+
+/// Record in BUFFER that it was saved to VISIT_FILE.
+#[cfg(unix)]
+fn set_buffer_saved(mut buffer: LispBufferRef, visit_file: LispStringRef) {
+    unsafe { (*buffer.text).save_modiff = buffer.modifications() };
+    buffer.save_length_ = (buffer.z() - buffer.beg()).into();
+    buffer.filename_ = visit_file.into();
+}
+
+/// Write current region into specified file.
+/// When called from a program, requires three arguments:
+/// START, END and FILENAME.  START and END are normally buffer positions
+/// specifying the part of the buffer to write.
+/// If START is nil, that means to use the entire buffer contents; END is
+/// ignored.
+/// If START is a string, then output that string to the file
+/// instead of any buffer contents; END is ignored.
+///
+/// Optional fourth argument APPEND if non-nil means
+///   append to existing file contents (if any).  If it is a number,
+///   seek to that offset in the file before writing.
+/// Optional fifth argument VISIT, if t or a string, means
+///   set the last-save-file-modtime of buffer to this file's modtime
+///   and mark buffer not modified.
+/// If VISIT is a string, it is a second file name;
+///   the output goes to FILENAME, but the buffer is marked as visiting VISIT.
+///   VISIT is also the file name to lock and unlock for clash detection.
+/// If VISIT is neither t nor nil nor a string, or if Emacs is in batch mode,
+///   do not display the \"Wrote file\" message.
+/// The optional sixth arg LOCKNAME, if non-nil, specifies the name to
+///   use for locking and unlocking, overriding FILENAME and VISIT.
+/// The optional seventh arg MUSTBENEW, if non-nil, insists on a check
+///   for an existing file with the same name.  If MUSTBENEW is `excl',
+///   that means to get an error if the file already exists; never overwrite.
+///   If MUSTBENEW is neither nil nor `excl', that means ask for
+///   confirmation before overwriting, but do go ahead and overwrite the file
+///   if the user confirms.
+///
+/// Unless APPEND is non-nil, the text is written to a new file in the
+/// directory of FILENAME, which then replaces FILENAME in one step, so
+/// that FILENAME never exists partially written.  The new file gets the
+/// owner, group, file modes and extended attributes of the old one; if
+/// that is not possible, FILENAME is overwritten in place instead.
+/// See `write-region-in-place' for when FILENAME is always overwritten
+/// in place.
+///
+/// This does code conversion according to the value of
+/// `coding-system-for-write', `buffer-file-coding-system', or
+/// `file-coding-system-alist', and sets the variable
+/// `last-coding-system-used' to the coding system actually used.
+///
+/// This calls `write-region-annotate-functions' at the start, and
+/// `write-region-post-annotation-function' at the end.
+#[cfg(unix)]
+#[lisp_fn(min = "3", intspec = "r\nFWrite region to file: \ni\ni\ni\np")]
+pub fn write_region(
+    start: LispObject,
+    end: LispObject,
+    filename: LispStringRef,
+    append: LispObject,
+    visit: LispObject,
+    lockname: LispObject,
+    mustbenew: LispObject,
+) -> LispObject {
+    let (mut start, mut end) = (start, end);
+    let visiting = visit.eq(Qt) || visit.is_string();
+    let quietly = visit.is_not_nil();
+
+    if ThreadState::current_buffer_unchecked()
+        .base_buffer()
+        .is_some()
+        && visiting
+    {
+        error!("Cannot do file visiting in an indirect buffer");
+    }
+
+    if start.is_not_nil() && !start.is_string() {
+        unsafe { validate_region(&mut start, &mut end) };
+    }
+
+    let filename = expand_file_name(filename, None);
+
+    if mustbenew.is_not_nil() && !mustbenew.eq(Qexcl) {
+        unsafe {
+            barf_or_query_if_file_exists(
+                filename.into(),
+                false,
+                "overwrite\0".as_ptr() as *const c_char,
+                true,
+                true,
+            )
+        };
+    }
+
+    let visit_file = visit
+        .as_string()
+        .map_or(filename, |visit| expand_file_name(visit, None));
+
+    let lockname = if lockname.is_nil() {
+        visit_file.into()
+    } else {
+        lockname
+    };
+
+    // If the file name has special constructs in it,
+    // call the corresponding file handler.
+    let mut handler = find_file_name_handler(filename, Qwrite_region);
+    // If FILENAME has no handler, see if VISIT has one.
+    if handler.is_nil() {
+        if let Some(visit) = visit.as_string() {
+            handler = find_file_name_handler(visit, Qwrite_region);
+        }
+    }
+    if handler.is_not_nil() {
+        let val = call!(
+            handler,
+            Qwrite_region,
+            start,
+            end,
+            filename.into(),
+            append,
+            visit,
+            lockname,
+            mustbenew
+        );
+        if visiting {
+            set_buffer_saved(ThreadState::current_buffer_unchecked(), visit_file);
+        }
+        return val;
+    }
+
+    let count = c_specpdl_index();
+    let mut coding: coding_system = unsafe { mem::zeroed() };
+    let mut annotations = unsafe {
+        write_region_annotate(
+            &mut start,
+            &mut end,
+            filename.into(),
+            append,
+            visit,
+            lockname,
+            &mut coding,
+        )
+    };
+
+    let auto_saving = unsafe { auto_saving };
+    let file_locked = !auto_saving;
+    if file_locked {
+        unsafe { lock_file(lockname) };
+    }
+    let signal_error = |string: &str, err: &io::Error| -> ! {
+        if file_locked {
+            unsafe { unlock_file(lockname) };
+        }
+        report_file_io_error(string, filename.into(), err)
+    };
+
+    let encoded_filename = encode_file_name(filename);
+    let old_st = fs::symlink_metadata(encoded_path(&encoded_filename)).ok();
+
+    // Unless appending or auto-saving, write to a temporary file that
+    // replaces FILENAME when complete.
+    let mut replacement = None;
+    let mut desc = -1;
+    let mut close_count = 0;
+    if append.is_nil() && !auto_saving && write_region_atomically(old_st.as_ref()) {
+        if mustbenew.eq(Qexcl) && old_st.is_some() {
+            signal_error(
+                "Opening output file\0",
+                &io::Error::from_raw_os_error(libc::EEXIST),
+            );
+        }
+        if let Ok((fd, tempname)) = create_temporary_sibling(encoded_filename) {
+            let temp_count = c_specpdl_index();
+            unsafe { record_unwind_protect(Some(delete_temporary_file_unwind), tempname.into()) };
+            close_count = c_specpdl_index();
+            unsafe { record_unwind_protect_int(Some(close_file_unwind), fd) };
+            if inherit_file_attributes(fd, old_st.as_ref(), encoded_filename) {
+                desc = fd;
+                replacement = Some((tempname, temp_count));
+            } else {
+                unbind_to(temp_count, Qnil);
+            }
+        }
+    }
+
+    let mut open_flags = libc::O_WRONLY | libc::O_CREAT;
+    if mustbenew.eq(Qexcl) {
+        open_flags |= libc::O_EXCL;
+    } else if append.is_nil() {
+        open_flags |= libc::O_TRUNC;
+    }
+    let offset = if append.is_number() {
+        Some(unsafe { file_offset(append) })
+    } else {
+        if append.is_not_nil() {
+            open_flags |= libc::O_APPEND;
+        }
+        None
+    };
+
+    if replacement.is_none() {
+        let mode = if auto_saving {
+            unsafe { auto_save_mode_bits }
+        } else {
+            0o666
+        };
+        desc = unsafe {
+            emacs_open(
+                encoded_filename.const_sdata_ptr(),
+                open_flags,
+                mode as c_int,
+            )
+        };
+        if desc < 0 {
+            signal_error("Opening output file\0", &io::Error::last_os_error());
+        }
+        close_count = c_specpdl_index();
+        unsafe { record_unwind_protect_int(Some(close_file_unwind), desc) };
+    }
+
+    if let Some(offset) = offset {
+        if unsafe { libc::lseek(desc, offset, libc::SEEK_SET) } < 0 {
+            signal_error("Lseek error\0", &io::Error::last_os_error());
+        }
+    }
+
+    let mut error = None;
+    if !unsafe { write_region_contents(desc, start, end, &mut annotations, &mut coding) } {
+        error = Some(io::Error::last_os_error());
+    }
+
+    // fsync is not crucial for auto-save files, since they might lose
+    // some work anyway.
+    let sync = !auto_saving && !unsafe { globals.write_region_inhibit_fsync };
+    if error.is_none() && sync {
+        error = fsync_retrying(desc).err();
+    }
+
+    let output = ManuallyDrop::new(unsafe { File::from_raw_fd(desc) });
+    let mut st = None;
+    if visiting {
+        match output.metadata() {
+            Ok(metadata) => st = Some(metadata),
+            Err(err) => error = Some(err),
+        }
+    }
+
+    // NFS can report a write failure now.
+    unsafe { clear_unwind_protect(close_count) };
+    if unsafe { emacs_close(desc) } < 0 {
+        error = Some(io::Error::last_os_error());
+    }
+
+    if let Some((tempname, temp_count)) = replacement {
+        let renamed = if error.is_some() {
+            Err(None)
+        } else if mustbenew.eq(Qexcl) {
+            rename_noreplace(tempname, encoded_filename).map_err(Some)
+        } else {
+            fs::rename(encoded_path(&tempname), encoded_path(&encoded_filename)).map_err(Some)
+        };
+        match renamed {
+            Ok(()) => {
+                unsafe { clear_unwind_protect(temp_count) };
+                if sync {
+                    if let Err(err) = fsync_parent_directory(encoded_filename) {
+                        error = Some(err);
+                    }
+                }
+            }
+            // FILENAME is unchanged, so the buffer should not look like
+            // it was saved to it.
+            Err(err) => {
+                st = None;
+                if err.is_some() {
+                    error = err;
+                }
+            }
+        }
+    }
+
+    let mut modtime = st
+        .as_ref()
+        .map(|st| (st.mtime(), st.mtime_nsec(), st.size()));
+
+    // Some file systems have a bug where st_mtime is not updated
+    // properly after a write.  For example, CIFS might not see the
+    // st_mtime change until after the file is opened again.
+    //
+    // Attempt to detect this file system bug, and update MODTIME to the
+    // newer st_mtime if the bug appears to be present.  This introduces
+    // a race condition, so to avoid most instances of the race condition
+    // on non-buggy file systems, skip this check if the most recently
+    // encountered non-buggy file system was the current file system.
+    //
+    // A race condition can occur if some other process modifies the
+    // file between the fstat above and the fstat below, but the race is
+    // unlikely and a similar race between the last write and the fstat
+    // above cannot possibly be closed anyway.
+    if let Some(st) = st {
+        let known_good =
+            unsafe { valid_timestamp_file_system && st.dev() == timestamp_file_system as u64 };
+        let desc1 = if known_good {
+            -1
+        } else {
+            unsafe { emacs_open(encoded_filename.const_sdata_ptr(), libc::O_WRONLY, 0) }
+        };
+        if desc1 >= 0 {
+            let file1 = unsafe { File::from_raw_fd(desc1) };
+            if let Ok(st1) = file1.metadata() {
+                if st.dev() == st1.dev() && st.ino() == st1.ino() {
+                    // Use the heuristic if it appears to be valid.  With
+                    // neither O_EXCL nor O_TRUNC, if Emacs happened to
+                    // write nothing to the file, the time stamp won't
+                    // change.  Also, some non-POSIX systems don't update
+                    // an empty file's time stamp when truncating it.
+                    // Finally, file systems with 100 ns or worse
+                    // resolution sometimes seem to have bugs: on a system
+                    // with ns resolution, checking ns % 100 incorrectly
+                    // avoids the heuristic 1% of the time, but the
+                    // problem should be temporary as we will try again
+                    // on the next time stamp.
+                    let use_heuristic = (replacement.is_some()
+                        || open_flags & (libc::O_EXCL | libc::O_TRUNC) != 0)
+                        && st.size() != 0
+                        && st.mtime_nsec() % 100 != 0;
+
+                    let modtime1 = (st1.mtime(), st1.mtime_nsec(), st1.size());
+                    if use_heuristic && modtime == Some(modtime1) {
+                        unsafe {
+                            timestamp_file_system = st.dev() as _;
+                            valid_timestamp_file_system = true;
+                        }
+                    } else {
+                        modtime = Some(modtime1);
+                    }
+                }
+            }
+        }
+    }
+
+    unsafe { write_region_post_annotations() };
+
+    unbind_to(count, Qnil);
+
+    if file_locked {
+        unsafe { unlock_file(lockname) };
+    }
+
+    let mut buffer = ThreadState::current_buffer_unchecked();
+
+    // Do this before reporting IO error
+    // to avoid a "file has changed on disk" warning on
+    // next attempt to save.
+    if let Some((sec, nsec, size)) = modtime {
+        buffer.modtime = unsafe { make_timespec(sec as _, nsec as _) };
+        buffer.modtime_size = size as _;
+    }
+
+    if let Some(err) = error {
+        report_file_io_error("Write error\0", filename.into(), &err);
+    }
+
+    let auto_saving_into_visited_file =
+        auto_saving && string_equal(buffer.filename_, buffer.auto_save_file_name_);
+    if visiting {
+        set_buffer_saved(buffer, visit_file);
+        unsafe { update_mode_lines = 14 };
+        if auto_saving_into_visited_file {
+            unsafe { unlock_file(lockname) };
+        }
+    } else if quietly {
+        if auto_saving_into_visited_file {
+            unsafe { (*buffer.text).save_modiff = buffer.modifications() };
+            unsafe { unlock_file(lockname) };
+        }
+
+        return Qnil;
+    }
+
+    if !auto_saving && !unsafe { noninteractive } {
+        let format = if append.is_number() {
+            "Updated %s\0"
+        } else if append.is_not_nil() {
+            "Added to %s\0"
+        } else {
+            "Wrote %s\0"
+        };
+        message_with_string!(format, visit_file.into(), true);
+    }
+
+    Qnil
+}
+
+/// Read a value whose size is unknown with READ, which works like
+/// `getxattr' and `listxattr': given an empty buffer, it returns the
+/// size of the value.
+#[cfg(target_os = "linux")]
+fn read_xattr_value(read: impl Fn(*mut c_void, usize) -> isize) -> io::Result<Vec<u8>> {
+    loop {
+        let size = read(ptr::null_mut(), 0);
+        if size < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut value = vec![0_u8; size as usize];
+        let size = read(value.as_mut_ptr() as *mut c_void, value.len());
+        if size >= 0 {
+            value.truncate(size as usize);
+            return Ok(value);
+        }
+        // The value grew since we asked for its size.
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::ERANGE) {
+            return Err(err);
+        }
+    }
+}
+
+/// Return the names of the extended attributes of the file whose
+/// encoded name is ENCODED, of the symlink itself if NOFOLLOW.
+#[cfg(target_os = "linux")]
+fn list_xattrs(encoded: LispStringRef, nofollow: bool) -> io::Result<Vec<Vec<u8>>> {
+    let path = encoded.const_sdata_ptr();
+    let names = read_xattr_value(|names, size| unsafe {
+        if nofollow {
+            libc::llistxattr(path, names as *mut c_char, size)
+        } else {
+            libc::listxattr(path, names as *mut c_char, size)
+        }
+    })?;
+    Ok(names
+        .split(|&c| c == 0)
+        .filter(|name| !name.is_empty())
+        .map(<[u8]>::to_vec)
+        .collect())
+}
+
+/// Return the value of the extended attribute NAME, a NUL-terminated
+/// byte string, of the file whose encoded name is ENCODED.
+#[cfg(target_os = "linux")]
+fn get_xattr(encoded: LispStringRef, name: *const c_char, nofollow: bool) -> io::Result<Vec<u8>> {
+    let path = encoded.const_sdata_ptr();
+    read_xattr_value(|value, size| unsafe {
+        if nofollow {
+            libc::lgetxattr(path, name, value, size)
+        } else {
+            libc::getxattr(path, name, value, size)
+        }
+    })
+}
+
+/// Set the extended attribute NAME, a NUL-terminated byte string, of
+/// the file whose encoded name is ENCODED to VALUE.
+#[cfg(target_os = "linux")]
+fn set_xattr(
+    encoded: LispStringRef,
+    name: *const c_char,
+    value: &[u8],
+    nofollow: bool,
+) -> io::Result<()> {
+    let path = encoded.const_sdata_ptr();
+    let value_ptr = value.as_ptr() as *const c_void;
+    let result = unsafe {
+        if nofollow {
+            libc::lsetxattr(path, name, value_ptr, value.len(), 0)
+        } else {
+            libc::setxattr(path, name, value_ptr, value.len(), 0)
+        }
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Whether ERR means that the file system does not support extended
+/// attributes.
+#[cfg(target_os = "linux")]
+fn xattrs_unsupported(err: &io::Error) -> bool {
+    match err.raw_os_error() {
+        Some(libc::ENOTSUP) | Some(libc::ENOSYS) => true,
+        _ => false,
+    }
+}
+
+/// The prefix of the extended attributes `file-extended-attributes'
+/// reports as `xattrs'.
+#[cfg(target_os = "linux")]
+const USER_XATTR_PREFIX: &[u8] = b"user.";
+
+/// Return the extended attributes in the \"user\" namespace of the file
+/// whose encoded name is ENCODED as an alist of (NAME . VALUE), or nil
+/// if they cannot be read.
+#[cfg(target_os = "linux")]
+fn user_xattrs(encoded: LispStringRef) -> LispObject {
+    let names = match list_xattrs(encoded, false) {
+        Ok(names) => names,
+        Err(_) => return Qnil,
+    };
+    let mut alist = Qnil;
+    for name in names.into_iter().rev() {
+        if !name.starts_with(USER_XATTR_PREFIX) {
+            continue;
+        }
+        let cname = CString::new(name).unwrap();
+        if let Ok(value) = get_xattr(encoded, cname.as_ptr(), false) {
+            let name = cname.as_bytes();
+            let entry = unsafe {
+                LispObject::cons(
+                    decode_file_name(make_unibyte_string(
+                        name.as_ptr() as *const c_char,
+                        name.len() as isize,
+                    )),
+                    make_unibyte_string(value.as_ptr() as *const c_char, value.len() as isize),
+                )
+            };
+            alist = LispObject::cons(entry, alist);
+        }
+    }
+    alist
+}
+
+#[cfg(not(target_os = "linux"))]
+fn user_xattrs(_encoded: LispStringRef) -> LispObject {
+    Qnil
+}
+
+/// Set the extended attributes of the file whose encoded name is
+/// ENCODED to those in ALIST, as returned by `user_xattrs'.  Return
+/// true if all of them could be set.
+#[cfg(target_os = "linux")]
+fn set_user_xattrs(encoded: LispStringRef, alist: LispObject) -> bool {
+    alist
+        .iter_cars(LispConsEndChecks::on, LispConsCircularChecks::on)
+        .all(|entry| {
+            let (name, value): (LispObject, LispObject) = entry.into();
+            let name = encode_file_name(name.into());
+            let value = LispStringRef::from(value);
+            set_xattr(encoded, name.const_sdata_ptr(), value.as_slice(), false).is_ok()
+        })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_user_xattrs(_encoded: LispStringRef, alist: LispObject) -> bool {
+    alist.is_nil()
+}
+
+/// Return an alist of extended attributes of file FILENAME.
+///
+/// Extended attributes are platform-specific metadata about the file,
+/// such as SELinux context, list of ACL entries, etc.  The `xattrs'
+/// entry is an alist of the attributes in the \"user\" namespace and
+/// their values, as returned by `file-xattr-get'.
+#[lisp_fn]
+pub fn file_extended_attributes(filename: LispStringRef) -> LispObject {
+    let absname = unsafe { expand_and_dir_to_file(filename.into()) };
+
+    // If the file name has special constructs in it,
+    // call the corresponding file handler.
+    let handler = find_file_name_handler(absname.into(), Qfile_extended_attributes);
+    if handler.is_not_nil() {
+        return call!(handler, Qfile_extended_attributes, absname);
+    }
+
+    list!(
+        LispObject::cons(Qacl, unsafe { Ffile_acl(absname) }),
+        LispObject::cons(Qselinux_context, unsafe { Ffile_selinux_context(absname) }),
+        LispObject::cons(Qxattrs, user_xattrs(encode_file_name(absname.into())))
+    )
+}
+
+/// Set extended attributes of file FILENAME to ATTRIBUTES.
+///
+/// ATTRIBUTES must be an alist of file attributes as returned by
+/// `file-extended-attributes'.
+/// Value is t if the function succeeds in setting the attributes.
+#[lisp_fn]
+pub fn set_file_extended_attributes(filename: LispStringRef, attributes: LispObject) -> bool {
+    let absname = unsafe { expand_and_dir_to_file(filename.into()) };
+
+    // If the file name has special constructs in it,
+    // call the corresponding file handler.
+    let handler = find_file_name_handler(absname.into(), Qset_file_extended_attributes);
+    if handler.is_not_nil() {
+        return call!(handler, Qset_file_extended_attributes, absname, attributes).is_not_nil();
+    }
+
+    let mut result = false;
+    for elt in attributes.iter_cars(LispConsEndChecks::on, LispConsCircularChecks::on) {
+        let (attr, val): (LispObject, LispObject) = elt.into();
+        let rv = match attr {
+            Qacl => unsafe { Fset_file_acl(absname, val) }.is_not_nil(),
+            Qselinux_context => unsafe { Fset_file_selinux_context(absname, val) }.is_not_nil(),
+            Qxattrs => set_user_xattrs(encode_file_name(absname.into()), val),
+            _ => false,
+        };
+        result = result || rv;
+    }
+    result
+}
+
+/// Return the names of the extended attributes of file FILENAME.
+/// Return nil if its file system does not support extended attributes.
+/// If optional NOFOLLOW is non-nil and FILENAME is a symbolic link,
+/// return the attributes of the link itself.
+#[cfg(target_os = "linux")]
+#[lisp_fn(min = "1")]
+pub fn file_xattr_list(filename: LispStringRef, nofollow: bool) -> LispObject {
+    let absname = unsafe { expand_and_dir_to_file(filename.into()) };
+
+    // If the file name has special constructs in it,
+    // call the corresponding file handler.
+    let handler = find_file_name_handler(absname.into(), Qfile_xattr_list);
+    if handler.is_not_nil() {
+        return call!(handler, Qfile_xattr_list, absname, nofollow.into());
+    }
+
+    match list_xattrs(encode_file_name(absname.into()), nofollow) {
+        Ok(names) => names.iter().rev().fold(Qnil, |list, name| unsafe {
+            LispObject::cons(
+                decode_file_name(make_unibyte_string(
+                    name.as_ptr() as *const c_char,
+                    name.len() as isize,
+                )),
+                list,
+            )
+        }),
+        Err(ref err) if xattrs_unsupported(err) => Qnil,
+        Err(err) => report_file_io_error("Listing extended attributes\0", absname, &err),
+    }
+}
+
+/// Return the value of the extended attribute NAME of file FILENAME.
+/// The value is a unibyte string.  Return nil if FILENAME has no such
+/// attribute.  If optional NOFOLLOW is non-nil and FILENAME is a
+/// symbolic link, return the attribute of the link itself.
+#[cfg(target_os = "linux")]
+#[lisp_fn(min = "2")]
+pub fn file_xattr_get(filename: LispStringRef, name: LispStringRef, nofollow: bool) -> LispObject {
+    let absname = unsafe { expand_and_dir_to_file(filename.into()) };
+
+    // If the file name has special constructs in it,
+    // call the corresponding file handler.
+    let handler = find_file_name_handler(absname.into(), Qfile_xattr_get);
+    if handler.is_not_nil() {
+        return call!(
+            handler,
+            Qfile_xattr_get,
+            absname,
+            name.into(),
+            nofollow.into()
+        );
+    }
+
+    let encoded_name = encode_file_name(name);
+    match get_xattr(
+        encode_file_name(absname.into()),
+        encoded_name.const_sdata_ptr(),
+        nofollow,
+    ) {
+        Ok(value) => unsafe {
+            make_unibyte_string(value.as_ptr() as *const c_char, value.len() as isize)
+        },
+        Err(ref err) if err.raw_os_error() == Some(libc::ENODATA) => Qnil,
+        Err(err) => {
+            report_file_io_error("Getting extended attribute\0", list!(absname, name), &err)
+        }
+    }
+}
+
+/// Set the extended attribute NAME of file FILENAME to VALUE, a string.
+/// The attribute is set to the bytes of VALUE, which should normally be
+/// a unibyte string.  If optional NOFOLLOW is non-nil and FILENAME is a
+/// symbolic link, set the attribute of the link itself.
+#[cfg(target_os = "linux")]
+#[lisp_fn(min = "3")]
+pub fn file_xattr_set(
+    filename: LispStringRef,
+    name: LispStringRef,
+    value: LispStringRef,
+    nofollow: bool,
+) -> LispObject {
+    let absname = unsafe { expand_and_dir_to_file(filename.into()) };
+
+    // If the file name has special constructs in it,
+    // call the corresponding file handler.
+    let handler = find_file_name_handler(absname.into(), Qfile_xattr_set);
+    if handler.is_not_nil() {
+        return call!(
+            handler,
+            Qfile_xattr_set,
+            absname,
+            name.into(),
+            value.into(),
+            nofollow.into()
+        );
+    }
+
+    let encoded_name = encode_file_name(name);
+    if let Err(err) = set_xattr(
+        encode_file_name(absname.into()),
+        encoded_name.const_sdata_ptr(),
+        value.as_slice(),
+        nofollow,
+    ) {
+        report_file_io_error("Setting extended attribute\0", list!(absname, name), &err);
+    }
+    Qnil
+}
+
+/// Remove the extended attribute NAME of file FILENAME.
+/// Return t if it was removed, nil if FILENAME has no such attribute.
+/// If optional NOFOLLOW is non-nil and FILENAME is a symbolic link,
+/// remove the attribute of the link itself.
+#[cfg(target_os = "linux")]
+#[lisp_fn(min = "2")]
+pub fn file_xattr_remove(filename: LispStringRef, name: LispStringRef, nofollow: bool) -> bool {
+    let absname = unsafe { expand_and_dir_to_file(filename.into()) };
+
+    // If the file name has special constructs in it,
+    // call the corresponding file handler.
+    let handler = find_file_name_handler(absname.into(), Qfile_xattr_remove);
+    if handler.is_not_nil() {
+        return call!(
+            handler,
+            Qfile_xattr_remove,
+            absname,
+            name.into(),
+            nofollow.into()
+        )
+        .is_not_nil();
+    }
+
+    let encoded = encode_file_name(absname.into());
+    let encoded_name = encode_file_name(name);
+    let result = unsafe {
+        if nofollow {
+            libc::lremovexattr(encoded.const_sdata_ptr(), encoded_name.const_sdata_ptr())
+        } else {
+            libc::removexattr(encoded.const_sdata_ptr(), encoded_name.const_sdata_ptr())
+        }
+    };
+    if result == 0 {
+        return true;
+    }
+    let err = io::Error::last_os_error();
+    if err.raw_os_error() != Some(libc::ENODATA) {
+        report_file_io_error("Removing extended attribute\0", list!(absname, name), &err);
+    }
+    false
+}
+
+#[allow(unused_doc_comments)]
+#[no_mangle]
+pub extern "C" fn rust_syms_of_fileio() {
+    def_lisp_sym!(Qhard_links, "hard-links");
+
+    def_lisp_sym!(Qacl, "acl");
+    def_lisp_sym!(Qselinux_context, "selinux-context");
+    def_lisp_sym!(Qxattrs, "xattrs");
+    def_lisp_sym!(Qfile_extended_attributes, "file-extended-attributes");
+    def_lisp_sym!(
+        Qset_file_extended_attributes,
+        "set-file-extended-attributes"
+    );
+    def_lisp_sym!(Qfile_xattr_get, "file-xattr-get");
+    def_lisp_sym!(Qfile_xattr_list, "file-xattr-list");
+    def_lisp_sym!(Qfile_xattr_remove, "file-xattr-remove");
+    def_lisp_sym!(Qfile_xattr_set, "file-xattr-set");
+
+    /// Whether `write-region' overwrites files in place.
+    /// If nil, `write-region' writes to a new file that replaces the old
+    /// one when complete, unless it appends to the file or cannot give
+    /// the new file the owner and permissions of the old one.
+    /// If `hard-links', it also overwrites files with several names in
+    /// place, so that all names keep referring to the same file.
+    /// If t, it always overwrites files in place, which is faster but
+    /// leaves a partially written file after a crash.
+    defvar_lisp!(Vwrite_region_in_place, "write-region-in-place", Qhard_links);
+}
+
 include!(concat!(env!("OUT_DIR"), "/fileio_exports.rs"));
@@ -0,0 +1,1167 @@
+//! The engine behind `format' and `format-message'.
+//!
+//! A %-sequence looks like
+//!
+//!   '%' [field-number] [flags] [field-width] [precision] conversion
+//!
+//! where field-number is [0-9]+ followed by '$', or {NAME} for
+//! `format-named', flags is [-+ #0]+,
+//! field-width is [0-9]+ and precision is '.' followed by [0-9]*.
+//! Numbers are converted here rather than by the C library's sprintf,
+//! but the output is the same as glibc's, down to rounding exact
+//! decimal ties to even.
+
+use libc::c_char;
+
+use crate::{
+    character::char_head_p,
+    editfns::char_to_string,
+    fns::nreverse,
+    lisp::LispObject,
+    lists::{LispConsCircularChecks, LispConsEndChecks},
+    multibyte::{multibyte_char_at, Codepoint, LispStringRef, MAX_MULTIBYTE_LENGTH},
+    numbers::MOST_POSITIVE_FIXNUM,
+    remacs_sys::{
+        add_text_properties_from_list, extend_property_ranges, make_composition_value_copy,
+        make_specified_string, multibyte_chars_in_text, string_overflow, text_property_list,
+        text_quoting_style, EmacsDouble, EmacsInt, EmacsUint, Fprin1_to_string, INTMASK,
+    },
+    remacs_sys::{Qnil, Qoverflow_error, Qt},
+};
+
+/// The flags, width, precision and conversion of one %-sequence.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FormatSpec {
+    /// Pad on the right instead of the left.
+    pub minus: bool,
+    /// Put a + before non-negative numbers.
+    pub plus: bool,
+    /// Put a space before non-negative numbers, unless `plus` is set.
+    pub space: bool,
+    /// Use the alternate form of the conversion.
+    pub sharp: bool,
+    /// Pad numbers with zeros instead of spaces.
+    pub zero: bool,
+    pub width: usize,
+    pub precision: Option<usize>,
+    /// The conversion character, such as b'd'.
+    pub conversion: u8,
+}
+
+/// Convert the ASCII decimal digits at the start of BYTES to a number,
+/// or PTRDIFF_MAX on overflow.  Return the number and how many digits
+/// there were.
+fn parse_number(bytes: &[u8]) -> (usize, usize) {
+    let len = bytes.iter().take_while(|b| b.is_ascii_digit()).count();
+    let limit = isize::max_value() as usize;
+    let n = bytes[..len].iter().fold(0_usize, |n, &b| {
+        n.checked_mul(10)
+            .and_then(|n| n.checked_add(usize::from(b - b'0')))
+            .map_or(limit, |n| n.min(limit))
+    });
+    (n, len)
+}
+
+/// Parse the field number "N$" at the start of BYTES, returning N and
+/// the length of the field number.
+pub fn parse_field_number(bytes: &[u8]) -> Option<(usize, usize)> {
+    match parse_number(bytes) {
+        (n, len) if len > 0 && bytes.get(len) == Some(&b'$') => Some((n, len + 1)),
+        _ => None,
+    }
+}
+
+/// Parse the field name "{NAME}" at the start of BYTES, returning NAME
+/// and the length of the field name, or None if there is no closing
+/// brace.
+pub fn parse_field_name(bytes: &[u8]) -> Option<(&[u8], usize)> {
+    debug_assert_eq!(bytes.first(), Some(&b'{'));
+    let end = bytes.iter().position(|&b| b == b'}')?;
+    Some((&bytes[1..end], end + 1))
+}
+
+/// Parse the flags, width, precision and conversion character at the
+/// start of BYTES, which follows the '%' and any field number.  Return
+/// the spec and its length, or None if BYTES ends before the conversion
+/// character.
+pub fn parse_spec(bytes: &[u8]) -> Option<(FormatSpec, usize)> {
+    let mut spec = FormatSpec::default();
+    let mut pos = 0;
+    loop {
+        match bytes.get(pos) {
+            Some(b'-') => spec.minus = true,
+            Some(b'+') => spec.plus = true,
+            Some(b' ') => spec.space = true,
+            Some(b'#') => spec.sharp = true,
+            Some(b'0') => spec.zero = true,
+            _ => break,
+        }
+        pos += 1;
+    }
+    // Ignore flags when sprintf ignores them.
+    spec.space &= !spec.plus;
+    spec.zero &= !spec.minus;
+
+    let (width, len) = parse_number(&bytes[pos..]);
+    spec.width = width;
+    pos += len;
+    if bytes.get(pos) == Some(&b'.') {
+        let (precision, len) = parse_number(&bytes[pos + 1..]);
+        spec.precision = Some(precision);
+        pos += 1 + len;
+    }
+
+    spec.conversion = *bytes.get(pos)?;
+    Some((spec, pos + 1))
+}
+
+/// A number to be formatted by a %-sequence.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Number {
+    Integer(EmacsInt),
+    Float(EmacsDouble),
+}
+
+/// The exact decimal expansion of a non-negative number, as `digits`
+/// from 0 to 9 with no leading or trailing zeros, and `point`, so that
+/// the value is 0.DIGITS times 10 to the power POINT.  Zero has no
+/// digits.
+#[derive(Clone, Debug, PartialEq)]
+struct Decimal {
+    digits: Vec<u8>,
+    point: isize,
+}
+
+impl Decimal {
+    fn new(mut digits: Vec<u8>, mut point: isize) -> Self {
+        let leading = digits.iter().take_while(|&&d| d == 0).count();
+        digits.drain(..leading);
+        point -= leading as isize;
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+        if digits.is_empty() {
+            point = 0;
+        }
+        Self { digits, point }
+    }
+
+    fn from_integer(n: u64) -> Self {
+        let digits: Vec<u8> = n.to_string().bytes().map(|b| b - b'0').collect();
+        let point = digits.len() as isize;
+        Self::new(digits, point)
+    }
+
+    /// The expansion of the magnitude of X, which must be finite.
+    fn from_float(x: f64) -> Self {
+        const BASE: u64 = 1_000_000_000;
+
+        // Multiply the little-endian base 10^9 number N by K in place.
+        fn multiply(n: &mut Vec<u64>, k: u64) {
+            let mut carry = 0;
+            for limb in n.iter_mut() {
+                let product = *limb * k + carry;
+                *limb = product % BASE;
+                carry = product / BASE;
+            }
+            while carry > 0 {
+                n.push(carry % BASE);
+                carry /= BASE;
+            }
+        }
+
+        let bits = x.to_bits();
+        let biased = ((bits >> 52) & 0x7ff) as isize;
+        let fraction = bits & ((1 << 52) - 1);
+        let (mut mantissa, mut exponent) = if biased == 0 {
+            (fraction, -1074)
+        } else {
+            (fraction | 1 << 52, biased - 1075)
+        };
+        if mantissa == 0 {
+            return Self::new(Vec::new(), 0);
+        }
+        while mantissa % 2 == 0 && exponent < 0 {
+            mantissa /= 2;
+            exponent += 1;
+        }
+
+        // X is MANTISSA * 2^EXPONENT.  When EXPONENT is negative, that is
+        // MANTISSA * 5^-EXPONENT / 10^-EXPONENT.
+        let mut n = vec![
+            mantissa % BASE,
+            mantissa / BASE % BASE,
+            mantissa / BASE / BASE,
+        ];
+        let (factor, big_factor, big_count, mut count) = if exponent >= 0 {
+            (2, 1 << 29, 29, exponent)
+        } else {
+            (5, 1_220_703_125, 13, -exponent)
+        };
+        while count >= big_count {
+            multiply(&mut n, big_factor);
+            count -= big_count;
+        }
+        while count > 0 {
+            multiply(&mut n, factor);
+            count -= 1;
+        }
+
+        let mut digits = Vec::with_capacity(n.len() * 9);
+        for limb in n.iter().rev() {
+            digits.extend(format!("{:09}", limb).bytes().map(|b| b - b'0'));
+        }
+        let point = digits.len() as isize + exponent.min(0);
+        Self::new(digits, point)
+    }
+
+    fn is_zero(&self) -> bool {
+        self.digits.is_empty()
+    }
+
+    /// The digit at index I of the expansion, which is 0 beyond the ends.
+    fn digit(&self, i: isize) -> u8 {
+        if i < 0 {
+            0
+        } else {
+            self.digits.get(i as usize).cloned().unwrap_or(0)
+        }
+    }
+
+    /// Round to the first LEN digits, which may be no digits at all or
+    /// fewer, with ties going to an even digit.
+    fn round(&mut self, len: isize) {
+        if len >= self.digits.len() as isize {
+            return;
+        }
+        if len < 0 {
+            *self = Self::new(Vec::new(), 0);
+            return;
+        }
+        let len = len as usize;
+        let first = self.digits[len];
+        let rest = self.digits.len() > len + 1;
+        let odd = len > 0 && self.digits[len - 1] % 2 == 1;
+        let up = first > 5 || (first == 5 && (rest || odd));
+        self.digits.truncate(len);
+        if up {
+            let mut i = len;
+            loop {
+                if i == 0 {
+                    self.digits.insert(0, 1);
+                    self.point += 1;
+                    break;
+                }
+                i -= 1;
+                if self.digits[i] == 9 {
+                    self.digits[i] = 0;
+                } else {
+                    self.digits[i] += 1;
+                    break;
+                }
+            }
+        }
+        *self = Self::new(std::mem::replace(&mut self.digits, Vec::new()), self.point);
+    }
+
+    /// The decimal exponent of the first digit after rounding to LEN
+    /// digits, as %e would print it.
+    fn exponent(&self, len: isize) -> isize {
+        if self.is_zero() {
+            return 0;
+        }
+        let mut rounded = self.clone();
+        rounded.round(len);
+        rounded.point - 1
+    }
+
+    /// Append the %f form with PRECISION decimal places to OUT.
+    fn write_fixed(&self, precision: usize, sharp: bool, out: &mut Vec<u8>) {
+        let mut value = self.clone();
+        value.round(self.point.saturating_add(precision as isize));
+        if value.point <= 0 {
+            out.push(b'0');
+        } else {
+            out.extend((0..value.point).map(|i| b'0' + value.digit(i)));
+        }
+        if precision > 0 || sharp {
+            out.push(b'.');
+        }
+        out.extend((0..precision as isize).map(|i| b'0' + value.digit(value.point + i)));
+    }
+
+    /// Append the %e form with PRECISION decimal places to OUT.
+    fn write_exponential(&self, precision: usize, sharp: bool, out: &mut Vec<u8>) {
+        let mut value = self.clone();
+        value.round((precision as isize).saturating_add(1));
+        out.push(b'0' + value.digit(0));
+        if precision > 0 || sharp {
+            out.push(b'.');
+        }
+        out.extend((1..=precision as isize).map(|i| b'0' + value.digit(i)));
+        let exponent = if value.is_zero() { 0 } else { value.point - 1 };
+        out.push(b'e');
+        out.push(if exponent < 0 { b'-' } else { b'+' });
+        out.extend(format!("{:02}", exponent.abs()).bytes());
+    }
+
+    /// Append the %g form with PRECISION significant digits to OUT.
+    fn write_general(&self, precision: usize, sharp: bool, out: &mut Vec<u8>) {
+        let precision = precision.max(1) as isize;
+        let exponent = self.exponent(precision);
+        let available = self.digits.len() as isize;
+        let start = out.len();
+        if -4 <= exponent && exponent < precision {
+            let mut places = precision - 1 - exponent;
+            if !sharp {
+                // Trailing zeros are removed below, so do not make them.
+                places = places.min((available - self.point).max(0));
+            }
+            self.write_fixed(places as usize, sharp, out);
+        } else {
+            let mut places = precision - 1;
+            if !sharp {
+                places = places.min((available - 1).max(0));
+            }
+            self.write_exponential(places as usize, sharp, out);
+        }
+        if !sharp {
+            let end = out[start..]
+                .iter()
+                .position(|&b| b == b'e')
+                .map_or(out.len(), |i| start + i);
+            if out[start..end].contains(&b'.') {
+                let mut keep = end;
+                while out[keep - 1] == b'0' {
+                    keep -= 1;
+                }
+                if out[keep - 1] == b'.' {
+                    keep -= 1;
+                }
+                out.drain(keep..end);
+            }
+        }
+    }
+}
+
+/// Append the sign that SPEC gives a number to OUT.
+fn write_sign(spec: &FormatSpec, negative: bool, out: &mut Vec<u8>) {
+    if negative {
+        out.push(b'-');
+    } else if spec.plus {
+        out.push(b'+');
+    } else if spec.space {
+        out.push(b' ');
+    }
+}
+
+/// Append the digits of N in BASE, with at least PRECISION of them, to
+/// OUT.  Zero has no digits if PRECISION is zero.
+fn write_integer_digits(
+    n: u64,
+    base: u64,
+    upper: bool,
+    precision: Option<usize>,
+    out: &mut Vec<u8>,
+) {
+    let digits = match base {
+        8 => format!("{:o}", n),
+        16 if upper => format!("{:X}", n),
+        16 => format!("{:x}", n),
+        _ => n.to_string(),
+    };
+    match precision {
+        Some(0) if n == 0 => {}
+        Some(precision) => {
+            out.extend(std::iter::repeat(b'0').take(precision.saturating_sub(digits.len())));
+            out.extend(digits.bytes());
+        }
+        None => out.extend(digits.bytes()),
+    }
+}
+
+/// Format VALUE according to SPEC, whose conversion must be one of the
+/// numeric conversions "cdioxXefg", padding it to the field width.
+/// Return None if VALUE is a float that %o, %x or %X cannot represent
+/// as an unsigned integer.
+pub fn format_number(spec: &FormatSpec, value: Number) -> Option<Vec<u8>> {
+    let mut spec = *spec;
+    let conversion = spec.conversion;
+    let mut body = Vec::new();
+    match conversion {
+        b'c' => {
+            if spec.precision != Some(0) {
+                if let Number::Integer(c) = value {
+                    body.push(c as u8);
+                }
+            }
+        }
+        b'd' | b'i' | b'o' | b'x' | b'X' => {
+            spec.zero &= spec.precision.is_none();
+            match (conversion, value) {
+                (b'd', Number::Integer(n)) | (b'i', Number::Integer(n)) => {
+                    write_sign(&spec, n < 0, &mut body);
+                    write_integer_digits(
+                        n.wrapping_abs() as u64,
+                        10,
+                        false,
+                        spec.precision,
+                        &mut body,
+                    );
+                }
+                (b'd', Number::Float(x)) | (b'i', Number::Float(x)) => {
+                    // Round the float to an integer, then treat the
+                    // precision as a minimum number of digits.
+                    write_sign(&spec, x.is_sign_negative(), &mut body);
+                    let mut digits = Vec::new();
+                    if x.is_finite() {
+                        Decimal::from_float(x).write_fixed(0, false, &mut digits);
+                    } else {
+                        digits.extend_from_slice(if x.is_nan() { b"nan" } else { b"inf" });
+                    }
+                    let precision = spec.precision.unwrap_or(0);
+                    body.extend(
+                        std::iter::repeat(b'0').take(precision.saturating_sub(digits.len())),
+                    );
+                    body.extend(digits);
+                }
+                (_, value) => {
+                    // Don't sign-extend for octal or hex printing.
+                    let n = match value {
+                        Number::Integer(n) => (n as EmacsUint & INTMASK as EmacsUint) as u64,
+                        Number::Float(x) if 0.0 <= x && x < 18_446_744_073_709_551_616.0 => {
+                            x as u64
+                        }
+                        Number::Float(_) => return None,
+                    };
+                    let mut digits = Vec::new();
+                    let base = if conversion == b'o' { 8 } else { 16 };
+                    write_integer_digits(n, base, conversion == b'X', spec.precision, &mut digits);
+                    if spec.sharp {
+                        if conversion == b'o' && digits.first() != Some(&b'0') {
+                            body.push(b'0');
+                        } else if conversion != b'o' && n != 0 {
+                            body.extend_from_slice(if conversion == b'X' { b"0X" } else { b"0x" });
+                        }
+                    }
+                    body.extend(digits);
+                }
+            }
+        }
+        _ => {
+            let (negative, decimal) = match value {
+                Number::Integer(n) => (n < 0, Some(Decimal::from_integer(n.wrapping_abs() as u64))),
+                Number::Float(x) if x.is_finite() => {
+                    (x.is_sign_negative(), Some(Decimal::from_float(x)))
+                }
+                Number::Float(x) => (x.is_sign_negative(), None),
+            };
+            write_sign(&spec, negative, &mut body);
+            match decimal {
+                None => {
+                    let nan = matches!(value, Number::Float(x) if x.is_nan());
+                    body.extend_from_slice(if nan { b"nan" } else { b"inf" });
+                }
+                Some(decimal) => {
+                    let precision = spec.precision.unwrap_or(6);
+                    match conversion {
+                        b'e' => decimal.write_exponential(precision, spec.sharp, &mut body),
+                        b'f' => decimal.write_fixed(precision, spec.sharp, &mut body),
+                        _ => decimal.write_general(precision, spec.sharp, &mut body),
+                    }
+                }
+            }
+        }
+    }
+    Some(pad_number(&spec, body))
+}
+
+/// Pad the formatted number BODY to the width of SPEC.  Zero padding
+/// goes after any sign and "0x" prefix, and only before digits or, as
+/// C's printf does, before a %c character.
+fn pad_number(spec: &FormatSpec, body: Vec<u8>) -> Vec<u8> {
+    let padding = spec.width.saturating_sub(body.len());
+    if padding == 0 {
+        return body;
+    }
+    let signed = match body.first() {
+        Some(b'-') | Some(b'+') | Some(b' ') => 1,
+        _ => 0,
+    };
+    let hex_prefix =
+        body.get(signed) == Some(&b'0') && matches!(body.get(signed + 1), Some(b'x') | Some(b'X'));
+    let prefix = signed + if hex_prefix { 2 } else { 0 };
+    let mut out = Vec::with_capacity(body.len() + padding);
+    if spec.zero
+        && (spec.conversion == b'c' || body.get(prefix).map_or(false, u8::is_ascii_hexdigit))
+    {
+        out.extend_from_slice(&body[..prefix]);
+        out.extend(std::iter::repeat(b'0').take(padding));
+        out.extend_from_slice(&body[prefix..]);
+    } else if spec.minus {
+        out.extend(body);
+        out.extend(std::iter::repeat(b' ').take(padding));
+    } else {
+        out.extend(std::iter::repeat(b' ').take(padding));
+        out.extend(body);
+    }
+    out
+}
+
+/// The largest number of bytes a string can have.
+const STRING_BYTES_BOUND: usize = MOST_POSITIVE_FIXNUM as usize;
+
+/// Left and right single quotation marks in UTF-8, which is also their
+/// multibyte form.
+const LEFT_QUOTE: &[u8] = b"\xE2\x80\x98";
+const RIGHT_QUOTE: &[u8] = b"\xE2\x80\x99";
+
+/// What is known about the argument of each %-sequence.
+#[derive(Clone, Copy)]
+struct SpecInfo {
+    /// The argument, converted to a string if that was needed.
+    argument: LispObject,
+    /// The character positions of the output in the result.
+    start: usize,
+    end: usize,
+    /// Whether the argument is a string with text properties.
+    intervals: bool,
+}
+
+/// How one scan through the format string ended.
+enum Scan {
+    Finished,
+    /// The result must be multibyte, which was not known at the start.
+    Retry,
+    /// The result is this object, without any copying.
+    Return(LispObject),
+}
+
+struct StyledFormat<'a> {
+    args: &'a [LispObject],
+    format: LispStringRef,
+    /// For `format-named', the names of the arguments after the first.
+    names: Option<&'a [Vec<u8>]>,
+    multibyte: bool,
+    quoting_style: Option<text_quoting_style::Type>,
+    /// Kept across retries, so that no argument is converted twice.
+    specs: Vec<SpecInfo>,
+    buf: Vec<u8>,
+    nchars: usize,
+    /// discarded[I] is 1 if byte I of the format string was not copied
+    /// into the output, and 2 if it was not the first byte of its
+    /// character.
+    discarded: Vec<u8>,
+    /// Whether the result differs from the format string.
+    new_result: bool,
+    /// Whether a byte might have combined with a preceding multibyte
+    /// character, so that `nchars` is unreliable.
+    maybe_combine_byte: bool,
+}
+
+impl<'a> StyledFormat<'a> {
+    fn push_padding(&mut self, padding: usize) {
+        self.buf.extend(std::iter::repeat(b' ').take(padding));
+        self.nchars += padding;
+    }
+
+    /// Append the first NBYTES bytes of the string ARG, converting raw
+    /// bytes to multibyte form if the result is multibyte.
+    fn push_string(&mut self, arg: LispStringRef, nbytes: usize) {
+        let bytes = &arg.as_slice()[..nbytes];
+        if self.multibyte && !arg.is_multibyte() {
+            let mut char_buf = [0; MAX_MULTIBYTE_LENGTH];
+            for &b in bytes {
+                let len = Codepoint::from_raw(b).write_to(&mut char_buf);
+                self.buf.extend_from_slice(&char_buf[..len]);
+            }
+        } else {
+            self.buf.extend_from_slice(bytes);
+        }
+    }
+
+    fn scan(&mut self) -> Scan {
+        let format = self.format;
+        let bytes = format.as_slice();
+        let multibyte_format = format.is_multibyte();
+        let end = bytes.len();
+
+        self.buf.clear();
+        self.nchars = 0;
+        self.discarded.clear();
+        self.discarded.resize(end, 0);
+        self.maybe_combine_byte = false;
+
+        // N is the argument index, ISPEC is the specification index.
+        let mut n: isize = 0;
+        let mut ispec = 0;
+        let mut pos = 0;
+
+        while pos < end {
+            let format0 = pos;
+            let format_char = bytes[pos];
+            pos += 1;
+
+            if format_char == b'%' {
+                let mut unnamed = false;
+                if let Some(names) = self.names {
+                    if bytes.get(pos) == Some(&b'{') {
+                        let (name, len) = parse_field_name(&bytes[pos..]).unwrap_or_else(|| {
+                            error!("Format string ends in middle of format specifier");
+                        });
+                        let index = names.iter().position(|n| n[..] == *name);
+                        let index = index.unwrap_or_else(|| {
+                            error!(
+                                "No value for format field {}",
+                                String::from_utf8_lossy(name)
+                            );
+                        });
+                        n = index as isize;
+                        pos += len;
+                    } else {
+                        unnamed = true;
+                    }
+                } else if let Some((num, len)) = parse_field_number(&bytes[pos..]) {
+                    n = num as isize - 1;
+                    pos += len;
+                }
+                let (mut spec, len) = parse_spec(&bytes[pos..]).unwrap_or_else(|| {
+                    error!("Format string ends in middle of format specifier");
+                });
+                pos += len;
+                if spec.width >= STRING_BYTES_BOUND {
+                    unsafe { string_overflow() };
+                }
+                let conversion = spec.conversion;
+                let copied = if conversion == b'%' { 1 } else { 0 };
+                for d in &mut self.discarded[format0..pos - copied] {
+                    *d = 1;
+                }
+                if conversion == b'%' {
+                    self.new_result = true;
+                    self.buf.push(b'%');
+                    self.nchars += 1;
+                    continue;
+                }
+                if unnamed {
+                    error!("Format directive without a field name");
+                }
+
+                n += 1;
+                if n >= self.args.len() as isize {
+                    error!("Not enough arguments for format string");
+                }
+                let n = n as usize;
+
+                if self.specs.len() <= ispec {
+                    self.specs.push(SpecInfo {
+                        argument: self.args[n],
+                        start: 0,
+                        end: 0,
+                        intervals: false,
+                    });
+                }
+                let mut arg = self.specs[ispec].argument;
+
+                // For 'S', prin1 the argument, and then treat like 's'.
+                // For 's', princ any argument that is not a string or
+                // symbol.  But don't do this conversion twice, which might
+                // happen after retrying.
+                if conversion == b'S'
+                    || (conversion == b's' && !arg.is_string() && !arg.is_symbol())
+                {
+                    if arg.eq(self.args[n]) {
+                        let noescape = if conversion == b'S' { Qnil } else { Qt };
+                        arg = unsafe { Fprin1_to_string(arg, noescape) };
+                        self.specs[ispec].argument = arg;
+                        if arg.force_string().is_multibyte() && !self.multibyte {
+                            self.multibyte = true;
+                            return Scan::Retry;
+                        }
+                    }
+                    spec.conversion = b's';
+                } else if conversion == b'c' {
+                    if arg.as_fixnum().map_or(false, |c| !(0..0x80).contains(&c)) {
+                        if !self.multibyte {
+                            self.multibyte = true;
+                            return Scan::Retry;
+                        }
+                        arg = char_to_string(Codepoint::from(arg));
+                        self.specs[ispec].argument = arg;
+                    }
+                    if !arg.eq(self.args[n]) {
+                        spec.conversion = b's';
+                    }
+                }
+
+                if let Some(symbol) = arg.as_symbol() {
+                    arg = symbol.symbol_name();
+                    self.specs[ispec].argument = arg;
+                    if arg.force_string().is_multibyte() && !self.multibyte {
+                        self.multibyte = true;
+                        return Scan::Retry;
+                    }
+                }
+
+                if spec.conversion == b's' {
+                    if pos == end && pos == 2 && !format.has_intervals() {
+                        return Scan::Return(arg);
+                    }
+
+                    let string = arg.force_string();
+                    let (width, nchars_string, nbytes) = match spec.precision {
+                        // GNU libc functions print nothing when the
+                        // precision is 0; imitate that.
+                        Some(0) => (0, 0, 0),
+                        precision => match string.display_width(precision) {
+                            (width, Some((nchars, nbytes))) => (width, nchars, nbytes),
+                            (width, None) => (
+                                width,
+                                string.len_chars() as usize,
+                                string.len_bytes() as usize,
+                            ),
+                        },
+                    };
+                    let padding = spec.width.saturating_sub(width);
+
+                    if !spec.minus {
+                        self.push_padding(padding);
+                    }
+                    self.specs[ispec].start = self.nchars;
+                    if self.buf.last().map_or(false, |&b| b >= 0x80)
+                        && self.multibyte
+                        && string.is_multibyte()
+                        && string
+                            .as_slice()
+                            .first()
+                            .map_or(false, |&b| !char_head_p(b))
+                    {
+                        self.maybe_combine_byte = true;
+                    }
+                    self.push_string(string, nbytes);
+                    self.nchars += nchars_string;
+                    if spec.minus {
+                        self.push_padding(padding);
+                    }
+                    self.specs[ispec].end = self.nchars;
+
+                    // If this argument has text properties, record where
+                    // in the result string it appears.
+                    if string.has_intervals() {
+                        self.specs[ispec].intervals = true;
+                    }
+                } else if !b"cdefgioxX".contains(&spec.conversion) {
+                    let c = if multibyte_format {
+                        multibyte_char_at(&bytes[pos - 1..]).0
+                    } else {
+                        Codepoint::from(conversion)
+                    };
+                    let c =
+                        std::char::from_u32(c.val()).unwrap_or(std::char::REPLACEMENT_CHARACTER);
+                    error!("Invalid format operation %{}", c);
+                } else {
+                    let value = match (arg.as_fixnum(), arg.as_float()) {
+                        (Some(n), _) => Number::Integer(n),
+                        (None, Some(x)) if spec.conversion != b'c' => Number::Float(x),
+                        _ => error!("Format specifier doesn't match argument type"),
+                    };
+                    // Excess precision would only add zeros, except for a
+                    // %g without the # flag, which drops them again.
+                    if spec.precision.map_or(false, |p| p >= STRING_BYTES_BOUND)
+                        && spec.conversion != b'c'
+                        && (spec.conversion != b'g' || spec.sharp)
+                    {
+                        unsafe { string_overflow() };
+                    }
+                    let text = format_number(&spec, value)
+                        .unwrap_or_else(|| xsignal!(Qoverflow_error, arg));
+                    self.specs[ispec].start = self.nchars;
+                    self.buf.extend_from_slice(&text);
+                    self.nchars += text.len();
+                    self.specs[ispec].end = self.nchars;
+                }
+                self.new_result = true;
+                ispec += 1;
+            } else {
+                match (format_char, self.quoting_style) {
+                    (b'`', Some(text_quoting_style::CURVE_QUOTING_STYLE))
+                    | (b'\'', Some(text_quoting_style::CURVE_QUOTING_STYLE)) => {
+                        if !self.multibyte {
+                            self.multibyte = true;
+                            return Scan::Retry;
+                        }
+                        let quote = if format_char == b'`' {
+                            LEFT_QUOTE
+                        } else {
+                            RIGHT_QUOTE
+                        };
+                        self.buf.extend_from_slice(quote);
+                        self.new_result = true;
+                    }
+                    (b'`', Some(text_quoting_style::STRAIGHT_QUOTING_STYLE)) => {
+                        self.buf.push(b'\'');
+                        self.new_result = true;
+                    }
+                    _ if multibyte_format => {
+                        // Copy a whole multibyte character.
+                        if self.buf.last().map_or(false, |&b| b >= 0x80)
+                            && !char_head_p(format_char)
+                        {
+                            self.maybe_combine_byte = true;
+                        }
+                        while pos < end && !char_head_p(bytes[pos]) {
+                            pos += 1;
+                        }
+                        self.buf.extend_from_slice(&bytes[format0..pos]);
+                        for d in &mut self.discarded[format0 + 1..pos] {
+                            *d = 2;
+                        }
+                    }
+                    _ if self.multibyte && format_char >= 0x80 => {
+                        let mut char_buf = [0; MAX_MULTIBYTE_LENGTH];
+                        let len = Codepoint::from_raw(format_char).write_to(&mut char_buf);
+                        self.buf.extend_from_slice(&char_buf[..len]);
+                        self.new_result = true;
+                    }
+                    _ => self.buf.push(format_char),
+                }
+                self.nchars += 1;
+            }
+        }
+        Scan::Finished
+    }
+
+    /// Translate the character position POS in the format string to one
+    /// in the result, continuing from the state in CURSOR.  Text that
+    /// a %-sequence produced belongs to no position of the format.
+    fn translate(&self, pos: usize, cursor: &mut PropertyCursor) {
+        while cursor.position < pos {
+            match self.discarded[cursor.bytepos] {
+                0 => {
+                    cursor.position += 1;
+                    cursor.translated += 1;
+                }
+                1 => {
+                    cursor.position += 1;
+                    if let Some(spec) = self.specs.get(cursor.fieldn) {
+                        if cursor.translated == spec.start {
+                            cursor.translated += spec.end - spec.start;
+                            cursor.fieldn += 1;
+                        }
+                    }
+                }
+                _ => {}
+            }
+            cursor.bytepos += 1;
+        }
+    }
+
+    /// Give the result VAL the text properties of the format string and
+    /// of the string arguments.
+    fn add_properties(&self, val: LispObject) {
+        let format = self.format;
+        let arg_intervals = self.specs.iter().any(|spec| spec.intervals);
+        if !format.has_intervals() && !arg_intervals {
+            return;
+        }
+
+        // Add text properties from the format string.
+        let len = LispObject::from(format.len_chars());
+        let props = unsafe { text_property_list(format.into(), 0.into(), len, Qnil) };
+        if props.is_cons() {
+            // Put the positions in PROPS in increasing order, so that we
+            // can do (effectively) one scan through the position space of
+            // the format string.
+            let props = nreverse(props);
+            let mut cursor = PropertyCursor::default();
+            for item in props.iter_cars(LispConsEndChecks::off, LispConsCircularChecks::off) {
+                let start = item.force_cons();
+                let end = start.cdr().force_cons();
+                self.translate(start.car().force_fixnum() as usize, &mut cursor);
+                start.set_car(cursor.translated);
+                self.translate(end.car().force_fixnum() as usize, &mut cursor);
+                end.set_car(cursor.translated);
+            }
+            unsafe { add_text_properties_from_list(val, props, 0.into()) };
+        }
+
+        // Add text properties from arguments.
+        for (i, spec) in self.specs.iter().enumerate() {
+            if !spec.intervals {
+                continue;
+            }
+            let len = LispObject::from(spec.argument.force_string().len_chars());
+            let new_len = LispObject::from(spec.end - spec.start);
+            unsafe {
+                let props = text_property_list(spec.argument, 0.into(), len, Qnil);
+                let props = extend_property_ranges(props, len, new_len);
+                // If successive arguments have properties, be sure that
+                // the value of `composition' property be the copy.
+                if 1 < i && self.specs[i - 1].end != 0 {
+                    make_composition_value_copy(props);
+                }
+                add_text_properties_from_list(val, props, spec.start.into());
+            }
+        }
+    }
+}
+
+/// Where `StyledFormat::translate` has got to in the format string.
+#[derive(Default)]
+struct PropertyCursor {
+    /// The byte position in the format string.
+    bytepos: usize,
+    /// The character position in the format string.
+    position: usize,
+    /// The character position in the result.
+    translated: usize,
+    /// The number of the next %-sequence to come to.
+    fieldn: usize,
+}
+
+/// Implement `format-message' if MESSAGE is true, `format' otherwise.
+pub fn styled_format(args: &[LispObject], message: bool) -> LispObject {
+    run(args, None, message)
+}
+
+/// Implement `format-named', where NAMES are the names of the arguments
+/// after the format string.
+pub fn named_format(args: &[LispObject], names: &[Vec<u8>]) -> LispObject {
+    debug_assert_eq!(args.len(), names.len() + 1);
+    run(args, Some(names), false)
+}
+
+fn run(args: &[LispObject], names: Option<&[Vec<u8>]>, message: bool) -> LispObject {
+    let format: LispStringRef = args[0].into();
+
+    // The output is multibyte if any of the inputs is.  Sometimes it
+    // needs to be multibyte for other reasons, such as an object that
+    // prin1 prints with multibyte characters, and a scan that finds that
+    // out starts again.
+    let multibyte = format.is_multibyte()
+        || args[1..]
+            .iter()
+            .any(|arg| arg.as_string().map_or(false, |s| s.is_multibyte()));
+
+    let mut state = StyledFormat {
+        args,
+        format,
+        names,
+        multibyte,
+        quoting_style: if message {
+            Some(unsafe { text_quoting_style() })
+        } else {
+            None
+        },
+        specs: Vec::new(),
+        buf: Vec::new(),
+        nchars: 0,
+        discarded: Vec::new(),
+        new_result: false,
+        maybe_combine_byte: false,
+    };
+
+    loop {
+        match state.scan() {
+            Scan::Finished => break,
+            Scan::Retry => continue,
+            Scan::Return(val) => return val,
+        }
+    }
+
+    if !state.new_result {
+        return args[0];
+    }
+
+    let nbytes = state.buf.len();
+    if state.maybe_combine_byte {
+        state.nchars =
+            unsafe { multibyte_chars_in_text(state.buf.as_ptr(), nbytes as isize) } as usize;
+    }
+    let val = unsafe {
+        make_specified_string(
+            state.buf.as_ptr() as *const c_char,
+            state.nchars as isize,
+            nbytes as isize,
+            state.multibyte,
+        )
+    };
+    state.add_properties(val);
+    val
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(cases: &[(&str, Number, &str)]) {
+        for &(format, value, expected) in cases {
+            let (spec, len) = parse_spec(&format.as_bytes()[1..]).unwrap();
+            assert_eq!(len, format.len() - 1, "{}", format);
+            let result = format_number(&spec, value).unwrap();
+            assert_eq!(
+                String::from_utf8(result).unwrap(),
+                expected,
+                "{} {:?}",
+                format,
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_spec() {
+        let (spec, len) = parse_spec(b"-+ #012.5dxyz").unwrap();
+        assert_eq!(len, 10);
+        assert_eq!(
+            spec,
+            FormatSpec {
+                minus: true,
+                plus: true,
+                space: false,
+                sharp: true,
+                zero: false,
+                width: 12,
+                precision: Some(5),
+                conversion: b'd',
+            }
+        );
+        let (spec, _) = parse_spec(b".s").unwrap();
+        assert_eq!(spec.precision, Some(0));
+        assert_eq!(parse_spec(b"5.3"), None);
+        assert_eq!(parse_spec(b""), None);
+
+        assert_eq!(parse_field_name(b"{count}5d"), Some((&b"count"[..], 7)));
+        assert_eq!(parse_field_name(b"{}s"), Some((&b""[..], 2)));
+        assert_eq!(parse_field_name(b"{count"), None);
+
+        assert_eq!(parse_field_number(b"12$s"), Some((12, 3)));
+        assert_eq!(parse_field_number(b"12s"), None);
+        assert_eq!(parse_field_number(b"$s"), None);
+        assert_eq!(
+            parse_field_number(b"99999999999999999999999$"),
+            Some((isize::max_value() as usize, 24))
+        );
+    }
+
+    #[test]
+    fn test_format_integers() {
+        use self::Number::Integer;
+        check(&[
+            ("%d", Integer(42), "42"),
+            ("%d", Integer(-42), "-42"),
+            ("%5d", Integer(42), "   42"),
+            ("%-5d", Integer(42), "42   "),
+            ("%05d", Integer(-42), "-0042"),
+            ("%+d", Integer(42), "+42"),
+            ("% d", Integer(42), " 42"),
+            ("%+ d", Integer(42), "+42"),
+            ("%.5d", Integer(42), "00042"),
+            ("%08.5d", Integer(42), "   00042"),
+            ("%.0d", Integer(0), ""),
+            ("%3.0d", Integer(0), "   "),
+            ("%#d", Integer(42), "42"),
+            ("%i", Integer(-7), "-7"),
+            (
+                "%d",
+                Integer(2_305_843_009_213_693_951),
+                "2305843009213693951",
+            ),
+            ("%o", Integer(8), "10"),
+            ("%#o", Integer(8), "010"),
+            ("%#o", Integer(0), "0"),
+            ("%#.0o", Integer(0), "0"),
+            ("%#.3o", Integer(8), "010"),
+            ("%x", Integer(255), "ff"),
+            ("%X", Integer(255), "FF"),
+            ("%#x", Integer(255), "0xff"),
+            ("%#X", Integer(255), "0XFF"),
+            ("%#x", Integer(0), "0"),
+            ("%#08x", Integer(255), "0x0000ff"),
+            ("%+x", Integer(255), "ff"),
+            ("%x", Integer(-1), "3fffffffffffffff"),
+            ("%o", Integer(-1), "377777777777777777777"),
+            ("%c", Integer(97), "a"),
+            ("%3c", Integer(97), "  a"),
+            ("%-3c", Integer(97), "a  "),
+            ("%03c", Integer(97), "00a"),
+            ("%03c", Integer(122), "00z"),
+            ("%.0c", Integer(97), ""),
+            ("%e", Integer(12345), "1.234500e+04"),
+            ("%.2f", Integer(-3), "-3.00"),
+            ("%g", Integer(1_000_000), "1e+06"),
+            (
+                "%.20e",
+                Integer(1_152_921_504_606_846_977),
+                "1.15292150460684697700e+18",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn test_format_floats() {
+        use self::Number::Float;
+        check(&[
+            ("%f", Float(std::f64::consts::PI), "3.141593"),
+            ("%.2f", Float(std::f64::consts::PI), "3.14"),
+            ("%06.2f", Float(std::f64::consts::PI), "003.14"),
+            ("%-8.2f", Float(-std::f64::consts::PI), "-3.14   "),
+            ("%+.1f", Float(2.0), "+2.0"),
+            ("% .1f", Float(2.0), " 2.0"),
+            ("%.0f", Float(0.5), "0"),
+            ("%.0f", Float(1.5), "2"),
+            ("%.0f", Float(2.5), "2"),
+            ("%.1f", Float(0.25), "0.2"),
+            ("%.1f", Float(0.35), "0.3"),
+            ("%.2f", Float(1.005), "1.00"),
+            ("%#.0f", Float(3.0), "3."),
+            ("%.1f", Float(-0.01), "-0.0"),
+            ("%f", Float(-0.0), "-0.000000"),
+            ("%f", Float(1e20), "100000000000000000000.000000"),
+            ("%.30f", Float(0.1), "0.100000000000000005551115123126"),
+            ("%e", Float(12345.678), "1.234568e+04"),
+            ("%.0e", Float(12345.0), "1e+04"),
+            ("%#.0e", Float(12345.0), "1.e+04"),
+            ("%e", Float(0.0), "0.000000e+00"),
+            ("%.3e", Float(9.9996), "1.000e+01"),
+            ("%e", Float(1e-300), "1.000000e-300"),
+            ("%e", Float(5e-324), "4.940656e-324"),
+            ("%e", Float(1.797_693_134_862_315_7e308), "1.797693e+308"),
+            ("%012.3e", Float(-1234.5), "-001.234e+03"),
+            ("%g", Float(0.0001), "0.0001"),
+            ("%g", Float(0.00001), "1e-05"),
+            ("%g", Float(123_456.0), "123456"),
+            ("%g", Float(1_234_567.0), "1.23457e+06"),
+            ("%g", Float(100.0), "100"),
+            ("%g", Float(0.0), "0"),
+            ("%g", Float(-0.0), "-0"),
+            ("%.0g", Float(123.0), "1e+02"),
+            ("%.1g", Float(0.95), "0.9"),
+            ("%.2g", Float(9.96), "10"),
+            ("%#g", Float(1.0), "1.00000"),
+            ("%#.3g", Float(100_000.0), "1.00e+05"),
+            ("%.17g", Float(0.1), "0.10000000000000001"),
+            ("%g", Float(1e100), "1e+100"),
+            ("%10.4g", Float(std::f64::consts::PI), "     3.142"),
+            ("%f", Float(std::f64::INFINITY), "inf"),
+            ("%+e", Float(std::f64::INFINITY), "+inf"),
+            ("%06f", Float(std::f64::NEG_INFINITY), "  -inf"),
+            ("%g", Float(std::f64::NAN), "nan"),
+            ("%-5f", Float(-std::f64::NAN), "-nan "),
+            ("%d", Float(2.5), "2"),
+            ("%d", Float(2.7), "3"),
+            ("%d", Float(-0.4), "-0"),
+            ("%.4d", Float(42.0), "0042"),
+            ("%05d", Float(-42.0), "-0042"),
+            ("%+d", Float(1e20), "+100000000000000000000"),
+            ("%d", Float(std::f64::INFINITY), "inf"),
+            ("%x", Float(255.9), "ff"),
+            ("%#o", Float(8.0), "010"),
+            ("%X", Float(1.8e19), "F9CCD8A1C5080000"),
+        ]);
+        let spec = parse_spec(b"x").unwrap().0;
+        assert_eq!(format_number(&spec, Float(-1.0)), None);
+        assert_eq!(format_number(&spec, Float(1.9e19)), None);
+        assert_eq!(format_number(&spec, Float(std::f64::NAN)), None);
+    }
+}
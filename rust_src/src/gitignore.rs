@@ -0,0 +1,334 @@
+//! Matching of file names against `.gitignore` style ignore files.
+
+use std::fs;
+use std::path::Path;
+
+/// The ignore files honoured in each directory, in increasing order of
+/// precedence.
+const IGNORE_FILE_NAMES: [&str; 2] = [".gitignore", ".ignore"];
+
+/// A single pattern line from an ignore file.
+#[derive(Debug, PartialEq)]
+struct Pattern {
+    /// The glob, without any leading `!` or `/` and trailing `/`.
+    glob: Vec<u8>,
+    /// The pattern started with `!`, so a match re-includes the file.
+    negated: bool,
+    /// The pattern ended with `/`, so it only matches directories.
+    dir_only: bool,
+    /// The pattern contains a `/`, so it is matched against the path
+    /// relative to the ignore file rather than against the file name.
+    anchored: bool,
+}
+
+impl Pattern {
+    /// Parses one line of an ignore file.  Returns [`None`] for blank
+    /// lines and comments.
+    fn parse(line: &str) -> Option<Self> {
+        let mut line = line.trim_end_matches('\r');
+
+        // Trailing spaces are ignored unless they are quoted with a backslash.
+        while line.ends_with(' ') && !line.ends_with("\\ ") {
+            line = &line[..line.len() - 1];
+        }
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let negated = line.starts_with('!');
+        if negated {
+            line = &line[1..];
+        } else if line.starts_with("\\!") || line.starts_with("\\#") {
+            line = &line[1..];
+        }
+
+        let dir_only = line.ends_with('/');
+        let line = line.trim_end_matches('/');
+        if line.is_empty() {
+            return None;
+        }
+
+        let anchored = line.contains('/');
+        let glob = line.trim_start_matches('/').as_bytes().to_vec();
+
+        Some(Self {
+            glob,
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+
+    /// Returns true if this pattern matches the file at `rel`, a path
+    /// relative to the directory of the ignore file.
+    fn matches(&self, rel: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+
+        let subject = if self.anchored {
+            rel
+        } else {
+            rel.rsplit('/').next().unwrap_or(rel)
+        };
+
+        glob_match(&self.glob, subject.as_bytes())
+    }
+}
+
+/// The patterns of the ignore files found in one directory.
+struct IgnoreFile {
+    /// The directory the ignore files live in, relative to the root of
+    /// the walk.  Empty for the root itself.
+    base: String,
+    patterns: Vec<Pattern>,
+}
+
+/// A stack of ignore files, one entry per directory being walked.
+#[derive(Default)]
+pub struct IgnoreRules {
+    files: Vec<IgnoreFile>,
+}
+
+impl IgnoreRules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the ignore files in DIR, which is at REL relative to the root
+    /// of the walk, and pushes their patterns.  Returns true if anything
+    /// was pushed, in which case the caller should call [`Self::pop`]
+    /// once it is done with DIR.
+    pub fn push_dir(&mut self, dir: &Path, rel: &str) -> bool {
+        let patterns: Vec<Pattern> = IGNORE_FILE_NAMES
+            .iter()
+            .filter_map(|name| fs::read_to_string(dir.join(name)).ok())
+            .flat_map(|contents| {
+                contents
+                    .lines()
+                    .filter_map(Pattern::parse)
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+
+        if patterns.is_empty() {
+            return false;
+        }
+
+        self.files.push(IgnoreFile {
+            base: rel.to_string(),
+            patterns,
+        });
+        true
+    }
+
+    pub fn pop(&mut self) {
+        self.files.pop();
+    }
+
+    /// Returns true if the file at REL, relative to the root of the walk,
+    /// is ignored.  Patterns in deeper directories take precedence, and
+    /// within one directory the last matching pattern wins.
+    pub fn is_ignored(&self, rel: &str, is_dir: bool) -> bool {
+        for file in self.files.iter().rev() {
+            let local = if file.base.is_empty() {
+                rel
+            } else {
+                match rel
+                    .strip_prefix(file.base.as_str())
+                    .and_then(|r| r.strip_prefix('/'))
+                {
+                    Some(local) => local,
+                    None => continue,
+                }
+            };
+
+            if let Some(pattern) = file
+                .patterns
+                .iter()
+                .rev()
+                .find(|p| p.matches(local, is_dir))
+            {
+                return !pattern.negated;
+            }
+        }
+
+        false
+    }
+}
+
+/// Matches TEXT against the gitignore glob PAT.  `*` and `?` do not match
+/// `/`, while `**` between slashes matches any number of directories.
+fn glob_match(pat: &[u8], text: &[u8]) -> bool {
+    match pat.first() {
+        None => text.is_empty(),
+        Some(b'*') if pat.get(1) == Some(&b'*') => {
+            let rest = &pat[2..];
+            match rest.first() {
+                // A trailing `**` matches everything.
+                None => true,
+                // `**/` matches zero or more directories.
+                Some(b'/') => {
+                    let rest = &rest[1..];
+                    glob_match(rest, text)
+                        || text
+                            .iter()
+                            .enumerate()
+                            .any(|(i, &c)| c == b'/' && glob_match(rest, &text[i + 1..]))
+                }
+                // Anywhere else `**` is just like `*`.
+                Some(_) => glob_star(rest, text),
+            }
+        }
+        Some(b'*') => glob_star(&pat[1..], text),
+        Some(b'?') => match text.first() {
+            Some(&c) if c != b'/' => glob_match(&pat[1..], &text[1..]),
+            _ => false,
+        },
+        Some(b'[') => match (parse_class(&pat[1..]), text.first()) {
+            (Some((matcher, len)), Some(&c)) => {
+                c != b'/' && matcher(c) && glob_match(&pat[len + 1..], &text[1..])
+            }
+            (Some(_), None) => false,
+            // An unterminated class is a literal `[`.
+            (None, _) => text.first() == Some(&b'[') && glob_match(&pat[1..], &text[1..]),
+        },
+        Some(b'\\') if pat.len() > 1 => {
+            text.first() == Some(&pat[1]) && glob_match(&pat[2..], &text[1..])
+        }
+        Some(&c) => text.first() == Some(&c) && glob_match(&pat[1..], &text[1..]),
+    }
+}
+
+/// Matches a single `*` followed by REST against TEXT.
+fn glob_star(rest: &[u8], text: &[u8]) -> bool {
+    for i in 0..=text.len() {
+        if glob_match(rest, &text[i..]) {
+            return true;
+        }
+        if i < text.len() && text[i] == b'/' {
+            break;
+        }
+    }
+    false
+}
+
+/// Parses a bracket expression starting just after the `[` in PAT.
+/// Returns a predicate for the bytes it matches and the length of the
+/// expression including the closing `]`.
+fn parse_class(pat: &[u8]) -> Option<(impl Fn(u8) -> bool, usize)> {
+    let mut idx = 0;
+    let negated = match pat.first() {
+        Some(b'!') | Some(b'^') => {
+            idx += 1;
+            true
+        }
+        _ => false,
+    };
+
+    let mut ranges = Vec::new();
+    let mut first = true;
+    loop {
+        let c = *pat.get(idx)?;
+        if c == b']' && !first {
+            break;
+        }
+        first = false;
+
+        let c = if c == b'\\' {
+            idx += 1;
+            *pat.get(idx)?
+        } else {
+            c
+        };
+
+        if pat.get(idx + 1) == Some(&b'-') && pat.get(idx + 2).map_or(false, |&e| e != b']') {
+            ranges.push((c, pat[idx + 2]));
+            idx += 3;
+        } else {
+            ranges.push((c, c));
+            idx += 1;
+        }
+    }
+
+    let matcher = move |c: u8| ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != negated;
+    Some((matcher, idx + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(base: &str, lines: &[&str]) -> IgnoreRules {
+        IgnoreRules {
+            files: vec![IgnoreFile {
+                base: base.to_string(),
+                patterns: lines.iter().filter_map(|l| Pattern::parse(l)).collect(),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_parse_pattern() {
+        assert_eq!(Pattern::parse(""), None);
+        assert_eq!(Pattern::parse("# comment"), None);
+        assert_eq!(
+            Pattern::parse("!/build/  "),
+            Some(Pattern {
+                glob: b"build".to_vec(),
+                negated: true,
+                dir_only: true,
+                anchored: true,
+            })
+        );
+        assert_eq!(
+            Pattern::parse("\\#file"),
+            Some(Pattern {
+                glob: b"#file".to_vec(),
+                negated: false,
+                dir_only: false,
+                anchored: false,
+            })
+        );
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match(b"*.o", b"main.o"));
+        assert!(!glob_match(b"*.o", b"src/main.o"));
+        assert!(glob_match(b"src/*.o", b"src/main.o"));
+        assert!(glob_match(b"**/foo", b"foo"));
+        assert!(glob_match(b"**/foo", b"a/b/foo"));
+        assert!(glob_match(b"a/**/b", b"a/b"));
+        assert!(glob_match(b"a/**/b", b"a/x/y/b"));
+        assert!(glob_match(b"a/**", b"a/x/y"));
+        assert!(glob_match(b"file?.[ch]", b"file1.c"));
+        assert!(!glob_match(b"file?.[!ch]", b"file1.c"));
+        assert!(glob_match(b"[a-c]x", b"bx"));
+        assert!(glob_match(b"[x", b"[x"));
+    }
+
+    #[test]
+    fn test_is_ignored() {
+        let r = rules("", &["*.log", "!keep.log", "build/", "/top"]);
+        assert!(r.is_ignored("debug.log", false));
+        assert!(r.is_ignored("sub/debug.log", false));
+        assert!(!r.is_ignored("keep.log", false));
+        assert!(r.is_ignored("build", true));
+        assert!(!r.is_ignored("build", false));
+        assert!(r.is_ignored("top", false));
+        assert!(!r.is_ignored("sub/top", false));
+    }
+
+    #[test]
+    fn test_nested_precedence() {
+        let mut r = rules("", &["*.txt"]);
+        r.files.push(IgnoreFile {
+            base: "docs".to_string(),
+            patterns: vec![Pattern::parse("!*.txt").unwrap()],
+        });
+        assert!(r.is_ignored("notes.txt", false));
+        assert!(!r.is_ignored("docs/notes.txt", false));
+        assert!(r.is_ignored("docsx/notes.txt", false));
+    }
+}
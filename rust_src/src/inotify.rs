@@ -0,0 +1,1072 @@
+//! Inotify support, with a polling fallback.
+//!
+//! Watches are normally inotify watches.  When the kernel cannot give
+//! us one (Emacs was built without inotify, inotify is unavailable, or
+//! the per-user watch limit has been reached), or `inotify-use-polling'
+//! is non-nil, the file is instead polled by a background thread.
+//! That thread writes the changes it sees to a datagram socket as
+//! `struct inotify_event` records, one per datagram, so both kinds of
+//! watch are delivered to Lisp by the same code.
+
+use std::collections::HashMap;
+use std::ffi::{CStr, OsStr, OsString};
+use std::fs;
+use std::io;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+use std::ptr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+use libc::{c_char, c_int, c_void};
+
+use remacs_macros::lisp_fn;
+
+use crate::{
+    coding::encode_file_name,
+    fns::provide,
+    lisp::LispObject,
+    lists::{nth, LispCons, LispConsCircularChecks, LispConsEndChecks},
+    multibyte::LispStringRef,
+    numbers::MOST_POSITIVE_FIXNUM,
+    obarray::intern,
+    remacs_sys::{
+        add_read_fd, decode_file_name, delete_read_fd, emacs_close, event_kind, globals,
+        input_event, kbd_buffer_store_event, make_unibyte_string, EmacsInt,
+    },
+    remacs_sys::{
+        Qaccess, Qall_events, Qattrib, Qclose, Qclose_nowrite, Qclose_write, Qcreate, Qdelete,
+        Qdelete_self, Qdont_follow, Qignored, Qisdir, Qmodify, Qmove, Qmove_self, Qmoved_from,
+        Qmoved_to, Qonlydir, Qopen, Qq_overflow, Qunmount,
+    },
+    remacs_sys::{Qfile_notify_error, Qnil, Qt},
+};
+
+/// The parts of <sys/inotify.h> we need.
+mod sys {
+    use libc::c_int;
+
+    pub const IN_ACCESS: u32 = 0x0000_0001;
+    pub const IN_MODIFY: u32 = 0x0000_0002;
+    pub const IN_ATTRIB: u32 = 0x0000_0004;
+    pub const IN_CLOSE_WRITE: u32 = 0x0000_0008;
+    pub const IN_CLOSE_NOWRITE: u32 = 0x0000_0010;
+    pub const IN_OPEN: u32 = 0x0000_0020;
+    pub const IN_MOVED_FROM: u32 = 0x0000_0040;
+    pub const IN_MOVED_TO: u32 = 0x0000_0080;
+    pub const IN_CREATE: u32 = 0x0000_0100;
+    pub const IN_DELETE: u32 = 0x0000_0200;
+    pub const IN_DELETE_SELF: u32 = 0x0000_0400;
+    pub const IN_MOVE_SELF: u32 = 0x0000_0800;
+    pub const IN_UNMOUNT: u32 = 0x0000_2000;
+    pub const IN_Q_OVERFLOW: u32 = 0x0000_4000;
+    pub const IN_IGNORED: u32 = 0x0000_8000;
+    pub const IN_ONLYDIR: u32 = 0x0100_0000;
+    pub const IN_DONT_FOLLOW: u32 = 0x0200_0000;
+    pub const IN_EXCL_UNLINK: u32 = 0x0400_0000;
+    pub const IN_MASK_ADD: u32 = 0x2000_0000;
+    pub const IN_ISDIR: u32 = 0x4000_0000;
+
+    pub const IN_CLOSE: u32 = IN_CLOSE_WRITE | IN_CLOSE_NOWRITE;
+    pub const IN_MOVE: u32 = IN_MOVED_FROM | IN_MOVED_TO;
+    pub const IN_ALL_EVENTS: u32 = 0x0000_0fff;
+
+    #[cfg(feature = "inotify")]
+    pub const IN_CLOEXEC: c_int = libc::O_CLOEXEC;
+    #[cfg(feature = "inotify")]
+    pub const IN_NONBLOCK: c_int = libc::O_NONBLOCK;
+
+    /// The fixed-size header of `struct inotify_event`.  It is followed
+    /// by LEN bytes holding the NUL-padded file name.
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    pub struct InotifyEvent {
+        pub wd: c_int,
+        pub mask: u32,
+        pub cookie: u32,
+        pub len: u32,
+    }
+
+    #[cfg(feature = "inotify")]
+    extern "C" {
+        pub fn inotify_init1(flags: c_int) -> c_int;
+        pub fn inotify_add_watch(fd: c_int, pathname: *const libc::c_char, mask: u32) -> c_int;
+        pub fn inotify_rm_watch(fd: c_int, wd: c_int) -> c_int;
+    }
+}
+
+use self::sys::{
+    InotifyEvent, IN_ACCESS, IN_ALL_EVENTS, IN_ATTRIB, IN_CLOSE, IN_CLOSE_NOWRITE, IN_CLOSE_WRITE,
+    IN_CREATE, IN_DELETE, IN_DELETE_SELF, IN_DONT_FOLLOW, IN_EXCL_UNLINK, IN_IGNORED, IN_ISDIR,
+    IN_MASK_ADD, IN_MODIFY, IN_MOVE, IN_MOVED_FROM, IN_MOVED_TO, IN_MOVE_SELF, IN_ONLYDIR, IN_OPEN,
+    IN_Q_OVERFLOW, IN_UNMOUNT,
+};
+
+/// File handle for inotify.
+static mut INOTIFY_FD: c_int = -1;
+
+/// The thread polling the watches inotify could not handle, if any.
+static mut POLLER: Option<Poller> = None;
+
+// Alist of files being watched.  We want the returned descriptor to
+// be unique for every watch, but inotify returns the same descriptor
+// WD for multiple calls to inotify_add_watch with the same file.
+// Supply a nonnegative integer ID, so that WD and ID together
+// uniquely identify a watch/file combination.
+//
+// For the same reason, we also need to store the watch's mask and we
+// can't allow the following flags to be used.
+//
+// IN_EXCL_UNLINK
+// IN_MASK_ADD
+// IN_ONESHOT
+//
+// Each element of this list is of the form (DESCRIPTOR . WATCHES)
+// where no two DESCRIPTOR values are the same.  DESCRIPTOR represents
+// the inotify watch descriptor and WATCHES is a list with elements of
+// the form (ID FILENAME CALLBACK MASK), where ID is the integer
+// described above, FILENAME names the file being watched, CALLBACK is
+// invoked when the event occurs, and MASK represents the aspects
+// being watched.  The WATCHES list is sorted by ID.
+declare_GC_protected_static!(watch_list, Qnil);
+
+/// The aspect symbols reported in events, paired with their bits.
+fn event_aspects() -> [(u32, LispObject); 16] {
+    [
+        (IN_ACCESS, Qaccess),
+        (IN_ATTRIB, Qattrib),
+        (IN_CLOSE_WRITE, Qclose_write),
+        (IN_CLOSE_NOWRITE, Qclose_nowrite),
+        (IN_CREATE, Qcreate),
+        (IN_DELETE, Qdelete),
+        (IN_DELETE_SELF, Qdelete_self),
+        (IN_MODIFY, Qmodify),
+        (IN_MOVE_SELF, Qmove_self),
+        (IN_MOVED_FROM, Qmoved_from),
+        (IN_MOVED_TO, Qmoved_to),
+        (IN_OPEN, Qopen),
+        (IN_IGNORED, Qignored),
+        (IN_ISDIR, Qisdir),
+        (IN_Q_OVERFLOW, Qq_overflow),
+        (IN_UNMOUNT, Qunmount),
+    ]
+}
+
+fn mask_to_aspects(mask: u32) -> LispObject {
+    event_aspects()
+        .iter()
+        .filter(|&&(bit, _)| mask & bit != 0)
+        .fold(Qnil, |aspects, &(_, symbol)| {
+            LispObject::cons(symbol, aspects)
+        })
+}
+
+fn symbol_to_inotifymask(symb: LispObject) -> u32 {
+    let requestable = [
+        (Qaccess, IN_ACCESS),
+        (Qattrib, IN_ATTRIB),
+        (Qclose_write, IN_CLOSE_WRITE),
+        (Qclose_nowrite, IN_CLOSE_NOWRITE),
+        (Qcreate, IN_CREATE),
+        (Qdelete, IN_DELETE),
+        (Qdelete_self, IN_DELETE_SELF),
+        (Qmodify, IN_MODIFY),
+        (Qmove_self, IN_MOVE_SELF),
+        (Qmoved_from, IN_MOVED_FROM),
+        (Qmoved_to, IN_MOVED_TO),
+        (Qopen, IN_OPEN),
+        (Qmove, IN_MOVE),
+        (Qclose, IN_CLOSE),
+        (Qdont_follow, IN_DONT_FOLLOW),
+        (Qonlydir, IN_ONLYDIR),
+        (Qt, IN_ALL_EVENTS),
+        (Qall_events, IN_ALL_EVENTS),
+    ];
+
+    requestable
+        .iter()
+        .find(|&&(symbol, _)| symbol == symb)
+        .map(|&(_, bit)| bit)
+        .unwrap_or_else(|| {
+            report_file_notify_error(
+                &io::Error::from_raw_os_error(libc::EINVAL),
+                "Unknown aspect",
+                symb,
+            )
+        })
+}
+
+fn aspect_to_inotifymask(aspect: LispObject) -> u32 {
+    if aspect.is_cons() || aspect.is_nil() {
+        aspect
+            .iter_cars(LispConsEndChecks::on, LispConsCircularChecks::on)
+            .fold(0, |mask, symb| mask | symbol_to_inotifymask(symb))
+    } else {
+        symbol_to_inotifymask(aspect)
+    }
+}
+
+/// Signal a `file-notify-error' for ERR.  STRING describes the
+/// failure and NAME is the file or descriptor involved.
+fn report_file_notify_error(err: &io::Error, string: &str, name: LispObject) -> ! {
+    let errno = err.raw_os_error().unwrap_or(0);
+    let errstring = unsafe { CStr::from_ptr(libc::strerror(errno)) };
+    let errstring = LispObject::from(&*errstring.to_string_lossy());
+    let data = if name.is_cons() || name.is_nil() {
+        name
+    } else {
+        list!(name)
+    };
+
+    xsignal!(
+        Qfile_notify_error,
+        LispObject::from(string),
+        LispObject::cons(errstring, data)
+    );
+}
+
+/// Convert the event EV for the file NAME, as read from the inotify
+/// file descriptor, to the Lisp event for WATCH.  Return nil if WATCH
+/// is not interested in it.
+fn inotifyevent_to_event(watch: LispObject, ev: &InotifyEvent, name: &[u8]) -> LispObject {
+    let mask = nth(3, watch).as_natnum_or_error() as u32;
+    if mask & ev.mask == 0 {
+        return Qnil;
+    }
+
+    let watch = LispCons::from(watch);
+    let name = if name.is_empty() {
+        LispCons::from(watch.cdr()).car()
+    } else {
+        let len = name
+            .iter()
+            .position(|&c| c == 0)
+            .unwrap_or_else(|| name.len());
+        unsafe {
+            decode_file_name(make_unibyte_string(
+                name.as_ptr() as *const c_char,
+                len as isize,
+            ))
+        }
+    };
+
+    list!(
+        list!(
+            LispObject::cons(ev.wd, watch.car()),
+            mask_to_aspects(ev.mask),
+            name,
+            ev.cookie
+        ),
+        nth(2, watch.into())
+    )
+}
+
+/// Add a new watch to watch-descriptor WD watching FILENAME and using
+/// IMASK and CALLBACK.  Return a cons (DESCRIPTOR . ID) uniquely
+/// identifying the new watch.
+fn add_watch(wd: c_int, filename: LispObject, imask: u32, callback: LispObject) -> LispObject {
+    let descriptor = LispObject::from(wd);
+
+    let mut id: EmacsInt = 0;
+    let tail = match find_descriptor(descriptor) {
+        None => {
+            let tail = LispCons::from(list!(descriptor));
+            unsafe { watch_list = LispObject::cons(tail, watch_list) };
+            tail
+        }
+        Some((_, elt)) => {
+            // Assign a watch ID that is not already in use, by looking
+            // for a gap in the existing sorted list.
+            let mut tail = LispCons::from(elt.car());
+            while let Some(next) = tail.cdr().as_cons() {
+                if LispCons::from(next.car()).car() != LispObject::from(id) {
+                    break;
+                }
+                tail = next;
+                id += 1;
+            }
+            tail
+        }
+    };
+
+    // Insert the newly-assigned ID into the previously-discovered gap,
+    // which is possibly at the end of the list.  Inserting it there
+    // keeps the list sorted.
+    let watch_id = LispObject::from(id);
+    let watch = list!(watch_id, filename, callback, imask);
+    tail.set_cdr(LispObject::cons(watch, tail.cdr()));
+
+    LispObject::cons(descriptor, watch_id)
+}
+
+/// Find the watch list element matching DESCRIPTOR.  Return the cons
+/// holding it, and the cons before that, if any.  This lets the caller
+/// easily remove the element without having to search for it again.
+fn find_descriptor(descriptor: LispObject) -> Option<(Option<LispCons>, LispCons)> {
+    let mut prevtail = None;
+    for tail in
+        unsafe { watch_list }.iter_tails(LispConsEndChecks::off, LispConsCircularChecks::off)
+    {
+        if LispCons::from(tail.car()).car() == descriptor {
+            return Some((prevtail, tail));
+        }
+        prevtail = Some(tail);
+    }
+    None
+}
+
+/// Remove all watches associated with the watch list element in TAIL,
+/// which follows PREVTAIL.  If INVALID_P is true, the descriptor is
+/// already invalid, i.e., it received a IN_IGNORED event.  In this
+/// case skip removing it from inotify.
+fn remove_descriptor(prevtail: Option<LispCons>, tail: LispCons, invalid_p: bool) {
+    let descriptor = LispCons::from(tail.car()).car();
+
+    let mut rm_error = None;
+    if !invalid_p {
+        let wd = descriptor.as_fixnum_or_error() as c_int;
+        let polled = unsafe { POLLER.as_mut() }.map_or(false, |poller| poller.remove(wd));
+        if !polled {
+            rm_error = kernel_rm_watch(wd).err();
+        }
+    }
+
+    match prevtail {
+        Some(prevtail) => prevtail.set_cdr(tail.cdr()),
+        None => unsafe { watch_list = tail.cdr() },
+    }
+    if unsafe { watch_list }.is_nil() {
+        close_inotify();
+    }
+
+    if let Some(err) = rm_error {
+        report_file_notify_error(&err, "Could not rm watch", descriptor);
+    }
+}
+
+/// Remove watch associated with (DESCRIPTOR . ID).
+fn remove_watch(descriptor: LispObject, id: LispObject) {
+    let (prevtail, tail) = match find_descriptor(descriptor) {
+        Some(found) => found,
+        None => return,
+    };
+
+    let elt = LispCons::from(tail.car());
+    let mut prev = elt;
+    while let Some(next) = prev.cdr().as_cons() {
+        if LispCons::from(next.car()).car() == id {
+            prev.set_cdr(next.cdr());
+            if elt.cdr().is_nil() {
+                remove_descriptor(prevtail, tail, false);
+            }
+            break;
+        }
+        prev = next;
+    }
+}
+
+fn close_inotify() {
+    unsafe {
+        if INOTIFY_FD >= 0 {
+            delete_read_fd(INOTIFY_FD);
+            emacs_close(INOTIFY_FD);
+            INOTIFY_FD = -1;
+        }
+        if let Some(poller) = POLLER.take() {
+            poller.stop();
+        }
+    }
+}
+
+/// This callback is called when FD is available for read.  The inotify
+/// events are read from FD and converted into input_events.
+extern "C" fn inotify_callback(fd: c_int, _data: *mut c_void) {
+    let mut to_read: c_int = 0;
+    if unsafe { libc::ioctl(fd, libc::FIONREAD, &mut to_read) } < 0 {
+        report_file_notify_error(
+            &io::Error::last_os_error(),
+            "Error while retrieving file system events",
+            Qnil,
+        );
+    }
+
+    let mut buffer = vec![0_u8; to_read.max(0) as usize];
+    let n = unsafe { libc::read(fd, buffer.as_mut_ptr() as *mut c_void, buffer.len()) };
+    if n < 0 {
+        report_file_notify_error(
+            &io::Error::last_os_error(),
+            "Error while reading file system events",
+            Qnil,
+        );
+    }
+    buffer.truncate(n as usize);
+
+    let mut event: input_event = unsafe { mem::zeroed() };
+    event.set_kind(event_kind::FILE_NOTIFY_EVENT);
+
+    let header_len = mem::size_of::<InotifyEvent>();
+    let mut i = 0;
+    while i + header_len <= buffer.len() {
+        let ev: InotifyEvent =
+            unsafe { ptr::read_unaligned(buffer[i..].as_ptr() as *const InotifyEvent) };
+        let name_end = i + header_len + ev.len as usize;
+        if name_end > buffer.len() {
+            // Both inotify and the polling thread only ever hand us
+            // whole records, so this cannot happen.
+            break;
+        }
+        let name = &buffer[i + header_len..name_end];
+
+        if let Some((prevtail, tail)) = find_descriptor(LispObject::from(ev.wd)) {
+            let watches = LispCons::from(tail.car()).cdr();
+            for watch in watches.iter_cars(LispConsEndChecks::off, LispConsCircularChecks::off) {
+                event.arg = inotifyevent_to_event(watch, &ev, name);
+                if event.arg.is_not_nil() {
+                    unsafe { kbd_buffer_store_event(&mut event) };
+                }
+            }
+            // If event was removed automatically: Drop it from watch list.
+            if ev.mask & IN_IGNORED != 0 {
+                remove_descriptor(prevtail, tail, true);
+            }
+        }
+
+        i = name_end;
+    }
+}
+
+/// Start inotify, if it is not running yet.  Return false if it is
+/// not available.
+#[cfg(feature = "inotify")]
+fn init_inotify() -> bool {
+    unsafe {
+        if INOTIFY_FD < 0 {
+            INOTIFY_FD = sys::inotify_init1(sys::IN_NONBLOCK | sys::IN_CLOEXEC);
+            if INOTIFY_FD < 0 {
+                return false;
+            }
+            add_read_fd(INOTIFY_FD, Some(inotify_callback), ptr::null_mut());
+        }
+    }
+    true
+}
+
+/// Ask inotify to watch the file whose encoded name is ENCODED for the
+/// events in MASK.  Return None if inotify is not available.
+#[cfg(feature = "inotify")]
+fn kernel_add_watch(encoded: LispStringRef, mask: u32) -> Option<io::Result<c_int>> {
+    if !init_inotify() {
+        return None;
+    }
+    let wd = unsafe { sys::inotify_add_watch(INOTIFY_FD, encoded.const_sdata_ptr(), mask) };
+    Some(if wd >= 0 {
+        Ok(wd)
+    } else {
+        Err(io::Error::last_os_error())
+    })
+}
+
+#[cfg(not(feature = "inotify"))]
+fn kernel_add_watch(_encoded: LispStringRef, _mask: u32) -> Option<io::Result<c_int>> {
+    None
+}
+
+/// Remove the inotify watch WD.
+#[cfg(feature = "inotify")]
+fn kernel_rm_watch(wd: c_int) -> io::Result<()> {
+    if unsafe { sys::inotify_rm_watch(INOTIFY_FD, wd) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Without inotify, every watch is polled.
+#[cfg(not(feature = "inotify"))]
+fn kernel_rm_watch(_wd: c_int) -> io::Result<()> {
+    Ok(())
+}
+
+/// Add a watch for FILE-NAME to inotify.
+///
+/// Return a watch descriptor.  The watch will look for ASPECT events and
+/// invoke CALLBACK when an event occurs.
+///
+/// ASPECT might be one of the following symbols or a list of those symbols:
+///
+/// access
+/// attrib
+/// close-write
+/// close-nowrite
+/// create
+/// delete
+/// delete-self
+/// modify
+/// move-self
+/// moved-from
+/// moved-to
+/// open
+///
+/// all-events or t
+/// move
+/// close
+///
+/// ASPECT can also contain the following symbols, which control whether
+/// the watch descriptor will be created:
+///
+/// dont-follow
+/// onlydir
+///
+/// Watching a directory is not recursive.  CALLBACK is passed a single argument
+/// EVENT which contains an event structure of the format
+///
+/// (WATCH-DESCRIPTOR ASPECTS NAME COOKIE)
+///
+/// WATCH-DESCRIPTOR is the same object that was returned by this function.  It can
+/// be tested for equality using `equal'.  ASPECTS describes the event.  It is a
+/// list of ASPECT symbols described above and can also contain one of the following
+/// symbols
+///
+/// ignored
+/// isdir
+/// q-overflow
+/// unmount
+///
+/// If a directory is watched then NAME is the name of file that caused the event.
+///
+/// COOKIE is an object that can be compared using `equal' to identify two matching
+/// renames (moved-from and moved-to).
+///
+/// See inotify(7) and inotify_add_watch(2) for further information.  The
+/// inotify fd is managed internally and there is no corresponding
+/// inotify_init.  Use `inotify-rm-watch' to remove a watch.
+///
+/// If inotify is not available, or no more inotify watches can be
+/// created, or `inotify-use-polling' is non-nil, FILE-NAME is polled
+/// every `inotify-polling-interval' seconds instead.  Polling only
+/// notices the aspects attrib, create, delete, delete-self and modify;
+/// a renamed file is reported as deleted and created.
+///
+/// The following inotify bit-masks cannot be used because descriptors are
+/// shared across different callers.
+///
+/// IN_EXCL_UNLINK
+/// IN_MASK_ADD
+/// IN_ONESHOT
+#[lisp_fn]
+pub fn inotify_add_watch(
+    file_name: LispStringRef,
+    aspect: LispObject,
+    callback: LispObject,
+) -> LispObject {
+    let imask = aspect_to_inotifymask(aspect);
+    let mask = imask | IN_MASK_ADD | IN_EXCL_UNLINK;
+    let encoded_file_name = encode_file_name(file_name);
+
+    let use_polling = unsafe { globals.Vinotify_use_polling }.is_not_nil();
+    let added = if use_polling {
+        None
+    } else {
+        kernel_add_watch(encoded_file_name, mask)
+    };
+    let wd = match added {
+        Some(Ok(wd)) => wd,
+        Some(Err(err)) => match err.raw_os_error() {
+            // The watch limit has been reached.
+            Some(libc::ENOSPC) | Some(libc::ENOMEM) => {
+                poll_watch(file_name.into(), encoded_file_name, imask)
+            }
+            _ => report_file_notify_error(&err, "Could not add watch for file", file_name.into()),
+        },
+        None => poll_watch(file_name.into(), encoded_file_name, imask),
+    };
+
+    add_watch(wd, file_name.into(), imask, callback)
+}
+
+/// Add a polled watch for FILENAME, whose encoded name is ENCODED.
+fn poll_watch(filename: LispObject, encoded: LispStringRef, imask: u32) -> c_int {
+    let interval = unsafe { globals.Vinotify_polling_interval }
+        .any_to_float()
+        .filter(|&secs| secs > 0.0)
+        .map_or(DEFAULT_POLLING_INTERVAL, Duration::from_secs_f64);
+
+    if unsafe { POLLER.is_none() } {
+        match Poller::start(interval) {
+            Ok(poller) => unsafe { POLLER = Some(poller) },
+            Err(err) => report_file_notify_error(&err, "File watching is not available", Qnil),
+        }
+    }
+
+    let poller = unsafe { POLLER.as_mut() }.unwrap();
+    let path = PathBuf::from(OsStr::from_bytes(encoded.as_slice()));
+    match poller.add(path, imask, interval) {
+        Ok(wd) => wd,
+        Err(err) => {
+            if unsafe { watch_list }.is_nil() {
+                close_inotify();
+            }
+            report_file_notify_error(&err, "Could not add watch for file", filename)
+        }
+    }
+}
+
+fn valid_watch_descriptor(wd: LispObject) -> bool {
+    wd.as_cons().map_or(false, |wd| {
+        wd.car()
+            .as_fixnum()
+            .map_or(false, |d| 0 <= d && d <= EmacsInt::from(c_int::max_value()))
+            && wd.cdr().is_natnum()
+    })
+}
+
+/// Remove an existing WATCH-DESCRIPTOR.
+///
+/// WATCH-DESCRIPTOR should be an object returned by `inotify-add-watch'.
+///
+/// See inotify_rm_watch(2) for more information.
+#[lisp_fn]
+pub fn inotify_rm_watch(watch_descriptor: LispObject) -> bool {
+    if !valid_watch_descriptor(watch_descriptor) {
+        report_file_notify_error(
+            &io::Error::from_raw_os_error(libc::EINVAL),
+            "Invalid descriptor ",
+            watch_descriptor,
+        );
+    }
+
+    let (descriptor, id) = watch_descriptor.into();
+    remove_watch(descriptor, id);
+
+    true
+}
+
+/// Check a watch specified by its WATCH-DESCRIPTOR.
+///
+/// WATCH-DESCRIPTOR should be an object returned by `inotify-add-watch'.
+///
+/// A watch can become invalid if the file or directory it watches is
+/// deleted, or if the watcher thread exits abnormally for any other
+/// reason.  Removing the watch by calling `inotify-rm-watch' also makes
+/// it invalid.
+#[lisp_fn]
+pub fn inotify_valid_p(watch_descriptor: LispObject) -> bool {
+    if !valid_watch_descriptor(watch_descriptor) {
+        return false;
+    }
+
+    let (descriptor, id) = watch_descriptor.into();
+    find_descriptor(descriptor).map_or(false, |(_, tail)| {
+        LispCons::from(tail.car())
+            .cdr()
+            .iter_cars(LispConsEndChecks::off, LispConsCircularChecks::off)
+            .any(|watch| LispCons::from(watch).car() == id)
+    })
+}
+
+// Polling.
+
+const DEFAULT_POLLING_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What polling looks at to decide that a file has changed.
+#[derive(Clone, Copy, PartialEq)]
+struct FileState {
+    ino: u64,
+    is_dir: bool,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    size: u64,
+    mtime: (i64, i64),
+    ctime: (i64, i64),
+}
+
+impl FileState {
+    fn new(md: &fs::Metadata) -> Self {
+        Self {
+            ino: md.ino(),
+            is_dir: md.is_dir(),
+            mode: md.mode(),
+            uid: md.uid(),
+            gid: md.gid(),
+            size: md.size(),
+            mtime: (md.mtime(), md.mtime_nsec()),
+            ctime: (md.ctime(), md.ctime_nsec()),
+        }
+    }
+
+    /// The inotify event for a change from SELF to NEW, if any.
+    fn change_to(&self, new: &Self) -> u32 {
+        if self.size != new.size || self.mtime != new.mtime {
+            IN_MODIFY
+        } else if self.mode != new.mode
+            || self.uid != new.uid
+            || self.gid != new.gid
+            || self.ctime != new.ctime
+        {
+            IN_ATTRIB
+        } else {
+            0
+        }
+    }
+
+    fn isdir_bit(&self) -> u32 {
+        if self.is_dir {
+            IN_ISDIR
+        } else {
+            0
+        }
+    }
+}
+
+/// A polled file, and what it looked like the last time.
+struct PollWatch {
+    path: PathBuf,
+    mask: u32,
+    state: FileState,
+    /// The entries of the file, if it is a directory.
+    entries: HashMap<OsString, FileState>,
+}
+
+impl PollWatch {
+    fn new(path: PathBuf, mask: u32) -> io::Result<Self> {
+        let md = if mask & IN_DONT_FOLLOW != 0 {
+            fs::symlink_metadata(&path)?
+        } else {
+            fs::metadata(&path)?
+        };
+        if mask & IN_ONLYDIR != 0 && !md.is_dir() {
+            return Err(io::Error::from_raw_os_error(libc::ENOTDIR));
+        }
+
+        let state = FileState::new(&md);
+        let entries = if state.is_dir {
+            read_entries(&path)
+        } else {
+            HashMap::new()
+        };
+
+        Ok(Self {
+            path,
+            mask,
+            state,
+            entries,
+        })
+    }
+
+    /// Look at the file again, appending the events for the changes
+    /// since the last time to EVENTS, as inotify would report them for
+    /// WD.  Return false if the watch is gone.
+    fn poll(&mut self, wd: c_int, events: &mut Vec<Vec<u8>>) -> bool {
+        let wanted = self.mask | IN_IGNORED;
+        let mut push = |mask: u32, name: &[u8]| {
+            if mask & wanted != 0 {
+                push_event(events, wd, mask, name);
+            }
+        };
+
+        let state = match fs::metadata(&self.path) {
+            // Like inotify, stop once the file is replaced by another.
+            Ok(ref md) if md.ino() == self.state.ino => FileState::new(md),
+            _ => {
+                push(IN_DELETE_SELF, b"");
+                push(IN_IGNORED, b"");
+                return false;
+            }
+        };
+
+        if self.state.is_dir {
+            let entries = read_entries(&self.path);
+            for (name, new) in &entries {
+                match self.entries.get(name) {
+                    None => push(IN_CREATE | new.isdir_bit(), name.as_bytes()),
+                    Some(old) if old.ino != new.ino => {
+                        push(IN_DELETE | old.isdir_bit(), name.as_bytes());
+                        push(IN_CREATE | new.isdir_bit(), name.as_bytes());
+                    }
+                    Some(old) => {
+                        let change = old.change_to(new);
+                        if change != 0 && !new.is_dir {
+                            push(change, name.as_bytes());
+                        }
+                    }
+                }
+            }
+            for (name, old) in &self.entries {
+                if !entries.contains_key(name) {
+                    push(IN_DELETE | old.isdir_bit(), name.as_bytes());
+                }
+            }
+            self.entries = entries;
+
+            // Adding or removing entries changes the directory itself,
+            // which inotify does not report.
+            if self.state.mode != state.mode
+                || self.state.uid != state.uid
+                || self.state.gid != state.gid
+            {
+                push(IN_ATTRIB | IN_ISDIR, b"");
+            }
+        } else {
+            let change = self.state.change_to(&state);
+            if change != 0 {
+                push(change, b"");
+            }
+        }
+
+        self.state = state;
+        true
+    }
+}
+
+fn read_entries(dir: &PathBuf) -> HashMap<OsString, FileState> {
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .filter_map(|entry| {
+                    let md = entry.metadata().ok()?;
+                    Some((entry.file_name(), FileState::new(&md)))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Append an inotify event record to EVENTS.
+fn push_event(events: &mut Vec<Vec<u8>>, wd: c_int, mask: u32, name: &[u8]) {
+    let len = if name.is_empty() { 0 } else { name.len() + 1 };
+    let header = InotifyEvent {
+        wd,
+        mask,
+        cookie: 0,
+        len: len as u32,
+    };
+    let header: [u8; mem::size_of::<InotifyEvent>()] = unsafe { mem::transmute(header) };
+    let mut record = header.to_vec();
+    if len > 0 {
+        record.extend_from_slice(name);
+        record.push(0);
+    }
+    events.push(record);
+}
+
+/// State shared between Emacs and the polling thread.  Each watch has
+/// a lock of its own, so that the thread can look at the files without
+/// holding the lock on the table.
+struct PollShared {
+    watches: HashMap<c_int, Arc<Mutex<PollWatch>>>,
+    interval: Duration,
+    stopped: bool,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+struct Poller {
+    shared: Arc<Mutex<PollShared>>,
+    /// Our end of the socket the polling thread writes events to.
+    fd: c_int,
+    /// Polled watches get descriptors counting down from the largest
+    /// int (or fixnum), well out of the way of the ones inotify hands
+    /// out.
+    next_wd: c_int,
+}
+
+impl Poller {
+    fn start(interval: Duration) -> io::Result<Self> {
+        // A datagram socket keeps the records apart, so that a read
+        // never ends in the middle of one.
+        let mut fds = [-1; 2];
+        if unsafe { libc::socketpair(libc::AF_UNIX, libc::SOCK_DGRAM, 0, fds.as_mut_ptr()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        for &fd in &fds {
+            unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+        }
+        let (fd, thread_fd) = (fds[0], fds[1]);
+
+        let shared = Arc::new(Mutex::new(PollShared {
+            watches: HashMap::new(),
+            interval,
+            stopped: false,
+        }));
+        let thread_shared = Arc::clone(&shared);
+        let spawned = thread::Builder::new()
+            .name("inotify-poll".to_string())
+            .spawn(move || poll_loop(&thread_shared, thread_fd));
+        if let Err(err) = spawned {
+            unsafe {
+                libc::close(fd);
+                libc::close(thread_fd);
+            }
+            return Err(err);
+        }
+
+        unsafe { add_read_fd(fd, Some(inotify_callback), ptr::null_mut()) };
+        Ok(Self {
+            shared,
+            fd,
+            next_wd: EmacsInt::from(c_int::max_value()).min(MOST_POSITIVE_FIXNUM) as c_int,
+        })
+    }
+
+    /// Start polling PATH for the events in MASK.  Polling the same
+    /// file twice returns the same descriptor, like inotify does.
+    fn add(&mut self, path: PathBuf, mask: u32, interval: Duration) -> io::Result<c_int> {
+        let mut shared = lock(&self.shared);
+        shared.interval = interval;
+
+        for (&wd, watch) in &shared.watches {
+            let mut watch = lock(watch);
+            if watch.path == path {
+                watch.mask |= mask;
+                return Ok(wd);
+            }
+        }
+
+        let watch = PollWatch::new(path, mask)?;
+        let wd = self.next_wd;
+        self.next_wd -= 1;
+        shared.watches.insert(wd, Arc::new(Mutex::new(watch)));
+        Ok(wd)
+    }
+
+    /// Stop polling WD.  Return false if it is not a polled watch.
+    /// The polling thread may already have dropped a watch whose file
+    /// is gone, so this does not look at the current watches.
+    fn remove(&mut self, wd: c_int) -> bool {
+        lock(&self.shared).watches.remove(&wd);
+        wd > self.next_wd
+    }
+
+    fn stop(self) {
+        lock(&self.shared).stopped = true;
+        unsafe {
+            delete_read_fd(self.fd);
+            emacs_close(self.fd);
+        }
+    }
+}
+
+/// Flags for sending events, so that a closed socket does not raise
+/// SIGPIPE where the platform lets us ask for that.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const SEND_FLAGS: c_int = libc::MSG_NOSIGNAL;
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+const SEND_FLAGS: c_int = 0;
+
+/// The body of the polling thread.  It must not touch any Lisp object.
+fn poll_loop(shared: &Mutex<PollShared>, fd: c_int) {
+    loop {
+        let interval = lock(shared).interval;
+        thread::sleep(interval);
+
+        // Look at the files without holding the lock on the table,
+        // so that a slow file system does not hold up Emacs when it
+        // adds or removes a watch.
+        let watches: Vec<(c_int, Arc<Mutex<PollWatch>>)> = {
+            let shared = lock(shared);
+            if shared.stopped {
+                break;
+            }
+            shared
+                .watches
+                .iter()
+                .map(|(&wd, watch)| (wd, Arc::clone(watch)))
+                .collect()
+        };
+
+        let mut events = Vec::new();
+        let gone: Vec<c_int> = watches
+            .iter()
+            .filter(|(wd, watch)| !lock(watch).poll(*wd, &mut events))
+            .map(|&(wd, _)| wd)
+            .collect();
+        if !gone.is_empty() {
+            let mut shared = lock(shared);
+            for wd in gone {
+                shared.watches.remove(&wd);
+            }
+        }
+
+        // Write outside the locks, so that Emacs can still add and
+        // remove watches if it is slow to read.
+        for record in &events {
+            loop {
+                let n = unsafe {
+                    libc::send(
+                        fd,
+                        record.as_ptr() as *const c_void,
+                        record.len(),
+                        SEND_FLAGS,
+                    )
+                };
+                if n >= 0 {
+                    break;
+                }
+                if io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+                    // Emacs closed its end.
+                    unsafe { libc::close(fd) };
+                    return;
+                }
+            }
+        }
+    }
+
+    unsafe { libc::close(fd) };
+}
+
+#[allow(unused_doc_comments)]
+#[no_mangle]
+pub extern "C" fn syms_of_inotify() {
+    def_lisp_sym!(Qaccess, "access"); // IN_ACCESS
+    def_lisp_sym!(Qattrib, "attrib"); // IN_ATTRIB
+    def_lisp_sym!(Qclose_write, "close-write"); // IN_CLOSE_WRITE
+    def_lisp_sym!(Qclose_nowrite, "close-nowrite"); // IN_CLOSE_NOWRITE
+    def_lisp_sym!(Qcreate, "create"); // IN_CREATE
+    def_lisp_sym!(Qdelete, "delete"); // IN_DELETE
+    def_lisp_sym!(Qdelete_self, "delete-self"); // IN_DELETE_SELF
+    def_lisp_sym!(Qmodify, "modify"); // IN_MODIFY
+    def_lisp_sym!(Qmove_self, "move-self"); // IN_MOVE_SELF
+    def_lisp_sym!(Qmoved_from, "moved-from"); // IN_MOVED_FROM
+    def_lisp_sym!(Qmoved_to, "moved-to"); // IN_MOVED_TO
+    def_lisp_sym!(Qopen, "open"); // IN_OPEN
+
+    def_lisp_sym!(Qall_events, "all-events"); // IN_ALL_EVENTS
+    def_lisp_sym!(Qmove, "move"); // IN_MOVE
+    def_lisp_sym!(Qclose, "close"); // IN_CLOSE
+
+    def_lisp_sym!(Qdont_follow, "dont-follow"); // IN_DONT_FOLLOW
+    def_lisp_sym!(Qonlydir, "onlydir"); // IN_ONLYDIR
+
+    def_lisp_sym!(Qignored, "ignored"); // IN_IGNORED
+    def_lisp_sym!(Qisdir, "isdir"); // IN_ISDIR
+    def_lisp_sym!(Qq_overflow, "q-overflow"); // IN_Q_OVERFLOW
+    def_lisp_sym!(Qunmount, "unmount"); // IN_UNMOUNT
+
+    /// Non-nil means `inotify-add-watch' polls files instead of using inotify.
+    /// This is useful for network file systems, on which inotify does not
+    /// notice changes made by other machines.  Files are also polled when
+    /// the kernel runs out of inotify watches.
+    defvar_lisp!(Vinotify_use_polling, "inotify-use-polling", Qnil);
+
+    /// Number of seconds between two looks at a polled file.
+    /// See `inotify-use-polling'.  A change takes effect the next time a
+    /// watch is added.
+    defvar_lisp!(
+        Vinotify_polling_interval,
+        "inotify-polling-interval",
+        LispObject::from(1)
+    );
+
+    provide(intern("inotify"), Qnil);
+}
+
+include!(concat!(env!("OUT_DIR"), "/inotify_exports.rs"));
@@ -0,0 +1,654 @@
+//! Parsing of ISO 8601 dates, times and durations, and formatting of
+//! RFC 3339 timestamps.
+
+use crate::time::{civil_from_days, days_from_civil, is_leap_year, BrokenDownTime};
+
+const NANOS_PER_SECOND: i64 = 1_000_000_000;
+
+/// Why a string is not valid ISO 8601, and where the problem was
+/// found, as a byte offset from 0.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ParseError {
+    pub position: usize,
+    pub message: &'static str,
+}
+
+/// A date and time of day.  Fields that the string leaves out are the
+/// first of their range, so "2018-07" is 2018-07-01T00:00:00.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DateTime {
+    pub year: i64,
+    /// The month, from 1.
+    pub month: i64,
+    pub day: i64,
+    /// The hour, which is 24 at the end of a day.
+    pub hour: i64,
+    pub minute: i64,
+    /// The second, which is 60 during a leap second.
+    pub second: i64,
+    pub nanosecond: i64,
+    /// The offset from UTC in seconds east, or None for local time.
+    pub offset: Option<i64>,
+}
+
+/// What an ISO 8601 string describes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Iso8601 {
+    DateTime(DateTime),
+    /// A duration of a fixed length, in seconds and nanoseconds.
+    Duration {
+        seconds: i64,
+        nanoseconds: i64,
+    },
+}
+
+/// Parse S, an ISO 8601 date with an optional time of day and UTC
+/// offset, or a duration such as "P1DT12H".
+///
+/// Dates are calendar dates ("2018-07-04"), ordinal dates ("2018-185")
+/// or week dates ("2018-W27-3"), in the extended or basic format, with
+/// a year of four digits or a signed year of at least four.  The time
+/// may follow "T", or a space as RFC 3339 allows, and its last
+/// component may have a decimal fraction.
+pub fn parse(s: &[u8]) -> Result<Iso8601, ParseError> {
+    let mut parser = Parser { s, pos: 0 };
+    let value = if parser.peek() == Some(b'P') {
+        parser.duration()?
+    } else {
+        Iso8601::DateTime(parser.date_time()?)
+    };
+    if parser.pos < s.len() {
+        return parser.error("Trailing garbage");
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    s: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error<T>(&self, message: &'static str) -> Result<T, ParseError> {
+        Self::error_at(self.pos, message)
+    }
+
+    fn error_at<T>(position: usize, message: &'static str) -> Result<T, ParseError> {
+        Err(ParseError { position, message })
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.s.get(self.pos).cloned()
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Return the number of digits at the current position.
+    fn digit_count(&self) -> usize {
+        self.s[self.pos..]
+            .iter()
+            .take_while(|c| c.is_ascii_digit())
+            .count()
+    }
+
+    /// Read a number of exactly N digits.
+    fn digits(&mut self, n: usize) -> Result<i64, ParseError> {
+        let mut value = 0;
+        for _ in 0..n {
+            match self.peek() {
+                Some(c) if c.is_ascii_digit() => value = value * 10 + i64::from(c - b'0'),
+                _ => return self.error("Expected a digit"),
+            }
+            self.pos += 1;
+        }
+        Ok(value)
+    }
+
+    /// Read a number of any length, as in a duration or expanded year.
+    fn number(&mut self) -> Result<i64, ParseError> {
+        let start = self.pos;
+        let n = self.digit_count();
+        if n == 0 {
+            return self.error("Expected a digit");
+        }
+        if n > 18 {
+            return Self::error_at(start, "Number out of range");
+        }
+        self.digits(n)
+    }
+
+    /// Read a decimal fraction, if there is one, as a numerator and
+    /// denominator.  Digits beyond the 18th are ignored.
+    fn fraction(&mut self) -> Result<Option<(u128, u128)>, ParseError> {
+        if !self.eat(b'.') && !self.eat(b',') {
+            return Ok(None);
+        }
+        let n = self.digit_count();
+        if n == 0 {
+            return self.error("Expected a digit");
+        }
+        let (mut num, mut den) = (0, 1);
+        for &c in &self.s[self.pos..self.pos + n.min(18)] {
+            num = num * 10 + u128::from(c - b'0');
+            den *= 10;
+        }
+        self.pos += n;
+        Ok(Some((num, den)))
+    }
+
+    fn date_time(&mut self) -> Result<DateTime, ParseError> {
+        let (year, month, day) = self.date()?;
+        let mut time = DateTime {
+            year,
+            month,
+            day,
+            ..DateTime::default()
+        };
+        match self.peek() {
+            Some(b'T') | Some(b't') | Some(b' ') => self.pos += 1,
+            _ => return Ok(time),
+        }
+
+        let nanos = self.time()?;
+        time.hour = nanos / (3600 * NANOS_PER_SECOND);
+        time.minute = nanos / (60 * NANOS_PER_SECOND) % 60;
+        time.second = nanos / NANOS_PER_SECOND % 60;
+        time.nanosecond = nanos % NANOS_PER_SECOND;
+        time.offset = self.offset()?;
+        Ok(time)
+    }
+
+    /// Read a date and return its year, month and day.
+    fn date(&mut self) -> Result<(i64, i64, i64), ParseError> {
+        // Expanded years have a sign and as many digits as they need,
+        // which leaves no room for the basic format.
+        let expanded = match self.peek() {
+            Some(c @ b'+') | Some(c @ b'-') => {
+                self.pos += 1;
+                Some(c == b'-')
+            }
+            _ => None,
+        };
+        let year = match expanded {
+            Some(negative) => {
+                if self.digit_count() < 4 {
+                    return self.error("Expected a year of at least four digits");
+                }
+                let year = self.number()?;
+                if negative {
+                    -year
+                } else {
+                    year
+                }
+            }
+            None => self.digits(4)?,
+        };
+
+        let extended = self.eat(b'-');
+        if !extended && expanded.is_some() {
+            return Ok((year, 1, 1));
+        }
+
+        let start = self.pos;
+        if self.eat(b'W') {
+            let week = self.digits(2)?;
+            let weekday = if (extended && self.eat(b'-')) || (!extended && self.digit_count() > 0) {
+                let day_start = self.pos;
+                let weekday = self.digits(1)?;
+                if weekday < 1 || weekday > 7 {
+                    return Self::error_at(day_start, "Day of the week out of range");
+                }
+                weekday
+            } else {
+                1
+            };
+            return match week_date(year, week, weekday) {
+                Some(date) => Ok(date),
+                None => Self::error_at(start + 1, "Week out of range"),
+            };
+        }
+
+        match self.digit_count() {
+            0 if !extended => Ok((year, 1, 1)),
+            3 => {
+                let yday = self.digits(3)?;
+                let length = if is_leap_year(year) { 366 } else { 365 };
+                if yday < 1 || yday > length {
+                    return Self::error_at(start, "Day of the year out of range");
+                }
+                let (_, month, day) = civil_from_days(days_from_civil(year, 1, 1) + yday - 1);
+                Ok((year, month, day))
+            }
+            2 if extended => {
+                let month = self.digits(2)?;
+                if month < 1 || month > 12 {
+                    return Self::error_at(start, "Month out of range");
+                }
+                let day = if self.eat(b'-') {
+                    self.day(year, month)?
+                } else {
+                    1
+                };
+                Ok((year, month, day))
+            }
+            4 if !extended => {
+                let month = self.digits(2)?;
+                if month < 1 || month > 12 {
+                    return Self::error_at(start, "Month out of range");
+                }
+                Ok((year, month, self.day(year, month)?))
+            }
+            _ => self.error("Expected a month, a day of the year or a week"),
+        }
+    }
+
+    /// Read the day of the month MONTH of YEAR.
+    fn day(&mut self, year: i64, month: i64) -> Result<i64, ParseError> {
+        let start = self.pos;
+        let day = self.digits(2)?;
+        let length =
+            days_from_civil(year + month / 12, month % 12 + 1, 1) - days_from_civil(year, month, 1);
+        if day < 1 || day > length {
+            return Self::error_at(start, "Day out of range");
+        }
+        Ok(day)
+    }
+
+    /// Read a time of day and return it in nanoseconds since midnight.
+    fn time(&mut self) -> Result<i64, ParseError> {
+        const UNITS: [(i64, i64, &str); 3] = [
+            (3600, 24, "Hour out of range"),
+            (60, 59, "Minute out of range"),
+            (1, 60, "Second out of range"),
+        ];
+
+        let mut nanos = 0;
+        let mut extended = None;
+        let hour_start = self.pos;
+        for (i, &(unit, max, message)) in UNITS.iter().enumerate() {
+            if i > 0 {
+                // The extended format separates components with colons,
+                // and a string must use one format throughout.
+                let colon = self.peek() == Some(b':');
+                if extended.is_none() && (colon || self.digit_count() >= 2) {
+                    extended = Some(colon);
+                }
+                match extended {
+                    Some(true) if colon => self.pos += 1,
+                    Some(false) if self.digit_count() >= 2 => (),
+                    _ => break,
+                }
+            }
+
+            let start = self.pos;
+            let value = self.digits(2)?;
+            if value > max {
+                return Self::error_at(start, message);
+            }
+            nanos += value * unit * NANOS_PER_SECOND;
+
+            if let Some((num, den)) = self.fraction()? {
+                nanos += scale(i128::from(unit * NANOS_PER_SECOND), num, den) as i64;
+                break;
+            }
+        }
+
+        // Hour 24 is only for the end of a day, 24:00:00.
+        if nanos > 24 * 3600 * NANOS_PER_SECOND && self.s[hour_start..hour_start + 2] == *b"24" {
+            return Self::error_at(hour_start, "Hour out of range");
+        }
+        Ok(nanos)
+    }
+
+    /// Read an optional UTC offset, returning it in seconds east.
+    fn offset(&mut self) -> Result<Option<i64>, ParseError> {
+        let sign = match self.peek() {
+            Some(b'Z') | Some(b'z') => {
+                self.pos += 1;
+                return Ok(Some(0));
+            }
+            Some(b'+') => 1,
+            Some(b'-') => -1,
+            _ => return Ok(None),
+        };
+        self.pos += 1;
+
+        let start = self.pos;
+        let hours = self.digits(2)?;
+        if hours > 24 {
+            return Self::error_at(start, "Offset out of range");
+        }
+        let minutes = if self.eat(b':') || self.digit_count() > 0 {
+            let start = self.pos;
+            let minutes = self.digits(2)?;
+            if minutes > 59 {
+                return Self::error_at(start, "Offset out of range");
+            }
+            minutes
+        } else {
+            0
+        };
+        Ok(Some(sign * (hours * 3600 + minutes * 60)))
+    }
+
+    /// Read a duration such as "P3W" or "PT1.5S".
+    fn duration(&mut self) -> Result<Iso8601, ParseError> {
+        // The designators in the order they must appear, with the length
+        // in seconds of their units; years and months vary in length.
+        const DATE_UNITS: [(u8, Option<i64>); 4] = [
+            (b'Y', None),
+            (b'M', None),
+            (b'W', Some(7 * 24 * 3600)),
+            (b'D', Some(24 * 3600)),
+        ];
+        const TIME_UNITS: [(u8, Option<i64>); 3] =
+            [(b'H', Some(3600)), (b'M', Some(60)), (b'S', Some(1))];
+
+        self.pos += 1;
+        let mut nanos: i128 = 0;
+        let mut components = 0;
+        let mut fractional = false;
+        let mut units = &DATE_UNITS[..];
+        let mut time = false;
+        loop {
+            if !time && self.eat(b'T') {
+                time = true;
+                units = &TIME_UNITS[..];
+                if self.digit_count() == 0 {
+                    return self.error("Expected a digit");
+                }
+            }
+            if self.pos == self.s.len() {
+                break;
+            }
+            if fractional {
+                return self.error("Only the last component can have a fraction");
+            }
+
+            let start = self.pos;
+            let value = self.number()?;
+            let fraction = self.fraction()?;
+            let designator = self.peek();
+            let index = match units.iter().position(|&(c, _)| Some(c) == designator) {
+                Some(index) => index,
+                None if self.pos == self.s.len() => return self.error("Expected a designator"),
+                None => return self.error("Unexpected designator"),
+            };
+            let unit = match units[index].1 {
+                Some(unit) => i128::from(unit) * i128::from(NANOS_PER_SECOND),
+                None => return self.error("Years and months have no fixed length"),
+            };
+            self.pos += 1;
+            units = &units[index + 1..];
+
+            nanos = match i128::from(value)
+                .checked_mul(unit)
+                .and_then(|n| nanos.checked_add(n))
+                .and_then(|n| n.checked_add(fraction.map_or(0, |(num, den)| scale(unit, num, den))))
+            {
+                Some(n) if n / i128::from(NANOS_PER_SECOND) <= i128::from(i64::max_value()) => n,
+                _ => return Self::error_at(start, "Duration out of range"),
+            };
+            fractional = fraction.is_some();
+            components += 1;
+        }
+
+        if components == 0 {
+            return self.error("Expected a digit");
+        }
+        Ok(Iso8601::Duration {
+            seconds: (nanos / i128::from(NANOS_PER_SECOND)) as i64,
+            nanoseconds: (nanos % i128::from(NANOS_PER_SECOND)) as i64,
+        })
+    }
+}
+
+/// Return UNIT times the fraction NUM/DEN, which is less than 1,
+/// rounding towards zero.
+fn scale(unit: i128, num: u128, den: u128) -> i128 {
+    let (num, den) = (num as i128, den as i128);
+    unit / den * num + unit % den * num / den
+}
+
+/// Return the year, month and day of WEEKDAY (1 for Monday) of the ISO
+/// week WEEK of YEAR, or None if YEAR has no such week.
+fn week_date(year: i64, week: i64, weekday: i64) -> Option<(i64, i64, i64)> {
+    // Week 1 is the one with the year's first Thursday, and so the one
+    // with January 4.
+    let first_monday = |year| {
+        let jan4 = days_from_civil(year, 1, 4);
+        jan4 - (jan4 + 3).rem_euclid(7)
+    };
+    let start = first_monday(year);
+    let weeks = (first_monday(year + 1) - start) / 7;
+    if week < 1 || week > weeks {
+        return None;
+    }
+    Some(civil_from_days(start + (week - 1) * 7 + weekday - 1))
+}
+
+/// Return the number of fraction digits needed to show NANOSECOND
+/// exactly.
+pub fn fraction_digits(nanosecond: i64) -> usize {
+    if nanosecond == 0 {
+        return 0;
+    }
+    let mut digits = 9;
+    let mut n = nanosecond;
+    while n % 10 == 0 {
+        n /= 10;
+        digits -= 1;
+    }
+    digits
+}
+
+/// Format TM, whose time has NANOSECOND nanoseconds, as an RFC 3339
+/// timestamp with DIGITS digits of fraction, truncating the rest.
+/// Return None if the year or UTC offset cannot be represented.
+pub fn format_rfc3339(tm: &BrokenDownTime, nanosecond: i64, digits: usize) -> Option<Vec<u8>> {
+    if tm.year < 0 || tm.year > 9999 || tm.utcoff % 60 != 0 {
+        return None;
+    }
+
+    let mut text = format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        tm.year,
+        tm.mon + 1,
+        tm.mday,
+        tm.hour,
+        tm.min,
+        tm.sec
+    );
+    if digits > 0 {
+        let fraction = format!("{:09}", nanosecond);
+        text.push('.');
+        text.push_str(&fraction[..digits.min(9)]);
+    }
+    if tm.utcoff == 0 {
+        text.push('Z');
+    } else {
+        let offset = tm.utcoff.abs() / 60;
+        let sign = if tm.utcoff < 0 { '-' } else { '+' };
+        text.push_str(&format!("{}{:02}:{:02}", sign, offset / 60, offset % 60));
+    }
+    Some(text.into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date_time(s: &str) -> DateTime {
+        match parse(s.as_bytes()) {
+            Ok(Iso8601::DateTime(time)) => time,
+            other => panic!("{}: {:?}", s, other),
+        }
+    }
+
+    fn date(s: &str) -> (i64, i64, i64) {
+        let time = date_time(s);
+        (time.year, time.month, time.day)
+    }
+
+    fn duration(s: &str) -> (i64, i64) {
+        match parse(s.as_bytes()) {
+            Ok(Iso8601::Duration {
+                seconds,
+                nanoseconds,
+            }) => (seconds, nanoseconds),
+            other => panic!("{}: {:?}", s, other),
+        }
+    }
+
+    fn error(s: &str) -> (usize, &'static str) {
+        let error = parse(s.as_bytes()).unwrap_err();
+        (error.position, error.message)
+    }
+
+    #[test]
+    fn test_dates() {
+        assert_eq!(date("2018-07-04"), (2018, 7, 4));
+        assert_eq!(date("20180704"), (2018, 7, 4));
+        assert_eq!(date("2018-185"), (2018, 7, 4));
+        assert_eq!(date("2018185"), (2018, 7, 4));
+        assert_eq!(date("2018-W27-3"), (2018, 7, 4));
+        assert_eq!(date("2018W273"), (2018, 7, 4));
+        assert_eq!(date("2018-W27"), (2018, 7, 2));
+        assert_eq!(date("2018-07"), (2018, 7, 1));
+        assert_eq!(date("2018"), (2018, 1, 1));
+        assert_eq!(date("2016-366"), (2016, 12, 31));
+        assert_eq!(date("+12018-07-04"), (12018, 7, 4));
+        assert_eq!(date("-0044-03-15"), (-44, 3, 15));
+        // Week 1 is the week with the first Thursday of the year.
+        assert_eq!(date("2009-W01-1"), (2008, 12, 29));
+        assert_eq!(date("2020-W53-5"), (2021, 1, 1));
+    }
+
+    #[test]
+    fn test_times() {
+        assert_eq!(
+            date_time("2018-07-04T16:05:09Z"),
+            DateTime {
+                year: 2018,
+                month: 7,
+                day: 4,
+                hour: 16,
+                minute: 5,
+                second: 9,
+                nanosecond: 0,
+                offset: Some(0),
+            }
+        );
+        let time = date_time("20180704T160509.123456789+0200");
+        assert_eq!((time.hour, time.minute, time.second), (16, 5, 9));
+        assert_eq!((time.nanosecond, time.offset), (123_456_789, Some(7200)));
+
+        let time = date_time("2018-07-04 16:05:09,5-08:00");
+        assert_eq!((time.nanosecond, time.offset), (500_000_000, Some(-28800)));
+        assert_eq!(date_time("2018-07-04t16:05z").offset, Some(0));
+        assert_eq!(date_time("2018-07-04T16:05+05").offset, Some(18000));
+        assert_eq!(date_time("2018-07-04T16:05").offset, None);
+
+        // Fractions of hours and minutes.
+        let time = date_time("2018-07-04T10.5");
+        assert_eq!((time.hour, time.minute, time.second), (10, 30, 0));
+        let time = date_time("2018-07-04T10:30.25");
+        assert_eq!((time.hour, time.minute, time.second), (10, 30, 15));
+        let time = date_time("2018-07-04T1030.25");
+        assert_eq!((time.hour, time.minute, time.second), (10, 30, 15));
+        assert_eq!(
+            date_time("2018-07-04T16:05:09.1234567891").nanosecond,
+            123_456_789
+        );
+
+        // The end of a day, and a leap second.
+        assert_eq!(date_time("2018-07-04T24:00").hour, 24);
+        let time = date_time("2016-12-31T23:59:60Z");
+        assert_eq!((time.hour, time.minute, time.second), (24, 0, 0));
+    }
+
+    #[test]
+    fn test_durations() {
+        assert_eq!(duration("P1W"), (604_800, 0));
+        assert_eq!(duration("P1DT12H"), (129_600, 0));
+        assert_eq!(duration("PT1H30M15S"), (5415, 0));
+        assert_eq!(duration("PT0.5S"), (0, 500_000_000));
+        assert_eq!(duration("PT1,5M"), (90, 0));
+        assert_eq!(duration("P0D"), (0, 0));
+        assert_eq!(duration("P2W3D"), (1_468_800, 0));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(error("2018-13-01"), (5, "Month out of range"));
+        assert_eq!(error("2018-02-29"), (8, "Day out of range"));
+        assert_eq!(error("2019-366"), (5, "Day of the year out of range"));
+        assert_eq!(error("2019-W53"), (6, "Week out of range"));
+        assert_eq!(error("2018-W27-8"), (9, "Day of the week out of range"));
+        assert_eq!(
+            error("2018-7-4"),
+            (5, "Expected a month, a day of the year or a week")
+        );
+        assert_eq!(error("18-07-04"), (2, "Expected a digit"));
+        assert_eq!(
+            error("+018-07-04"),
+            (1, "Expected a year of at least four digits")
+        );
+        assert_eq!(error("2018-07-04T25:00"), (11, "Hour out of range"));
+        assert_eq!(error("2018-07-04T24:00:01"), (11, "Hour out of range"));
+        assert_eq!(error("2018-07-04T16:60"), (14, "Minute out of range"));
+        assert_eq!(error("2018-07-04T16:05:61"), (17, "Second out of range"));
+        assert_eq!(error("2018-07-04T16:05:09."), (20, "Expected a digit"));
+        assert_eq!(
+            error("2018-07-04T16:05:09+25:00"),
+            (20, "Offset out of range")
+        );
+        assert_eq!(error("2018-07-04T16:05:09Z "), (20, "Trailing garbage"));
+        assert_eq!(error("2018-07-04T16:05:09\u{e4}"), (19, "Trailing garbage"));
+
+        assert_eq!(error("P"), (1, "Expected a digit"));
+        assert_eq!(error("P1DT"), (4, "Expected a digit"));
+        assert_eq!(error("P1"), (2, "Expected a designator"));
+        assert_eq!(error("P1Y"), (2, "Years and months have no fixed length"));
+        assert_eq!(error("P1D2W"), (4, "Unexpected designator"));
+        assert_eq!(
+            error("PT1.5M3S"),
+            (6, "Only the last component can have a fraction")
+        );
+        assert_eq!(error("P999999999999999999W"), (1, "Duration out of range"));
+        assert_eq!(error("P9999999999999999999D"), (1, "Number out of range"));
+    }
+
+    #[test]
+    fn test_format_rfc3339() {
+        let format = |utcoff, nsec, digits| {
+            let tm = BrokenDownTime::from_utc(1_530_720_309, utcoff, Vec::new()).unwrap();
+            format_rfc3339(&tm, nsec, digits).map(|text| String::from_utf8(text).unwrap())
+        };
+        assert_eq!(format(0, 0, 0).unwrap(), "2018-07-04T16:05:09Z");
+        assert_eq!(
+            format(7200, 123_456_789, 3).unwrap(),
+            "2018-07-04T18:05:09.123+02:00"
+        );
+        assert_eq!(
+            format(-12600, 5_000, 9).unwrap(),
+            "2018-07-04T12:35:09.000005000-03:30"
+        );
+        assert_eq!(format(3208, 0, 0), None);
+
+        let tm = BrokenDownTime::from_utc(253_402_300_800, 0, Vec::new()).unwrap();
+        assert_eq!(format_rfc3339(&tm, 0, 0), None);
+
+        assert_eq!(fraction_digits(0), 0);
+        assert_eq!(fraction_digits(123_456_789), 9);
+        assert_eq!(fraction_digits(500_000_000), 1);
+        assert_eq!(fraction_digits(120_000), 5);
+    }
+}
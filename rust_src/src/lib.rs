@@ -30,6 +30,7 @@ extern crate base64 as base64_crate;
 extern crate libc;
 extern crate md5;
 extern crate rand;
+extern crate regex as regex_crate;
 extern crate sha1;
 extern crate sha2;
 
@@ -62,6 +63,7 @@ mod strings;
 mod vector_macros;
 mod str2sig;
 
+mod aho_corasick;
 mod alloc;
 mod base64;
 mod buffers;
@@ -80,6 +82,7 @@ mod composite;
 mod crypto;
 mod data;
 mod decompress;
+mod diff;
 mod dired;
 #[cfg(unix)]
 mod dired_unix;
@@ -95,11 +98,17 @@ mod filelock;
 mod floatfns;
 mod fns;
 mod fonts;
+mod format;
+#[cfg(unix)]
+mod gitignore;
 mod hashtable;
 mod image;
 mod indent;
+#[cfg(any(feature = "inotify", feature = "notify-polling"))]
+mod inotify;
 mod interactive;
 mod intervals;
+mod iso8601;
 mod keyboard;
 mod keymap;
 mod libm;
@@ -115,6 +124,7 @@ mod objects;
 mod print;
 mod process;
 mod profiler;
+mod regex;
 #[allow(clippy::all)]
 mod remacs_sys;
 mod search;
@@ -124,6 +134,7 @@ mod terminal;
 mod textprop;
 mod threads;
 mod time;
+mod tzif;
 mod util;
 mod vectors;
 mod window_configuration;
@@ -32,20 +32,26 @@
 //! `&str`, and this module regrettably contains adapted copies of
 //! stretches of `std::str` functions.
 
+use std::char;
+use std::cmp::Reverse;
 use std::convert::TryFrom;
 use std::fmt;
+use std::mem;
 use std::ptr;
 use std::slice;
 
 use libc::{c_char, c_int, c_uchar, c_uint, c_void, memset, ptrdiff_t, size_t};
 
+use remacs_macros::lisp_fn;
+
 use crate::{
     hashtable::LispHashTableRef,
     lisp::{ExternalPtr, LispObject, LispStructuralEqual},
+    lists::{list, LispConsCircularChecks, LispConsEndChecks},
     obarray::LispObarrayRef,
     remacs_sys::{
-        buffer_display_table, char_width, compare_string_intervals, empty_unibyte_string,
-        find_composition as c_find_composition, get_composition_id, string_char_to_byte,
+        buffer_display_table, char_width, compare_string_intervals, downcase, empty_unibyte_string,
+        find_composition as c_find_composition, get_composition_id, globals, string_char_to_byte,
     },
     remacs_sys::{
         char_bits, composition_table, equal_kind, EmacsDouble, EmacsInt, Lisp_Interval,
@@ -474,6 +480,11 @@ impl LispStringRef {
         s.intervals = interval;
     }
 
+    /// Whether the string has text properties.
+    pub fn has_intervals(self) -> bool {
+        !unsafe { self.u.s.intervals }.is_null()
+    }
+
     pub fn as_slice(&self) -> &[u8] {
         unsafe { slice::from_raw_parts(self.u.s.data as *const u8, self.len_bytes() as usize) }
     }
@@ -626,7 +637,6 @@ impl LispStringRef {
         }
     }
 
-    #[allow(dead_code)]
     pub fn chars(&self) -> LispStringRefCharIterator {
         LispStringRefCharIterator(self.char_indices())
     }
@@ -1205,3 +1215,294 @@ pub extern "C" fn lisp_string_width(
     };
     width as isize
 }
+
+// Fuzzy matching, for completion.
+
+/// The score of each matched character.
+const FUZZY_MATCH_SCORE: i64 = 16;
+/// The bonus for matching the first character of a word.
+const FUZZY_BOUNDARY_BONUS: i64 = 8;
+/// The bonus for matching an upper-case letter after a lower-case one.
+const FUZZY_CAMEL_BONUS: i64 = 7;
+/// The bonus for matching the character after the previous match.
+const FUZZY_CONSECUTIVE_BONUS: i64 = 8;
+/// The penalty for skipping characters between two matches...
+const FUZZY_GAP_START: i64 = 3;
+/// ...and for each skipped character after the first.
+const FUZZY_GAP_EXTEND: i64 = 1;
+/// The number of skipped characters before the first match that are
+/// penalized, with `FUZZY_GAP_EXTEND` each.
+const FUZZY_MAX_LEADING_GAP: usize = 3;
+
+/// The result of matching a pattern fuzzily against a string.
+#[derive(Debug, PartialEq)]
+pub struct FuzzyMatch {
+    pub score: i64,
+    /// The indexes of the characters of the string that were matched.
+    pub positions: Vec<usize>,
+}
+
+fn fuzzy_separator_p(c: Codepoint) -> bool {
+    char::from_u32(c.val()).map_or(false, |c| c.is_whitespace() || "-_/.:\\".contains(c))
+}
+
+/// Return the bonus for matching the character at INDEX in STRING.
+fn fuzzy_bonus(string: &[Codepoint], index: usize) -> i64 {
+    if index == 0 {
+        return FUZZY_BOUNDARY_BONUS;
+    }
+    let (prev, c) = (string[index - 1], string[index]);
+    if fuzzy_separator_p(prev) && !fuzzy_separator_p(c) {
+        return FUZZY_BOUNDARY_BONUS;
+    }
+    let lower_then_upper = char::from_u32(prev.val())
+        .zip(char::from_u32(c.val()))
+        .map_or(false, |(prev, c)| prev.is_lowercase() && c.is_uppercase());
+    if lower_then_upper {
+        FUZZY_CAMEL_BONUS
+    } else {
+        0
+    }
+}
+
+/// Match PATTERN against STRING, in which its characters must appear
+/// in order but not necessarily next to each other.  Characters are
+/// compared after applying FOLD to them.  Return the best scoring way
+/// to match, or None if there is none.
+pub fn fuzzy_match(
+    pattern: &[Codepoint],
+    string: &[Codepoint],
+    fold: impl Fn(Codepoint) -> Codepoint,
+) -> Option<FuzzyMatch> {
+    if pattern.is_empty() {
+        return Some(FuzzyMatch {
+            score: 0,
+            positions: Vec::new(),
+        });
+    }
+    let mut trail = Vec::new();
+    let (mut j, score) = fuzzy_match_1(pattern, string, fold, Some(&mut trail))?;
+    let mut positions = vec![j; pattern.len()];
+    for (i, from) in trail.iter().enumerate().rev() {
+        let index = from.binary_search_by_key(&j, |&(j, _)| j).unwrap();
+        j = from[index].1;
+        positions[i] = j;
+    }
+    Some(FuzzyMatch { score, positions })
+}
+
+/// Like `fuzzy_match`, but return only the score.
+pub fn fuzzy_score(
+    pattern: &[Codepoint],
+    string: &[Codepoint],
+    fold: impl Fn(Codepoint) -> Codepoint,
+) -> Option<i64> {
+    if pattern.is_empty() {
+        return Some(0);
+    }
+    fuzzy_match_1(pattern, string, fold, None).map(|(_, score)| score)
+}
+
+/// Do the work of `fuzzy_match` for a non-empty PATTERN.  Return the
+/// best score and where the last character of PATTERN is then matched.
+/// If TRAIL is given, push to it, for each character of PATTERN after
+/// the first, the pairs (J, K) sorted by J such that the best way to
+/// match it at J matches the character before it at K.
+fn fuzzy_match_1(
+    pattern: &[Codepoint],
+    string: &[Codepoint],
+    fold: impl Fn(Codepoint) -> Codepoint,
+    mut trail: Option<&mut Vec<Vec<(usize, usize)>>>,
+) -> Option<(usize, i64)> {
+    let (m, n) = (pattern.len(), string.len());
+    if m > n {
+        return None;
+    }
+    let pattern: Vec<_> = pattern.iter().map(|&c| fold(c)).collect();
+    let folded: Vec<_> = string.iter().map(|&c| fold(c)).collect();
+
+    // Most candidates don't match at all, so check that first.
+    let mut rest = folded.iter();
+    if !pattern.iter().all(|c| rest.any(|d| d == c)) {
+        return None;
+    }
+
+    // ROW[J] is the best score for matching the first I + 1 characters
+    // of PATTERN with the last one at J, and PREV is ROW for I - 1.
+    // Only these two rows are kept.
+    let bonuses: Vec<_> = (0..n).map(|j| fuzzy_bonus(string, j)).collect();
+    let mut prev: Vec<Option<i64>> = vec![None; n];
+    let mut row = prev.clone();
+    for j in 0..n {
+        if folded[j] == pattern[0] {
+            let leading = j.min(FUZZY_MAX_LEADING_GAP) as i64;
+            prev[j] = Some(FUZZY_MATCH_SCORE + bonuses[j] - FUZZY_GAP_EXTEND * leading);
+        }
+    }
+    for i in 1..m {
+        for score in &mut row {
+            *score = None;
+        }
+        let mut from = trail.as_ref().map(|_| Vec::new());
+        // The best way to reach J after a gap, and where the gap starts.
+        let mut gap: Option<(i64, usize)> = None;
+        // Matching character I at J leaves room for the rest only if
+        // J < N - (M - 1 - I).
+        for j in i..n - (m - 1 - i) {
+            if j >= 2 {
+                gap = gap.map(|(score, k)| (score - FUZZY_GAP_EXTEND, k));
+                if let Some(score) = prev[j - 2] {
+                    let score = score - FUZZY_GAP_START;
+                    if gap.map_or(true, |(best, _)| score > best) {
+                        gap = Some((score, j - 2));
+                    }
+                }
+            }
+            if folded[j] != pattern[i] {
+                continue;
+            }
+            let consecutive = prev[j - 1].map(|score| (score + FUZZY_CONSECUTIVE_BONUS, j - 1));
+            let best = match (consecutive, gap) {
+                (Some(consecutive), Some(gap)) if gap.0 > consecutive.0 => Some(gap),
+                (consecutive, gap) => consecutive.or(gap),
+            };
+            if let Some((score, k)) = best {
+                row[j] = Some(score + FUZZY_MATCH_SCORE + bonuses[j]);
+                if let Some(from) = from.as_mut() {
+                    from.push((j, k));
+                }
+            }
+        }
+        if let (Some(trail), Some(from)) = (trail.as_mut(), from) {
+            trail.push(from);
+        }
+        mem::swap(&mut prev, &mut row);
+    }
+
+    prev.iter()
+        .enumerate()
+        .filter_map(|(j, score)| score.map(|score| (j, score)))
+        .fold(None, |best: Option<(usize, i64)>, (j, score)| match best {
+            Some((_, best_score)) if best_score >= score => best,
+            _ => Some((j, score)),
+        })
+}
+
+/// Return the function that folds characters for fuzzy completion.
+fn completion_fold() -> impl Fn(Codepoint) -> Codepoint {
+    let ignore_case = unsafe { globals.completion_ignore_case };
+    move |c: Codepoint| {
+        if ignore_case {
+            Codepoint::from(unsafe { downcase(c.val() as c_int) } as u32)
+        } else {
+            c
+        }
+    }
+}
+
+/// Return the string of a completion CANDIDATE, which is a string, a
+/// symbol, or a cons whose car is one of those.
+fn completion_candidate_string(candidate: LispObject) -> LispStringRef {
+    let candidate = candidate.as_cons().map_or(candidate, |cons| cons.car());
+    match candidate.as_symbol() {
+        Some(symbol) => symbol.symbol_name().into(),
+        None => candidate.into(),
+    }
+}
+
+/// Match PATTERN fuzzily against STRING.
+/// The characters of PATTERN must appear in STRING in the same order,
+/// but not necessarily next to each other.  Return nil if they don't,
+/// or else a cons (SCORE . POSITIONS), where POSITIONS lists the indexes
+/// in STRING of the characters matched, chosen to make SCORE as high as
+/// possible.  Matches at the start of words and runs of consecutive
+/// characters score higher; skipped characters score lower.
+/// Case is ignored if `completion-ignore-case' is non-nil.
+#[lisp_fn]
+pub fn string_fuzzy_match(pattern: LispStringRef, string: LispStringRef) -> LispObject {
+    let pattern: Vec<_> = pattern.chars().collect();
+    let chars: Vec<_> = string.chars().collect();
+    match fuzzy_match(&pattern, &chars, completion_fold()) {
+        Some(found) => {
+            let positions: Vec<LispObject> =
+                found.positions.into_iter().map(LispObject::from).collect();
+            LispObject::cons(found.score, list(&positions))
+        }
+        None => Qnil,
+    }
+}
+
+/// Return the elements of CANDIDATES that match PATTERN fuzzily, best first.
+/// CANDIDATES is a list of strings, symbols, or conses whose car is one
+/// of those, as for `all-completions'.  The candidates are matched as by
+/// `string-fuzzy-match', and sorted by decreasing score; candidates with
+/// the same score are sorted shortest first, and then keep their order.
+/// Case is ignored if `completion-ignore-case' is non-nil.
+#[lisp_fn]
+pub fn completion_fuzzy_filter(pattern: LispStringRef, candidates: LispObject) -> LispObject {
+    let pattern: Vec<_> = pattern.chars().collect();
+    let fold = completion_fold();
+    let mut matches: Vec<_> = candidates
+        .iter_cars(LispConsEndChecks::on, LispConsCircularChecks::on)
+        .filter_map(|candidate| {
+            let chars: Vec<_> = completion_candidate_string(candidate).chars().collect();
+            fuzzy_score(&pattern, &chars, &fold).map(|score| (score, chars.len(), candidate))
+        })
+        .collect();
+    matches.sort_by_key(|&(score, len, _)| (Reverse(score), len));
+    let matches: Vec<_> = matches
+        .into_iter()
+        .map(|(_, _, candidate)| candidate)
+        .collect();
+    list(&matches)
+}
+
+include!(concat!(env!("OUT_DIR"), "/multibyte_exports.rs"));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codepoints(s: &str) -> Vec<Codepoint> {
+        s.chars().map(|c| Codepoint::from(c as u32)).collect()
+    }
+
+    fn positions(pattern: &str, string: &str) -> Option<Vec<usize>> {
+        fuzzy_match(&codepoints(pattern), &codepoints(string), |c| c).map(|m| m.positions)
+    }
+
+    #[test]
+    fn test_fuzzy_match_positions() {
+        assert_eq!(positions("fb", "foo-bar"), Some(vec![0, 4]));
+        assert_eq!(
+            positions("fap", "find-file-at-point"),
+            Some(vec![5, 10, 13])
+        );
+        assert_eq!(positions("ab", "aab"), Some(vec![1, 2]));
+        assert_eq!(positions("", "abc"), Some(vec![]));
+        assert_eq!(positions("ba", "abc"), None);
+        assert_eq!(positions("aaa", "aa"), None);
+    }
+
+    #[test]
+    fn test_fuzzy_match_scores() {
+        let lower = |c: Codepoint| match char::from_u32(c.val()) {
+            Some(c) => Codepoint::from(c.to_ascii_lowercase() as u32),
+            None => c,
+        };
+        let score = |pattern, string| {
+            fuzzy_match(&codepoints(pattern), &codepoints(string), lower).map(|m| m.score)
+        };
+        assert!(score("bar", "barfoo") > score("bar", "foo-bar"));
+        assert!(score("bar", "foo-bar") > score("bar", "foobar"));
+        assert!(score("fb", "fooBar") > score("fb", "foobar"));
+        assert!(score("fb", "fb") > score("fb", "f-b"));
+        for (pattern, string) in &[("fap", "find-file-at-point"), ("aa", "a-aa"), ("", "a")] {
+            assert_eq!(
+                fuzzy_score(&codepoints(pattern), &codepoints(string), lower),
+                score(pattern, string)
+            );
+        }
+        assert_eq!(score("abc", "ab"), None);
+    }
+}
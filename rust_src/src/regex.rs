@@ -0,0 +1,1636 @@
+//! A backtracking matcher for Emacs regular expressions.
+//!
+//! Patterns are parsed into a syntax tree and compiled to a small
+//! program, in the spirit of regex.c.  Everything that depends on
+//! the state of the editor (case tables, syntax and category tables,
+//! the position of point) is reached through the `Env` trait, and the
+//! text being matched is reached through `Text`, which may be split in
+//! two around a buffer's gap.  Positions are byte offsets into the
+//! text.
+
+use std::fmt;
+
+use crate::{
+    character::char_head_p,
+    multibyte::{multibyte_char_at, Codepoint},
+};
+
+/// The largest repeat count that can be given with \{M,N\}.
+const RE_DUP_MAX: u32 = 0xFFFF;
+
+/// The largest number of instructions a compiled pattern may have.
+const MAX_PROGRAM_SIZE: usize = 1 << 20;
+
+/// The largest number of backtrack points the matcher keeps.
+const MAX_BACKTRACK: usize = 1 << 21;
+
+/// How many instructions run between two calls to `Env::poll`.
+const POLL_INTERVAL: u32 = 1 << 16;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    /// The pattern is invalid; the message says why.
+    Invalid(&'static str),
+    /// The matcher ran out of backtrack points.
+    Overflow,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Invalid(msg) => write!(f, "{}", msg),
+            Error::Overflow => write!(f, "Stack overflow in regexp matcher"),
+        }
+    }
+}
+
+/// Character properties that `Env` knows about.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Property {
+    Alphabetic,
+    Alphanumeric,
+    Blank,
+    Graphic,
+    Printable,
+    Uppercase,
+    Lowercase,
+}
+
+/// The syntax classes the matcher needs to know about, numbered as
+/// in `enum syntaxcode'.
+pub const SWHITESPACE: u8 = 0;
+pub const SWORD: u8 = 2;
+pub const SSYMBOL: u8 = 3;
+
+/// The editor state a match depends on.
+pub trait Env {
+    /// Return whether case is being ignored.
+    fn folding(&self) -> bool;
+
+    /// Return the canonical case of C, or C if case is significant.
+    fn canon(&self, c: Codepoint) -> Codepoint;
+
+    /// Return the character after C in the cycle of characters that
+    /// have the same canonical case.
+    fn next_equivalent(&self, c: Codepoint) -> Codepoint;
+
+    /// Return the syntax class of C, found at position POS.
+    fn syntax(&self, c: Codepoint, pos: usize) -> u8;
+
+    /// Return whether C belongs to CATEGORY.
+    fn has_category(&self, c: Codepoint, category: u8) -> bool;
+
+    /// Return whether two word constituents C1 and C2, in this order,
+    /// have a word boundary between them.
+    fn word_boundary_p(&self, c1: Codepoint, c2: Codepoint) -> bool;
+
+    fn has_property(&self, c: Codepoint, property: Property) -> bool;
+
+    /// Return the position of point, if the text is a buffer.
+    fn point(&self) -> Option<usize>;
+
+    /// Called every now and then during long matches.
+    fn poll(&self) {}
+}
+
+/// The text to match, made of the contents of FIRST followed by the
+/// contents of SECOND.  No character is split between the two.
+#[derive(Clone, Copy)]
+pub struct Text<'a> {
+    first: &'a [u8],
+    second: &'a [u8],
+    multibyte: bool,
+}
+
+impl<'a> Text<'a> {
+    pub fn new(first: &'a [u8], second: &'a [u8], multibyte: bool) -> Self {
+        Self {
+            first,
+            second,
+            multibyte,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.first.len() + self.second.len()
+    }
+
+    fn bytes_from(&self, pos: usize) -> &'a [u8] {
+        if pos < self.first.len() {
+            &self.first[pos..]
+        } else {
+            &self.second[pos - self.first.len()..]
+        }
+    }
+
+    /// Return the character at POS and its length in bytes.
+    pub fn char_at(&self, pos: usize) -> (Codepoint, usize) {
+        let bytes = self.bytes_from(pos);
+        if self.multibyte {
+            multibyte_char_at(bytes)
+        } else {
+            (Codepoint::from(bytes[0]).unibyte_to_char(), 1)
+        }
+    }
+
+    /// Return the character before POS and its length in bytes.
+    pub fn char_before(&self, pos: usize) -> (Codepoint, usize) {
+        let bytes = if pos <= self.first.len() {
+            &self.first[..pos]
+        } else {
+            &self.second[..pos - self.first.len()]
+        };
+        let mut start = bytes.len() - 1;
+        if self.multibyte {
+            let limit = bytes.len().saturating_sub(5);
+            while start > limit && !char_head_p(bytes[start]) {
+                start -= 1;
+            }
+        }
+        self.char_at(pos - (bytes.len() - start))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Assertion {
+    LineStart,
+    LineEnd,
+    TextStart,
+    TextEnd,
+    Point,
+    WordBoundary,
+    NotWordBoundary,
+    WordStart,
+    WordEnd,
+    SymbolStart,
+    SymbolEnd,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CharClass {
+    Alnum,
+    Alpha,
+    Ascii,
+    Blank,
+    Cntrl,
+    Digit,
+    Graph,
+    Lower,
+    Multibyte,
+    Nonascii,
+    Print,
+    Punct,
+    Space,
+    Unibyte,
+    Upper,
+    Word,
+    Xdigit,
+}
+
+impl CharClass {
+    fn from_name(name: &[Codepoint]) -> Option<Self> {
+        let name: String = name
+            .iter()
+            .map(|c| std::char::from_u32(c.val()).unwrap_or('\u{0}'))
+            .collect();
+        let class = match name.as_str() {
+            "alnum" => CharClass::Alnum,
+            "alpha" => CharClass::Alpha,
+            "ascii" => CharClass::Ascii,
+            "blank" => CharClass::Blank,
+            "cntrl" => CharClass::Cntrl,
+            "digit" => CharClass::Digit,
+            "graph" => CharClass::Graph,
+            "lower" => CharClass::Lower,
+            "multibyte" => CharClass::Multibyte,
+            "nonascii" => CharClass::Nonascii,
+            "print" => CharClass::Print,
+            "punct" => CharClass::Punct,
+            "space" => CharClass::Space,
+            "unibyte" => CharClass::Unibyte,
+            "upper" => CharClass::Upper,
+            "word" => CharClass::Word,
+            "xdigit" => CharClass::Xdigit,
+            _ => return None,
+        };
+        Some(class)
+    }
+
+    fn matches<E: Env>(self, c: Codepoint, pos: usize, env: &E) -> bool {
+        let v = c.val();
+        let ascii = v < 0x80;
+        let ascii_alpha =
+            (v >= 'a' as u32 && v <= 'z' as u32) || (v >= 'A' as u32 && v <= 'Z' as u32);
+        let ascii_digit = v >= '0' as u32 && v <= '9' as u32;
+        match self {
+            CharClass::Alnum if ascii => ascii_alpha || ascii_digit,
+            CharClass::Alnum => env.has_property(c, Property::Alphanumeric),
+            CharClass::Alpha if ascii => ascii_alpha,
+            CharClass::Alpha => env.has_property(c, Property::Alphabetic),
+            CharClass::Ascii => ascii,
+            CharClass::Blank if ascii => v == ' ' as u32 || v == '\t' as u32,
+            CharClass::Blank => env.has_property(c, Property::Blank),
+            CharClass::Cntrl => v < ' ' as u32,
+            CharClass::Digit => ascii_digit,
+            CharClass::Graph if v < 0x100 => v > 0x20 && !(v >= 0x7F && v <= 0xA0),
+            CharClass::Graph => env.has_property(c, Property::Graphic),
+            CharClass::Multibyte => v >= 0x100,
+            CharClass::Nonascii => !ascii,
+            CharClass::Print if v < 0x100 => v >= 0x20 && !(v >= 0x7F && v < 0xA0),
+            CharClass::Print => env.has_property(c, Property::Printable),
+            CharClass::Punct if ascii => v > 0x20 && v < 0x7F && !ascii_alpha && !ascii_digit,
+            CharClass::Punct => env.syntax(c, pos) != SWORD,
+            CharClass::Space => env.syntax(c, pos) == SWHITESPACE,
+            CharClass::Unibyte => v < 0x100,
+            // When ignoring case, [:upper:] and [:lower:] match every
+            // character that has a case.
+            CharClass::Lower | CharClass::Upper if env.folding() => {
+                env.has_property(c, Property::Lowercase) || env.has_property(c, Property::Uppercase)
+            }
+            CharClass::Lower => env.has_property(c, Property::Lowercase),
+            CharClass::Upper => env.has_property(c, Property::Uppercase),
+            CharClass::Word => env.syntax(c, pos) == SWORD,
+            CharClass::Xdigit => {
+                ascii_digit
+                    || (v >= 'a' as u32 && v <= 'f' as u32)
+                    || (v >= 'A' as u32 && v <= 'F' as u32)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct CharSet {
+    negated: bool,
+    ranges: Vec<(Codepoint, Codepoint)>,
+    classes: Vec<CharClass>,
+}
+
+impl CharSet {
+    fn contains<E: Env>(&self, c: Codepoint, pos: usize, env: &E) -> bool {
+        let in_ranges = |c: Codepoint| self.ranges.iter().any(|&(from, to)| from <= c && c <= to);
+        let mut found = in_ranges(c) || self.classes.iter().any(|class| class.matches(c, pos, env));
+        if !found && env.folding() {
+            // Try the other characters with the same canonical case.
+            let mut equivalent = env.next_equivalent(c);
+            for _ in 0..8 {
+                if equivalent == c {
+                    break;
+                }
+                if in_ranges(equivalent) {
+                    found = true;
+                    break;
+                }
+                equivalent = env.next_equivalent(equivalent);
+            }
+        }
+        found != self.negated
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    Empty,
+    Char(Codepoint),
+    Any,
+    Set(Box<CharSet>),
+    Assert(Assertion),
+    Syntax(u8, bool),
+    Category(u8, bool),
+    Backref(usize),
+    Group(Option<usize>, Box<Node>),
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: u32,
+        max: Option<u32>,
+        greedy: bool,
+    },
+}
+
+impl Node {
+    /// Return whether the node can match the empty string.
+    fn nullable(&self) -> bool {
+        match self {
+            Node::Empty | Node::Assert(_) | Node::Backref(_) => true,
+            Node::Char(_) | Node::Any | Node::Set(_) | Node::Syntax(..) | Node::Category(..) => {
+                false
+            }
+            Node::Group(_, node) => node.nullable(),
+            Node::Concat(nodes) => nodes.iter().all(Node::nullable),
+            Node::Alt(nodes) => nodes.iter().any(Node::nullable),
+            Node::Repeat { node, min, .. } => *min == 0 || node.nullable(),
+        }
+    }
+
+    /// Return whether the node matches exactly one character.
+    fn is_single_char(&self) -> bool {
+        matches!(
+            self,
+            Node::Char(_) | Node::Any | Node::Set(_) | Node::Syntax(..) | Node::Category(..)
+        )
+    }
+}
+
+/// Return the syntax class designated by C in \sC, or None.
+fn syntax_class(c: Codepoint) -> Option<u8> {
+    let code = match std::char::from_u32(c.val())? {
+        ' ' | '-' => 0,
+        '.' => 1,
+        'w' => 2,
+        '_' => 3,
+        '(' => 4,
+        ')' => 5,
+        '\'' => 6,
+        '"' => 7,
+        '$' => 8,
+        '\\' => 9,
+        '/' => 10,
+        '<' => 11,
+        '>' => 12,
+        '@' => 13,
+        '!' => 14,
+        '|' => 15,
+        _ => return None,
+    };
+    Some(code)
+}
+
+struct Parser<'a> {
+    pattern: &'a [Codepoint],
+    pos: usize,
+    /// The number of the highest group so far.
+    groups: usize,
+    /// The groups that have been closed.
+    closed: Vec<bool>,
+    /// Whether groups capture; not when parsing the whitespace regexp.
+    capture: bool,
+    whitespace: Option<&'a Node>,
+}
+
+fn is(c: Codepoint, ch: char) -> bool {
+    c.val() == ch as u32
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<Codepoint> {
+        self.pattern.get(self.pos).copied()
+    }
+
+    fn peek_is(&self, offset: usize, ch: char) -> bool {
+        self.pattern
+            .get(self.pos + offset)
+            .map_or(false, |&c| is(c, ch))
+    }
+
+    fn fetch(&mut self) -> Result<Codepoint, Error> {
+        let c = self
+            .peek()
+            .ok_or(Error::Invalid("Premature end of regular expression"))?;
+        self.pos += 1;
+        Ok(c)
+    }
+
+    /// Return whether the parser is at the end of an alternative, i.e.
+    /// before \| or \) or at the end of the pattern.
+    fn at_alternative_end(&self, offset: usize) -> bool {
+        self.pos + offset == self.pattern.len()
+            || (self.peek_is(offset, '\\')
+                && (self.peek_is(offset + 1, '|') || self.peek_is(offset + 1, ')')))
+    }
+
+    fn parse_alternatives(&mut self, depth: usize) -> Result<Node, Error> {
+        let mut alternatives = vec![self.parse_sequence()?];
+        loop {
+            if self.pos == self.pattern.len() {
+                if depth > 0 {
+                    return Err(Error::Invalid("Unmatched ( or \\("));
+                }
+                break;
+            }
+            // We are before \| or \).
+            self.pos += 2;
+            if is(self.pattern[self.pos - 1], ')') {
+                if depth == 0 {
+                    return Err(Error::Invalid("Unmatched ) or \\)"));
+                }
+                break;
+            }
+            alternatives.push(self.parse_sequence()?);
+        }
+        Ok(if alternatives.len() == 1 {
+            alternatives.pop().unwrap()
+        } else {
+            Node::Alt(alternatives)
+        })
+    }
+
+    fn parse_sequence(&mut self) -> Result<Node, Error> {
+        let mut nodes: Vec<Node> = Vec::new();
+        let start = self.pos;
+        while !self.at_alternative_end(0) {
+            let c = self.fetch()?;
+            let node = match std::char::from_u32(c.val()).unwrap_or('\u{0}') {
+                '^' if self.pos - 1 == start => Node::Assert(Assertion::LineStart),
+                '$' if self.at_alternative_end(0) => Node::Assert(Assertion::LineEnd),
+                '*' | '+' | '?' if nodes.last().map_or(false, Self::repeatable) => {
+                    let (min, max) = match std::char::from_u32(c.val()) {
+                        Some('*') => (0, None),
+                        Some('+') => (1, None),
+                        _ => (0, Some(1)),
+                    };
+                    self.repeat(&mut nodes, min, max);
+                    continue;
+                }
+                '.' => Node::Any,
+                '[' => self.parse_set()?,
+                ' ' if self.whitespace.is_some() && !self.before_repetition() => {
+                    while self.peek().map_or(false, |c| is(c, ' ')) {
+                        self.pos += 1;
+                    }
+                    self.whitespace.unwrap().clone()
+                }
+                '\\' => {
+                    let c = self.peek().ok_or(Error::Invalid("Trailing backslash"))?;
+                    if is(c, '{') && nodes.last().map_or(false, Self::repeatable) {
+                        self.pos += 1;
+                        let (min, max) = self.parse_interval()?;
+                        self.repeat(&mut nodes, min, max);
+                        continue;
+                    }
+                    self.pos += 1;
+                    self.parse_escape(c)?
+                }
+                _ => Node::Char(c),
+            };
+            nodes.push(node);
+        }
+        Ok(match nodes.len() {
+            0 => Node::Empty,
+            1 => nodes.pop().unwrap(),
+            _ => Node::Concat(nodes),
+        })
+    }
+
+    /// Return whether the parser is before spaces followed by a
+    /// repetition operator.
+    fn before_repetition(&self) -> bool {
+        let mut offset = 0;
+        while self.peek_is(offset, ' ') {
+            offset += 1;
+        }
+        self.peek_is(offset, '*')
+            || self.peek_is(offset, '+')
+            || self.peek_is(offset, '?')
+            || (self.peek_is(offset, '\\') && self.peek_is(offset + 1, '{'))
+    }
+
+    fn repeatable(node: &Node) -> bool {
+        !matches!(node, Node::Empty | Node::Assert(_))
+    }
+
+    /// Apply a repetition to the last node of NODES, handling the
+    /// non-greedy forms *?, +? and ??.
+    fn repeat(&mut self, nodes: &mut Vec<Node>, min: u32, max: Option<u32>) {
+        let greedy = !self.peek().map_or(false, |c| is(c, '?'));
+        if !greedy {
+            self.pos += 1;
+        }
+        let node = nodes.pop().unwrap();
+        nodes.push(Node::Repeat {
+            node: Box::new(node),
+            min,
+            max,
+            greedy,
+        });
+    }
+
+    fn parse_number(&mut self) -> Option<u32> {
+        let mut value: Option<u32> = None;
+        while let Some(digit) = self
+            .peek()
+            .and_then(|c| std::char::from_u32(c.val())?.to_digit(10))
+        {
+            self.pos += 1;
+            value = Some(value.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+        }
+        value
+    }
+
+    /// Parse the rest of \{M,N\}.
+    fn parse_interval(&mut self) -> Result<(u32, Option<u32>), Error> {
+        let min = self.parse_number();
+        let max = if self.peek().map_or(false, |c| is(c, ',')) {
+            self.pos += 1;
+            self.parse_number()
+        } else {
+            Some(min.unwrap_or(0))
+        };
+        if self.pos >= self.pattern.len() {
+            return Err(Error::Invalid("Unmatched \\{"));
+        }
+        if !(self.peek_is(0, '\\') && self.peek_is(1, '}')) {
+            return Err(Error::Invalid("Invalid content of \\{\\}"));
+        }
+        self.pos += 2;
+        let min = min.unwrap_or(0);
+        match max {
+            Some(max) if max < min || max > RE_DUP_MAX => {
+                Err(Error::Invalid("Invalid content of \\{\\}"))
+            }
+            _ if min > RE_DUP_MAX => Err(Error::Invalid("Invalid content of \\{\\}")),
+            _ => Ok((min, max)),
+        }
+    }
+
+    fn parse_escape(&mut self, c: Codepoint) -> Result<Node, Error> {
+        let node = match std::char::from_u32(c.val()).unwrap_or('\u{0}') {
+            '(' => return self.parse_group(),
+            '1'..='9' => {
+                let group = (c.val() - '0' as u32) as usize;
+                if group > self.groups || !self.closed[group] {
+                    return Err(Error::Invalid("Invalid back reference"));
+                }
+                Node::Backref(group)
+            }
+            'w' => Node::Syntax(SWORD, false),
+            'W' => Node::Syntax(SWORD, true),
+            's' | 'S' => {
+                let class = syntax_class(self.fetch()?)
+                    .ok_or(Error::Invalid("Invalid regular expression"))?;
+                Node::Syntax(class, is(c, 'S'))
+            }
+            'c' | 'C' => {
+                let category = self.fetch()?;
+                if category.val() < 0x20 || category.val() > 0x7E {
+                    return Err(Error::Invalid("Invalid regular expression"));
+                }
+                Node::Category(category.val() as u8, is(c, 'C'))
+            }
+            '`' => Node::Assert(Assertion::TextStart),
+            '\'' => Node::Assert(Assertion::TextEnd),
+            '=' => Node::Assert(Assertion::Point),
+            'b' => Node::Assert(Assertion::WordBoundary),
+            'B' => Node::Assert(Assertion::NotWordBoundary),
+            '<' => Node::Assert(Assertion::WordStart),
+            '>' => Node::Assert(Assertion::WordEnd),
+            '_' => match std::char::from_u32(self.fetch()?.val()) {
+                Some('<') => Node::Assert(Assertion::SymbolStart),
+                Some('>') => Node::Assert(Assertion::SymbolEnd),
+                _ => return Err(Error::Invalid("Invalid regular expression")),
+            },
+            _ => Node::Char(c),
+        };
+        Ok(node)
+    }
+
+    /// Parse a group, after its \(.
+    fn parse_group(&mut self) -> Result<Node, Error> {
+        let mut number = None;
+        if self.peek().map_or(false, |c| is(c, '?')) {
+            self.pos += 1;
+            // Shy groups are \(?: and explicitly numbered ones \(?N:.
+            if let Some(n) = self.parse_number() {
+                if n == 0 {
+                    return Err(Error::Invalid("Invalid regular expression"));
+                }
+                number = Some(n as usize);
+            }
+            if !self.fetch().map_or(false, |c| is(c, ':')) {
+                return Err(Error::Invalid("Invalid regular expression"));
+            }
+        } else if self.capture {
+            number = Some(self.groups + 1);
+        }
+        if let Some(n) = number {
+            if !self.capture {
+                number = None;
+            } else {
+                if n <= self.groups && !self.closed[n] {
+                    // The group is still open.
+                    return Err(Error::Invalid("Invalid regular expression"));
+                }
+                self.groups = self.groups.max(n);
+                self.closed.resize(self.groups + 1, false);
+                self.closed[n] = false;
+            }
+        }
+
+        let node = self.parse_alternatives(1)?;
+        if let Some(n) = number {
+            self.closed[n] = true;
+        }
+        Ok(Node::Group(number, Box::new(node)))
+    }
+
+    /// Parse a bracket expression, after its [.
+    fn parse_set(&mut self) -> Result<Node, Error> {
+        let unmatched = Error::Invalid("Unmatched [ or [^");
+        let mut set = CharSet::default();
+        if self.peek().map_or(false, |c| is(c, '^')) {
+            self.pos += 1;
+            set.negated = true;
+        }
+        let mut first = true;
+        loop {
+            let c = self.peek().ok_or(unmatched)?;
+            self.pos += 1;
+            if is(c, ']') && !first {
+                break;
+            }
+            first = false;
+
+            if is(c, '[') && self.peek().map_or(false, |c| is(c, ':')) {
+                // A character class, if there is a closing :].
+                let name_start = self.pos + 1;
+                let close = (name_start..self.pattern.len().saturating_sub(1))
+                    .find(|&i| is(self.pattern[i], ':') && is(self.pattern[i + 1], ']'));
+                if let Some(close) = close {
+                    let class = CharClass::from_name(&self.pattern[name_start..close])
+                        .ok_or(Error::Invalid("Invalid character class name"))?;
+                    set.classes.push(class);
+                    self.pos = close + 2;
+                    continue;
+                }
+            }
+
+            if self.peek_is(0, '-')
+                && self
+                    .pattern
+                    .get(self.pos + 1)
+                    .map_or(false, |&c| !is(c, ']'))
+            {
+                self.pos += 1;
+                let end = self.peek().ok_or(unmatched)?;
+                self.pos += 1;
+                // A reversed range is empty.
+                if c <= end {
+                    set.ranges.push((c, end));
+                }
+            } else {
+                set.ranges.push((c, c));
+            }
+        }
+        Ok(Node::Set(Box::new(set)))
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Inst {
+    Char(Codepoint),
+    Any,
+    Set(Box<CharSet>),
+    Assert(Assertion),
+    Syntax(u8, bool),
+    Category(u8, bool),
+    Backref(usize),
+    /// Store the position in a register.
+    Save(usize),
+    /// Continue at the first address, backtracking to the second.
+    Split(usize, usize),
+    Jump(usize),
+    /// Match as many characters as possible with the instruction that
+    /// follows, backtracking one character at a time.
+    Star,
+    /// Store the position in a progress mark.
+    Mark(usize),
+    /// Leave the loop that ends at the given address unless the
+    /// position moved since the progress mark was set.
+    Progress(usize, usize),
+    Match,
+}
+
+struct Compiler {
+    program: Vec<Inst>,
+    marks: usize,
+}
+
+impl Compiler {
+    fn emit(&mut self, inst: Inst) -> Result<usize, Error> {
+        if self.program.len() >= MAX_PROGRAM_SIZE {
+            return Err(Error::Invalid("Regular expression too big"));
+        }
+        self.program.push(inst);
+        Ok(self.program.len() - 1)
+    }
+
+    fn compile(&mut self, node: &Node) -> Result<(), Error> {
+        match node {
+            Node::Empty => {}
+            Node::Char(c) => {
+                self.emit(Inst::Char(*c))?;
+            }
+            Node::Any => {
+                self.emit(Inst::Any)?;
+            }
+            Node::Set(set) => {
+                self.emit(Inst::Set(set.clone()))?;
+            }
+            Node::Assert(assertion) => {
+                self.emit(Inst::Assert(*assertion))?;
+            }
+            Node::Syntax(class, negated) => {
+                self.emit(Inst::Syntax(*class, *negated))?;
+            }
+            Node::Category(category, negated) => {
+                self.emit(Inst::Category(*category, *negated))?;
+            }
+            Node::Backref(group) => {
+                self.emit(Inst::Backref(*group))?;
+            }
+            Node::Group(number, node) => {
+                if let Some(n) = number {
+                    self.emit(Inst::Save(2 * n))?;
+                }
+                self.compile(node)?;
+                if let Some(n) = number {
+                    self.emit(Inst::Save(2 * n + 1))?;
+                }
+            }
+            Node::Concat(nodes) => {
+                for node in nodes {
+                    self.compile(node)?;
+                }
+            }
+            Node::Alt(nodes) => {
+                let mut jumps = Vec::new();
+                for (i, node) in nodes.iter().enumerate() {
+                    let split = if i + 1 < nodes.len() {
+                        Some(self.emit(Inst::Split(0, 0))?)
+                    } else {
+                        None
+                    };
+                    self.compile(node)?;
+                    if let Some(split) = split {
+                        jumps.push(self.emit(Inst::Jump(0))?);
+                        let next = self.program.len();
+                        self.program[split] = Inst::Split(split + 1, next);
+                    }
+                }
+                let end = self.program.len();
+                for jump in jumps {
+                    self.program[jump] = Inst::Jump(end);
+                }
+            }
+            Node::Repeat {
+                node,
+                min,
+                max,
+                greedy,
+            } => {
+                for _ in 0..*min {
+                    self.compile(node)?;
+                }
+                match max {
+                    None if *greedy && node.is_single_char() => {
+                        self.emit(Inst::Star)?;
+                        self.compile(node)?;
+                    }
+                    None => {
+                        let nullable = node.nullable();
+                        let split = self.emit(Inst::Split(0, 0))?;
+                        let mark = self.marks;
+                        if nullable {
+                            self.marks += 1;
+                            self.emit(Inst::Mark(mark))?;
+                        }
+                        self.compile(node)?;
+                        let progress = if nullable {
+                            Some(self.emit(Inst::Progress(mark, 0))?)
+                        } else {
+                            None
+                        };
+                        self.emit(Inst::Jump(split))?;
+                        let end = self.program.len();
+                        self.program[split] = self.split(*greedy, split + 1, end);
+                        if let Some(progress) = progress {
+                            self.program[progress] = Inst::Progress(mark, end);
+                        }
+                    }
+                    Some(max) => {
+                        let mut splits = Vec::new();
+                        for _ in *min..*max {
+                            splits.push(self.emit(Inst::Split(0, 0))?);
+                            self.compile(node)?;
+                        }
+                        let end = self.program.len();
+                        for split in splits {
+                            self.program[split] = self.split(*greedy, split + 1, end);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn split(&self, greedy: bool, body: usize, exit: usize) -> Inst {
+        if greedy {
+            Inst::Split(body, exit)
+        } else {
+            Inst::Split(exit, body)
+        }
+    }
+}
+
+/// The positions of a match and of its subexpressions.
+pub type Registers = Vec<Option<(usize, usize)>>;
+
+/// A compiled regular expression.
+#[derive(Debug)]
+pub struct Regex {
+    program: Vec<Inst>,
+    /// The number of subexpressions.
+    groups: usize,
+    marks: usize,
+    posix: bool,
+    /// Whether the pattern can only match at the start of the text.
+    anchored: bool,
+    /// The character every match starts with, if any.
+    first_char: Option<Codepoint>,
+}
+
+impl Regex {
+    /// Compile PATTERN.  If WHITESPACE is given, runs of spaces in
+    /// PATTERN match it instead of themselves.  If POSIX, matches are
+    /// the longest possible ones.
+    pub fn new(
+        pattern: &[Codepoint],
+        whitespace: Option<&[Codepoint]>,
+        posix: bool,
+    ) -> Result<Self, Error> {
+        let whitespace = match whitespace {
+            Some(whitespace) => {
+                let mut parser = Parser {
+                    pattern: whitespace,
+                    pos: 0,
+                    groups: 0,
+                    closed: vec![false],
+                    capture: false,
+                    whitespace: None,
+                };
+                Some(parser.parse_alternatives(0)?)
+            }
+            None => None,
+        };
+        let mut parser = Parser {
+            pattern,
+            pos: 0,
+            groups: 0,
+            closed: vec![false],
+            capture: true,
+            whitespace: whitespace.as_ref(),
+        };
+        let node = parser.parse_alternatives(0)?;
+        let groups = parser.groups;
+
+        let mut compiler = Compiler {
+            program: Vec::new(),
+            marks: 0,
+        };
+        compiler.emit(Inst::Save(0))?;
+        compiler.compile(&node)?;
+        compiler.emit(Inst::Save(1))?;
+        compiler.emit(Inst::Match)?;
+
+        let (anchored, first_char) = match compiler.program.get(1) {
+            Some(Inst::Assert(Assertion::TextStart)) => (true, None),
+            Some(Inst::Char(c)) => (false, Some(*c)),
+            _ => (false, None),
+        };
+        Ok(Self {
+            program: compiler.program,
+            groups,
+            marks: compiler.marks,
+            posix,
+            anchored,
+            first_char,
+        })
+    }
+
+    /// Return the number of subexpressions.
+    pub fn groups(&self) -> usize {
+        self.groups
+    }
+
+    /// Match against TEXT at START, consuming nothing beyond STOP.
+    pub fn match_at<E: Env>(
+        &self,
+        text: &Text,
+        env: &E,
+        start: usize,
+        stop: usize,
+    ) -> Result<Option<Registers>, Error> {
+        Matcher::new(self, text, env, stop).run(start)
+    }
+
+    /// Search TEXT for a match starting between START and LAST,
+    /// inclusive, trying the starting positions in order, which is
+    /// backwards if LAST is before START.  Matches may not extend
+    /// beyond STOP.
+    pub fn search<E: Env>(
+        &self,
+        text: &Text,
+        env: &E,
+        start: usize,
+        last: usize,
+        stop: usize,
+    ) -> Result<Option<Registers>, Error> {
+        let mut matcher = Matcher::new(self, text, env, stop);
+        if self.anchored {
+            return if start.min(last) == 0 {
+                matcher.run(0)
+            } else {
+                Ok(None)
+            };
+        }
+        let mut pos = start;
+        loop {
+            let candidate = match self.first_char {
+                Some(c) if pos < stop => {
+                    let (found, _) = text.char_at(pos);
+                    found == c || (env.folding() && env.canon(found) == env.canon(c))
+                }
+                Some(_) => false,
+                None => true,
+            };
+            if candidate {
+                if let Some(registers) = matcher.run(pos)? {
+                    return Ok(Some(registers));
+                }
+            }
+            if pos == last {
+                return Ok(None);
+            }
+            if last > start {
+                pos += text.char_at(pos).1;
+            } else {
+                pos -= text.char_before(pos).1;
+            }
+        }
+    }
+}
+
+/// A point to backtrack to.
+enum Frame {
+    Retry(usize, usize),
+    /// Retry the instruction after a `Star` with one character less,
+    /// down to the position given first.
+    Star(usize, usize, usize),
+    Register(usize, Option<usize>),
+    Mark(usize, usize),
+}
+
+struct Matcher<'a, 'b, E: Env> {
+    regex: &'a Regex,
+    text: &'a Text<'b>,
+    env: &'a E,
+    stop: usize,
+    registers: Vec<Option<usize>>,
+    marks: Vec<usize>,
+    stack: Vec<Frame>,
+    steps: u32,
+}
+
+impl<'a, 'b, E: Env> Matcher<'a, 'b, E> {
+    fn new(regex: &'a Regex, text: &'a Text<'b>, env: &'a E, stop: usize) -> Self {
+        Self {
+            regex,
+            text,
+            env,
+            stop,
+            registers: vec![None; 2 * regex.groups + 2],
+            marks: vec![0; regex.marks],
+            stack: Vec::new(),
+            steps: 0,
+        }
+    }
+
+    fn registers(&self) -> Registers {
+        self.registers
+            .chunks(2)
+            .map(|pair| match (pair[0], pair[1]) {
+                (Some(start), Some(end)) => Some((start, end)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn push(&mut self, frame: Frame) -> Result<(), Error> {
+        if self.stack.len() >= MAX_BACKTRACK {
+            return Err(Error::Overflow);
+        }
+        self.stack.push(frame);
+        Ok(())
+    }
+
+    /// Return the syntax class of the character before POS, or None at
+    /// the start of the text.
+    fn syntax_before(&self, pos: usize) -> Option<(Codepoint, u8)> {
+        if pos == 0 {
+            None
+        } else {
+            let (c, len) = self.text.char_before(pos);
+            Some((c, self.env.syntax(c, pos - len)))
+        }
+    }
+
+    /// Return the syntax class of the character after POS, or None at
+    /// the end of the text.
+    fn syntax_after(&self, pos: usize) -> Option<(Codepoint, u8)> {
+        if pos == self.text.len() {
+            None
+        } else {
+            let (c, _) = self.text.char_at(pos);
+            Some((c, self.env.syntax(c, pos)))
+        }
+    }
+
+    fn check(&self, assertion: Assertion, pos: usize) -> bool {
+        let newline = |c: Codepoint| is(c, '\n');
+        match assertion {
+            Assertion::LineStart => pos == 0 || newline(self.text.char_before(pos).0),
+            Assertion::LineEnd => pos == self.text.len() || newline(self.text.char_at(pos).0),
+            Assertion::TextStart => pos == 0,
+            Assertion::TextEnd => pos == self.text.len(),
+            Assertion::Point => self.env.point() == Some(pos),
+            Assertion::WordBoundary | Assertion::NotWordBoundary => {
+                let boundary = match (self.syntax_before(pos), self.syntax_after(pos)) {
+                    (Some((c1, s1)), Some((c2, s2))) => {
+                        (s1 == SWORD) != (s2 == SWORD)
+                            || (s1 == SWORD && self.env.word_boundary_p(c1, c2))
+                    }
+                    _ => true,
+                };
+                boundary == (assertion == Assertion::WordBoundary)
+            }
+            Assertion::WordStart => match self.syntax_after(pos) {
+                Some((c2, SWORD)) => match self.syntax_before(pos) {
+                    Some((c1, SWORD)) => self.env.word_boundary_p(c1, c2),
+                    _ => true,
+                },
+                _ => false,
+            },
+            Assertion::WordEnd => match self.syntax_before(pos) {
+                Some((c1, SWORD)) => match self.syntax_after(pos) {
+                    Some((c2, SWORD)) => self.env.word_boundary_p(c1, c2),
+                    _ => true,
+                },
+                _ => false,
+            },
+            Assertion::SymbolStart => {
+                let constituent = |s: Option<(Codepoint, u8)>| {
+                    s.map_or(false, |(_, s)| s == SWORD || s == SSYMBOL)
+                };
+                constituent(self.syntax_after(pos)) && !constituent(self.syntax_before(pos))
+            }
+            Assertion::SymbolEnd => {
+                let constituent = |s: Option<(Codepoint, u8)>| {
+                    s.map_or(false, |(_, s)| s == SWORD || s == SSYMBOL)
+                };
+                constituent(self.syntax_before(pos)) && !constituent(self.syntax_after(pos))
+            }
+        }
+    }
+
+    /// If the single-character instruction INST matches at POS, return
+    /// the position after the character.
+    fn step(&self, inst: &Inst, pos: usize) -> Option<usize> {
+        if pos >= self.stop {
+            return None;
+        }
+        let (c, len) = self.text.char_at(pos);
+        let matched = match inst {
+            Inst::Char(expected) => {
+                c == *expected
+                    || (self.env.folding() && self.env.canon(c) == self.env.canon(*expected))
+            }
+            Inst::Any => !is(c, '\n'),
+            Inst::Set(set) => set.contains(c, pos, self.env),
+            Inst::Syntax(class, negated) => (self.env.syntax(c, pos) == *class) != *negated,
+            Inst::Category(category, negated) => self.env.has_category(c, *category) != *negated,
+            _ => unreachable!(),
+        };
+        if matched {
+            Some(pos + len)
+        } else {
+            None
+        }
+    }
+
+    /// If the text of group GROUP matches at POS, return the position
+    /// after it.
+    fn backref(&self, group: usize, mut pos: usize) -> Option<usize> {
+        let (mut from, to) = match (self.registers[2 * group], self.registers[2 * group + 1]) {
+            (Some(from), Some(to)) if from <= to => (from, to),
+            _ => return None,
+        };
+        while from < to {
+            if pos >= self.stop {
+                return None;
+            }
+            let (expected, len1) = self.text.char_at(from);
+            let (c, len2) = self.text.char_at(pos);
+            if c != expected
+                && !(self.env.folding() && self.env.canon(c) == self.env.canon(expected))
+            {
+                return None;
+            }
+            from += len1;
+            pos += len2;
+        }
+        Some(pos)
+    }
+
+    fn run(&mut self, start: usize) -> Result<Option<Registers>, Error> {
+        for register in &mut self.registers {
+            *register = None;
+        }
+        self.stack.clear();
+        let mut best: Option<Registers> = None;
+        let (mut pc, mut pos) = (0, start);
+        loop {
+            self.steps += 1;
+            if self.steps == POLL_INTERVAL {
+                self.steps = 0;
+                self.env.poll();
+            }
+            let regex: &'a Regex = self.regex;
+            let program = &regex.program;
+            let next = match &program[pc] {
+                inst @ Inst::Char(_)
+                | inst @ Inst::Any
+                | inst @ Inst::Set(_)
+                | inst @ Inst::Syntax(..)
+                | inst @ Inst::Category(..) => self.step(inst, pos).map(|pos| (pc + 1, pos)),
+                Inst::Assert(assertion) => {
+                    if self.check(*assertion, pos) {
+                        Some((pc + 1, pos))
+                    } else {
+                        None
+                    }
+                }
+                Inst::Backref(group) => self.backref(*group, pos).map(|pos| (pc + 1, pos)),
+                Inst::Save(register) => {
+                    let register = *register;
+                    let old = self.registers[register];
+                    self.push(Frame::Register(register, old))?;
+                    self.registers[register] = Some(pos);
+                    Some((pc + 1, pos))
+                }
+                Inst::Split(first, second) => {
+                    let (first, second) = (*first, *second);
+                    self.push(Frame::Retry(second, pos))?;
+                    Some((first, pos))
+                }
+                Inst::Jump(target) => Some((*target, pos)),
+                Inst::Star => {
+                    let inst = &program[pc + 1];
+                    let mut end = pos;
+                    while let Some(next) = self.step(inst, end) {
+                        end = next;
+                    }
+                    if end > pos {
+                        self.push(Frame::Star(pc + 2, pos, end))?;
+                    }
+                    Some((pc + 2, end))
+                }
+                Inst::Mark(mark) => {
+                    let mark = *mark;
+                    let old = self.marks[mark];
+                    self.push(Frame::Mark(mark, old))?;
+                    self.marks[mark] = pos;
+                    Some((pc + 1, pos))
+                }
+                Inst::Progress(mark, exit) => {
+                    if self.marks[*mark] == pos {
+                        Some((*exit, pos))
+                    } else {
+                        Some((pc + 1, pos))
+                    }
+                }
+                Inst::Match => {
+                    if !self.regex.posix {
+                        return Ok(Some(self.registers()));
+                    }
+                    // Keep looking for a longer match.
+                    let longest = best.as_ref().map_or(0, |best| best[0].unwrap().1);
+                    if best.is_none() || pos > longest {
+                        best = Some(self.registers());
+                    }
+                    if pos == self.stop {
+                        return Ok(best);
+                    }
+                    None
+                }
+            };
+
+            match next {
+                Some((next_pc, next_pos)) => {
+                    pc = next_pc;
+                    pos = next_pos;
+                }
+                None => loop {
+                    match self.stack.pop() {
+                        None => return Ok(best),
+                        Some(Frame::Retry(retry_pc, retry_pos)) => {
+                            pc = retry_pc;
+                            pos = retry_pos;
+                            break;
+                        }
+                        Some(Frame::Star(retry_pc, min, end)) => {
+                            let end = end - self.text.char_before(end).1;
+                            if end > min {
+                                self.stack.push(Frame::Star(retry_pc, min, end));
+                            }
+                            pc = retry_pc;
+                            pos = end;
+                            break;
+                        }
+                        Some(Frame::Register(register, old)) => self.registers[register] = old,
+                        Some(Frame::Mark(mark, old)) => self.marks[mark] = old,
+                    }
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An environment with ASCII case folding, where letters, digits
+    /// and Greek letters are word constituents, `_' is a symbol
+    /// constituent and Greek letters are in category `g'.
+    struct MockEnv {
+        folding: bool,
+        point: Option<usize>,
+    }
+
+    fn env() -> MockEnv {
+        MockEnv {
+            folding: false,
+            point: None,
+        }
+    }
+
+    fn is_greek(c: Codepoint) -> bool {
+        c.val() >= 0x370 && c.val() < 0x400
+    }
+
+    impl Env for MockEnv {
+        fn folding(&self) -> bool {
+            self.folding
+        }
+
+        fn canon(&self, c: Codepoint) -> Codepoint {
+            match std::char::from_u32(c.val()) {
+                Some(ch) if self.folding && ch.is_ascii_uppercase() => {
+                    Codepoint::from(ch.to_ascii_lowercase() as u32)
+                }
+                _ => c,
+            }
+        }
+
+        fn next_equivalent(&self, c: Codepoint) -> Codepoint {
+            match std::char::from_u32(c.val()) {
+                Some(ch) if self.folding && ch.is_ascii_uppercase() => {
+                    Codepoint::from(ch.to_ascii_lowercase() as u32)
+                }
+                Some(ch) if self.folding && ch.is_ascii_lowercase() => {
+                    Codepoint::from(ch.to_ascii_uppercase() as u32)
+                }
+                _ => c,
+            }
+        }
+
+        fn syntax(&self, c: Codepoint, _pos: usize) -> u8 {
+            match std::char::from_u32(c.val()) {
+                Some(ch) if ch.is_ascii_alphanumeric() || is_greek(c) => SWORD,
+                Some('_') => SSYMBOL,
+                Some(' ') | Some('\t') | Some('\n') => SWHITESPACE,
+                Some('(') => 4,
+                _ => 1,
+            }
+        }
+
+        fn has_category(&self, c: Codepoint, category: u8) -> bool {
+            category == b'g' && is_greek(c)
+        }
+
+        fn word_boundary_p(&self, c1: Codepoint, c2: Codepoint) -> bool {
+            is_greek(c1) != is_greek(c2)
+        }
+
+        fn has_property(&self, c: Codepoint, property: Property) -> bool {
+            let ch = std::char::from_u32(c.val()).unwrap();
+            match property {
+                Property::Alphabetic => ch.is_alphabetic(),
+                Property::Alphanumeric => ch.is_alphanumeric(),
+                Property::Blank => ch == '\u{a0}' || ch == '\u{3000}',
+                Property::Graphic | Property::Printable => !ch.is_control(),
+                Property::Uppercase => ch.is_uppercase(),
+                Property::Lowercase => ch.is_lowercase(),
+            }
+        }
+
+        fn point(&self) -> Option<usize> {
+            self.point
+        }
+    }
+
+    fn chars(s: &str) -> Vec<Codepoint> {
+        s.chars().map(|c| Codepoint::from(c as u32)).collect()
+    }
+
+    fn compile(pattern: &str) -> Regex {
+        Regex::new(&chars(pattern), None, false).unwrap()
+    }
+
+    /// Return the registers of the first match of PATTERN in TEXT, as
+    /// byte offsets.
+    fn search_with(env: &MockEnv, pattern: &str, text: &str) -> Option<Registers> {
+        let regex = compile(pattern);
+        let text = Text::new(text.as_bytes(), &[], true);
+        regex.search(&text, env, 0, text.len(), text.len()).unwrap()
+    }
+
+    fn find(pattern: &str, text: &str) -> Option<(usize, usize)> {
+        search_with(&env(), pattern, text).map(|registers| registers[0].unwrap())
+    }
+
+    fn groups(pattern: &str, text: &str) -> Vec<Option<&'static str>> {
+        let text: &'static str = Box::leak(text.to_string().into_boxed_str());
+        search_with(&env(), pattern, text)
+            .unwrap()
+            .into_iter()
+            .map(|group| group.map(|(start, end)| &text[start..end]))
+            .collect()
+    }
+
+    fn error(pattern: &str) -> &'static str {
+        match Regex::new(&chars(pattern), None, false) {
+            Err(Error::Invalid(msg)) => msg,
+            other => panic!("{} compiled to {:?}", pattern, other),
+        }
+    }
+
+    #[test]
+    fn test_literals_and_operators() {
+        assert_eq!(find("abc", "xxabcxx"), Some((2, 5)));
+        assert_eq!(find("a.c", "abc a\nc"), Some((0, 3)));
+        assert_eq!(find("a.c", "a\nc"), None);
+        assert_eq!(find("ab*c", "xac"), Some((1, 3)));
+        assert_eq!(find("ab+c", "xac abbc"), Some((4, 8)));
+        assert_eq!(find("ab?c", "abbc abc"), Some((5, 8)));
+        assert_eq!(find("a\\{2,3\\}", "a aaaa"), Some((2, 5)));
+        assert_eq!(find("xa\\{2\\}", "xaaa"), Some((0, 3)));
+        assert_eq!(find("xa\\{,2\\}", "xaaa"), Some((0, 3)));
+        assert_eq!(find("xa\\{2,\\}", "xa xaaaa"), Some((3, 8)));
+        assert_eq!(find("foo\\|ba[rz]", "a baz"), Some((2, 5)));
+        assert_eq!(find("\\n", "a n"), Some((2, 3)));
+        assert_eq!(find("€+", "a €€ b"), Some((2, 8)));
+    }
+
+    #[test]
+    fn test_context_dependent_operators() {
+        // Operators with nothing to repeat are ordinary characters.
+        assert_eq!(find("*a", "a *a"), Some((2, 4)));
+        assert_eq!(find("\\(*\\)", "a*"), Some((1, 2)));
+        assert_eq!(find("x\\|+", "a+"), Some((1, 2)));
+        assert_eq!(find("^*", "a*"), None);
+        assert_eq!(find("\\{", "a{"), Some((1, 2)));
+        // So are anchors in the middle of a pattern.
+        assert_eq!(find("a^b", "a^b"), Some((0, 3)));
+        assert_eq!(find("a$b", "a$b"), Some((0, 3)));
+        assert_eq!(find("^a", "ba\na"), Some((3, 4)));
+        assert_eq!(find("a$", "ab\nab a"), Some((6, 7)));
+        assert_eq!(find("\\(^a\\)", "ba\na"), Some((3, 4)));
+        assert_eq!(find("x\\|^a", "ba\na"), Some((3, 4)));
+    }
+
+    #[test]
+    fn test_non_greedy() {
+        assert_eq!(find("<.*>", "<a><b>"), Some((0, 6)));
+        assert_eq!(find("<.*?>", "<a><b>"), Some((0, 3)));
+        assert_eq!(find("a+?", "aaa"), Some((0, 1)));
+        assert_eq!(find("ba??", "baa"), Some((0, 1)));
+    }
+
+    #[test]
+    fn test_groups() {
+        assert_eq!(
+            groups("\\(a\\)\\(?:b\\)\\(c\\)?", "abd"),
+            vec![Some("ab"), Some("a"), None]
+        );
+        assert_eq!(
+            groups("\\(?2:a\\)\\(b\\)", "ab"),
+            vec![Some("ab"), None, Some("a"), Some("b")]
+        );
+        assert_eq!(
+            groups("\\(a\\|b\\)*", "abba"),
+            vec![Some("abba"), Some("a")]
+        );
+        assert_eq!(groups("\\(a*\\)*b", "aab"), vec![Some("aab"), Some("")]);
+        assert_eq!(groups("\\(\\)*x", "x"), vec![Some("x"), Some("")]);
+    }
+
+    #[test]
+    fn test_backreferences() {
+        assert_eq!(find("\\(a+\\)b\\1", "aab aabaa"), Some((4, 9)));
+        assert_eq!(find("\\([a-z]\\)\\1", "abccd"), Some((2, 4)));
+        assert_eq!(find("\\(x\\)?y\\1", "yy"), None);
+
+        let env = MockEnv {
+            folding: true,
+            point: None,
+        };
+        let registers = search_with(&env, "\\(ab\\)\\1", "ab AB abAB").unwrap();
+        assert_eq!(registers[0], Some((6, 10)));
+    }
+
+    #[test]
+    fn test_sets() {
+        assert_eq!(find("[]a]+", "x]a]"), Some((1, 4)));
+        assert_eq!(find("[^a-c]", "abcd"), Some((3, 4)));
+        assert_eq!(find("[^a]", "a\n"), Some((1, 2)));
+        assert_eq!(find("[a-]+", "b-a-"), Some((1, 4)));
+        assert_eq!(find("[z-a]", "az-"), None);
+        assert_eq!(find("[α-ω]+", "xαβγ"), Some((1, 7)));
+        assert_eq!(find("[\\]+", "a\\\\"), Some((1, 3)));
+        assert_eq!(find("[[:digit:]]+", "ab123"), Some((2, 5)));
+        assert_eq!(find("[[:alpha:]]+", "12éa3"), Some((2, 5)));
+        assert_eq!(find("[^[:space:]]+", "  ab "), Some((2, 4)));
+        assert_eq!(find("[[:upper:]]", "abC"), Some((2, 3)));
+        assert_eq!(find("[[:word:]_]+", "-a_b-"), Some((1, 4)));
+        assert_eq!(find("[[:nonascii:]]", "aé"), Some((1, 3)));
+        assert_eq!(find("[[:punct:]]", "a,"), Some((1, 2)));
+        assert_eq!(find("[[:xdigit:]]+", "xfF0g"), Some((1, 4)));
+        // An unterminated class name is not a class.
+        assert_eq!(find("[[:a]+", "b:[a"), Some((1, 4)));
+    }
+
+    #[test]
+    fn test_case_folding() {
+        let env = MockEnv {
+            folding: true,
+            point: None,
+        };
+        let find = |pattern, text| search_with(&env, pattern, text).map(|r| r[0].unwrap());
+        assert_eq!(find("abc", "xABc"), Some((1, 4)));
+        assert_eq!(find("ABC", "xabc"), Some((1, 4)));
+        assert_eq!(find("[a-c]+", "xAbC"), Some((1, 4)));
+        assert_eq!(find("[^a-c]", "AbCd"), Some((3, 4)));
+        assert_eq!(find("[[:upper:]]+", "-aB-"), Some((1, 3)));
+        assert_eq!(find("[[:lower:]]+", "-aB-"), Some((1, 3)));
+    }
+
+    #[test]
+    fn test_syntax_and_categories() {
+        assert_eq!(find("\\s-+", "ab \t c"), Some((2, 5)));
+        assert_eq!(find("\\S-+", "  ab "), Some((2, 4)));
+        assert_eq!(find("\\s(", "a(b"), Some((1, 2)));
+        assert_eq!(find("\\sw+", "--ab1--"), Some((2, 5)));
+        assert_eq!(find("\\w+", "--ab1--"), Some((2, 5)));
+        assert_eq!(find("\\W+", "ab--c"), Some((2, 4)));
+        assert_eq!(find("\\s_", "a_b"), Some((1, 2)));
+        assert_eq!(find("\\cg+", "abαβc"), Some((2, 6)));
+        assert_eq!(find("\\Cg+", "αβcd"), Some((4, 6)));
+    }
+
+    #[test]
+    fn test_boundaries() {
+        assert_eq!(find("\\bfoo\\b", "foobar foo"), Some((7, 10)));
+        assert_eq!(find("\\Boo", "oo foo"), Some((4, 6)));
+        assert_eq!(find("\\<b", "ab b"), Some((3, 4)));
+        assert_eq!(find("a\\>", "ab a"), Some((3, 4)));
+        assert_eq!(find("\\_<foo\\_>", "foo_bar foo-bar"), Some((8, 11)));
+        assert_eq!(find("\\_<_x", "a_x _x"), Some((4, 6)));
+        assert_eq!(find("o\\_>", "o_x o"), Some((4, 5)));
+        // Words in different scripts are separate.
+        assert_eq!(find("\\<α", "aα"), Some((1, 3)));
+        assert_eq!(find("a\\b", "aα"), Some((0, 1)));
+        assert_eq!(find("\\`a", "ba"), None);
+        assert_eq!(find("\\`b", "ba"), Some((0, 1)));
+        assert_eq!(find("a\\'", "aba"), Some((2, 3)));
+
+        let env = MockEnv {
+            folding: false,
+            point: Some(2),
+        };
+        let registers = search_with(&env, "\\=.", "abc").unwrap();
+        assert_eq!(registers[0], Some((2, 3)));
+    }
+
+    #[test]
+    fn test_gap_and_bounds() {
+        let regex = compile("b\\(é\\)c");
+        let env = env();
+        // The text "abécd" split inside the match.
+        let text = Text::new(b"ab\xc3\xa9", b"cd", true);
+        let registers = regex
+            .search(&text, &env, 0, text.len(), text.len())
+            .unwrap()
+            .unwrap();
+        assert_eq!(registers, vec![Some((1, 5)), Some((2, 4))]);
+        // The match may not extend past STOP.
+        assert_eq!(regex.search(&text, &env, 0, 4, 4).unwrap(), None);
+        // But assertions look beyond it.
+        let regex = compile("b$");
+        let text = Text::new(b"ab", b"c", true);
+        assert_eq!(regex.search(&text, &env, 0, 2, 2).unwrap(), None);
+        let regex = compile("\\w\\b");
+        assert_eq!(regex.search(&text, &env, 0, 2, 2).unwrap(), None);
+
+        // Backward searches try the starting positions in reverse.
+        let regex = compile("a.");
+        let text = Text::new("éaxay".as_bytes(), &[], true);
+        let registers = regex.search(&text, &env, 5, 0, 6).unwrap().unwrap();
+        assert_eq!(registers[0], Some((4, 6)));
+        let registers = regex.search(&text, &env, 4, 0, 4).unwrap().unwrap();
+        assert_eq!(registers[0], Some((2, 4)));
+        assert_eq!(regex.search(&text, &env, 2, 0, 3).unwrap(), None);
+    }
+
+    #[test]
+    fn test_unibyte_text() {
+        let env = env();
+        let regex = Regex::new(&[Codepoint::from(0xe9_u8).unibyte_to_char()], None, false).unwrap();
+        let text = Text::new(b"a\xe9", &[], false);
+        let registers = regex.search(&text, &env, 0, 2, 2).unwrap().unwrap();
+        assert_eq!(registers[0], Some((1, 2)));
+        // A raw byte is not the character with the same code.
+        let regex = compile("é");
+        assert_eq!(regex.search(&text, &env, 0, 2, 2).unwrap(), None);
+    }
+
+    #[test]
+    fn test_posix() {
+        let env = env();
+        let text = Text::new(b"abcd", &[], false);
+        let regex = compile("a\\|ab\\|abc");
+        let registers = regex.search(&text, &env, 0, 4, 4).unwrap().unwrap();
+        assert_eq!(registers[0], Some((0, 1)));
+        let regex = Regex::new(&chars("a\\|ab\\|abc"), None, true).unwrap();
+        let registers = regex.search(&text, &env, 0, 4, 4).unwrap().unwrap();
+        assert_eq!(registers[0], Some((0, 3)));
+    }
+
+    #[test]
+    fn test_whitespace() {
+        let env = env();
+        let whitespace = chars("[ \t\n]+");
+        let regex = Regex::new(&chars("a b"), Some(&whitespace), false).unwrap();
+        let text = Text::new(b"ab a \t\nb", &[], false);
+        let registers = regex.search(&text, &env, 0, 8, 8).unwrap().unwrap();
+        assert_eq!(registers[0], Some((3, 8)));
+        // Spaces before a repetition operator stay literal.
+        let regex = Regex::new(&chars("a *b"), Some(&whitespace), false).unwrap();
+        let text = Text::new(b"a\tb a  b", &[], false);
+        let registers = regex.search(&text, &env, 0, 8, 8).unwrap().unwrap();
+        assert_eq!(registers[0], Some((4, 8)));
+        // Groups in the whitespace regexp do not capture.
+        let whitespace = chars("\\( \\)+");
+        let regex = Regex::new(&chars("\\(a\\) b"), Some(&whitespace), false).unwrap();
+        assert_eq!(regex.groups(), 1);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(error("\\(a"), "Unmatched ( or \\(");
+        assert_eq!(error("a\\)"), "Unmatched ) or \\)");
+        assert_eq!(error("[a"), "Unmatched [ or [^");
+        assert_eq!(error("[]"), "Unmatched [ or [^");
+        assert_eq!(error("a\\"), "Trailing backslash");
+        assert_eq!(error("\\1"), "Invalid back reference");
+        assert_eq!(error("\\(a\\1\\)"), "Invalid back reference");
+        assert_eq!(error("a\\{2,1\\}"), "Invalid content of \\{\\}");
+        assert_eq!(error("a\\{1"), "Unmatched \\{");
+        assert_eq!(error("a\\{x\\}"), "Invalid content of \\{\\}");
+        assert_eq!(error("a\\{70000\\}"), "Invalid content of \\{\\}");
+        assert_eq!(error("[[:foo:]]"), "Invalid character class name");
+        assert_eq!(error("\\s"), "Premature end of regular expression");
+        assert_eq!(error("\\sk"), "Invalid regular expression");
+        assert_eq!(error("\\_a"), "Invalid regular expression");
+        assert_eq!(error("\\(?x\\)"), "Invalid regular expression");
+        assert_eq!(error("\\(?0:a\\)"), "Invalid regular expression");
+        assert_eq!(error("\\(?1:\\(?1:a\\)\\)"), "Invalid regular expression");
+    }
+
+    #[test]
+    fn test_overflow() {
+        let env = env();
+        let regex = compile("\\(?:a\\|b\\)*c");
+        let long = vec![b'a'; MAX_BACKTRACK];
+        let text = Text::new(&long, &[], false);
+        assert_eq!(
+            regex.match_at(&text, &env, 0, text.len()),
+            Err(Error::Overflow)
+        );
+        // Simple repetitions don't use the backtrack stack.
+        let regex = compile("a*c");
+        assert_eq!(regex.match_at(&text, &env, 0, text.len()), Ok(None));
+    }
+}
@@ -1,19 +1,603 @@
 //! String search routines
 
+use std::{borrow::Cow, cell::RefCell, char, iter, mem, ptr, rc::Rc, slice, str};
+
+use regex_crate::bytes;
+
 use remacs_macros::lisp_fn;
 
 use crate::{
+    aho_corasick::Automaton,
+    buffers::{LispBufferRef, BEG_BYTE},
+    casefiddle::{upcase, upcase_initials, upcase_initials_region, upcase_region},
+    casetab::translate_char,
+    chartable::LispCharTableRef,
+    eval::{record_unwind_protect, unbind_to},
+    fns::concat,
     lisp::LispObject,
-    remacs_sys::{looking_at_1, match_limit, search_command, string_match_1},
+    lists::{list, LispCons, LispConsCircularChecks, LispConsEndChecks},
+    marker::{set_marker, unchain_marker},
+    multibyte::{multibyte_length_by_head, Codepoint, LispStringRef},
+    regex::{self, Env, Property, Regex, Text},
+    remacs_sys::{
+        alphabeticp, alphanumericp, blankp, get_char_property_and_overlay, globals, graphicp,
+        lowercasep, make_buffer_string, maybe_quit, move_gap_both, move_if_not_intangible,
+        printablep, regoff_t, replace_range, running_asynch_code, save_search_regs, search_command,
+        set_point_both, string_byte_to_char, string_char_to_byte, temp_set_point, uppercasep,
+        word_boundary_p, xrealloc, EmacsInt, CHAR_HAS_CATEGORY,
+    },
+    remacs_sys::{Fmake_marker, Fsubstring, Fsubstring_no_properties},
+    remacs_sys::{Qinvalid_regexp, Qnil, Qsearch_failed, Qsyntax_table, Qt},
+    threads::{c_specpdl_index, ThreadState},
+    vectors::length,
 };
 
+/// The number of compiled patterns kept in `REGEXP_CACHE`.
+const REGEXP_CACHE_SIZE: usize = 20;
+
+/// A compiled pattern, with everything its compilation depended on.
+struct CachedRegexp {
+    pattern: Vec<Codepoint>,
+    whitespace: Option<Vec<Codepoint>>,
+    posix: bool,
+    regex: Rc<Regex>,
+}
+
+thread_local! {
+    /// The most recently used compiled patterns, most recent first.
+    static REGEXP_CACHE: RefCell<Vec<CachedRegexp>> = RefCell::new(Vec::new());
+}
+
+/// A compiled pattern in the syntax of the regex crate, with
+/// everything its compilation depended on.
+struct CachedRustRegex {
+    pattern: Vec<u8>,
+    case_fold: bool,
+    unicode: bool,
+    regex: Rc<RustRegex>,
+}
+
+thread_local! {
+    /// Like `REGEXP_CACHE', for `re-search-forward-pcre' and
+    /// `string-match-pcre'.
+    static RUST_REGEX_CACHE: RefCell<Vec<CachedRustRegex>> = RefCell::new(Vec::new());
+}
+
+/// A pattern in the syntax of the regex crate.  Multibyte text is
+/// matched as UTF-8, so that matches always start and end at character
+/// boundaries; unibyte text is matched byte by byte.
+enum RustRegex {
+    Multibyte(regex_crate::Regex),
+    Unibyte(bytes::Regex),
+}
+
+impl RustRegex {
+    /// Return the bounds of the groups of the first match in TEXT that
+    /// starts at or after START.  Positions are byte offsets in the
+    /// original text.
+    fn captures_at(&self, text: &PcreText, start: usize) -> Option<Vec<Option<(usize, usize)>>> {
+        let bounds = |group: Option<(usize, usize)>| {
+            group.map(|(start, end)| (text.original_offset(start), text.original_offset(end)))
+        };
+        match (self, text) {
+            (RustRegex::Multibyte(regex), PcreText::Multibyte { text: matched, .. }) => {
+                let mut locations = regex.capture_locations();
+                regex.captures_read_at(&mut locations, matched, text.matched_offset(start))?;
+                Some(
+                    (0..locations.len())
+                        .map(|i| bounds(locations.get(i)))
+                        .collect(),
+                )
+            }
+            (RustRegex::Unibyte(regex), PcreText::Unibyte(matched)) => {
+                let mut locations = regex.capture_locations();
+                regex.captures_read_at(&mut locations, matched, start)?;
+                Some((0..locations.len()).map(|i| locations.get(i)).collect())
+            }
+            _ => panic!("Regex compiled for the wrong kind of text"),
+        }
+    }
+}
+
+/// Text for a `RustRegex` to match.  Multibyte text is converted to
+/// UTF-8 where necessary: raw bytes and characters beyond Unicode
+/// become U+FFFD.
+enum PcreText<'a> {
+    Multibyte {
+        text: Cow<'a, str>,
+        /// The offsets just after each replaced character, in the
+        /// original text and in TEXT.
+        shifts: Vec<(usize, usize)>,
+    },
+    Unibyte(&'a [u8]),
+}
+
+impl<'a> PcreText<'a> {
+    fn new(bytes: &'a [u8], multibyte: bool) -> Self {
+        if !multibyte {
+            return PcreText::Unibyte(bytes);
+        }
+        let mut valid = match str::from_utf8(bytes) {
+            Ok(text) => {
+                return PcreText::Multibyte {
+                    text: Cow::Borrowed(text),
+                    shifts: Vec::new(),
+                }
+            }
+            Err(err) => err.valid_up_to(),
+        };
+
+        let mut text = String::with_capacity(bytes.len());
+        let mut shifts = Vec::new();
+        let mut pos = 0;
+        loop {
+            text.push_str(unsafe { str::from_utf8_unchecked(&bytes[pos..pos + valid]) });
+            pos += valid;
+            if pos == bytes.len() {
+                break;
+            }
+            text.push(char::REPLACEMENT_CHARACTER);
+            pos = (pos + multibyte_length_by_head(bytes[pos])).min(bytes.len());
+            shifts.push((pos, text.len()));
+            valid = match str::from_utf8(&bytes[pos..]) {
+                Ok(rest) => rest.len(),
+                Err(err) => err.valid_up_to(),
+            };
+        }
+        PcreText::Multibyte {
+            text: Cow::Owned(text),
+            shifts,
+        }
+    }
+
+    /// Convert POS, a character boundary in the original text, to the
+    /// corresponding offset in the text matched.
+    fn matched_offset(&self, pos: usize) -> usize {
+        match self {
+            PcreText::Multibyte { shifts, .. } => {
+                match shifts.binary_search_by_key(&pos, |&(orig, _)| orig) {
+                    Ok(i) => shifts[i].1,
+                    Err(0) => pos,
+                    Err(i) => shifts[i - 1].1 + (pos - shifts[i - 1].0),
+                }
+            }
+            PcreText::Unibyte(_) => pos,
+        }
+    }
+
+    /// The inverse of `matched_offset'.
+    fn original_offset(&self, pos: usize) -> usize {
+        match self {
+            PcreText::Multibyte { shifts, .. } => {
+                match shifts.binary_search_by_key(&pos, |&(_, matched)| matched) {
+                    Ok(i) => shifts[i].0,
+                    Err(0) => pos,
+                    Err(i) => shifts[i - 1].0 + (pos - shifts[i - 1].1),
+                }
+            }
+            PcreText::Unibyte(_) => pos,
+        }
+    }
+}
+
+/// An automaton for `search-forward-any', with the case-folded
+/// patterns it was built from.
+struct CachedAutomaton {
+    patterns: Vec<Vec<Codepoint>>,
+    automaton: Rc<Automaton>,
+}
+
+thread_local! {
+    /// Like `REGEXP_CACHE', for `search-forward-any'.
+    static AUTOMATON_CACHE: RefCell<Vec<CachedAutomaton>> = RefCell::new(Vec::new());
+}
+
+/// Return the characters of STRING.  The bytes of a unibyte string
+/// are taken as raw bytes, as in the text being matched.
+fn string_codepoints(string: LispStringRef) -> Vec<Codepoint> {
+    if string.is_multibyte() {
+        string.chars().collect()
+    } else {
+        string
+            .as_slice()
+            .iter()
+            .map(|&b| Codepoint::from(b).unibyte_to_char())
+            .collect()
+    }
+}
+
+/// Signal the error ERR from the regexp engine.
+fn regexp_error(err: regex::Error) -> ! {
+    match err {
+        regex::Error::Invalid(msg) => xsignal!(Qinvalid_regexp, msg),
+        regex::Error::Overflow => error!("Stack overflow in regexp matcher"),
+    }
+}
+
+/// Compile REGEXP, or find it in the cache.  Runs of spaces in REGEXP
+/// stand for `search-spaces-regexp' when it is non-nil.
+fn compile_regexp(regexp: LispStringRef, posix: bool) -> Rc<Regex> {
+    let pattern = string_codepoints(regexp);
+    let whitespace = unsafe { globals.Vsearch_spaces_regexp }
+        .as_string()
+        .map(string_codepoints);
+
+    let cached = REGEXP_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        let index = cache.iter().position(|entry| {
+            entry.posix == posix && entry.pattern == pattern && entry.whitespace == whitespace
+        })?;
+        let entry = cache.remove(index);
+        let regex = Rc::clone(&entry.regex);
+        cache.insert(0, entry);
+        Some(regex)
+    });
+    if let Some(regex) = cached {
+        return regex;
+    }
+
+    let regex = match Regex::new(&pattern, whitespace.as_deref(), posix) {
+        Ok(regex) => Rc::new(regex),
+        Err(err) => regexp_error(err),
+    };
+    REGEXP_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        cache.truncate(REGEXP_CACHE_SIZE - 1);
+        cache.insert(
+            0,
+            CachedRegexp {
+                pattern,
+                whitespace,
+                posix,
+                regex: Rc::clone(&regex),
+            },
+        );
+    });
+    regex
+}
+
+/// Compile REGEXP, in the syntax of the regex crate, or find it in the
+/// cache.  Case is ignored if CASE_FOLD.  Unless UNICODE, the text is
+/// matched byte by byte; otherwise, patterns that could match part of
+/// a character, such as `(?-u:\xFF)', are refused.
+fn compile_rust_regex(regexp: LispStringRef, case_fold: bool, unicode: bool) -> Rc<RustRegex> {
+    let pattern = regexp.as_slice();
+
+    let cached = RUST_REGEX_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        let index = cache.iter().position(|entry| {
+            entry.case_fold == case_fold && entry.unicode == unicode && entry.pattern == pattern
+        })?;
+        let entry = cache.remove(index);
+        let regex = Rc::clone(&entry.regex);
+        cache.insert(0, entry);
+        Some(regex)
+    });
+    if let Some(regex) = cached {
+        return regex;
+    }
+
+    let source = match str::from_utf8(pattern) {
+        Ok(source) => source,
+        Err(_) => xsignal!(Qinvalid_regexp, "Invalid UTF-8 in regexp"),
+    };
+    let regex = if unicode {
+        regex_crate::RegexBuilder::new(source)
+            .case_insensitive(case_fold)
+            .build()
+            .map(RustRegex::Multibyte)
+    } else {
+        bytes::RegexBuilder::new(source)
+            .case_insensitive(case_fold)
+            .unicode(false)
+            .build()
+            .map(RustRegex::Unibyte)
+    };
+    let regex = match regex {
+        Ok(regex) => Rc::new(regex),
+        Err(err) => xsignal!(Qinvalid_regexp, err.to_string().as_str()),
+    };
+    RUST_REGEX_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        cache.truncate(REGEXP_CACHE_SIZE - 1);
+        cache.insert(
+            0,
+            CachedRustRegex {
+                pattern: pattern.to_vec(),
+                case_fold,
+                unicode,
+                regex: Rc::clone(&regex),
+            },
+        );
+    });
+    regex
+}
+
+/// Return an automaton that finds PATTERNS, from the cache if possible.
+fn pattern_automaton(patterns: Vec<Vec<Codepoint>>) -> Rc<Automaton> {
+    let cached = AUTOMATON_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        let index = cache.iter().position(|entry| entry.patterns == patterns)?;
+        let entry = cache.remove(index);
+        let automaton = Rc::clone(&entry.automaton);
+        cache.insert(0, entry);
+        Some(automaton)
+    });
+    if let Some(automaton) = cached {
+        return automaton;
+    }
+
+    let automaton = Rc::new(Automaton::new(&patterns));
+    AUTOMATON_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        cache.truncate(REGEXP_CACHE_SIZE - 1);
+        cache.insert(
+            0,
+            CachedAutomaton {
+                patterns,
+                automaton: Rc::clone(&automaton),
+            },
+        );
+    });
+    automaton
+}
+
+/// Return the accessible portion of BUFFER's text, in two parts
+/// around the gap.  The text must not change while the result is in
+/// use.
+unsafe fn buffer_text<'a>(buffer: LispBufferRef) -> Text<'a> {
+    let gpt = buffer.gpt_byte().max(buffer.begv_byte).min(buffer.zv_byte);
+    let first = slice::from_raw_parts(
+        buffer.beg_addr().offset(buffer.begv_byte - BEG_BYTE),
+        (gpt - buffer.begv_byte) as usize,
+    );
+    let second = slice::from_raw_parts(
+        buffer.beg_addr().offset(gpt - BEG_BYTE + buffer.gap_size()),
+        (buffer.zv_byte - gpt) as usize,
+    );
+    Text::new(first, second, buffer.multibyte_characters_enabled())
+}
+
+/// The state of the current buffer that a match depends on.
+struct MatchEnv {
+    buffer: LispBufferRef,
+    /// Whether the text being matched is the buffer's.
+    in_buffer: bool,
+    /// The canonicalize and equivalence tables, if case is ignored.
+    case_tables: Option<(LispCharTableRef, LispCharTableRef)>,
+    syntax_table: LispCharTableRef,
+}
+
+impl MatchEnv {
+    fn new(in_buffer: bool) -> Self {
+        let buffer = ThreadState::current_buffer_unchecked();
+        let case_tables = if buffer.case_fold_search().is_nil() {
+            None
+        } else {
+            buffer
+                .case_canon_table_
+                .as_char_table()
+                .and_then(|canon| Some((canon, buffer.case_eqv_table_.as_char_table()?)))
+        };
+        Self {
+            buffer,
+            in_buffer,
+            case_tables,
+            syntax_table: buffer.syntax_table_.force_char_table(),
+        }
+    }
+}
+
+/// Return the syntax class given by a syntax table ENTRY.
+fn syntax_class(entry: LispObject) -> u8 {
+    entry
+        .as_cons()
+        .and_then(|cons| cons.car().as_fixnum())
+        .map_or(regex::SWHITESPACE, |code| (code & 0xff) as u8)
+}
+
+impl Env for MatchEnv {
+    fn folding(&self) -> bool {
+        self.case_tables.is_some()
+    }
+
+    fn canon(&self, c: Codepoint) -> Codepoint {
+        self.case_tables
+            .map_or(c, |(canon, _)| translate_char(canon, c))
+    }
+
+    fn next_equivalent(&self, c: Codepoint) -> Codepoint {
+        self.case_tables
+            .map_or(c, |(_, eqv)| translate_char(eqv, c))
+    }
+
+    fn syntax(&self, c: Codepoint, pos: usize) -> u8 {
+        if self.in_buffer && unsafe { globals.parse_sexp_lookup_properties } {
+            let charpos = self
+                .buffer
+                .bytepos_to_charpos(self.buffer.begv_byte + pos as isize);
+            let property = unsafe {
+                get_char_property_and_overlay(
+                    charpos.into(),
+                    Qsyntax_table,
+                    self.buffer.into(),
+                    ptr::null_mut(),
+                )
+            };
+            if property.is_cons() {
+                return syntax_class(property);
+            }
+            if let Some(table) = property.as_char_table() {
+                return syntax_class(table.get(c.val() as isize));
+            }
+        }
+        syntax_class(self.syntax_table.get(c.val() as isize))
+    }
+
+    fn has_category(&self, c: Codepoint, category: u8) -> bool {
+        unsafe { CHAR_HAS_CATEGORY(c.val() as i32, i32::from(category)) }
+    }
+
+    fn word_boundary_p(&self, c1: Codepoint, c2: Codepoint) -> bool {
+        unsafe { word_boundary_p(c1.val() as i32, c2.val() as i32) }
+    }
+
+    fn has_property(&self, c: Codepoint, property: Property) -> bool {
+        let c = c.val() as i32;
+        unsafe {
+            match property {
+                Property::Alphabetic => alphabeticp(c),
+                Property::Alphanumeric => alphanumericp(c),
+                Property::Blank => blankp(c),
+                Property::Graphic => graphicp(c),
+                Property::Printable => printablep(c),
+                Property::Uppercase => uppercasep(c),
+                Property::Lowercase => lowercasep(c),
+            }
+        }
+    }
+
+    fn point(&self) -> Option<usize> {
+        if self.in_buffer {
+            Some((self.buffer.pt_byte - self.buffer.begv_byte) as usize)
+        } else {
+            None
+        }
+    }
+
+    fn poll(&self) {
+        unsafe { maybe_quit() };
+    }
+}
+
+fn match_data_inhibited() -> bool {
+    unsafe { globals.Vinhibit_changing_match_data }.is_not_nil()
+}
+
+/// The match data: where the last search matched, and in what.
+#[derive(Clone)]
+pub struct MatchRegisters {
+    /// The start and end of the text matched by the whole pattern and
+    /// by each of its groups, or None for the groups that didn't match.
+    pub groups: Vec<Option<(isize, isize)>>,
+    /// The buffer searched, t for a string, or nil if nothing was.
+    pub thing: LispObject,
+}
+
+/// Return the starts and the ends of the current thread's match
+/// registers.
+unsafe fn search_registers<'a>() -> (&'a mut [regoff_t], &'a mut [regoff_t]) {
+    let regs = &ThreadState::current_thread().m_search_regs;
+    if regs.num_regs == 0 {
+        return (&mut [], &mut []);
+    }
+    let num_regs = regs.num_regs as usize;
+    (
+        slice::from_raw_parts_mut(regs.start, num_regs),
+        slice::from_raw_parts_mut(regs.end, num_regs),
+    )
+}
+
+impl MatchRegisters {
+    /// Return the match data of the current thread.
+    pub fn current() -> Self {
+        let (starts, ends) = unsafe { search_registers() };
+        let groups = starts
+            .iter()
+            .zip(ends.iter())
+            .map(|(&start, &end)| if start >= 0 { Some((start, end)) } else { None })
+            .collect();
+        Self {
+            groups,
+            thing: ThreadState::current_thread().m_last_thing_searched,
+        }
+    }
+
+    /// Return the start and end of the text matched by group N.
+    pub fn group(&self, n: usize) -> Option<(isize, isize)> {
+        self.groups.get(n).copied().flatten()
+    }
+
+    /// Make these the match data of the current thread.  The registers
+    /// are never shrunk; those beyond `groups' are cleared.
+    pub fn install(&self) {
+        let mut thread = ThreadState::current_thread();
+        let regs = &mut thread.m_search_regs;
+        if (regs.num_regs as usize) < self.groups.len() {
+            let size = self.groups.len() * mem::size_of::<regoff_t>();
+            unsafe {
+                regs.start = xrealloc(regs.start.cast(), size).cast();
+                regs.end = xrealloc(regs.end.cast(), size).cast();
+            }
+            regs.num_regs = self.groups.len() as u32;
+        }
+        let (starts, ends) = unsafe { search_registers() };
+        for (i, (start, end)) in starts.iter_mut().zip(ends.iter_mut()).enumerate() {
+            let (beg, fin) = self.group(i).unwrap_or((-1, -1));
+            *start = beg;
+            *end = fin;
+        }
+        thread.m_last_thing_searched = self.thing;
+    }
+}
+
+/// Make REGISTERS the match data of a search in THING, a buffer or t
+/// for a string.  TO_POSITION turns the byte offsets of the registers
+/// into positions.
+fn record_match(
+    registers: &[Option<(usize, usize)>],
+    to_position: impl Fn(usize) -> isize,
+    thing: LispObject,
+) {
+    let groups = registers
+        .iter()
+        .map(|group| group.map(|(start, end)| (to_position(start), to_position(end))))
+        .collect();
+    MatchRegisters { groups, thing }.install();
+}
+
+/// Make REGISTERS, relative to the start of the accessible portion of
+/// BUFFER, the match data, unless `inhibit-changing-match-data'.
+fn set_buffer_match_data(buffer: LispBufferRef, registers: &[Option<(usize, usize)>]) {
+    if match_data_inhibited() {
+        return;
+    }
+    record_match(
+        registers,
+        |pos| buffer.bytepos_to_charpos(buffer.begv_byte + pos as isize),
+        buffer.into(),
+    );
+}
+
+fn looking_at_1(regexp: LispStringRef, posix: bool) -> bool {
+    if unsafe { running_asynch_code } {
+        unsafe { save_search_regs() };
+    }
+    let regex = compile_regexp(regexp, posix);
+
+    // Do a pending quit right away, to avoid paradoxical behavior.
+    unsafe { maybe_quit() };
+
+    let buffer = ThreadState::current_buffer_unchecked();
+    let text = unsafe { buffer_text(buffer) };
+    let point = (buffer.pt_byte - buffer.begv_byte) as usize;
+    let result = regex.match_at(&text, &MatchEnv::new(true), point, text.len());
+    match result.unwrap_or_else(regexp_error) {
+        Some(registers) => {
+            set_buffer_match_data(buffer, &registers);
+            true
+        }
+        None => false,
+    }
+}
+
 /// Return t if text after point matches regular expression REGEXP.
 /// This function modifies the match data that `match-beginning',
 /// `match-end' and `match-data' access; save and restore the match
 /// data if you want to preserve them.
 #[lisp_fn]
-pub fn looking_at(regexp: LispObject) -> LispObject {
-    unsafe { looking_at_1(regexp, false) }
+pub fn looking_at(regexp: LispStringRef) -> bool {
+    looking_at_1(regexp, false)
 }
 
 /// Return t if text after point matches regular expression REGEXP.
@@ -22,8 +606,59 @@ pub fn looking_at(regexp: LispObject) -> LispObject {
 /// `match-end' and `match-data' access; save and restore the match
 /// data if you want to preserve them.
 #[lisp_fn]
-pub fn posix_looking_at(regexp: LispObject) -> LispObject {
-    unsafe { looking_at_1(regexp, true) }
+pub fn posix_looking_at(regexp: LispStringRef) -> bool {
+    looking_at_1(regexp, true)
+}
+
+/// Return the byte position in STRING of the character index START,
+/// which counts from the end of STRING if negative.
+fn string_start_byte(string: LispStringRef, start: Option<EmacsInt>) -> usize {
+    match start {
+        None => 0,
+        Some(start) => {
+            let len = string.len_chars() as EmacsInt;
+            let pos = if start < 0 { len + start } else { start };
+            if pos < 0 || pos > len {
+                args_out_of_range!(string, start);
+            }
+            unsafe { string_char_to_byte(string.into(), pos as isize) as usize }
+        }
+    }
+}
+
+fn string_match_1(
+    regexp: LispStringRef,
+    string: LispStringRef,
+    start: Option<EmacsInt>,
+    posix: bool,
+) -> Option<EmacsInt> {
+    if unsafe { running_asynch_code } {
+        unsafe { save_search_regs() };
+    }
+
+    let pos_byte = string_start_byte(string, start);
+    let regex = compile_regexp(regexp, posix);
+    let text = Text::new(string.as_slice(), &[], string.is_multibyte());
+    let result = regex.search(
+        &text,
+        &MatchEnv::new(false),
+        pos_byte,
+        text.len(),
+        text.len(),
+    );
+
+    // Set last_thing_searched only when match data is changed.
+    let inhibited = match_data_inhibited();
+    if !inhibited {
+        ThreadState::current_thread().m_last_thing_searched = Qt;
+    }
+
+    let registers = result.unwrap_or_else(regexp_error)?;
+    let to_index = |pos: usize| unsafe { string_byte_to_char(string.into(), pos as isize) };
+    if !inhibited {
+        record_match(&registers, to_index, Qt);
+    }
+    registers[0].map(|(start, _)| to_index(start) as EmacsInt)
 }
 
 /// Return index of start of first match for REGEXP in STRING, or nil.
@@ -36,8 +671,12 @@ pub fn posix_looking_at(regexp: LispObject) -> LispObject {
 /// You can use the function `match-string' to extract the substrings
 /// matched by the parenthesis constructions in REGEXP.
 #[lisp_fn(min = "2")]
-pub fn string_match(regexp: LispObject, string: LispObject, start: LispObject) -> LispObject {
-    unsafe { string_match_1(regexp, string, start, false) }
+pub fn string_match(
+    regexp: LispStringRef,
+    string: LispStringRef,
+    start: Option<EmacsInt>,
+) -> Option<EmacsInt> {
+    string_match_1(regexp, string, start, false)
 }
 
 /// Return index of start of first match for REGEXP in STRING, or nil.
@@ -48,8 +687,12 @@ pub fn string_match(regexp: LispObject, string: LispObject, start: LispObject) -
 /// `match-end' and `match-beginning' also give indices of substrings
 /// matched by parenthesis constructs in the pattern.
 #[lisp_fn(min = "2")]
-pub fn posix_string_match(regexp: LispObject, string: LispObject, start: LispObject) -> LispObject {
-    unsafe { string_match_1(regexp, string, start, true) }
+pub fn posix_string_match(
+    regexp: LispStringRef,
+    string: LispStringRef,
+    start: Option<EmacsInt>,
+) -> Option<EmacsInt> {
+    string_match_1(regexp, string, start, true)
 }
 
 /// Search backward from point for STRING.
@@ -110,6 +753,121 @@ pub fn search_forward(
     unsafe { search_command(string, bound, noerror, count, 1, 0, false) }
 }
 
+/// Search the current buffer from POS_BYTE for the Nth match of
+/// REGEXP, backward if N is negative, without going beyond LIM_BYTE.
+/// Return the byte position of the end of the last match found, or of
+/// its start when searching backward, or None if there were fewer than
+/// N matches.
+fn search_buffer_regexp(
+    regexp: LispStringRef,
+    pos_byte: isize,
+    lim_byte: isize,
+    mut n: EmacsInt,
+    posix: bool,
+) -> Option<isize> {
+    if unsafe { running_asynch_code } {
+        unsafe { save_search_regs() };
+    }
+    let buffer = ThreadState::current_buffer_unchecked();
+    let mut pos = (pos_byte - buffer.begv_byte) as usize;
+
+    // Searching 0 times, or for the empty regexp, means don't move.
+    if regexp.len_chars() == 0 || n == 0 {
+        set_buffer_match_data(buffer, &[Some((pos, pos))]);
+        return Some(pos_byte);
+    }
+
+    let regex = compile_regexp(regexp, posix);
+
+    // Do a pending quit right away, to avoid paradoxical behavior.
+    unsafe { maybe_quit() };
+
+    let text = unsafe { buffer_text(buffer) };
+    let env = MatchEnv::new(true);
+    let lim = (lim_byte - buffer.begv_byte) as usize;
+    while n != 0 {
+        // Searching backward, a match may not extend past the origin.
+        let stop = if n < 0 { pos } else { lim };
+        let result = regex.search(&text, &env, pos, lim, stop);
+        let registers = result.unwrap_or_else(regexp_error)?;
+        let (start, end) = registers[0]?;
+        set_buffer_match_data(buffer, &registers);
+        if n < 0 {
+            pos = start;
+            n += 1;
+        } else {
+            pos = end;
+            n -= 1;
+        }
+    }
+    Some(buffer.begv_byte + pos as isize)
+}
+
+/// Return the character and byte positions of the limit of a search
+/// in BUFFER from point, given by BOUND, forward if FORWARD.
+fn search_limit(buffer: LispBufferRef, bound: LispObject, forward: bool) -> (isize, isize) {
+    if bound.is_nil() {
+        return if forward {
+            (buffer.zv, buffer.zv_byte)
+        } else {
+            (buffer.begv, buffer.begv_byte)
+        };
+    }
+    let lim = bound.as_fixnum_coerce_marker_or_error() as isize;
+    if (forward && lim < buffer.pt) || (!forward && lim > buffer.pt) {
+        error!("Invalid search bound (wrong side of point)");
+    }
+    if lim > buffer.zv {
+        (buffer.zv, buffer.zv_byte)
+    } else if lim < buffer.begv {
+        (buffer.begv, buffer.begv_byte)
+    } else {
+        (lim, buffer.charpos_to_bytepos(lim))
+    }
+}
+
+/// Handle the failure of a search for WHAT, according to NOERROR: signal
+/// `search-failed', or move to LIM, whose byte position is LIM_BYTE, if
+/// NOERROR isn't t.
+fn search_failed(what: LispObject, noerror: LispObject, lim: isize, lim_byte: isize) {
+    if noerror.is_nil() {
+        xsignal!(Qsearch_failed, what);
+    }
+    if !noerror.eq(Qt) {
+        unsafe { set_point_both(lim, lim_byte) };
+    }
+}
+
+/// Search from point for COUNT times DIRECTION matches of REGEXP, the
+/// way `re-search-forward' does.  SEARCH is called with REGEXP, the
+/// byte positions of point and of the limit of the search, and the
+/// signed number of matches to find; it returns what
+/// `search_buffer_regexp' does.
+fn re_search_command(
+    regexp: LispStringRef,
+    bound: LispObject,
+    noerror: LispObject,
+    count: Option<EmacsInt>,
+    direction: EmacsInt,
+    search: impl FnOnce(LispStringRef, isize, isize, EmacsInt) -> Option<isize>,
+) -> Option<EmacsInt> {
+    let n = direction * count.unwrap_or(1);
+    let buffer = ThreadState::current_buffer_unchecked();
+    let (lim, lim_byte) = search_limit(buffer, bound, n > 0);
+
+    match search(regexp, buffer.pt_byte, lim_byte, n) {
+        Some(pos_byte) => {
+            let pos = buffer.bytepos_to_charpos(pos_byte);
+            unsafe { set_point_both(pos, pos_byte) };
+            Some(pos as EmacsInt)
+        }
+        None => {
+            search_failed(regexp.into(), noerror, lim, lim_byte);
+            None
+        }
+    }
+}
+
 /// Search backward from point for regular expression REGEXP.
 /// This function is almost identical to `re-search-forward', except that
 /// by default it searches backward instead of forward, and the sign of
@@ -118,12 +876,14 @@ pub fn search_forward(
 /// See `re-search-forward' for details.
 #[lisp_fn(min = "1", intspec = "sRE search backward: ")]
 pub fn re_search_backward(
-    regexp: LispObject,
+    regexp: LispStringRef,
     bound: LispObject,
     noerror: LispObject,
-    count: LispObject,
-) -> LispObject {
-    unsafe { search_command(regexp, bound, noerror, count, -1, 1, false) }
+    count: Option<EmacsInt>,
+) -> Option<EmacsInt> {
+    re_search_command(regexp, bound, noerror, count, -1, |regexp, pos, lim, n| {
+        search_buffer_regexp(regexp, pos, lim, n, false)
+    })
 }
 
 /// Search forward from point for regular expression REGEXP.
@@ -152,12 +912,14 @@ pub fn re_search_backward(
 /// and `replace-match'.
 #[lisp_fn(min = "1", intspec = "sRE search: ")]
 pub fn re_search_forward(
-    regexp: LispObject,
+    regexp: LispStringRef,
     bound: LispObject,
     noerror: LispObject,
-    count: LispObject,
-) -> LispObject {
-    unsafe { search_command(regexp, bound, noerror, count, 1, 1, false) }
+    count: Option<EmacsInt>,
+) -> Option<EmacsInt> {
+    re_search_command(regexp, bound, noerror, count, 1, |regexp, pos, lim, n| {
+        search_buffer_regexp(regexp, pos, lim, n, false)
+    })
 }
 
 /// Search backward from point for match for regular expression REGEXP.
@@ -183,12 +945,14 @@ pub fn re_search_forward(
 /// and `replace-match'.
 #[lisp_fn(min = "1", intspec = "sPosix search backward: ")]
 pub fn posix_search_backward(
-    regexp: LispObject,
+    regexp: LispStringRef,
     bound: LispObject,
     noerror: LispObject,
-    count: LispObject,
-) -> LispObject {
-    unsafe { search_command(regexp, bound, noerror, count, -1, 1, true) }
+    count: Option<EmacsInt>,
+) -> Option<EmacsInt> {
+    re_search_command(regexp, bound, noerror, count, -1, |regexp, pos, lim, n| {
+        search_buffer_regexp(regexp, pos, lim, n, true)
+    })
 }
 
 /// Search forward from point for regular expression REGEXP.
@@ -214,12 +978,215 @@ pub fn posix_search_backward(
 /// and `replace-match'.
 #[lisp_fn(min = "1", intspec = "sPosix search: ")]
 pub fn posix_search_forward(
-    regexp: LispObject,
+    regexp: LispStringRef,
     bound: LispObject,
     noerror: LispObject,
-    count: LispObject,
-) -> LispObject {
-    unsafe { search_command(regexp, bound, noerror, count, 1, 1, true) }
+    count: Option<EmacsInt>,
+) -> Option<EmacsInt> {
+    re_search_command(regexp, bound, noerror, count, 1, |regexp, pos, lim, n| {
+        search_buffer_regexp(regexp, pos, lim, n, true)
+    })
+}
+
+/// Like `search_buffer_regexp', for a pattern in the syntax of the
+/// regex crate.  N may not be negative.
+fn search_buffer_rust_regex(
+    regexp: LispStringRef,
+    pos_byte: isize,
+    lim_byte: isize,
+    mut n: EmacsInt,
+) -> Option<isize> {
+    if unsafe { running_asynch_code } {
+        unsafe { save_search_regs() };
+    }
+    let buffer = ThreadState::current_buffer_unchecked();
+    let begv_byte = buffer.begv_byte;
+    let mut pos = (pos_byte - begv_byte) as usize;
+
+    if n == 0 {
+        set_buffer_match_data(buffer, &[Some((pos, pos))]);
+        return Some(pos_byte);
+    }
+
+    let multibyte = buffer.multibyte_characters_enabled();
+    let regex = compile_rust_regex(regexp, buffer.case_fold_search().is_not_nil(), multibyte);
+
+    // Do a pending quit right away, to avoid paradoxical behavior.
+    unsafe { maybe_quit() };
+
+    // The regex crate needs the text in one piece.  All of the
+    // accessible portion is matched, so that `$' and `\b' see the
+    // text after LIM_BYTE, but a match may not end after it.
+    let zv_byte = buffer.zv_byte;
+    let gpt_byte = buffer.gpt_byte();
+    if begv_byte < gpt_byte && gpt_byte < zv_byte {
+        unsafe { move_gap_both(buffer.zv, zv_byte) };
+    }
+    let bytes = unsafe {
+        slice::from_raw_parts(
+            buffer.byte_pos_addr(begv_byte),
+            (zv_byte - begv_byte) as usize,
+        )
+    };
+    let text = PcreText::new(bytes, multibyte);
+    let lim = (lim_byte - begv_byte) as usize;
+
+    while n > 0 {
+        let registers = regex.captures_at(&text, pos)?;
+        let (_, end) = registers[0]?;
+        if end > lim {
+            return None;
+        }
+        set_buffer_match_data(buffer, &registers);
+        pos = end;
+        n -= 1;
+    }
+    Some(begv_byte + pos as isize)
+}
+
+/// Search forward from point for any of the strings in PATTERNS.
+/// Set point to the end of the occurrence found, and return the index
+/// in PATTERNS of the string that was found.  The occurrence that
+/// starts first is found; of those that start at the same place, the
+/// longest one, and then the one that comes first in PATTERNS.
+/// `match-beginning' and `match-end' give the bounds of the occurrence.
+/// An optional second argument bounds the search; it is a buffer position.
+///   The match found must not end after that position.  A value of nil
+///   means search to the end of the accessible portion of the buffer.
+/// Optional third argument, if t, means if fail just return nil (no error).
+///   If not nil and not t, move to limit of search and return nil.
+///
+/// Search case-sensitivity is determined by the value of the variable
+/// `case-fold-search', which see.
+#[lisp_fn(min = "1")]
+pub fn search_forward_any(
+    patterns: LispObject,
+    bound: LispObject,
+    noerror: LispObject,
+) -> Option<EmacsInt> {
+    if unsafe { running_asynch_code } {
+        unsafe { save_search_regs() };
+    }
+    let buffer = ThreadState::current_buffer_unchecked();
+    let (lim_char, lim_byte) = search_limit(buffer, bound, true);
+
+    let env = MatchEnv::new(true);
+    let canonical = patterns
+        .iter_cars(LispConsEndChecks::on, LispConsCircularChecks::on)
+        .map(|pattern| {
+            string_codepoints(pattern.into())
+                .into_iter()
+                .map(|c| env.canon(c))
+                .collect()
+        })
+        .collect();
+    let automaton = pattern_automaton(canonical);
+
+    // Do a pending quit right away, to avoid paradoxical behavior.
+    unsafe { maybe_quit() };
+
+    let text = unsafe { buffer_text(buffer) };
+    let start = (buffer.pt_byte - buffer.begv_byte) as usize;
+    let lim = (lim_byte - buffer.begv_byte) as usize;
+    let mut pos = start;
+    let chars = iter::from_fn(|| {
+        if pos >= lim {
+            return None;
+        }
+        let (c, len) = text.char_at(pos);
+        pos += len;
+        Some((env.canon(c), len))
+    });
+
+    match automaton.find(start, chars) {
+        Some(found) => {
+            set_buffer_match_data(buffer, &[Some((found.start, found.end))]);
+            let end_byte = buffer.begv_byte + found.end as isize;
+            unsafe { set_point_both(buffer.bytepos_to_charpos(end_byte), end_byte) };
+            Some(found.pattern as EmacsInt)
+        }
+        None => {
+            search_failed(patterns, noerror, lim_char, lim_byte);
+            None
+        }
+    }
+}
+
+/// Search forward from point for REGEXP, written in Perl-like syntax.
+/// This is like `re-search-forward', and takes the same arguments, but
+/// REGEXP uses the syntax of the Rust regex crate: `(...)' for groups,
+/// `(?:...)' for shy groups, `\\d', `\\s', `{M,N}' and so on.  COUNT may
+/// not be negative, as such patterns can only be searched for forward.
+/// The match is found as if the search were not bounded, and the search
+/// fails if it ends after BOUND.  In a multibyte buffer, raw bytes and
+/// characters beyond Unicode are matched as U+FFFD.
+///
+/// Case is ignored if `case-fold-search' is non-nil, unless REGEXP
+/// turns that off with `(?-i)'.
+///
+/// The match data is set as by `re-search-forward', so `match-beginning',
+/// `match-end', `match-string' and `replace-match' work as usual.
+#[lisp_fn(min = "1", intspec = "sPCRE search: ")]
+pub fn re_search_forward_pcre(
+    regexp: LispStringRef,
+    bound: LispObject,
+    noerror: LispObject,
+    count: Option<EmacsInt>,
+) -> Option<EmacsInt> {
+    if count.map_or(false, |count| count < 0) {
+        error!("Cannot search backward for a PCRE regexp");
+    }
+    re_search_command(regexp, bound, noerror, count, 1, search_buffer_rust_regex)
+}
+
+/// Return index of start of first match for REGEXP in STRING, or nil.
+/// This is like `string-match', but REGEXP is written in the syntax of
+/// the Rust regex crate, as for `re-search-forward-pcre'.
+/// Case is ignored if `case-fold-search' is non-nil, unless REGEXP
+/// turns that off with `(?-i)'.
+/// If third arg START is non-nil, start search at that index in STRING.
+#[lisp_fn(min = "2")]
+pub fn string_match_pcre(
+    regexp: LispStringRef,
+    string: LispStringRef,
+    start: Option<EmacsInt>,
+) -> Option<EmacsInt> {
+    if unsafe { running_asynch_code } {
+        unsafe { save_search_regs() };
+    }
+
+    let pos_byte = string_start_byte(string, start);
+    let case_fold = ThreadState::current_buffer_unchecked()
+        .case_fold_search()
+        .is_not_nil();
+    let regex = compile_rust_regex(regexp, case_fold, string.is_multibyte());
+    let text = PcreText::new(string.as_slice(), string.is_multibyte());
+    let found = regex.captures_at(&text, pos_byte);
+
+    // Set last_thing_searched only when match data is changed.
+    let inhibited = match_data_inhibited();
+    if !inhibited {
+        ThreadState::current_thread().m_last_thing_searched = Qt;
+    }
+
+    let registers = found?;
+    let to_index = |pos: usize| unsafe { string_byte_to_char(string.into(), pos as isize) };
+    if !inhibited {
+        record_match(&registers, to_index, Qt);
+    }
+    Some(to_index(registers[0]?.0) as EmacsInt)
+}
+
+fn match_limit(subexp: EmacsInt, beginning: bool) -> Option<EmacsInt> {
+    if subexp < 0 {
+        args_out_of_range!(subexp, 0);
+    }
+    let registers = MatchRegisters::current();
+    if registers.groups.is_empty() {
+        error!("No match data, because no search succeeded");
+    }
+    let (start, end) = registers.group(subexp as usize)?;
+    Some(if beginning { start } else { end } as EmacsInt)
 }
 
 /// Return position of start of text matched by last search.
@@ -231,8 +1198,8 @@ pub fn posix_search_forward(
 ///
 /// Return value is undefined if the last search failed.
 #[lisp_fn]
-pub fn match_beginning(subexp: LispObject) -> LispObject {
-    unsafe { match_limit(subexp, true) }
+pub fn match_beginning(subexp: EmacsInt) -> Option<EmacsInt> {
+    match_limit(subexp, true)
 }
 
 /// Return position of end of text matched by last search.
@@ -244,8 +1211,527 @@ pub fn match_beginning(subexp: LispObject) -> LispObject {
 ///
 /// Return value is undefined if the last search failed.
 #[lisp_fn]
-pub fn match_end(subexp: LispObject) -> LispObject {
-    unsafe { match_limit(subexp, false) }
+pub fn match_end(subexp: EmacsInt) -> Option<EmacsInt> {
+    match_limit(subexp, false)
+}
+
+/// Return a list describing what the last search matched.
+/// Element 2N is `(match-beginning N)'; element 2N + 1 is `(match-end N)'.
+/// All the elements are markers or nil (nil if the Nth pair didn't match)
+/// if the last match was on a buffer; integers or nil if a string was matched.
+/// Use `set-match-data' to reinstate the data in this list.
+///
+/// If INTEGERS (the optional first argument) is non-nil, always use
+/// integers (rather than markers) to represent buffer positions.  In
+/// this case, and if the last match was in a buffer, the buffer will get
+/// stored as one additional element at the end of the list.
+///
+/// If REUSE is a list, reuse it as part of the value.  If REUSE is long
+/// enough to hold all the values, and if INTEGERS is non-nil, no consing
+/// is done.
+///
+/// If optional third arg RESEAT is non-nil, any previous markers on the
+/// REUSE list will be modified to point to nowhere.
+///
+/// Return value is undefined if the last search failed.
+#[lisp_fn(min = "0")]
+pub fn match_data(integers: bool, reuse: LispObject, reseat: bool) -> LispObject {
+    if reseat {
+        for tail in reuse.iter_tails(LispConsEndChecks::off, LispConsCircularChecks::on) {
+            if let Some(mut marker) = tail.car().as_marker() {
+                unchain_marker(marker.as_mut());
+                tail.set_car(Qnil);
+            }
+        }
+    }
+
+    let registers = MatchRegisters::current();
+    if registers.thing.is_nil() {
+        return Qnil;
+    }
+    let in_buffer = registers.thing.is_buffer();
+    let position = |pos: isize| -> LispObject {
+        if in_buffer && !integers {
+            let marker = unsafe { Fmake_marker() }.force_marker();
+            set_marker(marker, pos.into(), registers.thing).into()
+        } else {
+            pos.into()
+        }
+    };
+    let mut data = Vec::with_capacity(2 * registers.groups.len() + 1);
+    let mut len = 0;
+    for group in &registers.groups {
+        match *group {
+            Some((start, end)) => {
+                data.push(position(start));
+                data.push(position(end));
+                len = data.len();
+            }
+            None => data.extend_from_slice(&[Qnil, Qnil]),
+        }
+    }
+    data.truncate(len);
+    if in_buffer && integers {
+        data.push(registers.thing);
+    }
+
+    // If REUSE is a list, store as many value elements as will fit into
+    // its elements, and cons up the rest.
+    let reuse = match reuse.as_cons() {
+        Some(reuse) => reuse,
+        None => return list(&data),
+    };
+    let mut values = data.iter().copied();
+    let mut last = reuse;
+    for tail in reuse.iter_tails(LispConsEndChecks::off, LispConsCircularChecks::on) {
+        tail.set_car(values.next().unwrap_or(Qnil));
+        last = tail;
+    }
+    let rest: Vec<_> = values.collect();
+    if !rest.is_empty() {
+        last.set_cdr(list(&rest));
+    }
+    reuse.into()
+}
+
+/// Return the position given by the car of TAIL, part of a list made
+/// by `match-data'.  A marker that points nowhere counts as 0.  If
+/// RESEAT, make a marker point nowhere and replace it with nil.
+fn match_data_position(tail: LispCons, reseat: bool) -> isize {
+    let elt = tail.car();
+    match elt.as_marker() {
+        Some(mut marker) => {
+            let pos = marker.charpos().unwrap_or(0);
+            if reseat {
+                unchain_marker(marker.as_mut());
+                tail.set_car(Qnil);
+            }
+            pos
+        }
+        None => elt.as_fixnum_or_error() as isize,
+    }
+}
+
+/// Set internal data on last search match from elements of LIST.
+/// LIST should have been created by calling `match-data' previously.
+///
+/// If optional arg RESEAT is non-nil, make markers on LIST point nowhere.
+#[lisp_fn(min = "1")]
+pub fn set_match_data(list: LispObject, reseat: bool) {
+    if unsafe { running_asynch_code } {
+        unsafe { save_search_regs() };
+    }
+    list.check_list();
+
+    // Unless we find a marker with a buffer or an explicit buffer in
+    // LIST, assume that this match data came from a string.
+    let mut thing = Qt;
+    let length = length(list) / 2;
+    let mut groups = Vec::with_capacity(length);
+    let mut tails = list.iter_tails(LispConsEndChecks::off, LispConsCircularChecks::on);
+    while let Some(tail) = tails.next() {
+        let elt = tail.car();
+        if elt.is_buffer() {
+            thing = elt;
+            break;
+        }
+        if groups.len() >= length {
+            break;
+        }
+        if elt.is_nil() {
+            tails.next();
+            groups.push(None);
+            continue;
+        }
+        if let Some(buffer) = elt.as_marker().and_then(|marker| marker.buffer()) {
+            thing = buffer.into();
+        }
+        let start = match_data_position(tail, reseat);
+        match tails.next() {
+            Some(tail) => groups.push(Some((start, match_data_position(tail, reseat)))),
+            None => break,
+        }
+    }
+    MatchRegisters { groups, thing }.install();
+}
+
+unsafe extern "C" fn unwind_set_match_data(list: LispObject) {
+    // It is NOT ALWAYS safe to free (evaporate) the markers immediately.
+    set_match_data(list, true);
+}
+
+/// Arrange for the match data to be restored when the current binding
+/// level is unwound.
+#[no_mangle]
+pub extern "C" fn record_unwind_save_match_data() {
+    unsafe { record_unwind_protect(Some(unwind_set_match_data), match_data(false, Qnil, false)) };
+}
+
+/// Call BODY and restore the match data afterwards, even if BODY exits
+/// nonlocally.  This is `save-match-data' for primitives that search
+/// on their own account.
+pub fn save_match_data<T>(body: impl FnOnce() -> T) -> T {
+    let count = c_specpdl_index();
+    record_unwind_save_match_data();
+    let value = body();
+    unbind_to(count, Qnil);
+    value
+}
+
+/// How `replace-match' changes the case of the replacement text.
+#[derive(Clone, Copy, PartialEq)]
+enum CaseAction {
+    NoChange,
+    AllCaps,
+    CapInitial,
+}
+
+/// Decide how to change the case of the text that replaces the
+/// characters REPLACED, so that it looks like them.
+fn replacement_case_action(replaced: impl Iterator<Item = Codepoint>) -> CaseAction {
+    let syntax_table = ThreadState::current_buffer_unchecked()
+        .syntax_table_
+        .force_char_table();
+    let is_word = |c: Codepoint| syntax_class(syntax_table.get(c.val() as isize)) == regex::SWORD;
+
+    // Whether any original word is more than one letter long.
+    let mut some_multiletter_word = false;
+    let mut some_lowercase = false;
+    let mut some_uppercase = false;
+    let mut some_nonuppercase_initial = false;
+    let mut prev = Codepoint::from('\n');
+    for c in replaced {
+        if unsafe { lowercasep(c.val() as i32) } {
+            // Cannot be all caps if any original char is lower case.
+            some_lowercase = true;
+            if is_word(prev) {
+                some_multiletter_word = true;
+            } else {
+                some_nonuppercase_initial = true;
+            }
+        } else if unsafe { uppercasep(c.val() as i32) } {
+            some_uppercase = true;
+            if is_word(prev) {
+                some_multiletter_word = true;
+            }
+        } else if !is_word(prev) {
+            // If the initial is a caseless word constituent, treat that
+            // like a lowercase initial.
+            some_nonuppercase_initial = true;
+        }
+        prev = c;
+    }
+
+    if !some_lowercase && some_multiletter_word {
+        // Convert to all caps if the old text is all caps and has at
+        // least one multiletter word.
+        CaseAction::AllCaps
+    } else if !some_nonuppercase_initial && some_multiletter_word {
+        // Capitalize each word, if the old text has all capitalized words.
+        CaseAction::CapInitial
+    } else if !some_nonuppercase_initial && some_uppercase {
+        // Should x -> yz, operating on X, give Yz or YZ?  We'll assume
+        // the latter.
+        CaseAction::AllCaps
+    } else {
+        CaseAction::NoChange
+    }
+}
+
+/// A part of the text that `replace-match' substitutes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Substitution {
+    /// The characters of the replacement between two indexes.
+    Literal(usize, usize),
+    /// The text matched by a group, or nothing if it didn't match.
+    Group(usize),
+}
+
+/// Split REPLACEMENT, the characters of the NEWTEXT argument of
+/// `replace-match', into what they stand for.  `\&' stands for group
+/// SUB, and `\N' for group N; there are NUM_GROUPS groups.  `\?' and
+/// references to groups that don't exist are only allowed IN_STRING,
+/// for compatibility.
+fn parse_replacement(
+    replacement: &[Codepoint],
+    sub: usize,
+    num_groups: usize,
+    in_string: bool,
+) -> Vec<Substitution> {
+    let mut parts = Vec::new();
+    let mut literal_start = 0;
+    let mut i = 0;
+    while i < replacement.len() {
+        if replacement[i] != Codepoint::from('\\') {
+            i += 1;
+            continue;
+        }
+        if literal_start < i {
+            parts.push(Substitution::Literal(literal_start, i));
+        }
+        let c = replacement.get(i + 1).and_then(|c| char::from_u32(c.val()));
+        parts.push(match c {
+            Some('&') => Substitution::Group(sub),
+            Some(c @ '1'..='9') if in_string || (c as usize - '0' as usize) < num_groups => {
+                Substitution::Group(c as usize - '0' as usize)
+            }
+            Some('\\') => Substitution::Literal(i + 1, i + 2),
+            Some('?') if in_string => Substitution::Literal(i, i + 2),
+            _ => error!("Invalid use of `\\' in replacement text"),
+        });
+        i += 2;
+        literal_start = i;
+    }
+    if literal_start < replacement.len() {
+        parts.push(Substitution::Literal(literal_start, replacement.len()));
+    }
+    parts
+}
+
+/// Return NEWTEXT with the substitutions described by PARTS made.
+/// LITERAL returns the text of NEWTEXT between two indexes, and GROUP
+/// the text matched by a group.
+fn substitute_replacement(
+    parts: &[Substitution],
+    literal: impl Fn(usize, usize) -> LispObject,
+    group: impl Fn(usize) -> LispObject,
+) -> LispObject {
+    let mut pieces: Vec<_> = parts
+        .iter()
+        .map(|&part| match part {
+            Substitution::Literal(start, end) => literal(start, end),
+            Substitution::Group(n) => group(n),
+        })
+        .collect();
+    concat(&mut pieces)
+}
+
+/// Replace text matched by last search with NEWTEXT.
+/// Leave point at the end of the replacement text.
+///
+/// If optional second arg FIXEDCASE is non-nil, do not alter the case of
+/// the replacement text.  Otherwise, maybe capitalize the whole text, or
+/// maybe just word initials, based on the replaced text.  If the replaced
+/// text has only capital letters and has at least one multiletter word,
+/// convert NEWTEXT to all caps.  Otherwise if all words are capitalized
+/// in the replaced text, capitalize each word in NEWTEXT.
+///
+/// If optional third arg LITERAL is non-nil, insert NEWTEXT literally.
+/// Otherwise treat `\\' as special:
+///   `\\&' in NEWTEXT means substitute original matched text.
+///   `\\N' means substitute what matched the Nth `\\(...\\)'.
+///        If Nth parens didn't match, substitute nothing.
+///   `\\\\' means insert one `\\'.
+///   `\\?' is treated literally
+///        (for compatibility with `query-replace-regexp').
+///   Any other character following `\\' signals an error.
+/// Case conversion does not apply to these substitutions.
+///
+/// If optional fourth argument STRING is non-nil, it should be a string
+/// to act on; this should be the string on which the previous match was
+/// done via `string-match'.  In this case, `replace-match' creates and
+/// returns a new string, made by copying STRING and replacing the part of
+/// STRING that was matched (the original STRING itself is not altered).
+///
+/// The optional fifth argument SUBEXP specifies a subexpression;
+/// it says to replace just that subexpression with NEWTEXT,
+/// rather than replacing the entire matched text.
+/// This is, in a vague sense, the inverse of using `\\N' in NEWTEXT;
+/// `\\N' copies subexp N into NEWTEXT, but using N as SUBEXP puts
+/// NEWTEXT in place of subexp N.
+/// This is useful only after a regular expression search or match,
+/// since only regular expressions have distinguished subexpressions.
+#[lisp_fn(min = "1")]
+pub fn replace_match(
+    newtext: LispStringRef,
+    fixedcase: bool,
+    literal: bool,
+    string: Option<LispStringRef>,
+    subexp: Option<EmacsInt>,
+) -> LispObject {
+    let registers = MatchRegisters::current();
+    if registers.groups.is_empty() {
+        error!("`replace-match' called before any match found");
+    }
+    let num_groups = registers.groups.len();
+    let sub = match subexp {
+        None => 0,
+        Some(n) if 0 <= n && (n as usize) < num_groups => n as usize,
+        Some(n) => {
+            args_out_of_range!(n, num_groups);
+        }
+    };
+
+    let buffer = ThreadState::current_buffer_unchecked();
+    let (start, end) = registers.group(sub).unwrap_or((-1, -1));
+    let (min, max) = match string {
+        Some(string) => (0, string.len_chars()),
+        None => (buffer.begv, buffer.zv),
+    };
+    if start < min || start > end || end > max {
+        args_out_of_range!(start, end);
+    }
+
+    // Decide how to casify by examining the matched text.
+    let case_action = if fixedcase {
+        CaseAction::NoChange
+    } else if let Some(string) = string {
+        let chars = string_codepoints(string);
+        replacement_case_action(chars[start as usize..end as usize].iter().copied())
+    } else {
+        let text = unsafe { buffer_text(buffer) };
+        let mut pos = (buffer.charpos_to_bytepos(start) - buffer.begv_byte) as usize;
+        let end_byte = (buffer.charpos_to_bytepos(end) - buffer.begv_byte) as usize;
+        replacement_case_action(iter::from_fn(|| {
+            if pos >= end_byte {
+                return None;
+            }
+            let (c, len) = text.char_at(pos);
+            pos += len;
+            Some(c)
+        }))
+    };
+
+    // Work out what to substitute into NEWTEXT, if anything.
+    let parts = if literal {
+        None
+    } else {
+        let chars: Vec<_> = newtext.chars().collect();
+        if chars.contains(&Codepoint::from('\\')) {
+            Some(parse_replacement(&chars, sub, num_groups, string.is_some()))
+        } else {
+            None
+        }
+    };
+    let substring = |object: LispObject, start: isize, end: isize| unsafe {
+        Fsubstring(object, start.into(), end.into())
+    };
+
+    // Do replacement in a string.
+    if let Some(string) = string {
+        let mut newtext = parts.map_or(newtext.into(), |parts| {
+            substitute_replacement(
+                &parts,
+                |from, to| substring(newtext.into(), from as isize, to as isize),
+                |n| match registers.group(n) {
+                    Some((from, to)) => substring(string.into(), from, to),
+                    None => "".into(),
+                },
+            )
+        });
+        match case_action {
+            CaseAction::AllCaps => newtext = upcase(newtext),
+            CaseAction::CapInitial => newtext = upcase_initials(newtext),
+            CaseAction::NoChange => {}
+        }
+        let mut pieces = [substring(string.into(), 0, start), newtext, unsafe {
+            Fsubstring(string.into(), end.into(), Qnil)
+        }];
+        return concat(&mut pieces);
+    }
+
+    // Record point, then move (quietly) to the start of the match.
+    let opoint = if buffer.pt >= end {
+        buffer.pt - buffer.zv
+    } else if buffer.pt > start {
+        end - buffer.zv
+    } else {
+        buffer.pt
+    };
+
+    // The substituted text is inserted without its properties.
+    let newtext: LispStringRef = match parts {
+        Some(parts) => substitute_replacement(
+            &parts,
+            |from, to| unsafe { Fsubstring_no_properties(newtext.into(), from.into(), to.into()) },
+            |n| match registers.group(n) {
+                Some((from, to)) => unsafe { make_buffer_string(from, to, false) },
+                None => "".into(),
+            },
+        )
+        .into(),
+        None => newtext,
+    };
+
+    // The functions below modify the buffer, so they could trigger
+    // various modification hooks (see signal_before_change and
+    // signal_after_change).  If these hooks clobber the match data we
+    // error out since otherwise this will result in confusing bugs.
+    let newpoint = start + newtext.len_chars();
+
+    // Replace the old text with the new in the cleanest possible way.
+    unsafe { replace_range(start, end, newtext.into(), true, false, true, true) };
+
+    // Update saved data to match adjustment made by replace_range.
+    let change = newpoint - end;
+    let sub_start = if start >= end { start + change } else { start };
+    let sub_end = end + change;
+
+    match case_action {
+        CaseAction::AllCaps => upcase_region(sub_start.into(), newpoint.into(), false),
+        CaseAction::CapInitial => upcase_initials_region(sub_start.into(), newpoint.into()),
+        CaseAction::NoChange => {}
+    }
+
+    let after = MatchRegisters::current();
+    if after.group(sub) != Some((sub_start, sub_end)) || after.groups.len() != num_groups {
+        error!("Match data clobbered by buffer modification hooks");
+    }
+
+    // Put point back where it was in the text.
+    let mut buffer = ThreadState::current_buffer_unchecked();
+    let opoint = if opoint <= 0 {
+        opoint + buffer.zv
+    } else {
+        opoint
+    };
+    unsafe {
+        temp_set_point(buffer.as_mut(), opoint);
+        // Now move point "officially" to the start of the inserted
+        // replacement.
+        move_if_not_intangible(newpoint);
+    }
+    Qnil
+}
+
+/// Return REPLACEMENT as it will be inserted by `replace-match'.
+/// In other words, all back-references in the form `\\&' and `\\N'
+/// are substituted with actual strings matched by the last search.
+/// Optional FIXEDCASE, LITERAL, STRING and SUBEXP have the same
+/// meaning as for `replace-match'.
+#[lisp_fn(min = "1")]
+pub fn match_substitute_replacement(
+    replacement: LispStringRef,
+    fixedcase: bool,
+    literal: bool,
+    string: Option<LispStringRef>,
+    subexp: Option<EmacsInt>,
+) -> LispObject {
+    let registers = MatchRegisters::current();
+    let (start, end) = match registers.group(0) {
+        Some(group) => group,
+        None => error!("No match data, because no search succeeded"),
+    };
+    let matched = match string {
+        Some(string) => unsafe { Fsubstring(string.into(), start.into(), end.into()) },
+        None => unsafe { make_buffer_string(start, end, true) },
+    };
+    // Replace in the matched text, as if it had been matched by itself.
+    let groups = registers
+        .groups
+        .iter()
+        .map(|group| group.map(|(from, to)| (from - start, to - start)))
+        .collect();
+    save_match_data(|| {
+        MatchRegisters { groups, thing: Qt }.install();
+        replace_match(
+            replacement,
+            fixedcase,
+            literal,
+            Some(matched.into()),
+            subexp,
+        )
+    })
 }
 
 include!(concat!(env!("OUT_DIR"), "/search_exports.rs"));
@@ -7,7 +7,8 @@ use crate::{
     lisp::{ExternalPtr, LispObject},
     remacs_sys::Qthreadp,
     remacs_sys::{
-        current_thread as current_thread_pointer, pvec_type, thread_state, Lisp_Type, SPECPDL_INDEX,
+        current_thread as current_thread_pointer, pvec_type, thread_call_without_lock,
+        thread_state, Lisp_Type, SPECPDL_INDEX,
     },
     vectors::LispVectorlikeRef,
 };
@@ -72,6 +73,25 @@ pub fn c_specpdl_index() -> libc::ptrdiff_t {
     unsafe { SPECPDL_INDEX() }
 }
 
+/// Run F without holding the global lock, so that other Lisp threads
+/// can run while it blocks.  F must not touch any Lisp object, signal
+/// or call `maybe_quit`.
+pub fn without_global_lock<F: FnOnce()>(f: F) {
+    unsafe extern "C" fn trampoline<F: FnOnce()>(arg: *mut libc::c_void) {
+        if let Some(f) = (*(arg as *mut Option<F>)).take() {
+            f();
+        }
+    }
+
+    let mut f = Some(f);
+    unsafe {
+        thread_call_without_lock(
+            Some(trampoline::<F>),
+            &mut f as *mut Option<F> as *mut libc::c_void,
+        )
+    };
+}
+
 /// Return the name of the THREAD.
 /// The name is the same object that was passed to `make-thread'.
 #[lisp_fn]
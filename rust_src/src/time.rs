@@ -1,19 +1,39 @@
 //! Time support
 
 use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::ffi::{CStr, CString};
+use std::io;
+use std::mem;
 use std::ops::{Add, Sub};
 use std::ptr;
+use std::sync::Arc;
 
 use libc::timespec as c_timespec;
-use libc::{c_int, c_long, time_t};
+use libc::{c_char, c_int, c_long, clockid_t, time_t};
 
 use remacs_lib::current_timespec;
 use remacs_macros::lisp_fn;
 
 use crate::{
+    alloc::purecopy,
+    iso8601::{self, Iso8601},
     lisp::LispObject,
+    lists::put,
+    multibyte::LispStringRef,
     numbers::MOST_NEGATIVE_FIXNUM,
+    remacs_sys::{
+        build_string, code_convert_string_norecord, emacs_getenv_TZ, emacs_localtime_rz,
+        emacs_mktime_z, globals, lisp_time_argument, make_unibyte_string, strftime,
+        string_byte_to_char, string_overflow, synchronize_system_time_locale, timezone_t, tm,
+        tzlookup, xtzfree,
+    },
     remacs_sys::{lisp_time, EmacsDouble, EmacsInt},
+    remacs_sys::{
+        Qerror, Qerror_conditions, Qerror_message, Qinteger, Qiso8601_parse_error, Qlist, Qnatnump,
+        Qnil, Qt, Qwall,
+    },
+    tzif::{load_zone, ZoneRules, LOCALTIME_FILE},
 };
 
 const LO_TIME_BITS: i32 = 16;
@@ -117,6 +137,245 @@ impl Sub for LispTime {
     }
 }
 
+/// A time value as an exact number of seconds since the epoch, TICKS/HZ.
+/// HZ is always positive.
+#[derive(Clone, Copy, Debug)]
+pub struct TicksTime {
+    pub ticks: i128,
+    pub hz: i128,
+}
+
+/// The largest clock frequency used to represent a float exactly; finer
+/// fractions are truncated.
+const MAX_FLOAT_HZ_BITS: i32 = 52;
+
+fn gcd(mut a: i128, mut b: i128) -> i128 {
+    while b != 0 {
+        let r = a % b;
+        a = b;
+        b = r;
+    }
+    a.abs()
+}
+
+impl TicksTime {
+    /// Return TIME as a time value if it is a pair (TICKS . HZ), that is a
+    /// cons whose cdr is an integer, and `time-ticks-hz-pairs' is non-nil.
+    /// Signal an error if TICKS is not an integer or HZ is not positive.
+    pub fn from_pair(time: LispObject) -> Option<Self> {
+        if unsafe { globals.Vtime_ticks_hz_pairs }.is_nil() {
+            return None;
+        }
+        let pair: Option<(LispObject, LispObject)> = time.into();
+        let (ticks, hz) = pair?;
+        let hz = hz.as_fixnum()?;
+        match ticks.as_fixnum() {
+            Some(ticks) if hz > 0 => Some(Self {
+                ticks: ticks.into(),
+                hz: hz.into(),
+            }),
+            _ => invalid_time(),
+        }
+    }
+
+    /// Decode the Lisp time value TIME exactly: nil for now, a number of
+    /// seconds, (TICKS . HZ), or a list (HIGH LOW USEC PSEC).
+    pub fn from_lisp(time: LispObject) -> Self {
+        if let Some(t) = Self::from_pair(time) {
+            return t;
+        }
+        if time.is_nil() {
+            let now = current_timespec();
+            return Self {
+                ticks: i128::from(now.tv_sec) * i128::from(CLOCK_HZ) + i128::from(now.tv_nsec),
+                hz: CLOCK_HZ.into(),
+            };
+        }
+        if let Some(secs) = time.as_fixnum() {
+            return Self {
+                ticks: secs.into(),
+                hz: 1,
+            };
+        }
+        if let Some(secs) = time.as_float() {
+            return Self::from_float(secs).unwrap_or_else(|| time_overflow());
+        }
+
+        let mut len = 0;
+        let t = unsafe { lisp_time_struct(time, &mut len) };
+        let secs = (i128::from(t.hi) << LO_TIME_BITS) + i128::from(t.lo);
+        match len {
+            2 => Self { ticks: secs, hz: 1 },
+            3 => Self {
+                ticks: secs * 1_000_000 + i128::from(t.us),
+                hz: 1_000_000,
+            },
+            _ => Self {
+                ticks: (secs * 1_000_000 + i128::from(t.us)) * 1_000_000 + i128::from(t.ps),
+                hz: 1_000_000_000_000,
+            },
+        }
+    }
+
+    /// Return the value of the float SECS, whose denominator is a power of
+    /// two, or None if SECS is not finite or too large.
+    pub fn from_float(secs: f64) -> Option<Self> {
+        if !secs.is_finite() {
+            return None;
+        }
+        if secs == 0.0 {
+            return Some(Self { ticks: 0, hz: 1 });
+        }
+        let bits = secs.to_bits();
+        let biased = ((bits >> 52) & 0x7ff) as i32;
+        let fraction = i128::from(bits & ((1 << 52) - 1));
+        let (mut mantissa, mut exponent) = if biased == 0 {
+            (fraction, -1074)
+        } else {
+            (fraction | 1 << 52, biased - 1075)
+        };
+        if secs < 0.0 {
+            mantissa = -mantissa;
+        }
+
+        while mantissa % 2 == 0 && exponent < 0 {
+            mantissa /= 2;
+            exponent += 1;
+        }
+        if exponent >= 0 {
+            if exponent > 64 {
+                return None;
+            }
+            Some(Self {
+                ticks: mantissa << exponent,
+                hz: 1,
+            })
+        } else if -exponent <= MAX_FLOAT_HZ_BITS {
+            Some(Self {
+                ticks: mantissa,
+                hz: 1 << -exponent,
+            })
+        } else {
+            Some(Self {
+                ticks: mantissa >> (-exponent - MAX_FLOAT_HZ_BITS).min(127),
+                hz: 1 << MAX_FLOAT_HZ_BITS,
+            })
+        }
+    }
+
+    /// Combine this time and OTHER with OP, using the least common
+    /// multiple of their clock frequencies.
+    fn combine(self, other: Self, op: impl FnOnce(i128, i128) -> Option<i128>) -> Option<Self> {
+        let hz = self.hz.checked_mul(other.hz / gcd(self.hz, other.hz))?;
+        let a = self.ticks.checked_mul(hz / self.hz)?;
+        let b = other.ticks.checked_mul(hz / other.hz)?;
+        Some(Self {
+            ticks: op(a, b)?,
+            hz,
+        })
+    }
+
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        self.combine(other, i128::checked_add)
+    }
+
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        self.combine(other, i128::checked_sub)
+    }
+
+    /// Return this time with clock frequency HZ, rounding toward minus
+    /// infinity.
+    pub fn to_hz(self, hz: i128) -> Option<Self> {
+        let secs = self.ticks.div_euclid(self.hz);
+        let rest = self.ticks.rem_euclid(self.hz);
+        let ticks = secs
+            .checked_mul(hz)?
+            .checked_add(rest.checked_mul(hz)?.div_euclid(self.hz))?;
+        Some(Self { ticks, hz })
+    }
+
+    /// Return this time as a `lisp_time`, truncated to picoseconds, or
+    /// None if it is out of range.
+    pub fn to_lisp_time(self) -> Option<LispTime> {
+        let ps = self.to_hz(1_000_000_000_000)?.ticks;
+        let secs = ps.div_euclid(1_000_000_000_000);
+        let sub = ps.rem_euclid(1_000_000_000_000);
+        let hi = EmacsInt::try_from(secs >> LO_TIME_BITS).ok()?;
+        if LispObject::fixnum_overflow(hi) {
+            return None;
+        }
+        Some(LispTime {
+            hi,
+            lo: (secs & ((1 << LO_TIME_BITS) - 1)) as c_int,
+            us: (sub / 1_000_000) as c_int,
+            ps: (sub % 1_000_000) as c_int,
+        })
+    }
+
+    pub fn to_float(self) -> f64 {
+        let secs = self.ticks.div_euclid(self.hz);
+        let rest = self.ticks.rem_euclid(self.hz);
+        secs as f64 + rest as f64 / self.hz as f64
+    }
+
+    fn fits_fixnums(self) -> bool {
+        let fits = |n| EmacsInt::try_from(n).map_or(false, |n| !LispObject::fixnum_overflow(n));
+        fits(self.ticks) && fits(self.hz)
+    }
+
+    /// Return this time with the fraction TICKS/HZ in lowest terms if it
+    /// is too large for fixnums as it is.
+    pub fn reduce_to_fit(self) -> Self {
+        if self.fits_fixnums() {
+            return self;
+        }
+        let g = gcd(self.ticks, self.hz);
+        Self {
+            ticks: self.ticks / g,
+            hz: self.hz / g,
+        }
+    }
+
+    /// Return this time as a pair (TICKS . HZ), signaling an error if it
+    /// does not fit in fixnums.
+    pub fn into_lisp(self) -> LispObject {
+        if !self.fits_fixnums() {
+            time_overflow();
+        }
+        LispObject::cons(self.ticks as EmacsInt, self.hz as EmacsInt)
+    }
+}
+
+impl PartialEq for TicksTime {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for TicksTime {}
+
+impl PartialOrd for TicksTime {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TicksTime {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Compare whole seconds first so that the cross products of the
+        // fractions cannot overflow.
+        let (a, b) = (
+            self.ticks.div_euclid(self.hz),
+            other.ticks.div_euclid(other.hz),
+        );
+        let (ra, rb) = (
+            self.ticks.rem_euclid(self.hz),
+            other.ticks.rem_euclid(other.hz),
+        );
+        a.cmp(&b).then_with(|| (ra * other.hz).cmp(&(rb * self.hz)))
+    }
+}
+
 /// Return the upper part of the time T (everything but the bottom 16 bits).
 #[no_mangle]
 pub extern "C" fn hi_time(t: time_t) -> EmacsInt {
@@ -376,6 +635,13 @@ pub unsafe extern "C" fn lisp_time_struct(
     specified_time: LispObject,
     plen: *mut c_int,
 ) -> lisp_time {
+    if let Some(t) = TicksTime::from_pair(specified_time) {
+        if !plen.is_null() {
+            *plen = 4;
+        }
+        return t.to_lisp_time().unwrap_or_else(|| time_overflow());
+    }
+
     let mut high = LispObject::from_C(0);
     let mut low = LispObject::from_C(0);
     let mut usec = LispObject::from_C(0);
@@ -426,19 +692,145 @@ pub fn current_time() -> LispObject {
     make_lisp_time_1(current_timespec())
 }
 
+/// The frequency of the clocks read by `clock_ticks`.
+const CLOCK_HZ: i64 = 1_000_000_000;
+
+/// Return the reading of CLOCK in nanoseconds.
+fn clock_ticks(clock: clockid_t) -> i64 {
+    let mut t = c_timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    if unsafe { libc::clock_gettime(clock, &mut t) } != 0 {
+        error!("Cannot read clock: {}", io::Error::last_os_error());
+    }
+    i64::from(t.tv_sec)
+        .checked_mul(CLOCK_HZ)
+        .and_then(|ticks| ticks.checked_add(t.tv_nsec.into()))
+        .unwrap_or_else(|| time_overflow())
+}
+
+/// Make a Lisp timestamp (TICKS . HZ) for the clock reading TICKS.
+fn make_ticks_time(ticks: i64) -> LispObject {
+    if LispObject::fixnum_overflow(ticks) {
+        time_overflow();
+    }
+    LispObject::cons(ticks, CLOCK_HZ)
+}
+
+/// Return the time of a clock that never jumps, as (TICKS . HZ).
+/// TICKS counts units of 1/HZ seconds from an unspecified starting
+/// point, so only differences between values of this function are
+/// meaningful.  Unlike `current-time', the clock is not affected by
+/// changes to the system time, which makes it suitable for timeouts and
+/// benchmarks.  Other time functions accept the value only if
+/// `time-ticks-hz-pairs' is non-nil.
+#[lisp_fn]
+pub fn current_monotonic_time() -> LispObject {
+    make_ticks_time(clock_ticks(libc::CLOCK_MONOTONIC))
+}
+
+/// Return the processor time used by Emacs, as (TICKS . HZ).
+/// TICKS counts units of 1/HZ seconds.  If THREAD is non-nil, return
+/// the processor time used by the current system thread instead of the
+/// whole process.  See also `get-internal-run-time'.
+#[lisp_fn(min = "0")]
+pub fn current_cpu_time(thread: bool) -> LispObject {
+    let clock = if thread {
+        libc::CLOCK_THREAD_CPUTIME_ID
+    } else {
+        libc::CLOCK_PROCESS_CPUTIME_ID
+    };
+    make_ticks_time(clock_ticks(clock))
+}
+
+/// Call FUNCTION with no arguments REPETITIONS times and time the calls.
+/// Return a list (MIN MEDIAN MAX) of the shortest, median and longest
+/// times a call took, each of the form (TICKS . HZ) as from
+/// `current-monotonic-time'.  For an even number of calls, the median is
+/// the mean of the middle two.
+#[lisp_fn]
+pub fn benchmark_primitive(repetitions: EmacsInt, function: LispObject) -> LispObject {
+    let repetitions = match usize::try_from(repetitions) {
+        Ok(n) if n >= 1 => n,
+        _ => args_out_of_range!(repetitions, 1),
+    };
+
+    // REPETITIONS can be far more than would fit in memory, so let the
+    // samples grow as the calls are made.
+    let mut samples = Vec::new();
+    for _ in 0..repetitions {
+        let start = clock_ticks(libc::CLOCK_MONOTONIC);
+        call!(function);
+        samples.push(clock_ticks(libc::CLOCK_MONOTONIC) - start);
+    }
+    samples.sort_unstable();
+
+    let n = samples.len();
+    let median = (samples[(n - 1) / 2] + samples[n / 2]) / 2;
+    list!(
+        make_ticks_time(samples[0]),
+        make_ticks_time(median),
+        make_ticks_time(samples[n - 1])
+    )
+}
+
+/// Convert TIME to a time value of the form FORM.
+/// TIME is a time value as in `time-add', or nil for the current time.
+///
+/// If FORM is nil or t, return a pair (TICKS . HZ) of integers that
+/// represents TIME exactly, where HZ is the clock frequency of TIME: 1
+/// for an integer number of seconds, 1000000 for (HIGH LOW USEC),
+/// 1000000000000 for (HIGH LOW USEC PSEC), a power of two for a float,
+/// and 1000000000 for the current time.  If FORM is a positive integer,
+/// return (TICKS . FORM).  If FORM is `integer', return an integer number
+/// of seconds, and if it is `list', return (HIGH LOW USEC PSEC).
+///
+/// Conversions that lose precision truncate toward minus infinity.
+/// Other time functions, and this one, accept a pair (TICKS . HZ) only
+/// if `time-ticks-hz-pairs' is non-nil.
+#[lisp_fn(min = "1")]
+pub fn time_convert(time: LispObject, form: LispObject) -> LispObject {
+    let t = TicksTime::from_lisp(time);
+
+    if form.is_nil() || form.is_t() {
+        t.reduce_to_fit().into_lisp()
+    } else if form.eq(Qinteger) {
+        match EmacsInt::try_from(t.ticks.div_euclid(t.hz)) {
+            Ok(secs) if !LispObject::fixnum_overflow(secs) => secs.into(),
+            _ => time_overflow(),
+        }
+    } else if form.eq(Qlist) {
+        let t = t.to_lisp_time().unwrap_or_else(|| time_overflow());
+        t.into_vec(4).into()
+    } else {
+        match form.as_fixnum() {
+            Some(hz) if hz > 0 => t
+                .to_hz(hz.into())
+                .unwrap_or_else(|| time_overflow())
+                .into_lisp(),
+            _ => wrong_type!(Qnatnump, form),
+        }
+    }
+}
+
 /// Return the current time, as a float number of seconds since the
 /// epoch.  If TIME is given, it is the time to convert to float
 /// instead of the current time.  The argument should have the form
 /// (HIGH LOW) or (HIGH LOW USEC) or (HIGH LOW USEC PSEC).  Thus, you
 /// can use times from `current-time' and from `file-attributes'.
-/// TIME can also have the form (HIGH . LOW), but this is considered
-/// obsolete.
+/// TIME can also be a pair (TICKS . HZ) of integers, meaning TICKS/HZ
+/// seconds, if `time-ticks-hz-pairs' is non-nil.
 ///
 /// WARNING: Since the result is floating point, it may not be exact.
 /// If precise time stamps are required, use either `current-time',
 /// or (if you need time as a string) `format-time-string'.
 #[lisp_fn(min = "0")]
 pub fn float_time(time: LispObject) -> EmacsDouble {
+    if let Some(t) = TicksTime::from_pair(time) {
+        return t.to_float();
+    }
+
     let mut high = LispObject::from_C(0);
     let mut low = LispObject::from_C(0);
     let mut usec = LispObject::from_C(0);
@@ -456,4 +848,1367 @@ pub fn float_time(time: LispObject) -> EmacsDouble {
     t
 }
 
+const TM_YEAR_BASE: i64 = 1900;
+
+/// The calendar fields of a time stamp in some time zone, like C's
+/// `struct tm' but with a full-width year and the zone folded in.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BrokenDownTime {
+    pub sec: i32,
+    pub min: i32,
+    pub hour: i32,
+    pub mday: i32,
+    /// Month, from 0 (January) to 11.
+    pub mon: i32,
+    pub year: i64,
+    /// Day of the week, from 0 (Sunday) to 6.
+    pub wday: i32,
+    /// Day of the year, from 0 to 365.
+    pub yday: i32,
+    pub isdst: bool,
+    /// Seconds east of UTC.
+    pub utcoff: i64,
+    /// The time zone abbreviation, in the locale's encoding.
+    pub zone: Vec<u8>,
+}
+
+pub const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+pub fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+/// Return the number of days from 1970-01-01 to YEAR-MONTH-DAY in the
+/// proleptic Gregorian calendar.  MONTH counts from 1.
+pub fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The inverse of `days_from_civil': return the year, month (from 1)
+/// and day of DAYS since 1970-01-01.
+pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+impl BrokenDownTime {
+    /// Break down SECS, seconds since the epoch, for a zone UTCOFF
+    /// seconds east of UTC and abbreviated as ZONE.
+    pub fn from_utc(secs: i64, utcoff: i64, zone: Vec<u8>) -> Option<Self> {
+        let local = secs.checked_add(utcoff)?;
+        let days = local.div_euclid(SECONDS_PER_DAY);
+        let rem = local.rem_euclid(SECONDS_PER_DAY) as i32;
+        let (year, month, day) = civil_from_days(days);
+
+        Some(Self {
+            sec: rem % 60,
+            min: rem / 60 % 60,
+            hour: rem / 3600,
+            mday: day as i32,
+            mon: month as i32 - 1,
+            year,
+            wday: (days + 4).rem_euclid(7) as i32,
+            yday: (days - days_from_civil(year, 1, 1)) as i32,
+            isdst: false,
+            utcoff,
+            zone,
+        })
+    }
+}
+
+/// Return the seconds since the epoch of the wall clock time given by
+/// SEC, MIN, HOUR, MDAY, MON (from 0) and YEAR in a zone UTCOFF seconds
+/// east of UTC.  Out-of-range fields are normalized as by `mktime'.
+fn seconds_from_fields(fields: [i64; 6], utcoff: i64) -> Option<i64> {
+    let [sec, min, hour, mday, mon, year] = fields;
+    let year = year.checked_add(mon.div_euclid(12))?;
+    let days = days_from_civil(year, mon.rem_euclid(12) + 1, 1).checked_add(mday - 1)?;
+    days.checked_mul(SECONDS_PER_DAY)?
+        .checked_add(hour * 3600 + min * 60 + sec)?
+        .checked_sub(utcoff)
+}
+
+/// Return the abbreviation of a zone OFFSET seconds east of UTC that has
+/// no name of its own: "+HH", or "+HHMM" or "+HHMMSS" when the offset
+/// is not a whole number of hours.
+pub fn numeric_zone_abbreviation(offset: i64) -> Vec<u8> {
+    let sign = if offset < 0 { '-' } else { '+' };
+    let abs = offset.abs();
+    let (hour, min, sec) = (abs / 3600, abs / 60 % 60, abs % 60);
+    let abbr = if abs % 3600 == 0 {
+        format!("{}{:02}", sign, hour)
+    } else if sec == 0 {
+        format!("{}{:02}{:02}", sign, hour, min)
+    } else {
+        format!("{}{:02}{:02}{:02}", sign, hour, min, sec)
+    };
+    abbr.into_bytes()
+}
+
+/// The largest field width `format_time' accepts, as in `nstrftime'.
+const MAX_FORMAT_WIDTH: usize = i32::max_value() as usize;
+
+/// The flags, width and modifier of a single %-conversion.
+#[derive(Clone, Copy, Default)]
+struct Conversion {
+    /// One of `_', `-', `0' or `+', if given.
+    pad: Option<u8>,
+    width: Option<usize>,
+    /// `^': up-case textual output.
+    upcase: bool,
+    /// `#': change the letter-case of textual output.
+    change_case: bool,
+    /// `E' or `O', if given.
+    modifier: Option<u8>,
+}
+
+enum Case {
+    Keep,
+    Upper,
+    Lower,
+}
+
+impl Conversion {
+    /// Append the decimal VALUE to OUT, padded to the explicit width or
+    /// else to DIGITS, with DEFAULT_PAD unless a flag says otherwise.
+    fn number(&self, out: &mut Vec<u8>, value: i64, digits: usize, default_pad: u8) {
+        let pad = match self.pad {
+            Some(b'-') => None,
+            Some(b'_') => Some(b' '),
+            Some(b'0') | Some(b'+') => Some(b'0'),
+            _ => Some(default_pad),
+        };
+        let width = self.width.unwrap_or(digits);
+        let digits = value.to_string();
+        let magnitude = digits.trim_start_matches('-');
+        let len = digits.len();
+        let fill = if pad.is_some() {
+            width.saturating_sub(len)
+        } else {
+            0
+        };
+
+        match pad {
+            Some(b'0') => {
+                if value < 0 {
+                    out.push(b'-');
+                }
+                out.resize(out.len() + fill, b'0');
+            }
+            _ => {
+                out.resize(out.len() + fill, b' ');
+                if value < 0 {
+                    out.push(b'-');
+                }
+            }
+        }
+        out.extend_from_slice(magnitude.as_bytes());
+    }
+
+    /// Append TEXT to OUT, converted to CASE (or up-cased by `^') and
+    /// padded with blanks to the explicit width.
+    fn text(&self, out: &mut Vec<u8>, text: &[u8], case: Case) {
+        let case = if self.upcase { Case::Upper } else { case };
+        if let Some(width) = self.width {
+            let pad = match self.pad {
+                Some(b'-') => None,
+                Some(b'0') | Some(b'+') => Some(b'0'),
+                _ => Some(b' '),
+            };
+            if let Some(pad) = pad {
+                out.resize(out.len() + width.saturating_sub(text.len()), pad);
+            }
+        }
+        out.extend(text.iter().map(|&c| match case {
+            Case::Keep => c,
+            Case::Upper => c.to_ascii_uppercase(),
+            Case::Lower => c.to_ascii_lowercase(),
+        }));
+    }
+
+    /// Append the UTC offset OFFSET to OUT in the style of `%z' with
+    /// COLONS colons: +hhmm, +hh:mm, +hh:mm:ss or, for three colons,
+    /// the shortest of +hh, +hh:mm and +hh:mm:ss that is exact.
+    fn utc_offset(&self, out: &mut Vec<u8>, offset: i64, colons: usize) {
+        let abs = offset.abs();
+        let (hour, min, sec) = (abs / 3600, abs / 60 % 60, abs % 60);
+        let fields = match colons {
+            0 => format!("{:02}{:02}", hour, min),
+            1 => format!("{:02}:{:02}", hour, min),
+            2 => format!("{:02}:{:02}:{:02}", hour, min, sec),
+            _ if sec != 0 => format!("{:02}:{:02}:{:02}", hour, min, sec),
+            _ if min != 0 => format!("{:02}:{:02}", hour, min),
+            _ => format!("{:02}", hour),
+        };
+        let sign = if offset < 0 { b'-' } else { b'+' };
+        let fill = self.width.unwrap_or(0).saturating_sub(fields.len() + 1);
+
+        match self.pad {
+            Some(b'-') => out.push(sign),
+            Some(b'_') => {
+                out.resize(out.len() + fill, b' ');
+                out.push(sign);
+            }
+            _ => {
+                out.push(sign);
+                out.resize(out.len() + fill, b'0');
+            }
+        }
+        out.extend_from_slice(fields.as_bytes());
+    }
+}
+
+/// Return the ISO 8601 week-based year of TM and the number of days
+/// since the start of its first week.
+fn iso_week_date(tm: &BrokenDownTime) -> (i64, i64) {
+    // The day of the year of the Monday starting the week that contains
+    // YDAY, counting from the Monday of the week containing January 4.
+    let week_days = |yday: i64| -> i64 {
+        let big_enough_multiple_of_7 = (366 / 7 + 2) * 7;
+        yday - (yday - i64::from(tm.wday) + 4 + big_enough_multiple_of_7) % 7 + 3
+    };
+    let yday = i64::from(tm.yday);
+    let mut year = tm.year;
+    let mut days = week_days(yday);
+
+    if days < 0 {
+        year -= 1;
+        days = week_days(yday + 365 + i64::from(is_leap_year(year)));
+    } else {
+        let next = week_days(yday - 365 - i64::from(is_leap_year(year)));
+        if next >= 0 {
+            year += 1;
+            days = next;
+        }
+    }
+    (year, days)
+}
+
+/// Format TM according to FORMAT, the way `strftime' would, with SECS
+/// as the seconds since the epoch for `%s' and NSEC as the nanoseconds
+/// for `%N'.  Locale-dependent conversions are handed to LOCALE, which
+/// gets the conversion (say `Ec') and returns its text, if any.
+/// Return None if a field width is too large.
+pub fn format_time<F>(
+    format: &[u8],
+    tm: &BrokenDownTime,
+    secs: i64,
+    nsec: i32,
+    locale: &mut F,
+) -> Option<Vec<u8>>
+where
+    F: FnMut(&[u8]) -> Option<Vec<u8>>,
+{
+    let mut out = Vec::with_capacity(format.len());
+    let mut i = 0;
+
+    while i < format.len() {
+        if format[i] != b'%' {
+            out.push(format[i]);
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        let mut conv = Conversion::default();
+        i += 1;
+
+        while i < format.len() {
+            match format[i] {
+                c @ b'_' | c @ b'-' | c @ b'0' | c @ b'+' => conv.pad = Some(c),
+                b'^' => conv.upcase = true,
+                b'#' => conv.change_case = true,
+                _ => break,
+            }
+            i += 1;
+        }
+
+        while i < format.len() && format[i].is_ascii_digit() {
+            let width = conv.width.unwrap_or(0);
+            let digit = usize::from(format[i] - b'0');
+            conv.width = Some(width.saturating_mul(10).saturating_add(digit));
+            i += 1;
+        }
+        if conv.width.map_or(false, |width| width > MAX_FORMAT_WIDTH) {
+            return None;
+        }
+
+        let mut colons = 0;
+        while i < format.len() && format[i] == b':' {
+            colons += 1;
+            i += 1;
+        }
+
+        if i < format.len() && (format[i] == b'E' || format[i] == b'O') {
+            conv.modifier = Some(format[i]);
+            i += 1;
+        }
+
+        if i >= format.len() {
+            out.extend_from_slice(&format[start..]);
+            break;
+        }
+
+        let c = format[i];
+        i += 1;
+
+        if colons != 0 && (c != b'z' || colons > 3) {
+            out.extend_from_slice(&format[start..i]);
+            continue;
+        }
+
+        let hour12 = (tm.hour + 11) % 12 + 1;
+        let out = &mut out;
+
+        match c {
+            b'%' => conv.text(out, b"%", Case::Keep),
+            b'n' => conv.text(out, b"\n", Case::Keep),
+            b't' => conv.text(out, b"\t", Case::Keep),
+
+            b'a' | b'A' | b'b' | b'B' | b'h' | b'p' | b'P' | b'c' | b'x' | b'X' | b'r' => {
+                let case = match c {
+                    b'p' if conv.change_case => Case::Lower,
+                    b'P' => Case::Lower,
+                    b'a' | b'A' | b'b' | b'B' | b'h' if conv.change_case => Case::Upper,
+                    _ => Case::Keep,
+                };
+                let directive = match c {
+                    b'P' => b'p',
+                    _ => c,
+                };
+                let mut spec = Vec::with_capacity(2);
+                if let (Some(modifier), b'c') | (Some(modifier), b'x') | (Some(modifier), b'X') =
+                    (conv.modifier, c)
+                {
+                    spec.push(modifier);
+                }
+                spec.push(directive);
+                let text = match locale(&spec) {
+                    Some(text) => text,
+                    None => {
+                        let fallback: &[u8] = match directive {
+                            b'c' => b"%a %b %e %H:%M:%S %Y",
+                            b'x' => b"%m/%d/%y",
+                            b'X' => b"%H:%M:%S",
+                            b'r' => b"%I:%M:%S %p",
+                            _ => b"",
+                        };
+                        if fallback.is_empty() {
+                            c_locale_name(tm, directive).to_vec()
+                        } else {
+                            format_time(fallback, tm, secs, nsec, locale)?
+                        }
+                    }
+                };
+                conv.text(out, &text, case);
+            }
+
+            b'D' | b'F' | b'R' | b'T' => {
+                let subformat: &[u8] = match c {
+                    b'D' => b"%m/%d/%y",
+                    b'F' => b"%Y-%m-%d",
+                    b'R' => b"%H:%M",
+                    _ => b"%H:%M:%S",
+                };
+                let text = format_time(subformat, tm, secs, nsec, locale)?;
+                conv.text(out, &text, Case::Keep);
+            }
+
+            b'C' => conv.number(out, tm.year / 100, 2, b'0'),
+            b'd' => conv.number(out, tm.mday.into(), 2, b'0'),
+            b'e' => conv.number(out, tm.mday.into(), 2, b' '),
+            b'G' => conv.number(out, iso_week_date(tm).0, 1, b'0'),
+            b'g' => conv.number(out, (iso_week_date(tm).0 % 100).abs(), 2, b'0'),
+            b'V' => conv.number(out, iso_week_date(tm).1 / 7 + 1, 2, b'0'),
+            b'H' => conv.number(out, tm.hour.into(), 2, b'0'),
+            b'I' => conv.number(out, hour12.into(), 2, b'0'),
+            b'k' => conv.number(out, tm.hour.into(), 2, b' '),
+            b'l' => conv.number(out, hour12.into(), 2, b' '),
+            b'j' => conv.number(out, i64::from(tm.yday) + 1, 3, b'0'),
+            b'm' => conv.number(out, i64::from(tm.mon) + 1, 2, b'0'),
+            b'M' => conv.number(out, tm.min.into(), 2, b'0'),
+            b'S' => conv.number(out, tm.sec.into(), 2, b'0'),
+            b's' => conv.number(out, secs, 1, b'0'),
+            b'q' => conv.number(out, i64::from(tm.mon) / 3 + 1, 1, b'0'),
+            b'u' => conv.number(out, ((tm.wday + 6) % 7 + 1).into(), 1, b'0'),
+            b'w' => conv.number(out, tm.wday.into(), 1, b'0'),
+            b'U' => conv.number(out, ((tm.yday - tm.wday + 7) / 7).into(), 2, b'0'),
+            b'W' => conv.number(out, ((tm.yday - (tm.wday + 6) % 7 + 7) / 7).into(), 2, b'0'),
+            b'y' => conv.number(out, (tm.year % 100).abs(), 2, b'0'),
+            b'Y' => conv.number(out, tm.year, 1, b'0'),
+
+            b'N' => {
+                // An explicit width below 9 is a precision: %3N gives
+                // milliseconds, truncated.
+                let width = conv.width.unwrap_or(9);
+                let digits = 9usize.saturating_sub(width) as u32;
+                let value = i64::from(nsec) / 10i64.pow(digits);
+                conv.width = Some(width);
+                conv.number(out, value, width, b'0');
+            }
+
+            b'z' => conv.utc_offset(out, tm.utcoff, colons),
+            b'Z' => {
+                let case = if conv.change_case {
+                    Case::Lower
+                } else {
+                    Case::Keep
+                };
+                conv.text(out, &tm.zone, case);
+            }
+
+            _ => out.extend_from_slice(&format[start..i]),
+        }
+    }
+
+    Some(out)
+}
+
+/// Return the name that the C locale uses for DIRECTIVE, one of `a',
+/// `A', `b', `B', `h' and `p', in TM.
+fn c_locale_name(tm: &BrokenDownTime, directive: u8) -> &'static [u8] {
+    const DAYS: [&str; 7] = [
+        "Sunday",
+        "Monday",
+        "Tuesday",
+        "Wednesday",
+        "Thursday",
+        "Friday",
+        "Saturday",
+    ];
+    const MONTHS: [&str; 12] = [
+        "January",
+        "February",
+        "March",
+        "April",
+        "May",
+        "June",
+        "July",
+        "August",
+        "September",
+        "October",
+        "November",
+        "December",
+    ];
+    let day = DAYS[tm.wday as usize % 7].as_bytes();
+    let month = MONTHS[tm.mon as usize % 12].as_bytes();
+
+    match directive {
+        b'a' => &day[..3],
+        b'A' => day,
+        b'b' | b'h' => &month[..3],
+        b'B' => month,
+        _ if tm.hour < 12 => b"AM",
+        _ => b"PM",
+    }
+}
+
+/// Like `lisp_time_argument', except decode only the seconds part, and
+/// do not check the subseconds part.
+#[no_mangle]
+pub extern "C" fn lisp_seconds_argument(specified_time: LispObject) -> time_t {
+    if let Some(t) = TicksTime::from_pair(specified_time) {
+        let secs = t.ticks.div_euclid(t.hz);
+        return time_t::try_from(secs).unwrap_or_else(|_| time_overflow());
+    }
+
+    let mut high = LispObject::from_C(0);
+    let mut low = LispObject::from_C(0);
+    let mut usec = LispObject::from_C(0);
+    let mut psec = LispObject::from_C(0);
+    let mut t = lisp_time::default();
+
+    let mut val =
+        unsafe { disassemble_lisp_time(specified_time, &mut high, &mut low, &mut usec, &mut psec) };
+    if val != 0 {
+        val = unsafe {
+            decode_time_components(
+                high,
+                low,
+                LispObject::from(0),
+                LispObject::from(0),
+                &mut t,
+                ptr::null_mut(),
+            )
+        };
+        if 0 < val
+            && !(time_t::min_value() >> LO_TIME_BITS <= t.hi
+                && t.hi <= time_t::max_value() >> LO_TIME_BITS)
+        {
+            val = -1;
+        }
+    }
+    check_time_validity(val);
+
+    (t.hi << LO_TIME_BITS) + time_t::from(t.lo)
+}
+
+/// A time zone rule, as given by the ZONE argument of the time
+/// functions.
+enum TimeZoneRule {
+    /// Universal Time.
+    Utc,
+    /// A fixed offset in seconds east of UTC, and its abbreviation.
+    Fixed(i64, Vec<u8>),
+    /// Rules read from a zone file or TZ string without the C library.
+    Zoneinfo(Arc<ZoneRules>),
+    /// A rule only the C library understands.
+    System(timezone_t),
+}
+
+impl TimeZoneRule {
+    /// Convert the Lisp time zone rule ZONE: nil, t, `wall', a zone
+    /// name or TZ string, an integer offset or a list (OFFSET ABBR).
+    fn from_lisp(zone: LispObject) -> Self {
+        if zone.is_t() || zone == LispObject::from(0) {
+            return TimeZoneRule::Utc;
+        }
+
+        if let Some(offset) = zone.as_fixnum() {
+            return Self::fixed(zone, offset, numeric_zone_abbreviation(offset));
+        }
+
+        if let Some(cons) = zone.as_cons() {
+            if let (Some(offset), Some(tail)) = (cons.car().as_fixnum(), cons.cdr().as_cons()) {
+                let abbr: LispStringRef = tail.car().into();
+                let abbr = unsafe {
+                    code_convert_string_norecord(abbr.into(), globals.Vlocale_coding_system, true)
+                };
+                let abbr: LispStringRef = abbr.into();
+                return Self::fixed(zone, offset, abbr.as_slice().to_vec());
+            }
+        }
+
+        if let Some(rules) = Self::zoneinfo_spec(zone).and_then(|spec| load_zone(&spec)) {
+            return TimeZoneRule::Zoneinfo(rules);
+        }
+
+        // tzlookup handles whatever `load_zone' could not read, and
+        // signals an error for anything that is not a zone.
+        TimeZoneRule::System(unsafe { tzlookup(zone, false) })
+    }
+
+    /// Return the zone name, file name or TZ string that ZONE stands
+    /// for, if it is nil, `wall' or a string.  Emacs local time is
+    /// whatever `TZ' says, and system wall clock time is described by
+    /// /etc/localtime.
+    fn zoneinfo_spec(zone: LispObject) -> Option<Vec<u8>> {
+        if zone.is_nil() {
+            let tz = unsafe { emacs_getenv_TZ() };
+            if tz.is_null() {
+                Some(LOCALTIME_FILE.to_vec())
+            } else {
+                Some(unsafe { CStr::from_ptr(tz) }.to_bytes().to_vec())
+            }
+        } else if zone.eq(Qwall) {
+            Some(LOCALTIME_FILE.to_vec())
+        } else if zone.is_string() {
+            let spec =
+                unsafe { code_convert_string_norecord(zone, globals.Vlocale_coding_system, true) };
+            let spec: LispStringRef = spec.into();
+            Some(spec.as_slice().to_vec())
+        } else {
+            None
+        }
+    }
+
+    fn fixed(zone: LispObject, offset: EmacsInt, abbr: Vec<u8>) -> Self {
+        // POSIX TZ strings allow offsets of up to 24:59:59.
+        if offset.abs() >= 25 * 60 * 60 {
+            invalid_time_zone_specification(zone);
+        }
+        TimeZoneRule::Fixed(offset, abbr)
+    }
+
+    /// Break down SECS, seconds since the epoch, in this zone.
+    fn decode(&self, secs: time_t) -> Option<BrokenDownTime> {
+        match *self {
+            TimeZoneRule::Utc => BrokenDownTime::from_utc(secs, 0, b"UTC".to_vec()),
+            TimeZoneRule::Fixed(offset, ref abbr) => {
+                BrokenDownTime::from_utc(secs, offset, abbr.clone())
+            }
+            TimeZoneRule::Zoneinfo(ref rules) => {
+                let local = rules.lookup(secs);
+                let mut time = BrokenDownTime::from_utc(secs, local.utoff, local.abbr.clone())?;
+                time.isdst = local.isdst;
+                Some(time)
+            }
+            TimeZoneRule::System(tz) => {
+                let mut tm: tm = unsafe { mem::zeroed() };
+                if unsafe { emacs_localtime_rz(tz, &secs, &mut tm) }.is_null() {
+                    return None;
+                }
+                let zone = if tm.tm_zone.is_null() {
+                    Vec::new()
+                } else {
+                    unsafe { CStr::from_ptr(tm.tm_zone) }.to_bytes().to_vec()
+                };
+
+                Some(BrokenDownTime {
+                    sec: tm.tm_sec,
+                    min: tm.tm_min,
+                    hour: tm.tm_hour,
+                    mday: tm.tm_mday,
+                    mon: tm.tm_mon,
+                    year: i64::from(tm.tm_year) + TM_YEAR_BASE,
+                    wday: tm.tm_wday,
+                    yday: tm.tm_yday,
+                    isdst: tm.tm_isdst > 0,
+                    utcoff: tm.tm_gmtoff.into(),
+                    zone,
+                })
+            }
+        }
+    }
+
+    /// Return the seconds since the epoch of the local time FIELDS, the
+    /// second, minute, hour, day, month (from 0) and year.
+    fn encode(&self, fields: [i64; 6]) -> Option<time_t> {
+        match *self {
+            TimeZoneRule::Utc => seconds_from_fields(fields, 0),
+            TimeZoneRule::Fixed(offset, _) => seconds_from_fields(fields, offset),
+            TimeZoneRule::Zoneinfo(ref rules) => {
+                rules.local_to_utc(seconds_from_fields(fields, 0)?)
+            }
+            TimeZoneRule::System(tz) => {
+                let mut tm: tm = unsafe { mem::zeroed() };
+                tm.tm_sec = fields[0] as c_int;
+                tm.tm_min = fields[1] as c_int;
+                tm.tm_hour = fields[2] as c_int;
+                tm.tm_mday = fields[3] as c_int;
+                tm.tm_mon = fields[4] as c_int;
+                tm.tm_year = (fields[5] - TM_YEAR_BASE) as c_int;
+                tm.tm_isdst = -1;
+
+                match unsafe { emacs_mktime_z(tz, &mut tm) } {
+                    -1 => None,
+                    value => Some(value),
+                }
+            }
+        }
+    }
+}
+
+impl Drop for TimeZoneRule {
+    fn drop(&mut self) {
+        if let TimeZoneRule::System(tz) = *self {
+            unsafe { xtzfree(tz) };
+        }
+    }
+}
+
+fn invalid_time_zone_specification(zone: LispObject) -> ! {
+    xsignal!(Qerror, "Invalid time zone specification", zone);
+}
+
+/// Return the text of the locale-dependent conversion SPEC, such as `a'
+/// or `Ec', as the C library's `strftime' formats TM.
+fn locale_conversion(time: &BrokenDownTime, spec: &[u8]) -> Option<Vec<u8>> {
+    let year = time.year.checked_sub(TM_YEAR_BASE)?;
+    if year < i64::from(c_int::min_value()) || i64::from(c_int::max_value()) < year {
+        return None;
+    }
+    let zone = CString::new(time.zone.clone()).ok()?;
+
+    let mut tm: tm = unsafe { mem::zeroed() };
+    tm.tm_sec = time.sec;
+    tm.tm_min = time.min;
+    tm.tm_hour = time.hour;
+    tm.tm_mday = time.mday;
+    tm.tm_mon = time.mon;
+    tm.tm_year = year as c_int;
+    tm.tm_wday = time.wday;
+    tm.tm_yday = time.yday;
+    tm.tm_isdst = time.isdst.into();
+    tm.tm_gmtoff = time.utcoff as c_long;
+    tm.tm_zone = zone.as_ptr();
+
+    let mut format = Vec::with_capacity(spec.len() + 2);
+    format.push(b'%');
+    format.extend_from_slice(spec);
+    format.push(0);
+
+    // Locale names and formats are short, so a zero return means empty
+    // output, not a full buffer.
+    let mut buf = [0_u8; 1024];
+    let len = unsafe {
+        strftime(
+            buf.as_mut_ptr() as *mut c_char,
+            buf.len(),
+            format.as_ptr() as *const c_char,
+            &tm,
+        )
+    };
+
+    Some(buf[..len].to_vec())
+}
+
+/// Break down SECS in ZONE, signaling an error if that is impossible.
+fn decode_in_zone(secs: time_t, zone: LispObject) -> BrokenDownTime {
+    let rule = TimeZoneRule::from_lisp(zone);
+    let tm = rule.decode(secs);
+    // Free the rule before a possible nonlocal exit.
+    drop(rule);
+    tm.unwrap_or_else(|| time_overflow())
+}
+
+/// Format the time T, broken down as TM, according to FORMAT and decode
+/// the result with `locale-coding-system'.
+fn format_broken_down_time(format: &[u8], tm: &BrokenDownTime, t: c_timespec) -> LispObject {
+    unsafe { synchronize_system_time_locale() };
+
+    let text = match format_time(
+        format,
+        tm,
+        t.tv_sec,
+        t.tv_nsec as i32,
+        &mut |spec: &[u8]| locale_conversion(tm, spec),
+    ) {
+        Some(text) => text,
+        None => unsafe {
+            string_overflow();
+            unreachable!();
+        },
+    };
+
+    unsafe {
+        let text = make_unibyte_string(text.as_ptr() as *const c_char, text.len() as isize);
+        code_convert_string_norecord(text, globals.Vlocale_coding_system, false)
+    }
+}
+
+/// Use FORMAT-STRING to format the time TIME, or now if omitted or nil.
+/// TIME is specified as (HIGH LOW USEC PSEC), as returned by
+/// `current-time' or `file-attributes'.  It can also be a single integer
+/// number of seconds since the epoch, or a pair (TICKS . HZ) of integers
+/// meaning TICKS/HZ seconds if `time-ticks-hz-pairs' is non-nil.
+///
+/// The optional ZONE is omitted or nil for Emacs local time, t for
+/// Universal Time, `wall' for system wall clock time, or a string as in
+/// the TZ environment variable, such as "Europe/Berlin" or "EST5EDT".
+/// It can also be a list (as from `current-time-zone') or an integer
+/// (as from `decode-time') applied without consideration for daylight
+/// saving time.
+///
+/// The value is a copy of FORMAT-STRING, but with certain constructs replaced
+/// by text that describes the specified date and time in TIME:
+///
+/// %Y is the year, %y within the century, %C the century.
+/// %G is the year corresponding to the ISO week, %g within the century.
+/// %m is the numeric month.
+/// %b and %h are the locale's abbreviated month name, %B the full name.
+///  (%h is not supported on MS-Windows.)
+/// %d is the day of the month, zero-padded, %e is blank-padded.
+/// %u is the numeric day of week from 1 (Monday) to 7, %w from 0 (Sunday) to 6.
+/// %a is the locale's abbreviated name of the day of week, %A the full name.
+/// %U is the week number starting on Sunday, %W starting on Monday,
+///  %V according to ISO 8601.
+/// %j is the day of the year.
+///
+/// %H is the hour on a 24-hour clock, %I is on a 12-hour clock, %k is like %H
+///  only blank-padded, %l is like %I blank-padded.
+/// %p is the locale's equivalent of either AM or PM, %P is its lower-case form.
+/// %q is the calendar quarter (1–4).
+/// %M is the minute (00-59).
+/// %S is the second (00-59; 00-60 on platforms with leap seconds)
+/// %s is the number of seconds since 1970-01-01 00:00:00 +0000.
+/// %N is the nanosecond, %6N the microsecond, %3N the millisecond, etc.
+/// %Z is the time zone abbreviation, %z is the numeric form.
+/// %:z is like %z with a colon, as in +05:30; %::z adds seconds, and
+///  %:::z uses only as many colon-separated parts as are needed.
+///
+/// %c is the locale's date and time format.
+/// %x is the locale's "preferred" date format.
+/// %D is like "%m/%d/%y".
+/// %F is the ISO 8601 date format (like "%Y-%m-%d").
+///
+/// %R is like "%H:%M", %T is like "%H:%M:%S", %r is like "%I:%M:%S %p".
+/// %X is the locale's "preferred" time format.
+///
+/// Finally, %n is a newline, %t is a tab, %% is a literal %, and
+/// unrecognized %-sequences stand for themselves.
+///
+/// Certain flags and modifiers are available with some format controls.
+/// The flags are `_', `-', `^' and `#'.  For certain characters X,
+/// %_X is like %X, but padded with blanks; %-X is like %X,
+/// but without padding.  %^X is like %X, but with all textual
+/// characters up-cased; %#X is like %X, but with letter-case of
+/// all textual characters reversed.
+/// %NX (where N stands for an integer) is like %X,
+/// but takes up at least N (a number) positions.
+/// The modifiers are `E' and `O'.  For certain characters X,
+/// %EX is a locale's alternative version of %X;
+/// %OX is like %X, but uses the locale's number symbols.
+///
+/// For example, to produce full ISO 8601 format, use "%FT%T%z".
+#[lisp_fn(min = "1")]
+pub fn format_time_string(
+    format_string: LispStringRef,
+    time: LispObject,
+    zone: LispObject,
+) -> LispObject {
+    let t = unsafe { lisp_time_argument(time) };
+    let format: LispStringRef = unsafe {
+        code_convert_string_norecord(format_string.into(), globals.Vlocale_coding_system, true)
+    }
+    .into();
+    let tm = decode_in_zone(t.tv_sec, zone);
+
+    format_broken_down_time(format.as_slice(), &tm, t)
+}
+
+/// Decode a time value as (SEC MINUTE HOUR DAY MONTH YEAR DOW DST UTCOFF).
+/// The optional TIME should be a list of (HIGH LOW . IGNORED),
+/// as from `current-time' and `file-attributes', or nil to use the
+/// current time.  It can also be a single integer number of seconds since
+/// the epoch, or a pair (TICKS . HZ) of integers meaning TICKS/HZ
+/// seconds if `time-ticks-hz-pairs' is non-nil.
+///
+/// The optional ZONE is omitted or nil for Emacs local time, t for
+/// Universal Time, `wall' for system wall clock time, or a string as in
+/// the TZ environment variable, such as "Europe/Berlin" or "EST5EDT".
+/// It can also be a list (as from `current-time-zone') or an integer
+/// (the UTC offset in seconds) applied without consideration for
+/// daylight saving time.
+///
+/// The list has the following nine members: SEC is an integer between 0
+/// and 60; SEC is 60 for a leap second, which only some operating systems
+/// support.  MINUTE is an integer between 0 and 59.  HOUR is an integer
+/// between 0 and 23.  DAY is an integer between 1 and 31.  MONTH is an
+/// integer between 1 and 12.  YEAR is an integer indicating the
+/// four-digit year.  DOW is the day of week, an integer between 0 and 6,
+/// where 0 is Sunday.  DST is t if daylight saving time is in effect,
+/// otherwise nil.  UTCOFF is an integer indicating the UTC offset in
+/// seconds, i.e., the number of seconds east of Greenwich.  (Note that
+/// Common Lisp has different meanings for DOW and UTCOFF.)
+#[lisp_fn(min = "0")]
+pub fn decode_time(time: LispObject, zone: LispObject) -> LispObject {
+    let tm = decode_in_zone(lisp_seconds_argument(time), zone);
+    if LispObject::fixnum_overflow(tm.year) {
+        time_overflow();
+    }
+
+    list!(
+        tm.sec,
+        tm.min,
+        tm.hour,
+        tm.mday,
+        tm.mon + 1,
+        tm.year,
+        tm.wday,
+        tm.isdst,
+        tm.utcoff
+    )
+}
+
+/// Convert SECOND, MINUTE, HOUR, DAY, MONTH, YEAR and ZONE to internal time.
+/// This is the reverse operation of `decode-time', which see.
+///
+/// The optional ZONE is omitted or nil for Emacs local time, t for
+/// Universal Time, `wall' for system wall clock time, or a string as in
+/// the TZ environment variable, such as "Europe/Berlin" or "EST5EDT".
+/// It can also be a list (as from `current-time-zone') or an integer
+/// (as from `decode-time') applied without consideration for daylight
+/// saving time.
+///
+/// You can pass more than 7 arguments; then the first six arguments
+/// are used as SECOND through YEAR, and the *last* argument is used as ZONE.
+/// The intervening arguments are ignored.
+/// This feature lets (apply \\='encode-time (decode-time ...)) work.
+///
+/// Out-of-range values for SECOND, MINUTE, HOUR, DAY, or MONTH are allowed;
+/// for example, a DAY of 0 means the day preceding the given month.
+/// Year numbers less than 100 are treated just like other year numbers.
+/// If you want them to stand for years in this century, you must do that yourself.
+///
+/// Years before 1970 are not guaranteed to work.  On some systems,
+/// year values as low as 1901 do work.
+///
+/// usage: (encode-time SECOND MINUTE HOUR DAY MONTH YEAR &optional ZONE)
+#[lisp_fn(min = "6")]
+pub fn encode_time(args: &mut [LispObject]) -> LispObject {
+    let zone = if args.len() > 6 {
+        args[args.len() - 1]
+    } else {
+        Qnil
+    };
+
+    // Like `struct tm', count months from 0, and insist that every field
+    // fit in an int once the year is offset by TM_YEAR_BASE.
+    let mut fields = [0; 6];
+    for (i, field) in fields.iter_mut().enumerate() {
+        let offset = match i {
+            4 => 1,
+            5 => TM_YEAR_BASE,
+            _ => 0,
+        };
+        let value = args[i].as_fixnum_or_error() - offset;
+        if value < c_int::min_value().into() || value > c_int::max_value().into() {
+            time_overflow();
+        }
+        *field = if i == 5 { value + TM_YEAR_BASE } else { value };
+    }
+
+    let rule = TimeZoneRule::from_lisp(zone);
+    let value = rule.encode(fields);
+    drop(rule);
+    let value = value.unwrap_or_else(|| time_overflow());
+
+    list!(hi_time(value), lo_time(value))
+}
+
+/// Return the offset and name for the local time zone.
+/// This returns a list of the form (OFFSET NAME).
+/// OFFSET is an integer number of seconds ahead of UTC (east of Greenwich).
+///     A negative value means west of Greenwich.
+/// NAME is a string giving the name of the time zone.
+/// If SPECIFIED-TIME is given, the time zone offset is determined from it
+/// instead of using the current time.  The argument should have the form
+/// (HIGH LOW . IGNORED).  Thus, you can use times obtained from
+/// `current-time' and from `file-attributes'.  SPECIFIED-TIME can also be
+/// a single integer number of seconds since the epoch, or a pair
+/// (TICKS . HZ) of integers meaning TICKS/HZ seconds if
+/// `time-ticks-hz-pairs' is non-nil.
+///
+/// The optional ZONE is omitted or nil for Emacs local time, t for
+/// Universal Time, `wall' for system wall clock time, or a string as in
+/// the TZ environment variable, such as "Europe/Berlin" or "EST5EDT".
+/// It can also be a list (as from `current-time-zone') or an integer
+/// (as from `decode-time') applied without consideration for daylight
+/// saving time.
+///
+/// Some operating systems cannot provide all this information to Emacs;
+/// in this case, `current-time-zone' returns a list containing nil for
+/// the data it can't find.
+#[lisp_fn(min = "0")]
+pub fn current_time_zone(specified_time: LispObject, zone: LispObject) -> LispObject {
+    let secs = lisp_seconds_argument(specified_time);
+    let mut tm = decode_in_zone(secs, zone);
+
+    // Without a zone name, use the numeric form instead.
+    if tm.zone.is_empty() {
+        tm.zone = numeric_zone_abbreviation(tm.utcoff);
+    }
+    let name = format_broken_down_time(
+        b"%Z",
+        &tm,
+        c_timespec {
+            tv_sec: secs,
+            tv_nsec: 0,
+        },
+    );
+
+    list!(tm.utcoff, name)
+}
+
+fn iso8601_parse_error(string: LispStringRef, error: iso8601::ParseError) -> ! {
+    // The parser counts bytes, but Lisp indexes strings by character.
+    let position = unsafe { string_byte_to_char(string.into(), error.position as isize) };
+    xsignal!(
+        Qiso8601_parse_error,
+        error.message,
+        string,
+        position as EmacsInt
+    );
+}
+
+/// Parse STRING, an ISO 8601 date and time or duration, into a time value.
+/// STRING can be a calendar date such as "2018-07-04", an ordinal date
+/// such as "2018-185" or a week date such as "2018-W27-3", in the
+/// extended format or the basic format without hyphens.  A time of day
+/// can follow after "T" or a space, with an optional decimal fraction on
+/// its last component and an optional UTC offset: "Z", "+05:30", "-0800"
+/// or "+02".  Parts that STRING leaves out default to the start of their
+/// range, so "2018-07" is midnight on July 1.
+///
+/// A time without a UTC offset is taken to be in ZONE, which has the same
+/// meaning as in `format-time-string'.
+///
+/// If STRING is a duration such as "P1W", "P1DT12H" or "PT0.5S", return
+/// its length as a time value.  Durations in years or months have no
+/// fixed length and are rejected.
+///
+/// The value has the form (HIGH LOW USEC PSEC), as from `current-time'.
+/// If STRING is malformed, signal `iso8601-parse-error' with a message,
+/// STRING, and the position in STRING where the problem was found.
+#[lisp_fn(min = "1")]
+pub fn iso8601_parse_to_time(string: LispStringRef, zone: LispObject) -> LispObject {
+    let parsed =
+        iso8601::parse(string.as_slice()).unwrap_or_else(|err| iso8601_parse_error(string, err));
+
+    let (secs, nsec) = match parsed {
+        Iso8601::Duration {
+            seconds,
+            nanoseconds,
+        } => (seconds, nanoseconds),
+        Iso8601::DateTime(time) => {
+            if time.year - TM_YEAR_BASE < c_int::min_value().into()
+                || time.year - TM_YEAR_BASE > c_int::max_value().into()
+            {
+                time_overflow();
+            }
+            let fields = [
+                time.second,
+                time.minute,
+                time.hour,
+                time.day,
+                time.month - 1,
+                time.year,
+            ];
+            let secs = match time.offset {
+                Some(offset) => seconds_from_fields(fields, offset),
+                None => {
+                    let rule = TimeZoneRule::from_lisp(zone);
+                    let secs = rule.encode(fields);
+                    drop(rule);
+                    secs
+                }
+            };
+            (secs.unwrap_or_else(|| time_overflow()), time.nanosecond)
+        }
+    };
+
+    make_lisp_time_1(c_timespec {
+        tv_sec: secs as time_t,
+        tv_nsec: nsec as c_long,
+    })
+}
+
+/// Format TIME as an RFC 3339 timestamp such as "2018-07-04T18:05:09+02:00".
+/// TIME is a time value as in `format-time-string', or nil for now.
+/// ZONE has the same meaning as in `format-time-string'; Universal Time
+/// and other zones with a UTC offset of zero are written with "Z".  A zone
+/// whose offset is not a whole number of minutes, as with local mean
+/// time, cannot be written in RFC 3339, so TIME is then written in
+/// Universal Time.
+///
+/// FRACTION-DIGITS says how many digits of the fraction of a second to
+/// write: nil for none, an integer from 0 to 9, or t for as many as the
+/// fraction needs.  Digits beyond the requested ones are truncated.
+///
+/// Signal an error if the year of TIME is not from 0 to 9999, as RFC 3339
+/// requires.
+#[lisp_fn(min = "0")]
+pub fn format_rfc3339(
+    time: LispObject,
+    zone: LispObject,
+    fraction_digits: LispObject,
+) -> LispObject {
+    let t = unsafe { lisp_time_argument(time) };
+    let nsec = t.tv_nsec.into();
+    let digits = if fraction_digits.is_nil() {
+        0
+    } else if fraction_digits.is_t() {
+        iso8601::fraction_digits(nsec)
+    } else {
+        match fraction_digits.as_fixnum_or_error() {
+            digits @ 0..=9 => digits as usize,
+            _ => {
+                args_out_of_range!(fraction_digits, 0, 9);
+            }
+        }
+    };
+
+    let mut tm = decode_in_zone(t.tv_sec, zone);
+    if tm.utcoff % 60 != 0 {
+        tm = decode_in_zone(t.tv_sec, Qt);
+    }
+    let text = iso8601::format_rfc3339(&tm, nsec, digits).unwrap_or_else(|| time_overflow());
+
+    unsafe { make_unibyte_string(text.as_ptr() as *const c_char, text.len() as isize) }
+}
+
+#[no_mangle]
+pub extern "C" fn rust_syms_of_time() {
+    def_lisp_sym!(Qiso8601_parse_error, "iso8601-parse-error");
+    put(
+        Qiso8601_parse_error.into(),
+        Qerror_conditions,
+        purecopy(list!(Qiso8601_parse_error, Qerror)),
+    );
+    put(
+        Qiso8601_parse_error.into(),
+        Qerror_message,
+        purecopy(unsafe { build_string("Invalid ISO 8601 string\0".as_ptr() as *const c_char) }),
+    );
+
+    /// Non-nil means that a cons of two integers is a time value (TICKS . HZ).
+    /// It then stands for TICKS/HZ seconds, as returned by `time-convert'
+    /// and `current-monotonic-time', and `time-add' and `time-subtract'
+    /// compute exactly with it.  If nil, such a cons is the obsolete form
+    /// (HIGH . LOW), meaning HIGH * 2**16 + LOW seconds.
+    defvar_lisp!(Vtime_ticks_hz_pairs, "time-ticks-hz-pairs", Qnil);
+}
+
 include!(concat!(env!("OUT_DIR"), "/time_exports.rs"));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(format: &str, secs: i64, nsec: i32, utcoff: i64, zone: &str) -> String {
+        let tm = BrokenDownTime::from_utc(secs, utcoff, zone.as_bytes().to_vec()).unwrap();
+        let text = format_time(format.as_bytes(), &tm, secs, nsec, &mut |_: &[u8]| None);
+        String::from_utf8(text.unwrap()).unwrap()
+    }
+
+    // The timestamp just before the leap second of 1972-06-30.
+    const LOOK: i64 = 78_796_799;
+
+    #[test]
+    fn test_format_fixed_zones() {
+        let full = "%Y-%m-%d %H:%M:%S.%3N %z (%Z)";
+        assert_eq!(
+            format(full, LOOK, 999_999_999, 0, "UTC"),
+            "1972-06-30 23:59:59.999 +0000 (UTC)"
+        );
+        assert_eq!(
+            format(full, LOOK, 999_999_999, -28800, "PST"),
+            "1972-06-30 15:59:59.999 -0800 (PST)"
+        );
+        assert_eq!(
+            format(full, LOOK, 999_999_999, 19800, "IST"),
+            "1972-07-01 05:29:59.999 +0530 (IST)"
+        );
+    }
+
+    #[test]
+    fn test_format_nanoseconds() {
+        assert_eq!(
+            format("%N %6N %3N %1N %-N %12N", LOOK, 1_234_567, 0, "UTC"),
+            "001234567 001234 001 0 1234567 000001234567"
+        );
+    }
+
+    #[test]
+    fn test_format_utc_offsets() {
+        let offsets = "%z %:z %::z %:::z";
+        assert_eq!(
+            format(offsets, 0, 0, 0, "UTC"),
+            "+0000 +00:00 +00:00:00 +00"
+        );
+        assert_eq!(
+            format(offsets, 0, 0, -16200, "-0430"),
+            "-0430 -04:30 -04:30:00 -04:30"
+        );
+        assert_eq!(
+            format(offsets, 0, 0, 3 * 3600 + 7, "+030007"),
+            "+0300 +03:00 +03:00:07 +03:00:07"
+        );
+        assert_eq!(format("%::::z", 0, 0, 0, "UTC"), "%::::z");
+    }
+
+    #[test]
+    fn test_format_calendar() {
+        // 1972-06-30 was a Friday, in ISO week 26.
+        assert_eq!(
+            format(
+                "%a %A %b %B %h %p %j %u %w %U %W %V %G %g %q %C %y",
+                LOOK,
+                0,
+                0,
+                "UTC"
+            ),
+            "Fri Friday Jun June Jun PM 182 5 5 26 26 26 1972 72 2 19 72"
+        );
+        assert_eq!(
+            format("%c|%x|%X|%r|%D|%F|%R|%T|%s", LOOK, 0, 0, "UTC"),
+            "Fri Jun 30 23:59:59 1972|06/30/72|23:59:59|11:59:59 PM|06/30/72|1972-06-30|23:59|23:59:59|78796799"
+        );
+        // ISO weeks at the turn of the year.
+        let iso = "%F %G-W%V-%u";
+        assert_eq!(
+            format(iso, 1_230_508_800, 0, 0, "UTC"),
+            "2008-12-29 2009-W01-1"
+        );
+        assert_eq!(
+            format(iso, 1_262_476_800, 0, 0, "UTC"),
+            "2010-01-03 2009-W53-7"
+        );
+    }
+
+    #[test]
+    fn test_format_flags() {
+        let secs = 1_230_890_096; // 2009-01-02 09:54:56 UTC
+        assert_eq!(
+            format("%d|%_d|%-d|%e|%-e|%k|%l|%I|%5Y|%_5Y|%-j", secs, 0, 0, "UTC"),
+            "02| 2|2| 2|2| 9| 9|09|02009| 2009|2"
+        );
+        assert_eq!(
+            format(
+                "%^a|%#a|%#p|%P|%^B|%#Z|%^Z|%8a|%-8a|%08a",
+                secs,
+                0,
+                0,
+                "Utc"
+            ),
+            "FRI|FRI|am|am|JANUARY|utc|UTC|     Fri|Fri|00000Fri"
+        );
+    }
+
+    #[test]
+    fn test_format_unrecognized() {
+        assert_eq!(
+            format("%%|%n|%t|%Q|%5:Q|%", 0, 0, 0, "UTC"),
+            "%|\n|\t|%Q|%5:Q|%"
+        );
+        assert_eq!(format("%Ey %Od %OH", 0, 0, 0, "UTC"), "70 01 00");
+        let tm = BrokenDownTime::from_utc(0, 0, Vec::new()).unwrap();
+        assert_eq!(
+            format_time(b"%4294967296d", &tm, 0, 0, &mut |_: &[u8]| None),
+            None
+        );
+    }
+
+    #[test]
+    fn test_format_locale_conversions() {
+        let tm = BrokenDownTime::from_utc(LOOK, 0, b"UTC".to_vec()).unwrap();
+        let mut specs = Vec::new();
+        let text = format_time(b"%c %Ex %#A %r", &tm, LOOK, 0, &mut |spec: &[u8]| {
+            specs.push(spec.to_vec());
+            Some(b"locale".to_vec())
+        });
+        assert_eq!(text.unwrap(), b"locale locale LOCALE locale");
+        assert_eq!(
+            specs,
+            vec![b"c".to_vec(), b"Ex".to_vec(), b"A".to_vec(), b"r".to_vec()]
+        );
+    }
+
+    #[test]
+    fn test_broken_down_time() {
+        let tm = BrokenDownTime::from_utc(-1, 0, Vec::new()).unwrap();
+        assert_eq!(
+            (tm.year, tm.mon, tm.mday, tm.hour, tm.min, tm.sec, tm.wday, tm.yday),
+            (1969, 11, 31, 23, 59, 59, 3, 364)
+        );
+        let tm = BrokenDownTime::from_utc(951_782_400, 0, Vec::new()).unwrap();
+        assert_eq!((tm.year, tm.mon, tm.mday, tm.yday), (2000, 1, 29, 59));
+        assert_eq!(
+            BrokenDownTime::from_utc(i64::max_value(), 1, Vec::new()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_seconds_from_fields() {
+        assert_eq!(
+            seconds_from_fields([59, 59, 23, 30, 5, 1972], 0),
+            Some(LOOK)
+        );
+        assert_eq!(
+            seconds_from_fields([59, 59, 15, 30, 5, 1972], -28800),
+            Some(LOOK)
+        );
+        // Out-of-range fields are normalized.
+        assert_eq!(
+            seconds_from_fields([0, 0, 0, 0, 12, 1999], 0),
+            seconds_from_fields([0, 0, 0, 31, 11, 1999], 0)
+        );
+        assert_eq!(seconds_from_fields([-1, 0, 0, 1, -12, 1971], 0), Some(-1));
+    }
+
+    #[test]
+    fn test_numeric_zone_abbreviation() {
+        assert_eq!(numeric_zone_abbreviation(0), b"+00");
+        assert_eq!(numeric_zone_abbreviation(-28800), b"-08");
+        assert_eq!(numeric_zone_abbreviation(19800), b"+0530");
+        assert_eq!(numeric_zone_abbreviation(-19817), b"-053017");
+    }
+
+    fn t(ticks: i128, hz: i128) -> TicksTime {
+        TicksTime { ticks, hz }
+    }
+
+    fn parts(t: TicksTime) -> (i128, i128) {
+        (t.ticks, t.hz)
+    }
+
+    #[test]
+    fn test_ticks_from_float() {
+        assert_eq!(parts(TicksTime::from_float(0.5).unwrap()), (1, 2));
+        assert_eq!(parts(TicksTime::from_float(-0.75).unwrap()), (-3, 4));
+        assert_eq!(parts(TicksTime::from_float(3.0).unwrap()), (3, 1));
+        assert_eq!(parts(TicksTime::from_float(0.0).unwrap()), (0, 1));
+        assert_eq!(
+            parts(TicksTime::from_float(1e20).unwrap()),
+            (100_000_000_000_000_000_000, 1)
+        );
+        assert!(TicksTime::from_float(1e40).is_none());
+        let tiny = TicksTime::from_float(1e-300).unwrap();
+        assert_eq!(parts(tiny), (0, 1 << 52));
+        let neg_tiny = TicksTime::from_float(-1e-300).unwrap();
+        assert_eq!(parts(neg_tiny), (-1, 1 << 52));
+        assert!(TicksTime::from_float(std::f64::NAN).is_none());
+        let x = 1530720309.123;
+        let f = TicksTime::from_float(x).unwrap();
+        assert_eq!(f.to_float().to_bits(), x.to_bits());
+    }
+
+    #[test]
+    fn test_ticks_arithmetic() {
+        assert_eq!(parts(t(1, 3).checked_add(t(1, 6)).unwrap()), (3, 6));
+        assert_eq!(
+            parts(t(1, 1_000_000_000).checked_add(t(1, 1)).unwrap()),
+            (1_000_000_001, 1_000_000_000)
+        );
+        assert_eq!(parts(t(1, 4).checked_sub(t(1, 6)).unwrap()), (1, 12));
+        assert_eq!(parts(t(-5, 2).checked_sub(t(1, 2)).unwrap()), (-6, 2));
+        assert!(t(i128::max_value(), 3).checked_add(t(1, 2)).is_none());
+    }
+
+    #[test]
+    fn test_ticks_compare() {
+        assert!(t(1, 3) < t(1, 2));
+        assert!(t(-1, 3) > t(-1, 2));
+        assert_eq!(t(2, 4), t(1, 2));
+        assert!(t(333_333_333, 1_000_000_000) < t(1, 3));
+        assert!(t((1i128 << 100) + 1, 1 << 60) > t(1 << 40, 1));
+    }
+
+    #[test]
+    fn test_ticks_conversions() {
+        assert_eq!(parts(t(7, 2).to_hz(1).unwrap()), (3, 1));
+        assert_eq!(parts(t(-7, 2).to_hz(1).unwrap()), (-4, 1));
+        assert_eq!(parts(t(1, 3).to_hz(1000).unwrap()), (333, 1000));
+        assert_eq!(
+            t(1_530_720_309_123_456_789, 1_000_000_000)
+                .to_lisp_time()
+                .unwrap(),
+            LispTime {
+                hi: 23356,
+                lo: 61493,
+                us: 123456,
+                ps: 789000
+            }
+        );
+        assert_eq!(
+            t(-1, 2).to_lisp_time().unwrap(),
+            LispTime {
+                hi: -1,
+                lo: 65535,
+                us: 500000,
+                ps: 0
+            }
+        );
+        assert_eq!(t(1 << 100, 1).to_lisp_time(), None);
+        assert_eq!(t(-3, 2).to_float().to_bits(), (-1.5f64).to_bits());
+        assert!(t(3, 6).reduce_to_fit().fits_fixnums());
+        assert_eq!(parts(t(3, 6).reduce_to_fit()), (3, 6));
+        assert_eq!(parts(t(1 << 70, 1 << 68).reduce_to_fit()), (4, 1));
+    }
+}
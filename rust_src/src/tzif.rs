@@ -0,0 +1,737 @@
+//! Time zone rules read directly from TZif files (RFC 8536) and POSIX
+//! TZ strings, so that converting times for a zone does not have to
+//! set the `TZ` environment variable.
+
+use std::collections::HashMap;
+use std::env;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use crate::time::{civil_from_days, days_from_civil, is_leap_year, SECONDS_PER_DAY};
+
+/// Where zone files live unless the `TZDIR` environment variable says
+/// otherwise.
+const DEFAULT_TZDIR: &str = "/usr/share/zoneinfo";
+
+/// The zone file describing system wall clock time.
+pub const LOCALTIME_FILE: &[u8] = b"/etc/localtime";
+
+/// A local time type: an offset from UTC, whether it is daylight saving
+/// time, and its abbreviation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LocalTimeType {
+    /// Seconds east of UTC.
+    pub utoff: i64,
+    pub isdst: bool,
+    pub abbr: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TzError {
+    /// The data does not start with a TZif header.
+    BadMagic,
+    /// The data ends in the middle of a header or data block.
+    Truncated,
+    /// A count, index or transition time is out of range.
+    Invalid(&'static str),
+    /// The file records leap seconds, which are not supported.
+    LeapSeconds,
+    /// The TZ string footer could not be parsed.
+    BadFooter,
+}
+
+impl fmt::Display for TzError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TzError::BadMagic => write!(f, "not a TZif file"),
+            TzError::Truncated => write!(f, "truncated TZif file"),
+            TzError::Invalid(what) => write!(f, "invalid TZif file: {}", what),
+            TzError::LeapSeconds => write!(f, "leap seconds are not supported"),
+            TzError::BadFooter => write!(f, "invalid TZ string footer"),
+        }
+    }
+}
+
+/// The day of the year on which a POSIX TZ rule switches.
+#[derive(Clone, Debug, PartialEq)]
+enum RuleDay {
+    /// `Jn`: day N, from 1 to 365, never counting February 29.
+    Julian(i64),
+    /// `n`: day N, from 0 to 365, counting February 29.
+    Zero(i64),
+    /// `Mm.w.d`: weekday D (0 is Sunday) of week W (5 is the last) of
+    /// month M.
+    MonthWeekDay(i64, i64, i64),
+}
+
+impl RuleDay {
+    /// Return the days since the epoch of this day in YEAR.
+    fn in_year(&self, year: i64) -> i64 {
+        let jan1 = days_from_civil(year, 1, 1);
+        match *self {
+            RuleDay::Julian(n) => {
+                let leap_day = if is_leap_year(year) && n >= 60 { 1 } else { 0 };
+                jan1 + n - 1 + leap_day
+            }
+            RuleDay::Zero(n) => jan1 + n,
+            RuleDay::MonthWeekDay(month, week, wday) => {
+                let first = days_from_civil(year, month, 1);
+                let next_month = days_from_civil(year + month / 12, month % 12 + 1, 1);
+                let first_wday = (first + 4).rem_euclid(7);
+                let day = first + (wday - first_wday).rem_euclid(7) + (week - 1) * 7;
+                if day >= next_month {
+                    day - 7
+                } else {
+                    day
+                }
+            }
+        }
+    }
+}
+
+/// The daylight saving part of a POSIX TZ string: the DST local time
+/// type and the days and local times of day on which it starts and ends.
+#[derive(Clone, Debug, PartialEq)]
+struct DstRule {
+    dst: LocalTimeType,
+    start: (RuleDay, i64),
+    end: (RuleDay, i64),
+}
+
+/// A POSIX TZ string such as `CET-1CEST,M3.5.0,M10.5.0/3`.
+#[derive(Clone, Debug, PartialEq)]
+struct PosixTz {
+    std: LocalTimeType,
+    dst: Option<DstRule>,
+}
+
+/// A cursor over a POSIX TZ string.
+struct TzParser<'a> {
+    s: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> TzParser<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.s.get(self.pos).cloned()
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Parse a zone abbreviation: three or more letters, or `<...>`
+    /// quoting letters, digits and signs.
+    fn name(&mut self) -> Option<Vec<u8>> {
+        let quoted = self.eat(b'<');
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            let ok = c.is_ascii_alphabetic()
+                || (quoted && (c.is_ascii_digit() || c == b'+' || c == b'-'));
+            if !ok {
+                break;
+            }
+            self.pos += 1;
+        }
+        let name = self.s[start..self.pos].to_vec();
+        if (quoted && !self.eat(b'>')) || name.len() < 3 {
+            return None;
+        }
+        Some(name)
+    }
+
+    fn number(&mut self, max: i64) -> Option<i64> {
+        let start = self.pos;
+        let mut n: i64 = 0;
+        while let Some(c) = self.peek().filter(u8::is_ascii_digit) {
+            n = n * 10 + i64::from(c - b'0');
+            if n > max {
+                return None;
+            }
+            self.pos += 1;
+        }
+        if self.pos == start {
+            None
+        } else {
+            Some(n)
+        }
+    }
+
+    /// Parse `[+-]hh[:mm[:ss]]` with at most MAX_HOURS hours, returning
+    /// seconds.
+    fn time(&mut self, max_hours: i64) -> Option<i64> {
+        let sign = if self.eat(b'-') {
+            -1
+        } else {
+            self.eat(b'+');
+            1
+        };
+        let mut secs = self.number(max_hours)? * 3600;
+        if self.eat(b':') {
+            secs += self.number(59)? * 60;
+            if self.eat(b':') {
+                secs += self.number(59)?;
+            }
+        }
+        Some(sign * secs)
+    }
+
+    fn rule_day(&mut self) -> Option<RuleDay> {
+        if self.eat(b'J') {
+            let n = self.number(365)?;
+            if n == 0 {
+                return None;
+            }
+            Some(RuleDay::Julian(n))
+        } else if self.eat(b'M') {
+            let month = self.number(12)?;
+            if month == 0 || !self.eat(b'.') {
+                return None;
+            }
+            let week = self.number(5)?;
+            if week == 0 || !self.eat(b'.') {
+                return None;
+            }
+            Some(RuleDay::MonthWeekDay(month, week, self.number(6)?))
+        } else {
+            Some(RuleDay::Zero(self.number(365)?))
+        }
+    }
+
+    /// Parse `,date[/time]`; the time defaults to 02:00.
+    fn rule(&mut self) -> Option<(RuleDay, i64)> {
+        if !self.eat(b',') {
+            return None;
+        }
+        let day = self.rule_day()?;
+        // RFC 8536 extends POSIX to times from -167 to 167 hours.
+        let time = if self.eat(b'/') {
+            self.time(167)?
+        } else {
+            7200
+        };
+        Some((day, time))
+    }
+}
+
+impl PosixTz {
+    fn parse(s: &[u8]) -> Option<Self> {
+        let mut p = TzParser { s, pos: 0 };
+        let std_abbr = p.name()?;
+        // POSIX offsets count hours west of Greenwich.
+        let std = LocalTimeType {
+            utoff: -p.time(24)?,
+            isdst: false,
+            abbr: std_abbr,
+        };
+        if p.peek().is_none() {
+            return Some(PosixTz { std, dst: None });
+        }
+
+        let dst_abbr = p.name()?;
+        let dst_utoff = match p.peek() {
+            Some(c) if c.is_ascii_digit() || c == b'+' || c == b'-' => -p.time(24)?,
+            _ => std.utoff + 3600,
+        };
+        let dst = LocalTimeType {
+            utoff: dst_utoff,
+            isdst: true,
+            abbr: dst_abbr,
+        };
+
+        // Like glibc, fall back on the US rules when none are given.
+        let (start, end) = if p.peek().is_none() {
+            (
+                (RuleDay::MonthWeekDay(3, 2, 0), 7200),
+                (RuleDay::MonthWeekDay(11, 1, 0), 7200),
+            )
+        } else {
+            (p.rule()?, p.rule()?)
+        };
+        if p.peek().is_some() {
+            return None;
+        }
+
+        Some(PosixTz {
+            std,
+            dst: Some(DstRule { dst, start, end }),
+        })
+    }
+
+    fn lookup(&self, t: i64) -> &LocalTimeType {
+        let rule = match self.dst {
+            Some(ref rule) => rule,
+            None => return &self.std,
+        };
+
+        let local_days = t.saturating_add(self.std.utoff).div_euclid(SECONDS_PER_DAY);
+        let (year, _, _) = civil_from_days(local_days);
+        let switch = |(ref day, time): &(RuleDay, i64), utoff: i64| {
+            day.in_year(year)
+                .saturating_mul(SECONDS_PER_DAY)
+                .saturating_add(time - utoff)
+        };
+        let start = switch(&rule.start, self.std.utoff);
+        let end = switch(&rule.end, rule.dst.utoff);
+
+        // In the southern hemisphere, DST spans the turn of the year.
+        let isdst = if start < end {
+            start <= t && t < end
+        } else {
+            !(end <= t && t < start)
+        };
+        if isdst {
+            &rule.dst
+        } else {
+            &self.std
+        }
+    }
+}
+
+/// The rules of a time zone: the local time types in effect between
+/// transitions, and a POSIX TZ string for times after the last one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ZoneRules {
+    /// Transition times, in increasing order.
+    transitions: Vec<i64>,
+    /// The index into `types` of the type starting at each transition.
+    transition_types: Vec<usize>,
+    /// The local time types; the first applies before any transition.
+    types: Vec<LocalTimeType>,
+    footer: Option<PosixTz>,
+}
+
+/// A cursor over the contents of a TZif file.
+struct TzifReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> TzifReader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], TzError> {
+        let end = self.pos.checked_add(n).ok_or(TzError::Truncated)?;
+        let bytes = self.data.get(self.pos..end).ok_or(TzError::Truncated)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    /// Read a big-endian signed integer SIZE bytes long.
+    fn int(&mut self, size: usize) -> Result<i64, TzError> {
+        let bytes = self.bytes(size)?;
+        let unsigned = bytes.iter().fold(0_u64, |n, &b| (n << 8) | u64::from(b));
+        let shift = 64 - 8 * size as u32;
+        Ok(((unsigned << shift) as i64) >> shift)
+    }
+
+    fn count(&mut self) -> Result<usize, TzError> {
+        Ok(self.int(4)? as u32 as usize)
+    }
+
+    /// Read a header, returning the version byte and the counts
+    /// isutcnt, isstdcnt, leapcnt, timecnt, typecnt and charcnt.
+    fn header(&mut self) -> Result<(u8, [usize; 6]), TzError> {
+        if self.bytes(4).map_err(|_| TzError::BadMagic)? != b"TZif" {
+            return Err(TzError::BadMagic);
+        }
+        let version = self.bytes(1)?[0];
+        self.bytes(15)?;
+        let mut counts = [0; 6];
+        for count in counts.iter_mut() {
+            *count = self.count()?;
+        }
+        Ok((version, counts))
+    }
+
+    /// Read a data block whose transition times are TIME_SIZE bytes long.
+    fn block(&mut self, time_size: usize, counts: [usize; 6]) -> Result<ZoneRules, TzError> {
+        let [isutcnt, isstdcnt, leapcnt, timecnt, typecnt, charcnt] = counts;
+        if typecnt == 0 {
+            return Err(TzError::Invalid("no local time types"));
+        }
+        if leapcnt != 0 {
+            return Err(TzError::LeapSeconds);
+        }
+
+        let mut transitions = Vec::with_capacity(timecnt);
+        for _ in 0..timecnt {
+            let t = self.int(time_size)?;
+            if transitions.last().map_or(false, |&last| last >= t) {
+                return Err(TzError::Invalid("transition times out of order"));
+            }
+            transitions.push(t);
+        }
+
+        let transition_types: Vec<usize> = self
+            .bytes(timecnt)?
+            .iter()
+            .map(|&i| usize::from(i))
+            .collect();
+        if transition_types.iter().any(|&i| i >= typecnt) {
+            return Err(TzError::Invalid("local time type out of range"));
+        }
+
+        let mut raw_types = Vec::with_capacity(typecnt);
+        for _ in 0..typecnt {
+            let utoff = self.int(4)?;
+            let isdst = self.bytes(1)?[0];
+            let desigidx = usize::from(self.bytes(1)?[0]);
+            if isdst > 1 || desigidx >= charcnt {
+                return Err(TzError::Invalid("bad local time type"));
+            }
+            raw_types.push((utoff, isdst == 1, desigidx));
+        }
+
+        let chars = self.bytes(charcnt)?;
+        let mut types = Vec::with_capacity(typecnt);
+        for (utoff, isdst, desigidx) in raw_types {
+            let abbr = &chars[desigidx..];
+            let len = abbr
+                .iter()
+                .position(|&c| c == 0)
+                .ok_or(TzError::Invalid("unterminated abbreviation"))?;
+            types.push(LocalTimeType {
+                utoff,
+                isdst,
+                abbr: abbr[..len].to_vec(),
+            });
+        }
+
+        // The standard/wall and UT/local indicators only matter for
+        // POSIX-style rules derived from the file, which are not used.
+        self.bytes(isstdcnt)?;
+        self.bytes(isutcnt)?;
+
+        Ok(ZoneRules {
+            transitions,
+            transition_types,
+            types,
+            footer: None,
+        })
+    }
+
+    /// Read the newline-enclosed TZ string that ends a version 2+ file.
+    fn footer(&mut self) -> Result<Option<PosixTz>, TzError> {
+        let rest = &self.data[self.pos..];
+        if rest.first() != Some(&b'\n') {
+            return Err(TzError::BadFooter);
+        }
+        let len = rest[1..]
+            .iter()
+            .position(|&c| c == b'\n')
+            .ok_or(TzError::BadFooter)?;
+        let tz = &rest[1..=len];
+        if tz.is_empty() {
+            return Ok(None);
+        }
+        PosixTz::parse(tz).map(Some).ok_or(TzError::BadFooter)
+    }
+}
+
+impl ZoneRules {
+    /// Parse the contents of a TZif file.
+    pub fn parse(data: &[u8]) -> Result<Self, TzError> {
+        let mut reader = TzifReader { data, pos: 0 };
+        let (version, counts) = reader.header()?;
+        if version == 0 {
+            return reader.block(4, counts);
+        }
+
+        // Skip the version 1 block in favour of the 64-bit one.
+        let [isutcnt, isstdcnt, leapcnt, timecnt, typecnt, charcnt] = counts;
+        let v1_size = timecnt * 5 + typecnt * 6 + charcnt + leapcnt * 8 + isstdcnt + isutcnt;
+        reader.bytes(v1_size)?;
+        let (_, counts) = reader.header()?;
+        let mut rules = reader.block(8, counts)?;
+        rules.footer = reader.footer()?;
+        Ok(rules)
+    }
+
+    /// Return the rules given by the POSIX TZ string S, if it is one.
+    pub fn from_posix(s: &[u8]) -> Option<Self> {
+        let tz = PosixTz::parse(s)?;
+        Some(ZoneRules {
+            transitions: Vec::new(),
+            transition_types: Vec::new(),
+            types: vec![tz.std.clone()],
+            footer: Some(tz),
+        })
+    }
+
+    /// Return the local time type in effect at T, in seconds since the
+    /// epoch.
+    pub fn lookup(&self, t: i64) -> &LocalTimeType {
+        match self.transitions.binary_search(&t) {
+            Err(0) if !self.transitions.is_empty() => &self.types[0],
+            Err(i) if i == self.transitions.len() && self.footer.is_some() => {
+                self.footer.as_ref().unwrap().lookup(t)
+            }
+            Ok(i) => &self.types[self.transition_types[i]],
+            Err(0) => &self.types[0],
+            Err(i) => &self.types[self.transition_types[i - 1]],
+        }
+    }
+
+    /// Return the time since the epoch at which the wall clock reads
+    /// LOCAL, in seconds since the epoch as if it were UTC.  When LOCAL
+    /// occurs twice, prefer the earlier time; when it is skipped by a
+    /// transition, interpret it with the offset in effect before.
+    pub fn local_to_utc(&self, local: i64) -> Option<i64> {
+        let before = self.lookup(local.checked_sub(SECONDS_PER_DAY)?).utoff;
+        let after = self.lookup(local.checked_add(SECONDS_PER_DAY)?).utoff;
+
+        let valid = |utoff: i64| {
+            local
+                .checked_sub(utoff)
+                .filter(|&t| self.lookup(t).utoff == utoff)
+        };
+        match (valid(before), valid(after)) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (Some(t), None) | (None, Some(t)) => Some(t),
+            (None, None) => local.checked_sub(before),
+        }
+    }
+}
+
+/// Zone rules, with the modification time of the file they came from.
+type CachedZone = (Arc<ZoneRules>, Option<SystemTime>);
+
+lazy_static! {
+    /// Zone rules that have been read, by file name or TZ string.
+    static ref ZONE_CACHE: Mutex<HashMap<Vec<u8>, CachedZone>> = Mutex::new(HashMap::new());
+}
+
+/// Return the file that the zone name NAME refers to: NAME itself if it
+/// is absolute, otherwise NAME under `TZDIR`.
+fn zone_file_name(name: &[u8]) -> PathBuf {
+    let name = OsStr::from_bytes(name);
+    if name.as_bytes().starts_with(b"/") {
+        return PathBuf::from(name);
+    }
+    let mut path = env::var_os("TZDIR")
+        .filter(|dir| !dir.is_empty())
+        .map_or_else(|| PathBuf::from(DEFAULT_TZDIR), PathBuf::from);
+    path.push(name);
+    path
+}
+
+/// Return the rules for SPEC, a TZ setting: a zone name such as
+/// `Europe/Berlin`, the name of a TZif file, or a POSIX TZ string, with
+/// an optional leading colon.  Return None if SPEC is none of these, or
+/// names a zone file that cannot be used.
+pub fn load_zone(spec: &[u8]) -> Option<Arc<ZoneRules>> {
+    let spec = if spec.first() == Some(&b':') {
+        &spec[1..]
+    } else {
+        spec
+    };
+    if spec.is_empty() {
+        return None;
+    }
+
+    let path = zone_file_name(spec);
+    let modified = fs::metadata(&path)
+        .ok()
+        .filter(fs::Metadata::is_file)
+        .and_then(|metadata| metadata.modified().ok());
+    let mut cache = ZONE_CACHE.lock().unwrap();
+
+    if let Some(modified) = modified {
+        let key = path.as_os_str().as_bytes().to_vec();
+        if let Some((rules, Some(cached))) = cache.get(&key) {
+            if *cached == modified {
+                return Some(Arc::clone(rules));
+            }
+        }
+        if let Some(rules) = fs::read(&path)
+            .ok()
+            .and_then(|data| ZoneRules::parse(&data).ok())
+        {
+            let rules = Arc::new(rules);
+            cache.insert(key, (Arc::clone(&rules), Some(modified)));
+            return Some(rules);
+        }
+    }
+
+    if let Some((rules, None)) = cache.get(spec) {
+        return Some(Arc::clone(rules));
+    }
+    let rules = Arc::new(ZoneRules::from_posix(spec)?);
+    cache.insert(spec.to_vec(), (Arc::clone(&rules), None));
+    Some(rules)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BERLIN: &[u8] = include_bytes!("../../test/rust_src/src/time-resources/Test/Berlin");
+    const KOLKATA: &[u8] = include_bytes!("../../test/rust_src/src/time-resources/Test/Kolkata");
+    const SYDNEY: &[u8] = include_bytes!("../../test/rust_src/src/time-resources/Test/Sydney");
+
+    fn abbr_at(rules: &ZoneRules, t: i64) -> (i64, bool, String) {
+        let ty = rules.lookup(t);
+        (
+            ty.utoff,
+            ty.isdst,
+            String::from_utf8(ty.abbr.clone()).unwrap(),
+        )
+    }
+
+    fn local(abbr: &str, utoff: i64, isdst: bool) -> (i64, bool, String) {
+        (utoff, isdst, abbr.to_string())
+    }
+
+    #[test]
+    fn test_parse_fat_file() {
+        let rules = ZoneRules::parse(BERLIN).unwrap();
+        // 1893-04-01 switched from local mean time.
+        assert_eq!(abbr_at(&rules, -2_422_054_409), local("LMT", 3208, false));
+        assert_eq!(abbr_at(&rules, -2_422_054_408), local("CET", 3600, false));
+        // DST in 2018 ran from 03-25 01:00 UTC to 10-28 01:00 UTC.
+        assert_eq!(abbr_at(&rules, 1_521_939_599), local("CET", 3600, false));
+        assert_eq!(abbr_at(&rules, 1_521_939_600), local("CEST", 7200, true));
+        assert_eq!(abbr_at(&rules, 1_540_688_399), local("CEST", 7200, true));
+        assert_eq!(abbr_at(&rules, 1_540_688_400), local("CET", 3600, false));
+        // After 2037, the footer takes over: 2100-07-01 and 2100-01-01.
+        assert_eq!(abbr_at(&rules, 4_118_083_200), local("CEST", 7200, true));
+        assert_eq!(abbr_at(&rules, 4_102_444_800), local("CET", 3600, false));
+    }
+
+    #[test]
+    fn test_parse_fixed_file() {
+        let rules = ZoneRules::parse(KOLKATA).unwrap();
+        assert_eq!(abbr_at(&rules, -3_645_237_209), local("LMT", 21208, false));
+        assert_eq!(abbr_at(&rules, 0), local("IST", 19800, false));
+        assert_eq!(
+            abbr_at(&rules, i64::max_value()),
+            local("IST", 19800, false)
+        );
+    }
+
+    #[test]
+    fn test_parse_slim_file() {
+        let rules = ZoneRules::parse(SYDNEY).unwrap();
+        // Only the switch from local mean time and the first transition
+        // of the rule, at 2008-04-06 03:00 AEDT, are listed.
+        assert_eq!(rules.transitions, [-2_364_113_092, 1_207_411_200]);
+        // DST in the southern summer: 2018-01-01, 2018-07-01, 2018-12-31.
+        assert_eq!(abbr_at(&rules, 1_514_764_800), local("AEDT", 39600, true));
+        assert_eq!(abbr_at(&rules, 1_530_403_200), local("AEST", 36000, false));
+        assert_eq!(abbr_at(&rules, 1_546_214_400), local("AEDT", 39600, true));
+        // It ended at 2018-04-01 03:00 AEDT and started at 2018-10-07
+        // 02:00 AEST.
+        assert_eq!(abbr_at(&rules, 1_522_511_999), local("AEDT", 39600, true));
+        assert_eq!(abbr_at(&rules, 1_522_512_000), local("AEST", 36000, false));
+        assert_eq!(abbr_at(&rules, 1_538_841_599), local("AEST", 36000, false));
+        assert_eq!(abbr_at(&rules, 1_538_841_600), local("AEDT", 39600, true));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(ZoneRules::parse(b"TZ"), Err(TzError::BadMagic));
+        assert_eq!(ZoneRules::parse(b"TZif2"), Err(TzError::Truncated));
+        assert_eq!(
+            ZoneRules::parse(&BERLIN[..BERLIN.len() / 2]),
+            Err(TzError::Truncated)
+        );
+        let mut no_footer = BERLIN.to_vec();
+        no_footer.truncate(no_footer.len() - 1);
+        assert_eq!(ZoneRules::parse(&no_footer), Err(TzError::BadFooter));
+
+        // Find the first transition's type index in the 64-bit block.
+        let mut reader = TzifReader {
+            data: KOLKATA,
+            pos: 0,
+        };
+        let (_, [isutcnt, isstdcnt, leapcnt, timecnt, typecnt, charcnt]) = reader.header().unwrap();
+        reader
+            .bytes(timecnt * 5 + typecnt * 6 + charcnt + leapcnt * 8 + isstdcnt + isutcnt)
+            .unwrap();
+        let (_, counts) = reader.header().unwrap();
+        let index = reader.pos + counts[3] * 8;
+        let mut bad_index = KOLKATA.to_vec();
+        assert_eq!(bad_index[index], 1);
+        bad_index[index] = 7;
+        assert_eq!(
+            ZoneRules::parse(&bad_index),
+            Err(TzError::Invalid("local time type out of range"))
+        );
+    }
+
+    #[test]
+    fn test_posix_strings() {
+        let rules = ZoneRules::from_posix(b"EST5EDT").unwrap();
+        // The US rules apply by default: 2018-03-11 07:00 UTC.
+        assert_eq!(abbr_at(&rules, 1_520_751_599), local("EST", -18000, false));
+        assert_eq!(abbr_at(&rules, 1_520_751_600), local("EDT", -14400, true));
+
+        let rules = ZoneRules::from_posix(b"<+0330>-3:30").unwrap();
+        assert_eq!(abbr_at(&rules, 0), local("+0330", 12600, false));
+
+        let rules = ZoneRules::from_posix(b"NZST-12NZDT,M9.5.0,M4.1.0/3").unwrap();
+        assert_eq!(abbr_at(&rules, 1_514_764_800), local("NZDT", 46800, true));
+        assert_eq!(abbr_at(&rules, 1_530_403_200), local("NZST", 43200, false));
+
+        // Julian days: J60 is always March 1.
+        let rules = ZoneRules::from_posix(b"XST0XDT,J60/0,J61/0").unwrap();
+        assert_eq!(abbr_at(&rules, 951_868_800), local("XDT", 3600, true));
+        assert_eq!(abbr_at(&rules, 951_782_400), local("XST", 0, false));
+
+        // Daylight saving time all year.
+        let rules = ZoneRules::from_posix(b"EST5EDT,0/0,J365/25").unwrap();
+        assert_eq!(abbr_at(&rules, 1_514_764_800), local("EDT", -14400, true));
+        assert_eq!(abbr_at(&rules, 1_530_403_200), local("EDT", -14400, true));
+
+        for bad in &[
+            &b""[..],
+            b"UT",
+            b"UTC",
+            b"UTC+25",
+            b"<UTC0",
+            b"EST5EDT,M3.2.0",
+            b"EST5EDT,M13.2.0,M11.1.0",
+            b"EST5EDT,M3.2.0,M11.1.0x",
+        ] {
+            assert_eq!(ZoneRules::from_posix(bad), None);
+        }
+    }
+
+    #[test]
+    fn test_local_to_utc() {
+        let rules = ZoneRules::parse(BERLIN).unwrap();
+        // 2018-07-04 18:05:09 CEST.
+        assert_eq!(rules.local_to_utc(1_530_727_509), Some(1_530_720_309));
+        // 2018-03-25 02:30 does not exist; it is read as CET.
+        assert_eq!(rules.local_to_utc(1_521_945_000), Some(1_521_941_400));
+        // 2018-10-28 02:30 happens twice; the CEST one comes first.
+        assert_eq!(rules.local_to_utc(1_540_693_800), Some(1_540_686_600));
+    }
+
+    #[test]
+    fn test_load_zone() {
+        let dir = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../test/rust_src/src/time-resources/"
+        );
+        let path = format!("{}Test/Berlin", dir);
+        let rules = load_zone(path.as_bytes()).unwrap();
+        assert_eq!(*rules, ZoneRules::parse(BERLIN).unwrap());
+        let again = load_zone(format!(":{}", path).as_bytes()).unwrap();
+        assert!(Arc::ptr_eq(&rules, &again));
+
+        let posix = load_zone(b"IST-5:30").unwrap();
+        assert_eq!(posix.lookup(0).abbr, b"IST");
+        assert_eq!(load_zone(b"Test/No_Such_Zone"), None);
+        assert_eq!(load_zone(b""), None);
+    }
+}
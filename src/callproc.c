@@ -90,18 +90,16 @@ enum
   };
 
 
-/* Return the current buffer's working directory, or the home
-   directory if it's unreachable, as a string suitable for a system call.
-   Signal an error if the result would not be an accessible directory.  */
+/* Return DIRECTORY, or the home directory if it's unreachable, as a
+   string suitable for a system call.  Signal an error if the result
+   would not be an accessible directory.  */
 
 Lisp_Object
-encode_current_directory (void)
+encode_directory (Lisp_Object directory)
 {
   Lisp_Object dir;
 
-  dir = BVAR (current_buffer, directory);
-
-  dir = Funhandled_file_name_directory (dir);
+  dir = Funhandled_file_name_directory (directory);
 
   /* If the file name handler says that dir is unreachable, use
      a sensible default. */
@@ -111,19 +109,27 @@ encode_current_directory (void)
   dir = expand_and_dir_to_file (dir);
 
   if (NILP (Ffile_accessible_directory_p (dir)))
-    report_file_error ("Setting current directory",
-		       BVAR (current_buffer, directory));
+    report_file_error ("Setting current directory", directory);
 
   /* Remove "/:" from DIR and encode it.  */
   dir = ENCODE_FILE (remove_slash_colon (dir));
 
   if (! file_accessible_directory_p (dir))
-    report_file_error ("Setting current directory",
-		       BVAR (current_buffer, directory));
+    report_file_error ("Setting current directory", directory);
 
   return dir;
 }
 
+/* Return the current buffer's working directory, or the home
+   directory if it's unreachable, as a string suitable for a system call.
+   Signal an error if the result would not be an accessible directory.  */
+
+Lisp_Object
+encode_current_directory (void)
+{
+  return encode_directory (BVAR (current_buffer, directory));
+}
+
 /* If P is reapable, record it as a deleted process and kill it.
    Do this in a critical section.  Unless PID is wedged it will be
    reaped on receipt of the first SIGCHLD after the critical section.  */
@@ -201,7 +207,10 @@ static mode_t const default_output_mode = S_IREAD | S_IWRITE;
 static mode_t const default_output_mode = 0666;
 #endif
 
-/* Like Fcall_process (NARGS, ARGS), except use FILEFD as the input file.
+/* Like Fcall_process (NARGS, ARGS), except use FILEFD as the input file,
+   run the process in DIRECTORY and give it the environment ENVIRONMENT,
+   a list in the format of `process-environment'.  Relative output
+   file names are also expanded against DIRECTORY.
 
    If TEMPFILE_INDEX is nonnegative, it is the specpdl index of an
    unwinder that is intended to remove the input temporary file; in
@@ -211,7 +220,8 @@ static mode_t const default_output_mode = 0666;
 
 Lisp_Object
 call_process (ptrdiff_t nargs, Lisp_Object *args, int filefd,
-	      ptrdiff_t tempfile_index)
+	      ptrdiff_t tempfile_index, Lisp_Object directory,
+	      Lisp_Object environment)
 {
   Lisp_Object buffer, current_dir, path;
   bool display_p;
@@ -307,7 +317,7 @@ call_process (ptrdiff_t nargs, Lisp_Object *args, int filefd,
 	      if (NILP (stderr_file) || EQ (Qt, stderr_file))
 		error_file = stderr_file;
 	      else
-		error_file = Fexpand_file_name (stderr_file, Qnil);
+		error_file = Fexpand_file_name (stderr_file, directory);
 	    }
 
 	  buffer = XCAR (buffer);
@@ -316,8 +326,7 @@ call_process (ptrdiff_t nargs, Lisp_Object *args, int filefd,
       /* If the buffer is (still) a list, it might be a (:file "file") spec. */
       if (CONSP (buffer) && EQ (XCAR (buffer), QCfile))
 	{
-	  output_file = Fexpand_file_name (XCAR (XCDR (buffer)),
-					   BVAR (current_buffer, directory));
+	  output_file = Fexpand_file_name (XCAR (XCDR (buffer)), directory);
 	  CHECK_STRING (output_file);
 	  buffer = Qnil;
 	}
@@ -334,11 +343,10 @@ call_process (ptrdiff_t nargs, Lisp_Object *args, int filefd,
 	}
     }
 
-  /* Make sure that the child will be able to chdir to the current
-     buffer's current directory, or its unhandled equivalent.  We
-     can't just have the child check for an error when it does the
-     chdir, since it's in a vfork.  */
-  current_dir = encode_current_directory ();
+  /* Make sure that the child will be able to chdir to DIRECTORY, or
+     its unhandled equivalent.  We can't just have the child check for
+     an error when it does the chdir, since it's in a vfork.  */
+  current_dir = encode_directory (directory);
 
   if (STRINGP (error_file))
     error_file = ENCODE_FILE (error_file);
@@ -448,7 +456,8 @@ call_process (ptrdiff_t nargs, Lisp_Object *args, int filefd,
   block_child_signal (&oldset);
 
 #ifdef WINDOWSNT
-  pid = child_setup (filefd, fd_output, fd_error, new_argv, 0, current_dir);
+  pid = child_setup (filefd, fd_output, fd_error, new_argv, 0, current_dir,
+		     environment);
 #else  /* not WINDOWSNT */
 
   /* vfork, and prevent local vars from being clobbered by the vfork.  */
@@ -519,7 +528,8 @@ call_process (ptrdiff_t nargs, Lisp_Object *args, int filefd,
       signal (SIGPROF, SIG_DFL);
 #endif
 
-      child_setup (filefd, fd_output, fd_error, new_argv, 0, current_dir);
+      child_setup (filefd, fd_output, fd_error, new_argv, 0, current_dir,
+		   environment);
     }
 
 #endif /* not WINDOWSNT */
@@ -941,6 +951,9 @@ exec_failed (char const *name, int err)
 
 #endif
 
+static bool getenv_internal_1 (const char *, ptrdiff_t, char **, ptrdiff_t *,
+			       Lisp_Object);
+
 /* This is the last thing run in a newly forked inferior
    either synchronous or asynchronous.
    Copy descriptors IN, OUT and ERR as descriptors 0, 1 and 2.
@@ -954,13 +967,16 @@ exec_failed (char const *name, int err)
    a decent error from within the child, this should be verified as an
    executable directory by the parent.
 
+   ENVIRONMENT is a list in the format of `process-environment' giving
+   the variables the subprocess should see.
+
    On GNUish hosts, either exec or return an error number.
    On MS-Windows, either return a pid or signal an error.
    On MS-DOS, either return an exit status or signal an error.  */
 
 CHILD_SETUP_TYPE
 child_setup (int in, int out, int err, char **new_argv, bool set_pgrp,
-	     Lisp_Object current_dir)
+	     Lisp_Object current_dir, Lisp_Object environment)
 {
   char **env;
   char *pwd_var;
@@ -1021,7 +1037,7 @@ child_setup (int in, int out, int err, char **new_argv, bool set_pgrp,
 
     new_length = 0;
 
-    for (tem = Vprocess_environment;
+    for (tem = environment;
 	 CONSP (tem) && STRINGP (XCAR (tem));
 	 tem = XCDR (tem))
       {
@@ -1054,8 +1070,13 @@ child_setup (int in, int out, int err, char **new_argv, bool set_pgrp,
     env = new_env = alloca ((new_length + 2) * sizeof *env);
     /* If we have a PWD envvar, pass one down,
        but with corrected value.  */
-    if (egetenv ("PWD"))
-      *new_env++ = pwd_var;
+    {
+      char *pwd;
+      ptrdiff_t pwdlen;
+      if (getenv_internal_1 ("PWD", 3, &pwd, &pwdlen, environment)
+	  ? pwd != NULL : egetenv ("PWD") != NULL)
+	*new_env++ = pwd_var;
+    }
 
     if (STRINGP (display))
       {
@@ -1067,7 +1088,7 @@ child_setup (int in, int out, int err, char **new_argv, bool set_pgrp,
       }
 
     /* Overrides.  */
-    for (tem = Vprocess_environment;
+    for (tem = environment;
 	 CONSP (tem) && STRINGP (XCAR (tem));
 	 tem = XCDR (tem))
       new_env = add_env (env, new_env, SSDATA (XCAR (tem)));
@@ -1519,9 +1519,9 @@ Using an Emacs configured with --with-x-toolkit=lucid does not have this problem
 
       syms_of_gnutls ();
 
-#ifdef HAVE_INOTIFY
+#if defined HAVE_INOTIFY || defined HAVE_NOTIFY_POLLING
       syms_of_inotify ();
-#endif /* HAVE_INOTIFY */
+#endif /* HAVE_INOTIFY || HAVE_NOTIFY_POLLING */
 
 #ifdef HAVE_KQUEUE
       syms_of_kqueue ();
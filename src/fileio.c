@@ -89,7 +89,6 @@ along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.  */
 #include <acl.h>
 #include <allocator.h>
 #include <careadlinkat.h>
-#include <fsusage.h>
 #include <stat-time.h>
 #include <tempname.h>
 
@@ -102,25 +101,25 @@ along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.  */
 #include "commands.h"
 
 /* True during writing of auto-save files.  */
-static bool auto_saving;
+bool auto_saving;
 
 /* Emacs's real umask.  */
-static mode_t realmask;
+mode_t realmask;
 
 /* Nonzero umask during creation of auto-save directories.  */
-static mode_t auto_saving_dir_umask;
+mode_t auto_saving_dir_umask;
 
 /* Set by auto_save_1 to mode of original file so Fwrite_region will create
    a new file with the same mode as the original.  */
-static mode_t auto_save_mode_bits;
+mode_t auto_save_mode_bits;
 
 /* Set by auto_save_1 if an error occurred during the last auto-save.  */
 static bool auto_save_error_occurred;
 
 /* If VALID_TIMESTAMP_FILE_SYSTEM, then TIMESTAMP_FILE_SYSTEM is the device
    number of a file system where time stamps were observed to work.  */
-static bool valid_timestamp_file_system;
-static dev_t timestamp_file_system;
+bool valid_timestamp_file_system;
+dev_t timestamp_file_system;
 
 /* Each time an annotation function changes the buffer, the new buffer
    is added here.  */
@@ -570,7 +569,7 @@ directory_file_name (char *dst, char *src, ptrdiff_t srclen, bool multibyte)
    it requires NEWNAME to be a directory name (typically, by ending in
    "/").  */
 
-static Lisp_Object
+Lisp_Object
 expand_cp_target (Lisp_Object file, Lisp_Object newname)
 {
   return (!NILP (Fdirectory_name_p (newname))
@@ -1728,7 +1727,7 @@ expand_and_dir_to_file (Lisp_Object filename)
    and bypass the error if the user says to go ahead.
    If QUICK, ask for y or n, not yes or no.  */
 
-static void
+void
 barf_or_query_if_file_exists (Lisp_Object absname, bool known_to_exist,
 			      const char *querystring, bool interactive,
 			      bool quick)
@@ -1763,18 +1762,48 @@ barf_or_query_if_file_exists (Lisp_Object absname, bool known_to_exist,
     }
 }
 
-#ifndef WINDOWSNT
-/* Copy data to DEST from SOURCE if possible.  Return true if OK.  */
-static bool
-clone_file (int dest, int source)
+/* Return the SELinux context of the file open as FD, to be given to
+   a copy of it by set_fd_selinux_context, or NULL if SELinux is not
+   in use.  Signal an error mentioning FILE on failure.  */
+void *
+fd_selinux_context (int fd, Lisp_Object file)
 {
-#ifdef FICLONE
-  return ioctl (dest, FICLONE, source) == 0;
+#if HAVE_LIBSELINUX
+  if (is_selinux_enabled ())
+    {
+      security_context_t con;
+      int conlength = fgetfilecon (fd, &con);
+      if (conlength == -1)
+	report_file_error ("Doing fgetfilecon", file);
+      if (conlength > 0)
+	return con;
+    }
 #endif
-  return false;
+  return NULL;
 }
+
+/* Set the SELinux context of the file open as FD to CONTEXT, which
+   fd_selinux_context returned, and free CONTEXT.  Signal an error
+   mentioning FILE on failure.  */
+void
+set_fd_selinux_context (int fd, void *context, Lisp_Object file)
+{
+#if HAVE_LIBSELINUX
+  if (context)
+    {
+      /* Set the modified context back to the file.  */
+      bool fail = fsetfilecon (fd, context) != 0;
+      int err = errno;
+      freecon (context);
+      /* See https://debbugs.gnu.org/11245 for ENOTSUP.  */
+      if (fail && err != ENOTSUP)
+	report_file_errno ("Doing fsetfilecon", file, err);
+    }
 #endif
+}
 
+/* On other systems these primitives are defined in fileio.rs.  */
+#ifdef WINDOWSNT
 DEFUN ("copy-file", Fcopy_file, Scopy_file, 2, 6,
        "fCopy file: \nGCopy %s to file: \np\nP",
        doc: /* Copy FILE to NEWNAME.  Both args must be strings.
@@ -1811,20 +1840,8 @@ permissions.  */)
    Lisp_Object preserve_permissions)
 {
   Lisp_Object handler;
-  ptrdiff_t count = SPECPDL_INDEX ();
   Lisp_Object encoded_file, encoded_newname;
-#if HAVE_LIBSELINUX
-  security_context_t con;
-  int conlength = 0;
-#endif
-#ifdef WINDOWSNT
   int result;
-#else
-  bool already_exists = false;
-  mode_t new_mask;
-  int ifd, ofd;
-  struct stat st;
-#endif
 
   file = Fexpand_file_name (file, Qnil);
   newname = expand_cp_target (file, newname);
@@ -1843,7 +1860,6 @@ permissions.  */)
   encoded_file = ENCODE_FILE (file);
   encoded_newname = ENCODE_FILE (newname);
 
-#ifdef WINDOWSNT
   if (NILP (ok_if_already_exists)
       || INTEGERP (ok_if_already_exists))
     barf_or_query_if_file_exists (newname, false, "copy to it",
@@ -1864,162 +1880,6 @@ permissions.  */)
     case -4:
       report_file_error ("Copying permissions to", newname);
     }
-#else /* not WINDOWSNT */
-  ifd = emacs_open (SSDATA (encoded_file), O_RDONLY, 0);
-
-  if (ifd < 0)
-    report_file_error ("Opening input file", file);
-
-  record_unwind_protect_int (close_file_unwind, ifd);
-
-  if (fstat (ifd, &st) != 0)
-    report_file_error ("Input file status", file);
-
-  if (!NILP (preserve_permissions))
-    {
-#if HAVE_LIBSELINUX
-      if (is_selinux_enabled ())
-	{
-	  conlength = fgetfilecon (ifd, &con);
-	  if (conlength == -1)
-	    report_file_error ("Doing fgetfilecon", file);
-	}
-#endif
-    }
-
-  /* We can copy only regular files.  */
-  if (!S_ISREG (st.st_mode))
-    report_file_errno ("Non-regular file", file,
-		       S_ISDIR (st.st_mode) ? EISDIR : EINVAL);
-
-  new_mask = st.st_mode & (!NILP (preserve_uid_gid) ? 0700 : 0777);
-
-  ofd = emacs_open (SSDATA (encoded_newname), O_WRONLY | O_CREAT | O_EXCL,
-		    new_mask);
-  if (ofd < 0 && errno == EEXIST)
-    {
-      if (NILP (ok_if_already_exists) || INTEGERP (ok_if_already_exists))
-	barf_or_query_if_file_exists (newname, true, "copy to it",
-				      INTEGERP (ok_if_already_exists), false);
-      already_exists = true;
-      ofd = emacs_open (SSDATA (encoded_newname), O_WRONLY, 0);
-    }
-  if (ofd < 0)
-    report_file_error ("Opening output file", newname);
-
-  record_unwind_protect_int (close_file_unwind, ofd);
-
-  off_t oldsize = 0, newsize;
-
-  if (already_exists)
-    {
-      struct stat out_st;
-      if (fstat (ofd, &out_st) != 0)
-	report_file_error ("Output file status", newname);
-      if (st.st_dev == out_st.st_dev && st.st_ino == out_st.st_ino)
-	report_file_errno ("Input and output files are the same",
-			   list2 (file, newname), 0);
-      if (S_ISREG (out_st.st_mode))
-	oldsize = out_st.st_size;
-    }
-
-  maybe_quit ();
-
-  if (clone_file (ofd, ifd))
-    newsize = st.st_size;
-  else
-    {
-      char buf[MAX_ALLOCA];
-      ptrdiff_t n;
-      for (newsize = 0; 0 < (n = emacs_read_quit (ifd, buf, sizeof buf));
-	   newsize += n)
-	if (emacs_write_quit (ofd, buf, n) != n)
-	  report_file_error ("Write error", newname);
-      if (n < 0)
-	report_file_error ("Read error", file);
-    }
-
-  /* Truncate any existing output file after writing the data.  This
-     is more likely to work than truncation before writing, if the
-     file system is out of space or the user is over disk quota.  */
-  if (newsize < oldsize && ftruncate (ofd, newsize) != 0)
-    report_file_error ("Truncating output file", newname);
-
-  /* Preserve the original file permissions, and if requested, also its
-     owner and group.  */
-  {
-    mode_t preserved_permissions = st.st_mode & 07777;
-    mode_t default_permissions = st.st_mode & 0777 & ~realmask;
-    if (!NILP (preserve_uid_gid))
-      {
-	/* Attempt to change owner and group.  If that doesn't work
-	   attempt to change just the group, as that is sometimes allowed.
-	   Adjust the mode mask to eliminate setuid or setgid bits
-	   or group permissions bits that are inappropriate if the
-	   owner or group are wrong.  */
-	if (fchown (ofd, st.st_uid, st.st_gid) != 0)
-	  {
-	    if (fchown (ofd, -1, st.st_gid) == 0)
-	      preserved_permissions &= ~04000;
-	    else
-	      {
-		preserved_permissions &= ~06000;
-
-		/* Copy the other bits to the group bits, since the
-		   group is wrong.  */
-		preserved_permissions &= ~070;
-		preserved_permissions |= (preserved_permissions & 7) << 3;
-		default_permissions &= ~070;
-		default_permissions |= (default_permissions & 7) << 3;
-	      }
-	  }
-      }
-
-    switch (!NILP (preserve_permissions)
-	    ? qcopy_acl (SSDATA (encoded_file), ifd,
-			 SSDATA (encoded_newname), ofd,
-			 preserved_permissions)
-	    : (already_exists
-	       || (new_mask & ~realmask) == default_permissions)
-	    ? 0
-	    : fchmod (ofd, default_permissions))
-      {
-      case -2: report_file_error ("Copying permissions from", file);
-      case -1: report_file_error ("Copying permissions to", newname);
-      }
-  }
-
-#if HAVE_LIBSELINUX
-  if (conlength > 0)
-    {
-      /* Set the modified context back to the file.  */
-      bool fail = fsetfilecon (ofd, con) != 0;
-      /* See https://debbugs.gnu.org/11245 for ENOTSUP.  */
-      if (fail && errno != ENOTSUP)
-	report_file_error ("Doing fsetfilecon", newname);
-
-      freecon (con);
-    }
-#endif
-
-  if (!NILP (keep_time))
-    {
-      struct timespec atime = get_stat_atime (&st);
-      struct timespec mtime = get_stat_mtime (&st);
-      if (set_file_times (ofd, SSDATA (encoded_newname), atime, mtime) != 0)
-	xsignal2 (Qfile_date_error,
-		  build_string ("Cannot set file date"), newname);
-    }
-
-  if (emacs_close (ofd) < 0)
-    report_file_error ("Write error", newname);
-
-  emacs_close (ifd);
-
-#endif /* not WINDOWSNT */
-
-  /* Discard the unwind protects.  */
-  specpdl_ptr = specpdl + count;
 
   return Qnil;
 }
@@ -2108,6 +1968,7 @@ With a prefix argument, TRASH is nil.  */)
     report_file_error ("Removing old name", filename);
   return Qnil;
 }
+#endif /* WINDOWSNT */
 
 static Lisp_Object
 internal_delete_file_1 (Lisp_Object ignore)
@@ -2167,6 +2028,7 @@ file_name_case_insensitive_p (const char *filename)
 #endif
 }
 
+#ifdef WINDOWSNT
 DEFUN ("rename-file", Frename_file, Srename_file, 2, 3,
        "fRename file: \nGRename %s to file: \np",
        doc: /* Rename FILE as NEWNAME.  Both args must be strings.
@@ -2191,14 +2053,12 @@ This is what happens in interactive use with M-x.  */)
      not worry whether NEWNAME exists or whether it is a directory, as
      it is already another name for FILE.  */
   bool case_only_rename = false;
-#if defined CYGWIN || defined DOS_NT
   if (!NILP (Ffile_name_case_insensitive_p (file)))
     {
       newname = Fexpand_file_name (newname, Qnil);
       case_only_rename = !NILP (Fstring_equal (Fdowncase (file),
 					       Fdowncase (newname)));
     }
-#endif
 
   if (!case_only_rename)
     newname = expand_cp_target (Fdirectory_file_name (file), newname);
@@ -2286,6 +2146,7 @@ This is what happens in interactive use with M-x.  */)
     Fdelete_file (file, Qnil);
   return unbind_to (count, Qnil);
 }
+#endif /* WINDOWSNT */
 
 DEFUN ("add-name-to-file", Fadd_name_to_file, Sadd_name_to_file, 2, 3,
        "fAdd name to file: \nGName to add to %s: \np",
@@ -3183,7 +3044,7 @@ read_non_regular_quit (Lisp_Object ignore)
 
 /* Return the file offset that VAL represents, checking for type
    errors and overflow.  */
-static off_t
+off_t
 file_offset (Lisp_Object val)
 {
   if (RANGED_INTEGERP (0, val, TYPE_MAXIMUM (off_t)))
@@ -4535,6 +4396,127 @@ choose_write_coding_system (Lisp_Object start, Lisp_Object end, Lisp_Object file
   return val;
 }
 
+/* The parts of writing a region that deal with annotations and
+   encoding.  They are shared by write_region and the Rust
+   `write-region'.  */
+
+/* Widen if *START is nil, run `write-region-annotate-functions' and
+   choose the coding system to write the text with into CODING.  Update
+   *START and *END to the text to write, and return the annotations.
+   This pushes unwind protects that restore the restriction and the
+   current buffer; unbind them after calling
+   write_region_post_annotations.  */
+
+Lisp_Object
+write_region_annotate (Lisp_Object *start, Lisp_Object *end,
+		       Lisp_Object filename, Lisp_Object append,
+		       Lisp_Object visit, Lisp_Object lockname,
+		       struct coding_system *coding)
+{
+  Lisp_Object annotations = Qnil;
+  struct buffer *given_buffer;
+
+  record_unwind_protect (save_restriction_restore, save_restriction_save ());
+
+  /* Special kludge to simplify auto-saving.  */
+  if (NILP (*start))
+    {
+      /* Do it later, so write-region-annotate-function can work differently
+	 if we save "the buffer" vs "a region".
+	 This is useful in tar-mode.  --Stef
+      XSETFASTINT (start, BEG);
+      XSETFASTINT (end, Z); */
+      Fwiden ();
+    }
+
+  record_unwind_protect (build_annotations_unwind,
+			 Vwrite_region_annotation_buffers);
+  Vwrite_region_annotation_buffers = list1 (Fcurrent_buffer ());
+
+  given_buffer = current_buffer;
+
+  if (!STRINGP (*start))
+    {
+      annotations = build_annotations (*start, *end);
+
+      if (current_buffer != given_buffer)
+	{
+	  XSETFASTINT (*start, BEGV);
+	  XSETFASTINT (*end, ZV);
+	}
+    }
+
+  if (NILP (*start))
+    {
+      XSETFASTINT (*start, BEGV);
+      XSETFASTINT (*end, ZV);
+    }
+
+  /* Decide the coding-system to encode the data with.
+     We used to make this choice before calling build_annotations, but that
+     leads to problems when a write-annotate-function takes care of
+     unsavable chars (as was the case with X-Symbol).  */
+  Vlast_coding_system_used
+    = choose_write_coding_system (*start, *end, filename,
+                                 append, visit, lockname, coding);
+
+  return annotations;
+}
+
+/* Write the text between START and END, or the string START, to DESC
+   with *ANNOTATIONS, encoding it with CODING.  Return true if
+   successful, false with errno set otherwise.  */
+
+bool
+write_region_contents (int desc, Lisp_Object start, Lisp_Object end,
+		       Lisp_Object *annotations, struct coding_system *coding)
+{
+  bool ok;
+
+  if (STRINGP (start))
+    ok = a_write (desc, start, 0, SCHARS (start), annotations, coding);
+  else if (XINT (start) != XINT (end))
+    ok = a_write (desc, Qnil, XINT (start), XINT (end) - XINT (start),
+		  annotations, coding);
+  else
+    {
+      /* If file was empty, still need to write the annotations.  */
+      coding->mode |= CODING_MODE_LAST_BLOCK;
+      ok = a_write (desc, Qnil, XINT (end), 0, annotations, coding);
+    }
+
+  if (ok && CODING_REQUIRE_FLUSHING (coding)
+      && !(coding->mode & CODING_MODE_LAST_BLOCK))
+    {
+      /* We have to flush out a data. */
+      coding->mode |= CODING_MODE_LAST_BLOCK;
+      ok = e_write (desc, Qnil, 1, 1, coding);
+    }
+
+  return ok;
+}
+
+/* Call write-region-post-annotation-function. */
+
+void
+write_region_post_annotations (void)
+{
+  while (CONSP (Vwrite_region_annotation_buffers))
+    {
+      Lisp_Object buf = XCAR (Vwrite_region_annotation_buffers);
+      if (!NILP (Fbuffer_live_p (buf)))
+  	{
+  	  Fset_buffer (buf);
+  	  if (FUNCTIONP (Vwrite_region_post_annotation_function))
+  	    call0 (Vwrite_region_post_annotation_function);
+  	}
+      Vwrite_region_annotation_buffers
+  	= XCDR (Vwrite_region_annotation_buffers);
+    }
+}
+
+/* On other systems `write-region' is defined in fileio.rs.  */
+#ifdef WINDOWSNT
 DEFUN ("write-region", Fwrite_region, Swrite_region, 3, 7,
        "r\nFWrite region to file: \ni\ni\ni\np",
        doc: /* Write current region into specified file.
@@ -4579,6 +4561,7 @@ This calls `write-region-annotate-functions' at the start, and
   return write_region (start, end, filename, append, visit, lockname, mustbenew,
 		       -1);
 }
+#endif /* WINDOWSNT */
 
 /* Like Fwrite_region, except that if DESC is nonnegative, it is a file
    descriptor for FILENAME, so do not open or close FILENAME.  */
@@ -4606,7 +4589,6 @@ write_region (Lisp_Object start, Lisp_Object end, Lisp_Object filename,
   bool visiting = (EQ (visit, Qt) || STRINGP (visit));
   bool quietly = !NILP (visit);
   bool file_locked = 0;
-  struct buffer *given_buffer;
   struct coding_system coding;
 
   if (current_buffer->base_buffer && visiting)
@@ -4655,49 +4637,8 @@ write_region (Lisp_Object start, Lisp_Object end, Lisp_Object filename,
       return val;
     }
 
-  record_unwind_protect (save_restriction_restore, save_restriction_save ());
-
-  /* Special kludge to simplify auto-saving.  */
-  if (NILP (start))
-    {
-      /* Do it later, so write-region-annotate-function can work differently
-	 if we save "the buffer" vs "a region".
-	 This is useful in tar-mode.  --Stef
-      XSETFASTINT (start, BEG);
-      XSETFASTINT (end, Z); */
-      Fwiden ();
-    }
-
-  record_unwind_protect (build_annotations_unwind,
-			 Vwrite_region_annotation_buffers);
-  Vwrite_region_annotation_buffers = list1 (Fcurrent_buffer ());
-
-  given_buffer = current_buffer;
-
-  if (!STRINGP (start))
-    {
-      annotations = build_annotations (start, end);
-
-      if (current_buffer != given_buffer)
-	{
-	  XSETFASTINT (start, BEGV);
-	  XSETFASTINT (end, ZV);
-	}
-    }
-
-  if (NILP (start))
-    {
-      XSETFASTINT (start, BEGV);
-      XSETFASTINT (end, ZV);
-    }
-
-  /* Decide the coding-system to encode the data with.
-     We used to make this choice before calling build_annotations, but that
-     leads to problems when a write-annotate-function takes care of
-     unsavable chars (as was the case with X-Symbol).  */
-  Vlast_coding_system_used
-    = choose_write_coding_system (start, end, filename,
-                                 append, visit, lockname, &coding);
+  annotations = write_region_annotate (&start, &end, filename, append, visit,
+				       lockname, &coding);
 
   if (open_and_close_file && !auto_saving)
     {
@@ -4746,28 +4687,9 @@ write_region (Lisp_Object start, Lisp_Object end, Lisp_Object filename,
 	}
     }
 
-  if (STRINGP (start))
-    ok = a_write (desc, start, 0, SCHARS (start), &annotations, &coding);
-  else if (XINT (start) != XINT (end))
-    ok = a_write (desc, Qnil, XINT (start), XINT (end) - XINT (start),
-		  &annotations, &coding);
-  else
-    {
-      /* If file was empty, still need to write the annotations.  */
-      coding.mode |= CODING_MODE_LAST_BLOCK;
-      ok = a_write (desc, Qnil, XINT (end), 0, &annotations, &coding);
-    }
+  ok = write_region_contents (desc, start, end, &annotations, &coding);
   save_errno = errno;
 
-  if (ok && CODING_REQUIRE_FLUSHING (&coding)
-      && !(coding.mode & CODING_MODE_LAST_BLOCK))
-    {
-      /* We have to flush out a data. */
-      coding.mode |= CODING_MODE_LAST_BLOCK;
-      ok = e_write (desc, Qnil, 1, 1, &coding);
-      save_errno = errno;
-    }
-
   /* fsync is not crucial for temporary files.  Nor for auto-save
      files, since they might lose some work anyway.  */
   if (open_and_close_file && !auto_saving && !write_region_inhibit_fsync)
@@ -4861,19 +4783,7 @@ write_region (Lisp_Object start, Lisp_Object end, Lisp_Object filename,
 	}
     }
 
-  /* Call write-region-post-annotation-function. */
-  while (CONSP (Vwrite_region_annotation_buffers))
-    {
-      Lisp_Object buf = XCAR (Vwrite_region_annotation_buffers);
-      if (!NILP (Fbuffer_live_p (buf)))
-  	{
-  	  Fset_buffer (buf);
-  	  if (FUNCTIONP (Vwrite_region_post_annotation_function))
-  	    call0 (Vwrite_region_post_annotation_function);
-  	}
-      Vwrite_region_annotation_buffers
-  	= XCDR (Vwrite_region_annotation_buffers);
-    }
+  write_region_post_annotations ();
 
   unbind_to (count, Qnil);
 
@@ -5630,51 +5540,6 @@ effect except for flushing STREAM's data.  */)
   return (set_binary_mode (fileno (fp), binmode) == O_BINARY) ? Qt : Qnil;
 }
 
-#ifndef DOS_NT
-
-/* Yield a Lisp float as close as possible to BLOCKSIZE * BLOCKS, with
-   the result negated if NEGATE.  */
-static Lisp_Object
-blocks_to_bytes (uintmax_t blocksize, uintmax_t blocks, bool negate)
-{
-  /* On typical platforms the following code is accurate to 53 bits,
-     which is close enough.  BLOCKSIZE is invariably a power of 2, so
-     converting it to double does not lose information.  */
-  double bs = blocksize;
-  return make_float (negate ? -bs * -blocks : bs * blocks);
-}
-
-DEFUN ("file-system-info", Ffile_system_info, Sfile_system_info, 1, 1, 0,
-       doc: /* Return storage information about the file system FILENAME is on.
-Value is a list of numbers (TOTAL FREE AVAIL), where TOTAL is the total
-storage of the file system, FREE is the free storage, and AVAIL is the
-storage available to a non-superuser.  All 3 numbers are in bytes.
-If the underlying system call fails, value is nil.  */)
-  (Lisp_Object filename)
-{
-  Lisp_Object encoded = ENCODE_FILE (Fexpand_file_name (filename, Qnil));
-
-  /* If the file name has special constructs in it,
-     call the corresponding file handler.  */
-  Lisp_Object handler = Ffind_file_name_handler (encoded, Qfile_system_info);
-  if (!NILP (handler))
-    {
-      Lisp_Object result = call2 (handler, Qfile_system_info, encoded);
-      if (CONSP (result) || NILP (result))
-	return result;
-      error ("Invalid handler in `file-name-handler-alist'");
-    }
-
-  struct fs_usage u;
-  if (get_fs_usage (SSDATA (encoded), NULL, &u) != 0)
-    return Qnil;
-  return list3 (blocks_to_bytes (u.fsu_blocksize, u.fsu_blocks, false),
-		blocks_to_bytes (u.fsu_blocksize, u.fsu_bfree, false),
-		blocks_to_bytes (u.fsu_blocksize, u.fsu_bavail,
-				 u.fsu_bavail_top_bit_set));
-}
-
-#endif /* !DOS_NT */
 
 void
 init_fileio (void)
@@ -5703,6 +5568,8 @@ init_fileio (void)
   write_region_inhibit_fsync = noninteractive;
 }
 
+extern void rust_syms_of_fileio (void);
+
 void
 syms_of_fileio (void)
 {
@@ -5979,11 +5846,13 @@ This includes interactive calls to `delete-file' and
   defsubr (&Smake_temp_file_internal);
   defsubr (&Sexpand_file_name);
   defsubr (&Ssubstitute_in_file_name);
+#ifdef WINDOWSNT
   defsubr (&Scopy_file);
   defsubr (&Smake_directory_internal);
   defsubr (&Sdelete_directory_internal);
   defsubr (&Sdelete_file);
   defsubr (&Srename_file);
+#endif
   defsubr (&Sadd_name_to_file);
   defsubr (&Smake_symbolic_link);
   defsubr (&Sfile_readable_p);
@@ -6003,7 +5872,9 @@ This includes interactive calls to `delete-file' and
   defsubr (&Sdefault_file_modes);
   defsubr (&Sfile_newer_than_file_p);
   defsubr (&Sinsert_file_contents);
+#ifdef WINDOWSNT
   defsubr (&Swrite_region);
+#endif
   defsubr (&Sverify_visited_file_modtime);
   defsubr (&Svisited_file_modtime);
   defsubr (&Sset_visited_file_modtime);
@@ -6014,11 +5885,9 @@ This includes interactive calls to `delete-file' and
 
   defsubr (&Sset_binary_mode);
 
-#ifndef DOS_NT
-  defsubr (&Sfile_system_info);
-#endif
-
 #ifdef HAVE_SYNC
   defsubr (&Sunix_sync);
 #endif
+
+  rust_syms_of_fileio ();
 }
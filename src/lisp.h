@@ -30,6 +30,7 @@ along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.  */
 #include <float.h>
 #include <inttypes.h>
 #include <limits.h>
+#include <sys/types.h>
 
 #include <intprops.h>
 #include <verify.h>
@@ -4124,7 +4125,6 @@ extern void syms_of_module (void);
 extern void mark_threads (void);
 
 /* Defined in editfns.c.  */
-extern Lisp_Object styled_format (ptrdiff_t, Lisp_Object *, bool);
 extern void insert1 (Lisp_Object);
 extern Lisp_Object save_excursion_save (void);
 extern Lisp_Object save_restriction_save (void);
@@ -4181,6 +4181,26 @@ extern bool check_existing (const char *);
 extern bool file_name_absolute_p (const char *);
 extern bool file_name_case_insensitive_p (const char *);
 extern Lisp_Object expand_and_dir_to_file (Lisp_Object);
+extern Lisp_Object expand_cp_target (Lisp_Object, Lisp_Object);
+extern void barf_or_query_if_file_exists (Lisp_Object, bool, const char *,
+					  bool, bool);
+extern mode_t realmask;
+extern mode_t auto_saving_dir_umask;
+extern void *fd_selinux_context (int, Lisp_Object);
+extern void set_fd_selinux_context (int, void *, Lisp_Object);
+extern bool auto_saving;
+extern mode_t auto_save_mode_bits;
+extern off_t file_offset (Lisp_Object);
+extern bool valid_timestamp_file_system;
+extern dev_t timestamp_file_system;
+struct coding_system;
+extern Lisp_Object write_region_annotate (Lisp_Object *, Lisp_Object *,
+					  Lisp_Object, Lisp_Object,
+					  Lisp_Object, Lisp_Object,
+					  struct coding_system *);
+extern bool write_region_contents (int, Lisp_Object, Lisp_Object,
+				   Lisp_Object *, struct coding_system *);
+extern void write_region_post_annotations (void);
 extern Lisp_Object write_region (Lisp_Object, Lisp_Object, Lisp_Object,
 				 Lisp_Object, Lisp_Object, Lisp_Object,
 				 Lisp_Object, int);
@@ -4199,6 +4219,7 @@ extern void syms_of_fileio (void);
 
 /* Defined in search.c.  */
 extern void shrink_regexp_cache (void);
+extern void save_search_regs (void);
 extern void restore_search_regs (void);
 extern void update_search_regs (ptrdiff_t oldstart,
                                 ptrdiff_t oldend, ptrdiff_t newend);
@@ -4238,10 +4259,7 @@ extern ptrdiff_t find_before_next_newline (ptrdiff_t, ptrdiff_t,
 extern void syms_of_search (void);
 extern void clear_regexp_cache (void);
 
-Lisp_Object looking_at_1 (Lisp_Object string, bool posix);
-Lisp_Object match_limit (Lisp_Object num, bool beginningp);
 Lisp_Object search_command (Lisp_Object string, Lisp_Object bound, Lisp_Object noerror, Lisp_Object count, int direction, int RE, bool posix);
-Lisp_Object string_match_1 (Lisp_Object regexp, Lisp_Object string, Lisp_Object start, bool posix);
 
 /* Defined in minibuf.c.  */
 
@@ -4396,12 +4414,14 @@ extern void setup_process_coding_systems (Lisp_Object);
 #else
 # define CHILD_SETUP_TYPE int
 #endif
-extern CHILD_SETUP_TYPE child_setup (int, int, int, char **, bool, Lisp_Object);
+extern CHILD_SETUP_TYPE child_setup (int, int, int, char **, bool, Lisp_Object,
+				     Lisp_Object);
 extern void init_callproc_1 (void);
 extern void init_callproc (void);
 extern void set_initial_environment (void);
 extern void syms_of_callproc (void);
-extern Lisp_Object call_process (ptrdiff_t, Lisp_Object *, int, ptrdiff_t);
+extern Lisp_Object call_process (ptrdiff_t, Lisp_Object *, int, ptrdiff_t,
+				 Lisp_Object, Lisp_Object);
 extern int create_temp_file (ptrdiff_t, Lisp_Object *, Lisp_Object *);
 
 /* Defined in doc.c.  */
@@ -4537,8 +4557,8 @@ extern void init_font (void);
 extern void syms_of_fontset (void);
 #endif
 
-/* Defined in inotify.c */
-#ifdef HAVE_INOTIFY
+/* Defined in inotify.rs.  */
+#if defined HAVE_INOTIFY || defined HAVE_NOTIFY_POLLING
 extern void syms_of_inotify (void);
 #endif
 
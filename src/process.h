@@ -265,6 +265,7 @@ enum
 
 /* Defined in callproc.c.  */
 
+extern Lisp_Object encode_directory (Lisp_Object);
 extern Lisp_Object encode_current_directory (void);
 extern void record_kill_process (struct Lisp_Process *, Lisp_Object);
 
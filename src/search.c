@@ -82,7 +82,6 @@ static struct regexp_cache *searchbuf_head;
 /* static Lisp_Object last_thing_searched; */
 
 static void set_search_regs (ptrdiff_t, ptrdiff_t);
-static void save_search_regs (void);
 static EMACS_INT simple_search (EMACS_INT, unsigned char *, ptrdiff_t,
 				ptrdiff_t, Lisp_Object, ptrdiff_t, ptrdiff_t,
                                 ptrdiff_t, ptrdiff_t);
@@ -270,154 +269,6 @@ compile_pattern (Lisp_Object pattern, struct re_registers *regp,
 }
 
 
-Lisp_Object
-looking_at_1 (Lisp_Object string, bool posix)
-{
-  Lisp_Object val;
-  unsigned char *p1, *p2;
-  ptrdiff_t s1, s2;
-  register ptrdiff_t i;
-  struct re_pattern_buffer *bufp;
-
-  if (running_asynch_code)
-    save_search_regs ();
-
-  /* This is so set_image_of_range_1 in regex.c can find the EQV table.  */
-  set_char_table_extras (BVAR (current_buffer, case_canon_table), 2,
-			 BVAR (current_buffer, case_eqv_table));
-
-  CHECK_STRING (string);
-  bufp = compile_pattern (string,
-			  (NILP (Vinhibit_changing_match_data)
-			   ? &search_regs : NULL),
-			  (!NILP (BVAR (current_buffer, case_fold_search))
-			   ? BVAR (current_buffer, case_canon_table) : Qnil),
-			  posix,
-			  !NILP (BVAR (current_buffer, enable_multibyte_characters)));
-
-  /* Do a pending quit right away, to avoid paradoxical behavior */
-  maybe_quit ();
-
-  /* Get pointers and sizes of the two strings
-     that make up the visible portion of the buffer. */
-
-  p1 = BEGV_ADDR;
-  s1 = GPT_BYTE - BEGV_BYTE;
-  p2 = GAP_END_ADDR;
-  s2 = ZV_BYTE - GPT_BYTE;
-  if (s1 < 0)
-    {
-      p2 = p1;
-      s2 = ZV_BYTE - BEGV_BYTE;
-      s1 = 0;
-    }
-  if (s2 < 0)
-    {
-      s1 = ZV_BYTE - BEGV_BYTE;
-      s2 = 0;
-    }
-
-  re_match_object = Qnil;
-
-  freeze_buffer_relocation ();
-  i = re_match_2 (bufp, (char *) p1, s1, (char *) p2, s2,
-		  PT_BYTE - BEGV_BYTE,
-		  (NILP (Vinhibit_changing_match_data)
-		   ? &search_regs : NULL),
-		  ZV_BYTE - BEGV_BYTE);
-  thaw_buffer_relocation ();
-
-  if (i == -2)
-    matcher_overflow ();
-
-  val = (i >= 0 ? Qt : Qnil);
-  if (NILP (Vinhibit_changing_match_data) && i >= 0)
-  {
-    for (i = 0; i < search_regs.num_regs; i++)
-      if (search_regs.start[i] >= 0)
-	{
-	  search_regs.start[i]
-	    = BYTE_TO_CHAR (search_regs.start[i] + BEGV_BYTE);
-         search_regs.end[i]
-           = BYTE_TO_CHAR (search_regs.end[i] + BEGV_BYTE);
-       }
-    /* Set last_thing_searched only when match data is changed.  */
-    XSETBUFFER (last_thing_searched, current_buffer);
-  }
-
-  return val;
-}
-
-Lisp_Object
-string_match_1 (Lisp_Object regexp, Lisp_Object string, Lisp_Object start,
-		bool posix)
-{
-  ptrdiff_t val;
-  struct re_pattern_buffer *bufp;
-  EMACS_INT pos;
-  ptrdiff_t pos_byte, i;
-
-  if (running_asynch_code)
-    save_search_regs ();
-
-  CHECK_STRING (regexp);
-  CHECK_STRING (string);
-
-  if (NILP (start))
-    pos = 0, pos_byte = 0;
-  else
-    {
-      ptrdiff_t len = SCHARS (string);
-
-      CHECK_NUMBER (start);
-      pos = XINT (start);
-      if (pos < 0 && -pos <= len)
-	pos = len + pos;
-      else if (0 > pos || pos > len)
-	args_out_of_range (string, start);
-      pos_byte = string_char_to_byte (string, pos);
-    }
-
-  /* This is so set_image_of_range_1 in regex.c can find the EQV table.  */
-  set_char_table_extras (BVAR (current_buffer, case_canon_table), 2,
-			 BVAR (current_buffer, case_eqv_table));
-
-  bufp = compile_pattern (regexp,
-			  (NILP (Vinhibit_changing_match_data)
-			   ? &search_regs : NULL),
-			  (!NILP (BVAR (current_buffer, case_fold_search))
-			   ? BVAR (current_buffer, case_canon_table) : Qnil),
-			  posix,
-			  STRING_MULTIBYTE (string));
-  re_match_object = string;
-
-  val = re_search (bufp, SSDATA (string),
-		   SBYTES (string), pos_byte,
-		   SBYTES (string) - pos_byte,
-		   (NILP (Vinhibit_changing_match_data)
-		    ? &search_regs : NULL));
-
-  /* Set last_thing_searched only when match data is changed.  */
-  if (NILP (Vinhibit_changing_match_data))
-    last_thing_searched = Qt;
-
-  if (val == -2)
-    matcher_overflow ();
-  if (val < 0) return Qnil;
-
-  if (NILP (Vinhibit_changing_match_data))
-    for (i = 0; i < search_regs.num_regs; i++)
-      if (search_regs.start[i] >= 0)
-	{
-	  search_regs.start[i]
-	    = string_byte_to_char (string, search_regs.start[i]);
-	  search_regs.end[i]
-	    = string_byte_to_char (string, search_regs.end[i]);
-	}
-
-  return make_number (string_byte_to_char (string, val));
-}
-
 /* Match REGEXP against STRING using translation table TABLE,
    searching all of STRING, and return the index of the match,
    or negative on failure.  This does not clobber the match data.  */
@@ -2127,708 +1978,6 @@ set_search_regs (ptrdiff_t beg_byte, ptrdiff_t nbytes)
   XSETBUFFER (last_thing_searched, current_buffer);
 }
 
-
-DEFUN ("replace-match", Freplace_match, Sreplace_match, 1, 5, 0,
-       doc: /* Replace text matched by last search with NEWTEXT.
-Leave point at the end of the replacement text.
-
-If optional second arg FIXEDCASE is non-nil, do not alter the case of
-the replacement text.  Otherwise, maybe capitalize the whole text, or
-maybe just word initials, based on the replaced text.  If the replaced
-text has only capital letters and has at least one multiletter word,
-convert NEWTEXT to all caps.  Otherwise if all words are capitalized
-in the replaced text, capitalize each word in NEWTEXT.
-
-If optional third arg LITERAL is non-nil, insert NEWTEXT literally.
-Otherwise treat `\\' as special:
-  `\\&' in NEWTEXT means substitute original matched text.
-  `\\N' means substitute what matched the Nth `\\(...\\)'.
-       If Nth parens didn't match, substitute nothing.
-  `\\\\' means insert one `\\'.
-  `\\?' is treated literally
-       (for compatibility with `query-replace-regexp').
-  Any other character following `\\' signals an error.
-Case conversion does not apply to these substitutions.
-
-If optional fourth argument STRING is non-nil, it should be a string
-to act on; this should be the string on which the previous match was
-done via `string-match'.  In this case, `replace-match' creates and
-returns a new string, made by copying STRING and replacing the part of
-STRING that was matched (the original STRING itself is not altered).
-
-The optional fifth argument SUBEXP specifies a subexpression;
-it says to replace just that subexpression with NEWTEXT,
-rather than replacing the entire matched text.
-This is, in a vague sense, the inverse of using `\\N' in NEWTEXT;
-`\\N' copies subexp N into NEWTEXT, but using N as SUBEXP puts
-NEWTEXT in place of subexp N.
-This is useful only after a regular expression search or match,
-since only regular expressions have distinguished subexpressions.  */)
-  (Lisp_Object newtext, Lisp_Object fixedcase, Lisp_Object literal, Lisp_Object string, Lisp_Object subexp)
-{
-  enum { nochange, all_caps, cap_initial } case_action;
-  ptrdiff_t pos, pos_byte;
-  bool some_multiletter_word;
-  bool some_lowercase;
-  bool some_uppercase;
-  bool some_nonuppercase_initial;
-  int c, prevc;
-  ptrdiff_t sub;
-  ptrdiff_t opoint, newpoint;
-
-  CHECK_STRING (newtext);
-
-  if (! NILP (string))
-    CHECK_STRING (string);
-
-  case_action = nochange;	/* We tried an initialization */
-				/* but some C compilers blew it */
-
-  if (search_regs.num_regs <= 0)
-    error ("`replace-match' called before any match found");
-
-  if (NILP (subexp))
-    sub = 0;
-  else
-    {
-      CHECK_NUMBER (subexp);
-      if (! (0 <= XINT (subexp) && XINT (subexp) < search_regs.num_regs))
-	args_out_of_range (subexp, make_number (search_regs.num_regs));
-      sub = XINT (subexp);
-    }
-
-  if (NILP (string))
-    {
-      if (search_regs.start[sub] < BEGV
-	  || search_regs.start[sub] > search_regs.end[sub]
-	  || search_regs.end[sub] > ZV)
-	args_out_of_range (make_number (search_regs.start[sub]),
-			   make_number (search_regs.end[sub]));
-    }
-  else
-    {
-      if (search_regs.start[sub] < 0
-	  || search_regs.start[sub] > search_regs.end[sub]
-	  || search_regs.end[sub] > SCHARS (string))
-	args_out_of_range (make_number (search_regs.start[sub]),
-			   make_number (search_regs.end[sub]));
-    }
-
-  if (NILP (fixedcase))
-    {
-      /* Decide how to casify by examining the matched text. */
-      ptrdiff_t last;
-
-      pos = search_regs.start[sub];
-      last = search_regs.end[sub];
-
-      if (NILP (string))
-	pos_byte = CHAR_TO_BYTE (pos);
-      else
-	pos_byte = string_char_to_byte (string, pos);
-
-      prevc = '\n';
-      case_action = all_caps;
-
-      /* some_multiletter_word is set nonzero if any original word
-	 is more than one letter long. */
-      some_multiletter_word = 0;
-      some_lowercase = 0;
-      some_nonuppercase_initial = 0;
-      some_uppercase = 0;
-
-      while (pos < last)
-	{
-	  if (NILP (string))
-	    {
-	      c = FETCH_CHAR_AS_MULTIBYTE (pos_byte);
-	      INC_BOTH (pos, pos_byte);
-	    }
-	  else
-	    FETCH_STRING_CHAR_AS_MULTIBYTE_ADVANCE (c, string, pos, pos_byte);
-
-	  if (lowercasep (c))
-	    {
-	      /* Cannot be all caps if any original char is lower case */
-
-	      some_lowercase = 1;
-	      if (SYNTAX (prevc) != Sword)
-		some_nonuppercase_initial = 1;
-	      else
-		some_multiletter_word = 1;
-	    }
-	  else if (uppercasep (c))
-	    {
-	      some_uppercase = 1;
-	      if (SYNTAX (prevc) != Sword)
-		;
-	      else
-		some_multiletter_word = 1;
-	    }
-	  else
-	    {
-	      /* If the initial is a caseless word constituent,
-		 treat that like a lowercase initial.  */
-	      if (SYNTAX (prevc) != Sword)
-		some_nonuppercase_initial = 1;
-	    }
-
-	  prevc = c;
-	}
-
-      /* Convert to all caps if the old text is all caps
-	 and has at least one multiletter word.  */
-      if (! some_lowercase && some_multiletter_word)
-	case_action = all_caps;
-      /* Capitalize each word, if the old text has all capitalized words.  */
-      else if (!some_nonuppercase_initial && some_multiletter_word)
-	case_action = cap_initial;
-      else if (!some_nonuppercase_initial && some_uppercase)
-	/* Should x -> yz, operating on X, give Yz or YZ?
-	   We'll assume the latter.  */
-	case_action = all_caps;
-      else
-	case_action = nochange;
-    }
-
-  /* Do replacement in a string.  */
-  if (!NILP (string))
-    {
-      Lisp_Object before, after;
-
-      before = Fsubstring (string, make_number (0),
-			   make_number (search_regs.start[sub]));
-      after = Fsubstring (string, make_number (search_regs.end[sub]), Qnil);
-
-      /* Substitute parts of the match into NEWTEXT
-	 if desired.  */
-      if (NILP (literal))
-	{
-	  ptrdiff_t lastpos = 0;
-	  ptrdiff_t lastpos_byte = 0;
-	  /* We build up the substituted string in ACCUM.  */
-	  Lisp_Object accum;
-	  Lisp_Object middle;
-	  ptrdiff_t length = SBYTES (newtext);
-
-	  accum = Qnil;
-
-	  for (pos_byte = 0, pos = 0; pos_byte < length;)
-	    {
-	      ptrdiff_t substart = -1;
-	      ptrdiff_t subend = 0;
-	      bool delbackslash = 0;
-
-	      FETCH_STRING_CHAR_ADVANCE (c, newtext, pos, pos_byte);
-
-	      if (c == '\\')
-		{
-		  FETCH_STRING_CHAR_ADVANCE (c, newtext, pos, pos_byte);
-
-		  if (c == '&')
-		    {
-		      substart = search_regs.start[sub];
-		      subend = search_regs.end[sub];
-		    }
-		  else if (c >= '1' && c <= '9')
-		    {
-		      if (c - '0' < search_regs.num_regs
-			  && search_regs.start[c - '0'] >= 0)
-			{
-			  substart = search_regs.start[c - '0'];
-			  subend = search_regs.end[c - '0'];
-			}
-		      else
-			{
-			  /* If that subexp did not match,
-			     replace \\N with nothing.  */
-			  substart = 0;
-			  subend = 0;
-			}
-		    }
-		  else if (c == '\\')
-		    delbackslash = 1;
-		  else if (c != '?')
-		    error ("Invalid use of `\\' in replacement text");
-		}
-	      if (substart >= 0)
-		{
-		  if (pos - 2 != lastpos)
-		    middle = substring_both (newtext, lastpos,
-					     lastpos_byte,
-					     pos - 2, pos_byte - 2);
-		  else
-		    middle = Qnil;
-		  accum = concat3 (accum, middle,
-				   Fsubstring (string,
-					       make_number (substart),
-					       make_number (subend)));
-		  lastpos = pos;
-		  lastpos_byte = pos_byte;
-		}
-	      else if (delbackslash)
-		{
-		  middle = substring_both (newtext, lastpos,
-					   lastpos_byte,
-					   pos - 1, pos_byte - 1);
-
-		  accum = concat2 (accum, middle);
-		  lastpos = pos;
-		  lastpos_byte = pos_byte;
-		}
-	    }
-
-	  if (pos != lastpos)
-	    middle = substring_both (newtext, lastpos,
-				     lastpos_byte,
-				     pos, pos_byte);
-	  else
-	    middle = Qnil;
-
-	  newtext = concat2 (accum, middle);
-	}
-
-      /* Do case substitution in NEWTEXT if desired.  */
-      if (case_action == all_caps)
-	newtext = Fupcase (newtext);
-      else if (case_action == cap_initial)
-	newtext = Fupcase_initials (newtext);
-
-      return concat3 (before, newtext, after);
-    }
-
-  /* Record point, then move (quietly) to the start of the match.  */
-  if (PT >= search_regs.end[sub])
-    opoint = PT - ZV;
-  else if (PT > search_regs.start[sub])
-    opoint = search_regs.end[sub] - ZV;
-  else
-    opoint = PT;
-
-  /* If we want non-literal replacement,
-     perform substitution on the replacement string.  */
-  if (NILP (literal))
-    {
-      ptrdiff_t length = SBYTES (newtext);
-      unsigned char *substed;
-      ptrdiff_t substed_alloc_size, substed_len;
-      bool buf_multibyte = !NILP (BVAR (current_buffer, enable_multibyte_characters));
-      bool str_multibyte = STRING_MULTIBYTE (newtext);
-      bool really_changed = 0;
-
-      substed_alloc_size = (length <= (STRING_BYTES_BOUND - 100) / 2
-			    ? length * 2 + 100
-			    : STRING_BYTES_BOUND);
-      substed = xmalloc (substed_alloc_size);
-      substed_len = 0;
-
-      /* Go thru NEWTEXT, producing the actual text to insert in
-	 SUBSTED while adjusting multibyteness to that of the current
-	 buffer.  */
-
-      for (pos_byte = 0, pos = 0; pos_byte < length;)
-	{
-	  unsigned char str[MAX_MULTIBYTE_LENGTH];
-	  const unsigned char *add_stuff = NULL;
-	  ptrdiff_t add_len = 0;
-	  ptrdiff_t idx = -1;
-	  ptrdiff_t begbyte UNINIT;
-
-	  if (str_multibyte)
-	    {
-	      FETCH_STRING_CHAR_ADVANCE_NO_CHECK (c, newtext, pos, pos_byte);
-	      if (!buf_multibyte)
-		c = CHAR_TO_BYTE8 (c);
-	    }
-	  else
-	    {
-	      /* Note that we don't have to increment POS.  */
-	      c = SREF (newtext, pos_byte++);
-	      if (buf_multibyte)
-		MAKE_CHAR_MULTIBYTE (c);
-	    }
-
-	  /* Either set ADD_STUFF and ADD_LEN to the text to put in SUBSTED,
-	     or set IDX to a match index, which means put that part
-	     of the buffer text into SUBSTED.  */
-
-	  if (c == '\\')
-	    {
-	      really_changed = 1;
-
-	      if (str_multibyte)
-		{
-		  FETCH_STRING_CHAR_ADVANCE_NO_CHECK (c, newtext,
-						      pos, pos_byte);
-		  if (!buf_multibyte && !ASCII_CHAR_P (c))
-		    c = CHAR_TO_BYTE8 (c);
-		}
-	      else
-		{
-		  c = SREF (newtext, pos_byte++);
-		  if (buf_multibyte)
-		    MAKE_CHAR_MULTIBYTE (c);
-		}
-
-	      if (c == '&')
-		idx = sub;
-	      else if (c >= '1' && c <= '9' && c - '0' < search_regs.num_regs)
-		{
-		  if (search_regs.start[c - '0'] >= 1)
-		    idx = c - '0';
-		}
-	      else if (c == '\\')
-		add_len = 1, add_stuff = (unsigned char *) "\\";
-	      else
-		{
-		  xfree (substed);
-		  error ("Invalid use of `\\' in replacement text");
-		}
-	    }
-	  else
-	    {
-	      add_len = CHAR_STRING (c, str);
-	      add_stuff = str;
-	    }
-
-	  /* If we want to copy part of a previous match,
-	     set up ADD_STUFF and ADD_LEN to point to it.  */
-	  if (idx >= 0)
-	    {
-	      begbyte = CHAR_TO_BYTE (search_regs.start[idx]);
-	      add_len = CHAR_TO_BYTE (search_regs.end[idx]) - begbyte;
-	      if (search_regs.start[idx] < GPT && GPT < search_regs.end[idx])
-		move_gap_both (search_regs.start[idx], begbyte);
-	    }
-
-	  /* Now the stuff we want to add to SUBSTED
-	     is invariably ADD_LEN bytes starting at ADD_STUFF.  */
-
-	  /* Make sure SUBSTED is big enough.  */
-	  if (substed_alloc_size - substed_len < add_len)
-	    substed =
-	      xpalloc (substed, &substed_alloc_size,
-		       add_len - (substed_alloc_size - substed_len),
-		       STRING_BYTES_BOUND, 1);
-
-	  /* We compute this after the call to xpalloc, because that
-	     could cause buffer text be relocated when ralloc.c is used.  */
-	  if (idx >= 0)
-	    add_stuff = BYTE_POS_ADDR (begbyte);
-
-	  /* Now add to the end of SUBSTED.  */
-	  if (add_stuff)
-	    {
-	      memcpy (substed + substed_len, add_stuff, add_len);
-	      substed_len += add_len;
-	    }
-	}
-
-      if (really_changed)
-	newtext = make_specified_string ((const char *) substed, -1,
-					 substed_len, buf_multibyte);
-      xfree (substed);
-    }
-
-  /* The functions below modify the buffer, so they could trigger
-     various modification hooks (see signal_before_change and
-     signal_after_change).  If these hooks clobber the match data we
-     error out since otherwise this will result in confusing bugs.  */
-  ptrdiff_t sub_start = search_regs.start[sub];
-  ptrdiff_t sub_end = search_regs.end[sub];
-  unsigned  num_regs = search_regs.num_regs;
-  newpoint = search_regs.start[sub] + SCHARS (newtext);
-
-  /* Replace the old text with the new in the cleanest possible way.  */
-  replace_range (search_regs.start[sub], search_regs.end[sub],
-                 newtext, 1, 0, 1, 1);
-  /* Update saved data to match adjustment made by replace_range.  */
-  {
-    ptrdiff_t change = newpoint - sub_end;
-    if (sub_start >= sub_end)
-      sub_start += change;
-    sub_end += change;
-  }
-
-  if (case_action == all_caps)
-    Fupcase_region (make_number (search_regs.start[sub]),
-		    make_number (newpoint),
-		    Qnil);
-  else if (case_action == cap_initial)
-    Fupcase_initials_region (make_number (search_regs.start[sub]),
-			     make_number (newpoint));
-
-  if (search_regs.start[sub] != sub_start
-      || search_regs.end[sub] != sub_end
-      || search_regs.num_regs != num_regs)
-    error ("Match data clobbered by buffer modification hooks");
-
-  /* Put point back where it was in the text.  */
-  if (opoint <= 0)
-    TEMP_SET_PT (opoint + ZV);
-  else
-    TEMP_SET_PT (opoint);
-
-  /* Now move point "officially" to the start of the inserted replacement.  */
-  move_if_not_intangible (newpoint);
-
-  return Qnil;
-}
-
-Lisp_Object
-match_limit (Lisp_Object num, bool beginningp)
-{
-  EMACS_INT n;
-
-  CHECK_NUMBER (num);
-  n = XINT (num);
-  if (n < 0)
-    args_out_of_range (num, make_number (0));
-  if (search_regs.num_regs <= 0)
-    error ("No match data, because no search succeeded");
-  if (n >= search_regs.num_regs
-      || search_regs.start[n] < 0)
-    return Qnil;
-  return (make_number ((beginningp) ? search_regs.start[n]
-		                    : search_regs.end[n]));
-}
-
-DEFUN ("match-data", Fmatch_data, Smatch_data, 0, 3, 0,
-       doc: /* Return a list describing what the last search matched.
-Element 2N is `(match-beginning N)'; element 2N + 1 is `(match-end N)'.
-All the elements are markers or nil (nil if the Nth pair didn't match)
-if the last match was on a buffer; integers or nil if a string was matched.
-Use `set-match-data' to reinstate the data in this list.
-
-If INTEGERS (the optional first argument) is non-nil, always use
-integers (rather than markers) to represent buffer positions.  In
-this case, and if the last match was in a buffer, the buffer will get
-stored as one additional element at the end of the list.
-
-If REUSE is a list, reuse it as part of the value.  If REUSE is long
-enough to hold all the values, and if INTEGERS is non-nil, no consing
-is done.
-
-If optional third arg RESEAT is non-nil, any previous markers on the
-REUSE list will be modified to point to nowhere.
-
-Return value is undefined if the last search failed.  */)
-  (Lisp_Object integers, Lisp_Object reuse, Lisp_Object reseat)
-{
-  Lisp_Object tail, prev;
-  Lisp_Object *data;
-  ptrdiff_t i, len;
-
-  if (!NILP (reseat))
-    for (tail = reuse; CONSP (tail); tail = XCDR (tail))
-      if (MARKERP (XCAR (tail)))
-	{
-	  unchain_marker (XMARKER (XCAR (tail)));
-	  XSETCAR (tail, Qnil);
-	}
-
-  if (NILP (last_thing_searched))
-    return Qnil;
-
-  prev = Qnil;
-
-  USE_SAFE_ALLOCA;
-  SAFE_NALLOCA (data, 1, 2 * search_regs.num_regs + 1);
-
-  len = 0;
-  for (i = 0; i < search_regs.num_regs; i++)
-    {
-      ptrdiff_t start = search_regs.start[i];
-      if (start >= 0)
-	{
-	  if (EQ (last_thing_searched, Qt)
-	      || ! NILP (integers))
-	    {
-	      XSETFASTINT (data[2 * i], start);
-	      XSETFASTINT (data[2 * i + 1], search_regs.end[i]);
-	    }
-	  else if (BUFFERP (last_thing_searched))
-	    {
-	      data[2 * i] = Fmake_marker ();
-	      Fset_marker (data[2 * i],
-			   make_number (start),
-			   last_thing_searched);
-	      data[2 * i + 1] = Fmake_marker ();
-	      Fset_marker (data[2 * i + 1],
-			   make_number (search_regs.end[i]),
-			   last_thing_searched);
-	    }
-	  else
-	    /* last_thing_searched must always be Qt, a buffer, or Qnil.  */
-	    emacs_abort ();
-
-	  len = 2 * i + 2;
-	}
-      else
-	data[2 * i] = data[2 * i + 1] = Qnil;
-    }
-
-  if (BUFFERP (last_thing_searched) && !NILP (integers))
-    {
-      data[len] = last_thing_searched;
-      len++;
-    }
-
-  /* If REUSE is not usable, cons up the values and return them.  */
-  if (! CONSP (reuse))
-    reuse = Flist (len, data);
-  else
-    {
-      /* If REUSE is a list, store as many value elements as will fit
-	 into the elements of REUSE.  */
-      for (i = 0, tail = reuse; CONSP (tail);
-	   i++, tail = XCDR (tail))
-	{
-	  if (i < len)
-	    XSETCAR (tail, data[i]);
-	  else
-	    XSETCAR (tail, Qnil);
-	  prev = tail;
-	}
-
-      /* If we couldn't fit all value elements into REUSE,
-	 cons up the rest of them and add them to the end of REUSE.  */
-      if (i < len)
-	XSETCDR (prev, Flist (len - i, data + i));
-    }
-
-  SAFE_FREE ();
-  return reuse;
-}
-
-/* We used to have an internal use variant of `reseat' described as:
-
-      If RESEAT is `evaporate', put the markers back on the free list
-      immediately.  No other references to the markers must exist in this
-      case, so it is used only internally on the unwind stack and
-      save-match-data from Lisp.
-
-   But it was ill-conceived: those supposedly-internal markers get exposed via
-   the undo-list, so freeing them here is unsafe.  */
-
-DEFUN ("set-match-data", Fset_match_data, Sset_match_data, 1, 2, 0,
-       doc: /* Set internal data on last search match from elements of LIST.
-LIST should have been created by calling `match-data' previously.
-
-If optional arg RESEAT is non-nil, make markers on LIST point nowhere.  */)
-  (register Lisp_Object list, Lisp_Object reseat)
-{
-  ptrdiff_t i;
-  register Lisp_Object marker;
-
-  if (running_asynch_code)
-    save_search_regs ();
-
-  CHECK_LIST (list);
-
-  /* Unless we find a marker with a buffer or an explicit buffer
-     in LIST, assume that this match data came from a string.  */
-  last_thing_searched = Qt;
-
-  /* Allocate registers if they don't already exist.  */
-  {
-    EMACS_INT length = XFASTINT (Flength (list)) / 2;
-
-    if (length > search_regs.num_regs)
-      {
-	ptrdiff_t num_regs = search_regs.num_regs;
-	if (PTRDIFF_MAX < length)
-	  memory_full (SIZE_MAX);
-	search_regs.start =
-	  xpalloc (search_regs.start, &num_regs, length - num_regs,
-		   min (PTRDIFF_MAX, UINT_MAX), sizeof (regoff_t));
-	search_regs.end =
-	  xrealloc (search_regs.end, num_regs * sizeof (regoff_t));
-
-	for (i = search_regs.num_regs; i < num_regs; i++)
-	  search_regs.start[i] = -1;
-
-	search_regs.num_regs = num_regs;
-      }
-
-    for (i = 0; CONSP (list); i++)
-      {
-	marker = XCAR (list);
-	if (BUFFERP (marker))
-	  {
-	    last_thing_searched = marker;
-	    break;
-	  }
-	if (i >= length)
-	  break;
-	if (NILP (marker))
-	  {
-	    search_regs.start[i] = -1;
-	    list = XCDR (list);
-	  }
-	else
-	  {
-	    Lisp_Object from;
-	    Lisp_Object m;
-
-	    m = marker;
-	    if (MARKERP (marker))
-	      {
-		if (XMARKER (marker)->buffer == 0)
-		  XSETFASTINT (marker, 0);
-		else
-		  XSETBUFFER (last_thing_searched, XMARKER (marker)->buffer);
-	      }
-
-	    CHECK_NUMBER_COERCE_MARKER (marker);
-	    from = marker;
-
-	    if (!NILP (reseat) && MARKERP (m))
-	      {
-		unchain_marker (XMARKER (m));
-		XSETCAR (list, Qnil);
-	      }
-
-	    if ((list = XCDR (list), !CONSP (list)))
-	      break;
-
-	    m = marker = XCAR (list);
-
-	    if (MARKERP (marker) && XMARKER (marker)->buffer == 0)
-	      XSETFASTINT (marker, 0);
-
-	    CHECK_NUMBER_COERCE_MARKER (marker);
-	    if ((XINT (from) < 0
-		 ? TYPE_MINIMUM (regoff_t) <= XINT (from)
-		 : XINT (from) <= TYPE_MAXIMUM (regoff_t))
-		&& (XINT (marker) < 0
-		    ? TYPE_MINIMUM (regoff_t) <= XINT (marker)
-		    : XINT (marker) <= TYPE_MAXIMUM (regoff_t)))
-	      {
-		search_regs.start[i] = XINT (from);
-		search_regs.end[i] = XINT (marker);
-	      }
-	    else
-	      {
-		search_regs.start[i] = -1;
-	      }
-
-	    if (!NILP (reseat) && MARKERP (m))
-	      {
-		unchain_marker (XMARKER (m));
-		XSETCAR (list, Qnil);
-	      }
-	  }
-	list = XCDR (list);
-      }
-
-    for (; i < search_regs.num_regs; i++)
-      search_regs.start[i] = -1;
-  }
-
-  return Qnil;
-}
-
 /* If true the match data have been saved in saved_search_regs
    during the execution of a sentinel or filter. */
 /* static bool search_regs_saved; */
@@ -2837,7 +1986,7 @@ If optional arg RESEAT is non-nil, make markers on LIST point nowhere.  */)
 
 /* Called from Flooking_at, Fstring_match, search_buffer, Fstore_match_data
    if asynchronous code (filter or sentinel) is running. */
-static void
+void
 save_search_regs (void)
 {
   if (!search_regs_saved)
@@ -2896,21 +2045,6 @@ update_search_regs (ptrdiff_t oldstart, ptrdiff_t oldend, ptrdiff_t newend)
     }
 }
 
-static void
-unwind_set_match_data (Lisp_Object list)
-{
-  /* It is NOT ALWAYS safe to free (evaporate) the markers immediately.  */
-  Fset_match_data (list, Qt);
-}
-
-/* Called to unwind protect the match data.  */
-void
-record_unwind_save_match_data (void)
-{
-  record_unwind_protect (unwind_set_match_data,
-			 Fmatch_data (Qnil, Qnil, Qnil));
-}
-
 /* Quote a string to deactivate reg-expr chars */
 
 DEFUN ("regexp-quote", Fregexp_quote, Sregexp_quote, 1, 1, 0,
@@ -3199,9 +2333,6 @@ do not set the match data.  The proper way to use this variable
 is to bind it with `let' around a small expression.  */);
   Vinhibit_changing_match_data = Qnil;
 
-  defsubr (&Sreplace_match);
-  defsubr (&Smatch_data);
-  defsubr (&Sset_match_data);
   defsubr (&Sregexp_quote);
   defsubr (&Snewline_cache_check);
 }
@@ -98,6 +98,13 @@ extern int decode_time_components (Lisp_Object, Lisp_Object, Lisp_Object,
 				   Lisp_Object, struct lisp_time *, double *);
 extern struct timespec lisp_to_timespec (struct lisp_time);
 extern struct timespec lisp_time_argument (Lisp_Object);
+extern timezone_t tzlookup (Lisp_Object, bool);
+extern void xtzfree (timezone_t);
+extern struct tm *emacs_localtime_rz (timezone_t, time_t const *, struct tm *);
+extern time_t emacs_mktime_z (timezone_t, struct tm *);
+
+/* defined in time.rs */
+extern time_t lisp_seconds_argument (Lisp_Object);
 #endif
 
 INLINE_HEADER_END
@@ -605,6 +605,50 @@ thread_select (select_func *func, int max_fds, fd_set *rfds,
 
 
 
+struct without_lock_args
+{
+  void (*func) (void *);
+  void *arg;
+};
+
+static void
+really_call_without_lock (void *arg)
+{
+  struct without_lock_args *wa = arg;
+  struct thread_state *self = current_thread;
+  sigset_t oldset;
+
+  block_interrupt_signal (&oldset);
+  self->not_holding_lock = 1;
+  release_global_lock ();
+  restore_signal_mask (&oldset);
+
+  wa->func (wa->arg);
+
+  block_interrupt_signal (&oldset);
+  if (self->not_holding_lock)
+    {
+      acquire_global_lock (self);
+      self->not_holding_lock = 0;
+    }
+  restore_signal_mask (&oldset);
+}
+
+/* Call FUNC with ARG without holding the global lock, so that other
+   Lisp threads can run in the meantime.  FUNC must not access any
+   Lisp objects or signal errors.  */
+
+void
+thread_call_without_lock (void (*func) (void *), void *arg)
+{
+  struct without_lock_args wa;
+
+  wa.func = func;
+  wa.arg = arg;
+  flush_stack_call_func (really_call_without_lock, &wa);
+}
+
+
 static void
 mark_one_thread (struct thread_state *thread)
 {
@@ -308,6 +308,8 @@ extern bool in_current_thread (void);
 typedef int select_func (int, fd_set *, fd_set *, fd_set *,
 			 const struct timespec *, const sigset_t *);
 
+extern void thread_call_without_lock (void (*) (void *), void *);
+
 int thread_select  (select_func *func, int max_fds, fd_set *rfds,
 		    fd_set *wfds, fd_set *efds, struct timespec *timeout,
 		    sigset_t *sigmask);
@@ -0,0 +1,58 @@
+;;; format-spec-tests.el --- tests for format-spec.el
+
+;; Copyright (C) 2018 Free Software Foundation, Inc.
+
+;; This file is part of GNU Emacs.
+
+;; GNU Emacs is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; GNU Emacs is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with GNU Emacs.  If not, see <https://www.gnu.org/licenses/>.
+
+;;; Code:
+
+(require 'ert)
+(require 'format-spec)
+
+(ert-deftest format-spec-tests--basic ()
+  (should (equal (format-spec "bash %u %k" '((?u . "foo") (?k . 3)))
+                 "bash foo 3"))
+  (should (equal (format-spec "%a%a" '((?a . "x"))) "xx"))
+  (should (equal (format-spec "no specs" nil) "no specs"))
+  (should (equal (format-spec "" nil) "")))
+
+(ert-deftest format-spec-tests--percent ()
+  (should (equal (format-spec "100%% %a" '((?a . "done"))) "100% done"))
+  (should (equal (format-spec "%%a" '((?a . "x"))) "%a")))
+
+(ert-deftest format-spec-tests--width ()
+  (should (equal (format-spec "[%5a]" '((?a . "foo"))) "[  foo]"))
+  (should (equal (format-spec "[%-5a]" '((?a . "foo"))) "[foo  ]"))
+  (should (equal (format-spec "[%.2a]" '((?a . "foo"))) "[fo]"))
+  (should (equal (format-spec "[%3a]" '((?a . 7))) "[  7]")))
+
+(ert-deftest format-spec-tests--properties ()
+  (let ((result (format-spec (concat "x" (propertize "%a" 'face 'bold) "y")
+                             '((?a . "foo")))))
+    (should (equal result "xfooy"))
+    (should-not (get-text-property 0 'face result))
+    (should (eq (get-text-property 1 'face result) 'bold))
+    (should (eq (get-text-property 3 'face result) 'bold))
+    (should-not (get-text-property 4 'face result)))
+  (let ((result (format-spec "%a" `((?a . ,(propertize "foo" 'face 'italic))))))
+    (should (eq (get-text-property 0 'face result) 'italic))))
+
+(ert-deftest format-spec-tests--errors ()
+  (should-error (format-spec "%b" '((?a . "foo"))))
+  (should-error (format-spec "trailing %" nil))
+  (should-error (format-spec "%5" nil)))
+
+;;; format-spec-tests.el ends here
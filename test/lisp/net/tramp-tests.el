@@ -2442,6 +2442,41 @@ This tests also `file-directory-p' and `file-accessible-directory-p'."
 	;; Cleanup.
 	(ignore-errors (delete-directory tmp-name1 'recursive))))))
 
+(ert-deftest tramp-test16-directory-files-recursively ()
+  "Check `directory-files-recursively'."
+  (skip-unless (tramp--test-enabled))
+
+  (dolist (quoted (if (tramp--test-expensive-test) '(nil t) '(nil)))
+    (let* ((tmp-name1 (tramp--test-make-temp-name nil quoted))
+	   (tmp-name2 (expand-file-name "sub" tmp-name1))
+	   (tmp-name3 (expand-file-name "bla" tmp-name1))
+	   (tmp-name4 (expand-file-name "foo" tmp-name2))
+	   (tmp-name5 (expand-file-name ".git" tmp-name1)))
+      (unwind-protect
+	  (progn
+	    (make-directory tmp-name2 'parents)
+	    (make-directory tmp-name5)
+	    (write-region "foo" nil tmp-name3)
+	    (write-region "bla" nil tmp-name4)
+	    (write-region "git" nil (expand-file-name "config" tmp-name5))
+	    (should (equal (directory-files-recursively tmp-name1 "")
+			   `(,(expand-file-name "config" tmp-name5)
+			     ,tmp-name4 ,tmp-name3)))
+	    (should (equal (directory-files-recursively tmp-name1 "" t)
+			   `(,(expand-file-name "config" tmp-name5) ,tmp-name5
+			     ,tmp-name4 ,tmp-name2 ,tmp-name3)))
+	    (should (equal (directory-files-recursively tmp-name1 "" nil 0)
+			   `(,tmp-name3)))
+	    (should (equal (directory-files-recursively
+			    tmp-name1 "" nil nil nil "\\`sub\\'")
+			   `(,(expand-file-name "config" tmp-name5) ,tmp-name3)))
+	    (should (equal (directory-files-recursively
+			    tmp-name1 "" nil nil nil nil t)
+			   `(,tmp-name4 ,tmp-name3))))
+
+	;; Cleanup.
+	(ignore-errors (delete-directory tmp-name1 'recursive))))))
+
 ;; This is not a file name handler test.  But Tramp needed to apply an
 ;; advice for older Emacs versions, so we check that this has been fixed.
 (ert-deftest tramp-test16-file-expand-wildcards ()
@@ -3399,6 +3434,40 @@ This tests also `make-symbolic-link', `file-truename' and `add-name-to-file'."
 	(ignore-errors (delete-file tmp-name1))
 	(ignore-errors (delete-file tmp-name3))))))
 
+(ert-deftest tramp-test25-file-extended-attributes ()
+  "Check `file-extended-attributes' and the `file-xattr-*' functions."
+  (skip-unless (tramp--test-enabled))
+  (skip-unless (fboundp 'file-xattr-list))
+
+  (let ((tmp-name1 (tramp--test-make-temp-name))
+	(tmp-name2 (tramp--test-make-temp-name)))
+    (unwind-protect
+	(progn
+	  (write-region "foo" nil tmp-name1)
+	  (let ((attributes (file-extended-attributes tmp-name1)))
+	    (should (assq 'acl attributes))
+	    (should (assq 'selinux-context attributes))
+	    (should (assq 'xattrs attributes)))
+	  ;; Not all remote hosts support extended attributes.
+	  (when (ignore-errors
+		  (file-xattr-set tmp-name1 "user.remacs" "\377bar")
+		  t)
+	    (should (member "user.remacs" (file-xattr-list tmp-name1)))
+	    (should (equal (file-xattr-get tmp-name1 "user.remacs") "\377bar"))
+	    ;; Copy the attributes.
+	    (write-region "foo" nil tmp-name2)
+	    (should
+	     (set-file-extended-attributes
+	      tmp-name2 (file-extended-attributes tmp-name1)))
+	    (should (equal (file-xattr-get tmp-name2 "user.remacs") "\377bar"))
+	    (should (file-xattr-remove tmp-name1 "user.remacs"))
+	    (should-not (file-xattr-get tmp-name1 "user.remacs"))
+	    (should-not (file-xattr-remove tmp-name1 "user.remacs"))))
+
+      ;; Cleanup.
+      (ignore-errors (delete-file tmp-name1))
+      (ignore-errors (delete-file tmp-name2)))))
+
 (ert-deftest tramp-test26-file-name-completion ()
   "Check `file-name-completion' and `file-name-all-completions'."
   (skip-unless (tramp--test-enabled))
@@ -0,0 +1,156 @@
+;;; callproc-tests.el --- tests for callproc.rs functions
+
+;;; Code:
+
+(require 'ert)
+(require 'subr-x)
+
+(ert-deftest callproc-tests--call-process-to-string ()
+  (skip-unless (executable-find "printf"))
+  (should (equal (call-process-to-string "printf" nil "%s-%s" "a" "b") "a-b"))
+  (should (equal (call-process-to-string "printf" 'utf-8 "\303\251") "é"))
+  ;; Without CODING-SYSTEM, `coding-system-for-read' is used.
+  (let ((coding-system-for-read 'utf-8))
+    (should (equal (call-process-to-string "printf" nil "\303\251") "é")))
+  (let ((coding-system-for-read 'latin-1))
+    (should (equal (call-process-to-string "printf" 'utf-8 "\303\251") "é")))
+  (should-error (call-process-to-string "remacs-no-such-program")
+                :type 'file-missing))
+
+(ert-deftest callproc-tests--call-process-to-string-error ()
+  (skip-unless (executable-find "sh"))
+  (let ((err (should-error
+              (call-process-to-string "sh" nil "-c" "echo oops >&2; exit 3"))))
+    (should (string-match-p "status 3" (cadr err)))
+    (should (string-match-p "oops" (cadr err)))))
+
+(ert-deftest callproc-tests--call-process-lines ()
+  (skip-unless (executable-find "printf"))
+  (should (equal (call-process-lines "printf" nil "a\\nb\\n") '("a" "b")))
+  (should (equal (call-process-lines "printf" nil "a\\n\\nb") '("a" "" "b")))
+  (should-not (call-process-lines "printf" nil "")))
+
+(ert-deftest callproc-tests--call-process-lines-default-directory ()
+  (skip-unless (executable-find "pwd"))
+  (let ((default-directory temporary-file-directory))
+    (should (equal (file-truename (car (call-process-lines "pwd")))
+                   (file-truename (directory-file-name
+                                   temporary-file-directory))))))
+
+;; The keyword arguments of the synchronous process primitives.
+
+(ert-deftest callproc-tests--environment-keyword ()
+  (skip-unless (executable-find "sh"))
+  (should (equal (call-process-to-string
+                  :environment '("REMACS_TEST=one")
+                  "sh" nil "-c" "printf %s \"$REMACS_TEST\"")
+                 "one"))
+  (should (equal (call-process-to-string
+                  :environment '(("REMACS_TEST" . "two"))
+                  "sh" nil "-c" "printf %s \"$REMACS_TEST\"")
+                 "two"))
+  (let ((process-environment (cons "REMACS_TEST=outer" process-environment)))
+    (should (equal (call-process-to-string
+                    :environment '(("REMACS_TEST"))
+                    "sh" nil "-c" "printf %s \"${REMACS_TEST-unset}\"")
+                   "unset"))
+    (should (equal (call-process-to-string
+                    :environment '("OTHER=1") :inherit-environment nil
+                    "/bin/sh" nil "-c" "printf %s \"${REMACS_TEST-unset}\"")
+                   "unset"))))
+
+(ert-deftest callproc-tests--directory-keyword ()
+  (skip-unless (executable-find "pwd"))
+  (let ((dir (make-temp-file "callproc" t)))
+    (unwind-protect
+        (with-temp-buffer
+          (should (eq 0 (call-process :directory dir "pwd" nil t)))
+          (should (equal (file-truename (string-trim (buffer-string)))
+                         (file-truename dir)))
+          ;; `default-directory' is left untouched.
+          (should-not (equal default-directory (file-name-as-directory dir))))
+      (delete-directory dir))))
+
+(ert-deftest callproc-tests--call-process-region-keywords ()
+  (skip-unless (executable-find "sh"))
+  (with-temp-buffer
+    (insert "input")
+    (should (eq 0 (call-process-region
+                   :environment '("REMACS_TEST=env")
+                   (point-min) (point-max) "sh" t t nil
+                   "-c" "cat; printf %s \"$REMACS_TEST\"")))
+    (should (equal (buffer-string) "inputenv"))))
+
+(ert-deftest callproc-tests--keywords-not-bound-in-hooks ()
+  (skip-unless (executable-find "sh"))
+  (let ((dir (make-temp-file "callproc" t))
+        (process-environment (cons "REMACS_TEST=outer" process-environment))
+        seen)
+    (unwind-protect
+        (with-temp-buffer
+          (let ((outer default-directory))
+            (add-hook 'before-change-functions
+                      (lambda (&rest _)
+                        (push (list default-directory (getenv "REMACS_TEST"))
+                              seen))
+                      nil t)
+            (should (eq 0 (call-process :directory dir
+                                        :environment '("REMACS_TEST=inner")
+                                        "sh" nil t nil
+                                        "-c" "printf %s \"$REMACS_TEST\"")))
+            (should (equal (buffer-string) "inner"))
+            ;; The hook ran inside the call, but saw the caller's values.
+            (should seen)
+            (dolist (values seen)
+              (should (equal values (list outer "outer"))))))
+      (delete-directory dir))))
+
+(defvar callproc-tests--handler-directory nil
+  "Directory returned by `callproc-tests--handler'.")
+
+(defvar callproc-tests--handler-calls nil
+  "`default-directory' and $REMACS_TEST as seen by `callproc-tests--handler'.")
+
+(defun callproc-tests--handler (operation &rest args)
+  "File name handler mapping /callproc-test: names to a local directory."
+  (if (eq operation 'unhandled-file-name-directory)
+      (progn
+        (push (list default-directory (getenv "REMACS_TEST"))
+              callproc-tests--handler-calls)
+        callproc-tests--handler-directory)
+    (let ((inhibit-file-name-handlers
+           (cons 'callproc-tests--handler
+                 (and (eq inhibit-file-name-operation operation)
+                      inhibit-file-name-handlers)))
+          (inhibit-file-name-operation operation))
+      (apply operation args))))
+
+(ert-deftest callproc-tests--keywords-not-bound-in-handlers ()
+  (skip-unless (executable-find "sh"))
+  (let* ((dir (file-name-as-directory (make-temp-file "callproc" t)))
+         (callproc-tests--handler-directory dir)
+         (callproc-tests--handler-calls nil)
+         (file-name-handler-alist
+          (cons '("\\`/callproc-test:" . callproc-tests--handler)
+                file-name-handler-alist))
+         (process-environment (cons "REMACS_TEST=outer" process-environment)))
+    (unwind-protect
+        (with-temp-buffer
+          (let ((outer default-directory))
+            (should (equal (call-process-to-string
+                            :directory "/callproc-test:/x/"
+                            :environment '("REMACS_TEST=inner")
+                            "sh" nil "-c" "printf %s \"$REMACS_TEST\"")
+                           "inner"))
+            (should callproc-tests--handler-calls)
+            (dolist (call callproc-tests--handler-calls)
+              (should (equal call (list outer "outer"))))))
+      (delete-directory dir))))
+
+(ert-deftest callproc-tests--bad-keyword ()
+  (should-error (call-process :no-such-keyword 1 "true"))
+  (should-error (call-process :directory)))
+
+(provide 'callproc-tests)
+
+;;; callproc-tests.el ends here
@@ -25,6 +25,19 @@
     (should (file-attributes-lessp '(rstr t) '(wstr t)))
     (should (not (file-attributes-lessp '(wstr t) '(rstr t))))))
 
+(ert-deftest test-file-attributes-symlink-target ()
+  ;; The target is decoded with `file-name-coding-system', even when
+  ;; it is not valid UTF-8.
+  (skip-unless (not (eq system-type 'windows-nt)))
+  (let* ((dir (make-temp-file "dired-tests" t))
+         (link (expand-file-name "link" dir))
+         (file-name-coding-system 'latin-1))
+    (unwind-protect
+        (progn
+          (make-symbolic-link "café" link)
+          (should (equal (file-attribute-type (file-attributes link)) "café")))
+      (delete-directory dir t))))
+
 (ert-deftest test-system-users ()
   (should-error (eval '(system-users 'rms)) :type 'wrong-number-of-arguments)
   ;; The result should be a list of >= 1 user name(s) on all Unix and GNU systems.
@@ -46,3 +59,167 @@
       (should system-groups)
       (should (listp system-groups))
       (should (< 0 (length system-groups))))))
+
+(defmacro dired-tests--with-tree (files &rest body)
+  "Create a temporary directory containing FILES and run BODY.
+Entries of FILES ending in a slash are created as directories.  The
+variable `root' is bound to the directory while BODY runs."
+  (declare (indent 1))
+  `(let ((root (make-temp-file "dired-tests" t)))
+     (unwind-protect
+         (progn
+           (dolist (file ,files)
+             (let ((name (expand-file-name file root)))
+               (if (directory-name-p file)
+                   (make-directory name t)
+                 (make-directory (file-name-directory name) t)
+                 (write-region (if (string-match-p "ignore\\'" file) "*.log\n" "")
+                               nil name nil 'silent))))
+           ,@body)
+       (delete-directory root t))))
+
+(ert-deftest test-directory-files-recursively ()
+  (dired-tests--with-tree '("b.el" "a.el" "sub/c.el" "sub/d.txt" "sub/deeper/e.el" "empty/")
+    (should (equal (directory-files-recursively root "\\.el\\'")
+                   (mapcar (lambda (f) (expand-file-name f root))
+                           '("sub/deeper/e.el" "sub/c.el" "a.el" "b.el"))))
+    (should (equal (directory-files-recursively root "\\.el\\'" nil 1)
+                   (mapcar (lambda (f) (expand-file-name f root))
+                           '("sub/c.el" "a.el" "b.el"))))
+    (should (equal (directory-files-recursively root "\\.el\\'" nil 0)
+                   (mapcar (lambda (f) (expand-file-name f root))
+                           '("a.el" "b.el"))))
+    (should (member (expand-file-name "empty" root)
+                    (directory-files-recursively root "" t)))
+    (should-not (member (expand-file-name "empty" root)
+                        (directory-files-recursively root "")))
+    (should (equal (directory-files-recursively root "\\.el\\'" nil nil nil "\\`sub\\'")
+                   (mapcar (lambda (f) (expand-file-name f root))
+                           '("a.el" "b.el"))))))
+
+(ert-deftest test-directory-files-recursively-ignore-files ()
+  (dired-tests--with-tree '(".gitignore" "a.log" "a.el" "sub/b.log" "sub/b.el" ".git/config")
+    (should (equal (directory-files-recursively root "\\.log\\'")
+                   (mapcar (lambda (f) (expand-file-name f root))
+                           '("sub/b.log" "a.log"))))
+    (should-not (directory-files-recursively root "\\.log\\'" nil nil nil nil t))
+    (should-not (directory-files-recursively root "config" nil nil nil nil t))
+    (should (equal (directory-files-recursively root "\\.el\\'" nil nil nil nil t)
+                   (mapcar (lambda (f) (expand-file-name f root))
+                           '("sub/b.el" "a.el"))))))
+
+(ert-deftest test-directory-files-recursively-symlinks ()
+  (skip-unless (not (eq system-type 'windows-nt)))
+  (dired-tests--with-tree '("real/file.el")
+    (make-symbolic-link (expand-file-name "real" root)
+                        (expand-file-name "link" root))
+    (make-symbolic-link root (expand-file-name "real/loop" root))
+    (should (equal (directory-files-recursively root "\\.el\\'")
+                   (list (expand-file-name "real/file.el" root))))
+    (should (equal (directory-files-recursively root "\\.el\\'" nil nil t)
+                   (mapcar (lambda (f) (expand-file-name f root))
+                           '("link/file.el" "real/file.el"))))))
+
+(ert-deftest test-directory-files-recursively-undecodable-names ()
+  ;; Some file systems refuse names that are not valid UTF-8.
+  (skip-unless (not (memq system-type '(windows-nt darwin))))
+  (dired-tests--with-tree '("a.el")
+    (let* ((file-name-coding-system 'utf-8)
+           (name (decode-coding-string "bad\377.el" 'utf-8)))
+      (write-region "" nil (expand-file-name name root))
+      (should (equal (directory-files-recursively root "\\.el\\'")
+                     (mapcar (lambda (f) (expand-file-name f root))
+                             (list "a.el" name)))))))
+
+;; `directory-files' and `directory-files-and-attributes' with COUNT.
+
+(ert-deftest test-directory-files-count ()
+  (dired-tests--with-tree '("a" "b" "c")
+    (should (= (length (directory-files root nil nil nil 2)) 2))
+    (should (= (length (directory-files root nil nil nil 10)) 5))
+    (should-not (directory-files root nil nil nil 0))
+    (should (equal (directory-files root nil "\\`[^.]" nil 3)
+                   '("a" "b" "c")))
+    (should (= (length (directory-files-and-attributes root nil nil nil nil 1))
+               1))
+    (should-error (directory-files root nil nil nil -1))))
+
+(ert-deftest test-directory-files-and-attributes-many-files ()
+  ;; Enough files for the attributes to be collected in parallel.
+  (let ((files (mapcar (lambda (n) (format "file%04d" n))
+                       (number-sequence 1 600))))
+    (dired-tests--with-tree files
+      (let ((result (directory-files-and-attributes
+                     root nil "\\`file" nil 'string)))
+        (should (equal (mapcar #'car result) files))
+        (dolist (entry result)
+          (should (equal (cdr entry)
+                         (file-attributes (expand-file-name (car entry) root)
+                                          'string))))))))
+
+;; `file-attributes' with an extended ID-FORMAT.
+
+(ert-deftest test-file-attributes-extended ()
+  (skip-unless (not (eq system-type 'windows-nt)))
+  (dired-tests--with-tree '("file")
+    (let* ((file (expand-file-name "file" root))
+           (attrs (file-attributes file 'string))
+           (ext (file-attributes file '(string extended))))
+      (should (= (length attrs) 12))
+      (should (= (length ext) 16))
+      (should (equal (butlast ext 4) attrs))
+      (should (equal (file-attribute-user-id ext) (user-login-name)))
+      (should (natnump (file-attribute-blocks ext)))
+      (should (> (file-attribute-block-size ext) 0))
+      (let ((btime (file-attribute-birth-time ext)))
+        (when btime
+          (should-not (time-less-p (file-attribute-modification-time ext)
+                                   btime))))
+      (should (natnump (nth 2 (file-attributes file 'extended)))))))
+
+(ert-deftest test-file-attributes-subsecond-mtime ()
+  (skip-unless (not (eq system-type 'windows-nt)))
+  (dired-tests--with-tree '("file")
+    (let ((file (expand-file-name "file" root))
+          (mtime '(23456 12345 678901 234000)))
+      (set-file-times file mtime)
+      (should (equal (file-attribute-modification-time
+                      (file-attributes file))
+                     mtime))
+      (should (equal (file-attribute-modification-time
+                      (cdr (car (directory-files-and-attributes
+                                 root nil "\\`file\\'"))))
+                     mtime)))))
+
+(ert-deftest test-file-attributes-dangling-symlink ()
+  (skip-unless (not (eq system-type 'windows-nt)))
+  (dired-tests--with-tree '()
+    (let ((link (expand-file-name "link" root)))
+      (make-symbolic-link "no-such-file" link)
+      (should (equal (file-attribute-type (file-attributes link))
+                     "no-such-file")))))
+
+(ert-deftest test-file-system-info ()
+  (skip-unless (not (eq system-type 'windows-nt)))
+  (let ((info (file-system-info temporary-file-directory)))
+    (should (= (length info) 3))
+    (dolist (n info)
+      (should (floatp n)))
+    (should (<= (nth 2 info) (nth 1 info) (nth 0 info))))
+  (should-not (file-system-info "/nonexistent/dired-tests")))
+
+(ert-deftest test-file-disk-usage ()
+  (skip-unless (not (eq system-type 'windows-nt)))
+  (dired-tests--with-tree '("sub/" "empty/")
+    (let ((file (expand-file-name "sub/file" root)))
+      (write-region (make-string 10000 ?x) nil file nil 'silent)
+      (write-region "abc" nil (expand-file-name "top" root) nil 'silent)
+      (should (= (file-disk-usage file t) 10000))
+      (let ((before (file-disk-usage root t)))
+        (should (>= before 10003))
+        ;; Another name for the same file is not counted again.
+        (add-name-to-file file (expand-file-name "link" root))
+        (should (= (file-disk-usage root t) before)))
+      (should (>= (file-disk-usage root) (file-disk-usage file)))
+      (should-error (file-disk-usage (expand-file-name "missing" root))
+                    :type 'file-missing))))
@@ -96,3 +96,315 @@
     (should (equal fixed-time (time-subtract fixed-time 0)))
     (should (equal more-time (time-add fixed-time '(0 10))))
     (should (equal less-time (time-subtract fixed-time '(0 20))))))
+
+(ert-deftest time-arithmetic-legacy-pairs ()
+  ;; By default, a cons of integers is the obsolete (HIGH . LOW).
+  (should (= (float-time (time-add '(0 . 5) 0)) 5.0))
+  (should (= (float-time (time-add '(1 . 5) 0)) 65541.0))
+  (should (time-less-p '(0 . 5) 6))
+  (let ((time-ticks-hz-pairs t))
+    (should (equal (time-add '(0 . 5) 0) '(0 . 5)))))
+
+(ert-deftest time-arithmetic-ticks ()
+  ;; Mixed clock frequencies use a common multiple, and stay exact.
+  (let ((time-ticks-hz-pairs t))
+    (should (equal (time-add '(1 . 3) '(1 . 6)) '(3 . 6)))
+    (should (equal (time-subtract '(1 . 4) '(1 . 6)) '(1 . 12)))
+    (should (equal (time-add '(1 . 1000000000) 1) '(1000000001 . 1000000000)))
+    (should (equal (time-add '(1 . 2) 0.25) '(3 . 4)))
+    ;; Results too large for fixnums are reduced to lowest terms.
+    (should (equal (time-add '(0 . 1) '(23356 61493 123456 789000))
+                   '(1530720309123456789 . 1000000000)))
+    (should (equal (time-subtract '(5 . 1000) '(7 . 1000)) '(-2 . 1000)))
+    (should-error (time-add '(1 . 0) 1))
+    (should-error (time-add '(1.5 . 2) 1))))
+
+(ert-deftest time-comparison-ticks ()
+  (let ((time-ticks-hz-pairs t))
+    (should (time-less-p '(333333333 . 1000000000) '(1 . 3)))
+    (should-not (time-less-p '(1 . 3) '(2 . 6)))
+    (should-not (time-less-p '(2 . 6) '(1 . 3)))
+    (should (time-less-p '(23356 61493 123456 789000)
+                         '(1530720309123456790 . 1000000000)))
+    ;; The float 0.1 is slightly more than 1/10.
+    (should (time-less-p '(1 . 10) 0.1))))
+
+(ert-deftest format-strings ()
+  (let ((s "abc"))
+    (should (eq (format s) s))
+    (should (eq (format "%s" s) s)))
+  (should (equal (format "%5s|%-5s|" "ab" "cd") "   ab|cd   |"))
+  (should (equal (format "%.2s|%.0s|" "abcdef" "abc") "ab||"))
+  (should (equal (format "%s %s" 'sym '(1 "a")) "sym (1 a)"))
+  (should (equal (format "%S %S" 'sym '(1 "a")) "sym (1 \"a\")"))
+  (should (equal (format "%S" "a\"b") "\"a\\\"b\""))
+  (should (equal (format "%c%c" ?a ?λ) "aλ"))
+  (should (equal (format "%3c|%-3c|" ?a ?b) "  a|b  |"))
+  (should (equal (format "100%% %s" 'done) "100% done"))
+  ;; Double-width characters count twice toward the field width.
+  (should (equal (format "%4s|" "日") "  日|"))
+  (should (multibyte-string-p (format "%s%s" "\300" "é")))
+  (should (equal (format "%s%s" "\300" "é") "\300é")))
+
+(ert-deftest format-numbers ()
+  (dolist (case '(("%d" 42 "42")
+                  ("%-6d|" -42 "-42   |")
+                  ("%+d" 5 "+5")
+                  ("% d" 5 " 5")
+                  ("%05d" -42 "-0042")
+                  ("%.5d" 42 "00042")
+                  ("%#o" 8 "010")
+                  ("%#x" 255 "0xff")
+                  ("%#X" 255 "0XFF")
+                  ("%x" -1 "3fffffffffffffff")
+                  ("%#08x" 255 "0x0000ff")
+                  ("%06.2f" 3.14159 "003.14")
+                  ("%e" 12345.678 "1.234568e+04")
+                  ("%.0f" 2.5 "2")
+                  ("%.2f" 1.005 "1.00")
+                  ("%g" 0.0001 "0.0001")
+                  ("%g" 1e-5 "1e-05")
+                  ("%g" 123456789.0 "1.23457e+08")
+                  ("%#g" 1.0 "1.00000")
+                  ("%.3e" 7 "7.000e+00")
+                  ("%d" 2.5 "2")
+                  ("%x" 255.9 "ff")
+                  ("%f" 1.0e+INF "inf")
+                  ("%5.1f|" -0.0 " -0.0|")))
+    (should (equal (format (nth 0 case) (nth 1 case)) (nth 2 case)))))
+
+(ert-deftest format-field-numbers ()
+  (should (equal (format "%2$s %1$s" "a" "b") "b a"))
+  (should (equal (format "%1$s %s %1$S" "a" "b") "a b \"a\""))
+  (should (equal (format "%2$5.2f|%1$-4d|" 7 2.5) " 2.50|7   |")))
+
+(ert-deftest format-errors ()
+  (should-error (format "%") :type 'error)
+  (should-error (format "%d"))
+  (should-error (format "%d" "a"))
+  (should-error (format "%c" 1.0))
+  (should-error (format "%q" 1))
+  (should-error (format "%x" -1.0) :type 'overflow-error)
+  (should-error (format "%c" -1) :type 'wrong-type-argument)
+  (should (equal (condition-case err (format "%q" 1) (error (cadr err)))
+                 "Invalid format operation %q"))
+  (should (equal (condition-case err (format "%3$s" 1) (error (cadr err)))
+                 "Not enough arguments for format string")))
+
+(ert-deftest format-text-properties ()
+  (should (equal-including-properties
+           (format (propertize "%s!" 'face 'bold) "abc")
+           #("abc!" 0 4 (face bold))))
+  (should (equal-including-properties
+           (format (concat "<" (propertize "%d" 'face 'bold) ">") 12345)
+           #("<12345>" 1 6 (face bold))))
+  (should (equal-including-properties
+           (format "<%s>" (propertize "ab" 'face 'italic))
+           #("<ab>" 1 3 (face italic))))
+  (should (equal-including-properties
+           (format "%5s|%-4s|" (propertize "ab" 'x 1) (propertize "cd" 'y 2))
+           #("   ab|cd  |" 3 5 (x 1) 6 10 (y 2)))))
+
+(ert-deftest format-message-quoting ()
+  (let ((text-quoting-style 'curve))
+    (should (equal (format-message "`%s'" "a") "‘a’"))
+    (should (equal (format "`%s'" "a") "`a'")))
+  (let ((text-quoting-style 'straight))
+    (should (equal (format-message "`%s'" "a") "'a'")))
+  (let ((text-quoting-style 'grave))
+    (should (equal (format-message "`%s'" "a") "`a'"))))
+
+(ert-deftest format-named ()
+  (should (equal (format-named "%{name}s: %{count}5d"
+                               '((name . "foo") (count . 3)))
+                 "foo:     3"))
+  (should (equal (format-named "%{name}s: %{count}-4d|"
+                               '(:count 3 :name "foo"))
+                 "foo: 3   |"))
+  (should (equal (format-named "%{x}.2f %{x}S %%" '(("x" . 1.5)))
+                 "1.50 1.5 %"))
+  (should (equal (format-named "%{a}s" '((a . 1) (a . 2))) "1"))
+  (should (equal (format-named "plain" nil) "plain"))
+  (should (equal-including-properties
+               (format-named (propertize "%{a}s!" 'face 'bold)
+                             `((a . ,(propertize "x" 'y 1))))
+               #("x!" 0 1 (face bold y 1) 1 2 (face bold))))
+  (should-error (format-named "%{missing}s" '((a . 1))))
+  (should-error (format-named "%s" '((a . 1))))
+  (should-error (format-named "%{a" '((a . 1))))
+  (should-error (format-named "%{a}d" '(:a)) :type 'wrong-type-argument)
+  (should-error (format-named "%{a}d" '((1 . 2))) :type 'wrong-type-argument))
+
+(ert-deftest replace-buffer-contents-markers ()
+  (with-temp-buffer
+    (insert "foo bar baz qux")
+    (let ((source (current-buffer)))
+      (with-temp-buffer
+        (insert "foo BAR baz")
+        (goto-char 10)
+        (let ((before (copy-marker 2))
+              (after (copy-marker 10)))
+          (should (eq (replace-buffer-contents source) t))
+          (should (equal (buffer-string) "foo bar baz qux"))
+          (should (= (point) 10))
+          (should (= before 2))
+          (should (= after 10)))))))
+
+(ert-deftest replace-buffer-contents-multibyte ()
+  (with-temp-buffer
+    (insert "aäb\n€c")
+    (let ((source (current-buffer)))
+      (with-temp-buffer
+        (insert "aab\nc")
+        (replace-buffer-contents (buffer-name source) nil 10)
+        (should (equal (buffer-string) "aäb\n€c"))))))
+
+;; The change hooks run once, for the whole accessible portion.
+(ert-deftest replace-buffer-contents-change-hooks ()
+  (with-temp-buffer
+    (insert "foo bar baz qux")
+    (let ((source (current-buffer))
+          (calls nil))
+      (with-temp-buffer
+        (insert "foo BAR baz")
+        (add-hook 'before-change-functions
+                  (lambda (beg end) (push (list 'before beg end) calls))
+                  nil t)
+        (add-hook 'after-change-functions
+                  (lambda (beg end len) (push (list 'after beg end len) calls))
+                  nil t)
+        (replace-buffer-contents source)
+        (should (equal (nreverse calls) '((before 1 12) (after 1 16 11))))))))
+
+(ert-deftest replace-buffer-contents-errors ()
+  (with-temp-buffer
+    (should-error (replace-buffer-contents (current-buffer)))
+    (should-error (replace-buffer-contents " *no such buffer*"))
+    (should-error (replace-buffer-contents nil) :type 'wrong-type-argument)
+    (let ((dead (generate-new-buffer "dead")))
+      (kill-buffer dead)
+      (should-error (replace-buffer-contents dead)))))
+
+(ert-deftest replace-buffer-contents-max-secs ()
+  (with-temp-buffer
+    (insert "abc")
+    (let ((source (current-buffer)))
+      (with-temp-buffer
+        (insert "xyz")
+        (let ((marker (copy-marker 2)))
+          (should-not (replace-buffer-contents source 0))
+          (should (equal (buffer-string) "abc"))
+          (should (= marker 1)))))))
+
+(ert-deftest compare-buffer-substrings-case-fold ()
+  (with-temp-buffer
+    (insert "abcABD")
+    (let ((case-fold-search nil))
+      (should (= (compare-buffer-substrings nil 1 4 nil 4 7) 1))
+      (should (= (compare-buffer-substrings nil 4 7 nil 1 4) -1)))
+    (let ((case-fold-search t))
+      (should (= (compare-buffer-substrings nil 1 4 nil 4 7) -3))
+      (should (= (compare-buffer-substrings nil 4 1 nil 7 4) -3))
+      (should (= (compare-buffer-substrings nil 1 3 nil 4 6) 0))
+      (should (= (compare-buffer-substrings nil 1 3 nil 4 7) -3))
+      (should (= (compare-buffer-substrings nil 4 7 nil 1 3) 3)))
+    (should-error (compare-buffer-substrings nil 0 4 nil 1 2)
+                  :type 'args-out-of-range)))
+
+(ert-deftest string-diff-chars ()
+  (should (equal (string-diff "foo BAR baz" "foo bar baz qux")
+                 '((change 4 7 4 7) (insert 11 11 11 15))))
+  (should (equal (string-diff "abc" "abc") nil))
+  (should (equal (string-diff "abc" "") '((delete 0 3 0 0))))
+  (should (equal (string-diff "ä€" "a€" 'char) '((change 0 1 0 1)))))
+
+(ert-deftest string-diff-lines ()
+  (should (equal (string-diff "one\ntwo\nthree\n" "one\n2\nthree\nfour" 'line)
+                 '((change 4 8 4 6) (insert 14 14 12 16))))
+  (should-error (string-diff "a" "b" 'word)))
+
+(ert-deftest string-diff-buffers ()
+  (with-temp-buffer
+    (insert "xx hello world")
+    (let ((buffer (current-buffer)))
+      (should (equal (string-diff (list buffer 4 15) "hello there")
+                     '((change 10 12 6 9) (change 13 15 10 11))))
+      (narrow-to-region 4 9)
+      (should (equal (string-diff buffer "help") '((change 7 9 3 4))))
+      (should-error (string-diff (list buffer 1 9) "x")
+                    :type 'args-out-of-range)
+      (should-error (string-diff 42 "x") :type 'wrong-type-argument))))
+
+(ert-deftest transpose-regions-markers ()
+  (with-temp-buffer
+    (insert "aé-xyz€")
+    (put-text-property 4 7 'face 'bold)
+    (let ((m1 (copy-marker 2))
+          (m2 (copy-marker 5)))
+      (goto-char 3)
+      (transpose-regions 1 3 4 7)
+      (should (equal (buffer-string) "xyz-aé€"))
+      (should (equal (get-text-property 1 'face) 'bold))
+      (should (equal (get-text-property 3 'face) 'bold))
+      (should-not (get-text-property 5 'face))
+      (should (= m1 6))
+      (should (eq (char-after m1) ?é))
+      (should (= m2 2))
+      (should (eq (char-after m2) ?y))
+      (should (= (point) 4))
+      (should (eq (char-after) ?-)))))
+
+(ert-deftest transpose-regions-leave-markers ()
+  (with-temp-buffer
+    (insert "éa-b€")
+    (let ((m (copy-marker 5)))
+      (goto-char 2)
+      (transpose-regions 4 6 1 2 t)
+      (should (equal (buffer-string) "b€a-é"))
+      (should (= m 5))
+      (should (eq (char-after m) ?é))
+      (should (= (point) 2))
+      (should (eq (char-after) ?€))
+      (should (= (position-bytes (point)) 2)))
+    (should-error (transpose-regions 1 3 2 4))
+    (transpose-regions 1 1 1 3)
+    (should (equal (buffer-string) "b€a-é"))))
+
+(ert-deftest subst-char-in-region-multibyte ()
+  (with-temp-buffer
+    (buffer-enable-undo)
+    (insert "aébéc")
+    (subst-char-in-region 1 5 ?é ?è)
+    (should (equal (buffer-string) "aèbèc"))
+    (should (consp buffer-undo-list))
+    (should-error (subst-char-in-region 1 5 ?è ?x))
+    (set-buffer-modified-p nil)
+    (let ((undo-list buffer-undo-list))
+      (subst-char-in-region 1 (point-max) ?b ?x t)
+      (should (equal (buffer-string) "aèxèc"))
+      (should (eq buffer-undo-list undo-list))
+      (should-not (buffer-modified-p)))))
+
+(ert-deftest translate-region-internal-string ()
+  (with-temp-buffer
+    (insert "banana€")
+    (let ((table (apply #'string (number-sequence 0 127))))
+      (aset table ?a ?o)
+      (should (= (translate-region-internal 1 (point-max) table) 3))
+      (should (equal (buffer-string) "bonono€"))))
+  ;; A multibyte table only maps as many characters as it has.
+  (with-temp-buffer
+    (insert 1 4 5)
+    (should (= (translate-region-internal 1 (point-max) "ééé") 1))
+    (should (equal (buffer-string) (string ?é 4 5)))))
+
+(ert-deftest translate-region-internal-char-table ()
+  (with-temp-buffer
+    (insert "abcab quq")
+    (let ((table (make-char-table 'translation-table)))
+      (aset table ?a ?é)
+      (aset table ?c [?x ?y])
+      (aset table ?q '(([?q ?u] . ?k)))
+      (should (= (translate-region-internal 1 (point-max) table) 5))
+      (should (equal (buffer-string) "ébxyéb kq")))
+    (should-error (translate-region-internal 1 2 (make-char-table 'foo)))))
@@ -39,3 +39,202 @@
       (should-not (file-name-case-insensitive-p file)))
     (when (eq system-type 'darwin)
       (should (file-name-case-insensitive-p file)))))
+
+(defmacro fileio-tests--with-dir (&rest body)
+  "Evaluate BODY with `dir' bound to a new temporary directory."
+  (declare (indent 0))
+  `(let ((dir (make-temp-file "fileio-tests" t)))
+     (unwind-protect
+         (progn ,@body)
+       (delete-directory dir t))))
+
+(defun fileio-tests--contents (file)
+  (with-temp-buffer
+    (insert-file-contents-literally file)
+    (buffer-string)))
+
+(ert-deftest test-copy-file ()
+  (fileio-tests--with-dir
+    (let ((from (expand-file-name "from" dir))
+          (to (expand-file-name "to" dir)))
+      (write-region "hello\n" nil from nil 'silent)
+      (set-file-modes from #o640)
+      (set-file-times from '(1000000000 0))
+      (copy-file from to nil t nil t)
+      (should (equal (fileio-tests--contents to) "hello\n"))
+      (should (= (file-modes to) #o640))
+      (should (= (float-time (file-attribute-modification-time
+                              (file-attributes to)))
+                 1000000000))
+      ;; Overwriting a longer file truncates it.
+      (write-region "a much longer text\n" nil to nil 'silent)
+      (should-error (copy-file from to) :type 'file-already-exists)
+      (copy-file from to t)
+      (should (equal (fileio-tests--contents to) "hello\n"))
+      (should-error (copy-file from from t) :type 'file-error)
+      ;; A directory name as NEWNAME copies into that directory.
+      (make-directory-internal (expand-file-name "sub" dir))
+      (copy-file from (file-name-as-directory (expand-file-name "sub" dir)))
+      (should (file-regular-p (expand-file-name "sub/from" dir)))
+      (should-error (copy-file dir to t) :type 'file-error)
+      ;; New files are written under a temporary name first, which
+      ;; must not be left behind.
+      (should (equal (directory-files dir nil "\\`[^.]") '("from" "sub" "to"))))))
+
+(ert-deftest test-copy-file-large ()
+  (fileio-tests--with-dir
+    (let ((from (expand-file-name "from" dir))
+          (to (expand-file-name "to" dir))
+          (text (make-string 200000 ?x)))
+      (with-temp-buffer
+        (set-buffer-multibyte nil)
+        (dotimes (i 256)
+          (insert i))
+        (insert text)
+        (write-region nil nil from nil 'silent))
+      (copy-file from to)
+      (should (equal (fileio-tests--contents from)
+                     (fileio-tests--contents to))))))
+
+(ert-deftest test-rename-file ()
+  (fileio-tests--with-dir
+    (let ((from (expand-file-name "from" dir))
+          (to (expand-file-name "to" dir)))
+      (write-region "hello\n" nil from nil 'silent)
+      (rename-file from to)
+      (should-not (file-exists-p from))
+      (should (equal (fileio-tests--contents to) "hello\n"))
+      (write-region "again\n" nil from nil 'silent)
+      (should-error (rename-file from to) :type 'file-already-exists)
+      (rename-file from to t)
+      (should (equal (fileio-tests--contents to) "again\n"))
+      (should-error (rename-file from to t) :type 'file-missing))))
+
+(ert-deftest test-delete-file ()
+  (fileio-tests--with-dir
+    (let ((file (expand-file-name "file" dir))
+          (link (expand-file-name "link" dir)))
+      (write-region "hello\n" nil file nil 'silent)
+      (make-symbolic-link file link)
+      (delete-file link)
+      (should-not (file-symlink-p link))
+      (should (file-exists-p file))
+      (delete-file file)
+      (should-not (file-exists-p file))
+      ;; Deleting a missing file is not an error.
+      (delete-file file)
+      (should-error (delete-file dir) :type 'file-error))))
+
+(ert-deftest test-make-and-delete-directory-internal ()
+  (fileio-tests--with-dir
+    (let ((sub (expand-file-name "sub" dir)))
+      (make-directory-internal sub)
+      (should (file-directory-p sub))
+      (should-error (make-directory-internal sub) :type 'file-already-exists)
+      (write-region "" nil (expand-file-name "file" sub) nil 'silent)
+      (should-error (delete-directory-internal sub) :type 'file-error)
+      (delete-file (expand-file-name "file" sub))
+      (delete-directory-internal (file-name-as-directory sub))
+      (should-not (file-exists-p sub))
+      (should-error (delete-directory-internal sub) :type 'file-missing))))
+
+(defun fileio-tests--inode (file)
+  (file-attribute-inode-number (file-attributes file)))
+
+(ert-deftest test-write-region-replaces-file ()
+  (fileio-tests--with-dir
+    (let ((file (expand-file-name "file" dir)))
+      (write-region "old\n" nil file nil 'silent)
+      (set-file-modes file #o640)
+      (let ((inode (fileio-tests--inode file))
+            (write-region-in-place nil))
+        (write-region "new\n" nil file nil 'silent)
+        (should (equal (fileio-tests--contents file) "new\n"))
+        (should-not (equal (fileio-tests--inode file) inode))
+        (should (equal (file-modes file) #o640))
+        ;; No temporary file is left behind.
+        (should (equal (directory-files dir nil "\\`[^.]") '("file")))))))
+
+(ert-deftest test-write-region-in-place ()
+  (fileio-tests--with-dir
+    (let ((file (expand-file-name "file" dir))
+          (link (expand-file-name "link" dir)))
+      (write-region "old\n" nil file nil 'silent)
+      (add-name-to-file file link)
+      (let ((inode (fileio-tests--inode file)))
+        ;; A file with several names is overwritten in place by default.
+        (write-region "new\n" nil file nil 'silent)
+        (should (equal (fileio-tests--inode file) inode))
+        (should (equal (fileio-tests--contents link) "new\n"))
+        (let ((write-region-in-place t))
+          (delete-file link)
+          (write-region "newer\n" nil file nil 'silent)
+          (should (equal (fileio-tests--inode file) inode)))
+        ;; Appending always happens in place.
+        (let ((write-region-in-place nil))
+          (write-region "more\n" nil file t 'silent)
+          (should (equal (fileio-tests--inode file) inode))
+          (should (equal (fileio-tests--contents file) "newer\nmore\n")))))))
+
+(ert-deftest test-write-region-excl ()
+  (fileio-tests--with-dir
+    (let ((file (expand-file-name "file" dir)))
+      (write-region "old\n" nil file nil 'silent nil 'excl)
+      (should (equal (fileio-tests--contents file) "old\n"))
+      (should-error (write-region "new\n" nil file nil 'silent nil 'excl)
+                    :type 'file-already-exists)
+      (should (equal (fileio-tests--contents file) "old\n"))
+      (should (equal (directory-files dir nil "\\`[^.]") '("file"))))))
+
+(ert-deftest test-write-region-visit ()
+  (fileio-tests--with-dir
+    (let ((file (expand-file-name "file" dir)))
+      (with-temp-buffer
+        (insert "text\n")
+        (write-region nil nil file nil t)
+        (should-not (buffer-modified-p))
+        (should (equal buffer-file-name file))
+        (should (verify-visited-file-modtime (current-buffer)))))))
+
+(defun fileio-tests--xattrs-supported-p (file)
+  "Whether user extended attributes can be set on FILE."
+  (and (fboundp 'file-xattr-set)
+       (condition-case nil
+           (progn (file-xattr-set file "user.fileio-tests" "")
+                  (file-xattr-remove file "user.fileio-tests"))
+         (file-error nil))))
+
+(ert-deftest test-file-xattrs ()
+  (fileio-tests--with-dir
+    (let ((file (expand-file-name "file" dir)))
+      (write-region "" nil file nil 'silent)
+      (skip-unless (fileio-tests--xattrs-supported-p file))
+      (should-not (file-xattr-get file "user.comment"))
+      (file-xattr-set file "user.comment" "hello")
+      (should (equal (file-xattr-get file "user.comment") "hello"))
+      (should (member "user.comment" (file-xattr-list file)))
+      (should (file-xattr-remove file "user.comment"))
+      (should-not (file-xattr-remove file "user.comment"))
+      (should-not (member "user.comment" (file-xattr-list file)))
+      (should-error (file-xattr-get (expand-file-name "missing" dir)
+                                    "user.comment")
+                    :type 'file-missing))))
+
+(ert-deftest test-file-extended-attributes ()
+  (fileio-tests--with-dir
+    (let ((from (expand-file-name "from" dir))
+          (to (expand-file-name "to" dir)))
+      (write-region "" nil from nil 'silent)
+      (write-region "" nil to nil 'silent)
+      (skip-unless (fileio-tests--xattrs-supported-p from))
+      (file-xattr-set from "user.comment" "hello")
+      (let ((attributes (file-extended-attributes from)))
+        (should (assq 'acl attributes))
+        (should (assq 'selinux-context attributes))
+        (should (equal (cdr (assq 'xattrs attributes))
+                       '(("user.comment" . "hello"))))
+        (should (set-file-extended-attributes to attributes))
+        (should (equal (file-xattr-get to "user.comment") "hello")))
+      ;; Saving over a file keeps its attributes.
+      (write-region "text" nil from nil 'silent)
+      (should (equal (file-xattr-get from "user.comment") "hello")))))
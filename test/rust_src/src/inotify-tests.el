@@ -0,0 +1,118 @@
+;;; inotify-tests.el --- tests for inotify.rs functions
+
+;;; Code:
+
+(require 'ert)
+(require 'filenotify)
+
+(defmacro inotify-tests--with-dir (&rest body)
+  "Evaluate BODY with `dir' bound to a new temporary directory."
+  (declare (indent 0))
+  `(let ((dir (make-temp-file "inotify-tests" t)))
+     (unwind-protect
+         (progn ,@body)
+       (delete-directory dir t))))
+
+(defun inotify-tests--wait-for (pred)
+  "Read events until PRED returns non-nil, for at most ten seconds."
+  (let ((deadline (+ (float-time) 10)))
+    (while (and (not (funcall pred)) (< (float-time) deadline))
+      (read-event nil nil 0.1))
+    (funcall pred)))
+
+(defun inotify-tests--watch-directory ()
+  "Watch a temporary directory, touching and changing a file in it.
+Return the aspects reported for that file."
+  (inotify-tests--with-dir
+    (let* ((file (expand-file-name "foo" dir))
+           (aspects nil)
+           (wd (inotify-add-watch
+                dir '(create modify delete)
+                (lambda (event)
+                  (when (equal (nth 2 event) "foo")
+                    (setq aspects (append aspects (nth 1 event))))))))
+      (unwind-protect
+          (progn
+            (should (inotify-valid-p wd))
+            (write-region "foo" nil file nil 'silent)
+            (should (inotify-tests--wait-for
+                     (lambda () (memq 'create aspects))))
+            ;; Make sure the change is not lost within the polled
+            ;; file's mtime resolution.
+            (write-region "foo bar" nil file nil 'silent)
+            (should (inotify-tests--wait-for
+                     (lambda () (memq 'modify aspects))))
+            (delete-file file)
+            (should (inotify-tests--wait-for
+                     (lambda () (memq 'delete aspects)))))
+        (inotify-rm-watch wd))
+      (should-not (inotify-valid-p wd))
+      aspects)))
+
+(ert-deftest inotify-tests-watch-directory ()
+  (skip-unless (featurep 'inotify))
+  (inotify-tests--watch-directory))
+
+(ert-deftest inotify-tests-watch-directory-polling ()
+  (skip-unless (featurep 'inotify))
+  (let ((inotify-use-polling t)
+        (inotify-polling-interval 0.1))
+    (inotify-tests--watch-directory)))
+
+(ert-deftest inotify-tests-polling-deleted-file ()
+  (skip-unless (featurep 'inotify))
+  (inotify-tests--with-dir
+    (let* ((inotify-use-polling t)
+           (inotify-polling-interval 0.1)
+           (file (expand-file-name "foo" dir))
+           (events nil)
+           wd)
+      (write-region "foo" nil file nil 'silent)
+      (setq wd (inotify-add-watch file t (lambda (event)
+                                           (push event events))))
+      (should (inotify-valid-p wd))
+      (delete-file file)
+      (should (inotify-tests--wait-for
+               (lambda () (not (inotify-valid-p wd)))))
+      (should (memq 'delete-self
+                    (apply #'append (mapcar (lambda (event) (nth 1 event))
+                                            events)))))))
+
+(ert-deftest inotify-tests-polling-shares-descriptor ()
+  (skip-unless (featurep 'inotify))
+  (inotify-tests--with-dir
+    (let* ((inotify-use-polling t)
+           (wd1 (inotify-add-watch dir 'create #'ignore))
+           (wd2 (inotify-add-watch dir 'delete #'ignore)))
+      (should (equal (car wd1) (car wd2)))
+      (should-not (equal wd1 wd2))
+      (inotify-rm-watch wd1)
+      (should-not (inotify-valid-p wd1))
+      (should (inotify-valid-p wd2))
+      (inotify-rm-watch wd2)
+      (should-not (inotify-valid-p wd2)))))
+
+(ert-deftest inotify-tests-polling-missing-file ()
+  (skip-unless (featurep 'inotify))
+  (let ((inotify-use-polling t))
+    (should-error (inotify-add-watch "/nonexistent/inotify-tests" t #'ignore)
+                  :type 'file-notify-error)))
+
+(ert-deftest inotify-tests-file-notify ()
+  (skip-unless (featurep 'inotify))
+  (inotify-tests--with-dir
+    (let* ((inotify-use-polling t)
+           (inotify-polling-interval 0.1)
+           (actions nil)
+           (desc (file-notify-add-watch
+                  dir '(change)
+                  (lambda (event) (push (nth 1 event) actions)))))
+      (should (file-notify-valid-p desc))
+      (write-region "foo" nil (expand-file-name "foo" dir) nil 'silent)
+      (should (inotify-tests--wait-for (lambda () (memq 'created actions))))
+      (file-notify-rm-watch desc)
+      (should-not (file-notify-valid-p desc)))))
+
+(provide 'inotify-tests)
+
+;;; inotify-tests.el ends here
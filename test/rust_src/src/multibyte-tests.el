@@ -0,0 +1,38 @@
+;;; multibyte-tests.el --- tests for multibyte.rs functions -*- lexical-binding: t -*-
+
+;;; Code:
+
+(require 'ert)
+
+(ert-deftest multibyte-tests-string-fuzzy-match ()
+  (let ((completion-ignore-case nil))
+    (should (equal (string-fuzzy-match "fb" "foo-bar") '(43 0 4)))
+    (should (equal (cdr (string-fuzzy-match "fap" "find-file-at-point"))
+                   '(5 10 13)))
+    (should (equal (string-fuzzy-match "" "abc") '(0)))
+    (should-not (string-fuzzy-match "ba" "abc"))
+    (should-not (string-fuzzy-match "FB" "foo-bar"))
+    (should (equal (cdr (string-fuzzy-match "éé" "été")) '(0 2)))))
+
+(ert-deftest multibyte-tests-string-fuzzy-match-ignore-case ()
+  (let ((completion-ignore-case t))
+    (should (equal (string-fuzzy-match "FB" "foo-bar") '(43 0 4)))
+    (should (equal (cdr (string-fuzzy-match "ÉT" "Été")) '(0 1)))))
+
+(ert-deftest multibyte-tests-completion-fuzzy-filter ()
+  (let ((completion-ignore-case nil))
+    (should (equal (completion-fuzzy-filter
+                    "bar" '("foobar" "baz" "barfoo" "foo-bar"))
+                   '("barfoo" "foo-bar" "foobar")))
+    ;; Candidates with the same score are sorted shortest first.
+    (should (equal (completion-fuzzy-filter "ab" '("abc" "xyz" "ab"))
+                   '("ab" "abc")))
+    (should (equal (completion-fuzzy-filter
+                    "fb" '((foobar . 1) ("fb" . 2) foo-bar))
+                   '(("fb" . 2) foo-bar (foobar . 1))))
+    (should-not (completion-fuzzy-filter "q" '("abc" "xyz")))
+    (should-error (completion-fuzzy-filter "a" '("abc" . "abd")))))
+
+(provide 'multibyte-tests)
+
+;;; multibyte-tests.el ends here
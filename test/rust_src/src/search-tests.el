@@ -0,0 +1,387 @@
+;;; search-tests.el --- tests for search.rs functions -*- lexical-binding: t -*-
+
+;;; Code:
+
+(require 'ert)
+
+(ert-deftest search-tests-string-match ()
+  (should (= (string-match "b+" "abbbc") 1))
+  (should (= (match-end 0) 4))
+  (should-not (string-match "x" "abc"))
+  (should (= (string-match "a\\(b*\\)\\(x\\)?" "zzabb") 2))
+  (should (equal (match-data) '(2 5 3 5)))
+  (should (= (string-match "é+" "aéé") 1))
+  (should (= (match-end 0) 3)))
+
+(ert-deftest search-tests-string-match-start ()
+  (should (= (string-match "a" "abca" 1) 3))
+  (should (= (string-match "a" "abca" -1) 3))
+  (should-not (string-match "b" "abca" -2))
+  (should-error (string-match "a" "abc" 4) :type 'args-out-of-range)
+  (should-error (string-match "a" "abc" -4) :type 'args-out-of-range))
+
+(ert-deftest search-tests-string-match-case-fold ()
+  (let ((case-fold-search t))
+    (should (= (string-match "ABC" "xabc") 1))
+    (should (= (string-match "[A-C]+" "xabc") 1))
+    (should (= (string-match "ÉTÉ" "été") 0)))
+  (let ((case-fold-search nil))
+    (should-not (string-match "ABC" "xabc"))))
+
+(ert-deftest search-tests-backrefs-and-intervals ()
+  (should (= (string-match "\\(a+\\)b\\1" "aaba aabaa") 1))
+  (should (equal (match-data) '(1 4 1 2)))
+  (should (= (string-match "\\(a+\\)b\\1\\'" "aabaa") 0))
+  (should (equal (match-data) '(0 5 0 2)))
+  (should (= (string-match "x\\{2,3\\}" "xxxxx") 0))
+  (should (= (match-end 0) 3))
+  (should (= (string-match "a*?b" "aab") 0))
+  (should (= (string-match "\\(?:ab\\)+" "ababa") 0))
+  (should (= (match-end 0) 4))
+  (should (= (string-match "\\(?2:b\\)" "ab") 1))
+  (should-not (match-beginning 1))
+  (should (= (match-beginning 2) 1)))
+
+(ert-deftest search-tests-syntax-and-categories ()
+  (with-temp-buffer
+    (should (= (string-match "\\s-+" "ab  cd") 2))
+    (should (= (string-match "\\sw+" "  foo") 2))
+    (should (= (string-match "\\cg+" "abc αβγ") 4))
+    (should (= (match-end 0) 7))
+    (should (= (string-match "[[:digit:]]+" "ab123") 2))
+    (should (= (string-match "[[:space:]]" "ab c") 2))))
+
+(ert-deftest search-tests-boundaries ()
+  (with-temp-buffer
+    (emacs-lisp-mode)
+    (should (= (string-match "\\_<foo-bar\\_>" "(foo-bar-baz foo-bar)") 13))
+    (should (= (string-match "\\bbar\\b" "foobar bar") 7))
+    (should (= (string-match "\\<b" "ab b") 3))
+    (should (= (string-match "o\\>" "foo fo") 2))
+    (should (= (string-match "\\`a" "aa") 0))
+    (should-not (string-match "\\`a" "aa" 1))
+    (should (= (string-match "a\\'" "aa") 1))))
+
+(ert-deftest search-tests-posix-string-match ()
+  (should (= (string-match "a\\|ab" "ab") 0))
+  (should (= (match-end 0) 1))
+  (should (= (posix-string-match "a\\|ab" "ab") 0))
+  (should (= (match-end 0) 2)))
+
+(ert-deftest search-tests-invalid-regexp ()
+  (should-error (string-match "\\(" "a") :type 'invalid-regexp)
+  (should-error (string-match "[a" "a") :type 'invalid-regexp)
+  (should-error (string-match "a\\{2,1\\}" "a") :type 'invalid-regexp)
+  (should-error (looking-at "\\1") :type 'invalid-regexp))
+
+(ert-deftest search-tests-search-spaces-regexp ()
+  (let ((search-spaces-regexp "[ \t]+"))
+    (should (= (string-match "a b" "a \t b") 0))
+    (should (= (match-end 0) 5)))
+  (should-not (string-match "a b" "a \t b")))
+
+(ert-deftest search-tests-inhibit-changing-match-data ()
+  (string-match "b" "abc")
+  (let ((inhibit-changing-match-data t))
+    (should (= (string-match "c" "abc") 2)))
+  (should (equal (match-data) '(1 2))))
+
+(ert-deftest search-tests-looking-at ()
+  (with-temp-buffer
+    (insert "foo bar")
+    (goto-char 5)
+    ;; Put the gap in the middle of the text to be matched.
+    (insert "x")
+    (delete-char -1)
+    (goto-char 1)
+    (should (looking-at "\\(fo+\\) \\(b\\)"))
+    (should (equal (match-data t) '(1 6 1 4 5 6)))
+    (should-not (looking-at "bar"))
+    (goto-char 5)
+    (should (looking-at "bar\\'"))
+    (narrow-to-region 1 6)
+    (should-not (looking-at "bar"))
+    (should (looking-at "b\\'"))))
+
+(ert-deftest search-tests-posix-looking-at ()
+  (with-temp-buffer
+    (insert "abcd")
+    (goto-char 1)
+    (should (looking-at "a\\|abc"))
+    (should (= (match-end 0) 2))
+    (should (posix-looking-at "a\\|abc"))
+    (should (= (match-end 0) 4))))
+
+(ert-deftest search-tests-re-search-forward ()
+  (with-temp-buffer
+    (insert "one two three two one")
+    (goto-char 1)
+    (should (= (re-search-forward "t\\(wo\\)") 8))
+    (should (= (point) 8))
+    (should (equal (match-data t) '(5 8 6 8)))
+    (should (= (re-search-forward "o" nil nil 2) 20))
+    (should-not (re-search-forward "four" nil t))
+    (should (= (point) 20))
+    (should-not (re-search-forward "four" nil 'move))
+    (should (= (point) (point-max)))
+    (goto-char 1)
+    (should-error (re-search-forward "four") :type 'search-failed)
+    (should-not (re-search-forward "three" 10 t))
+    (should-error (re-search-forward "t" 0) :type 'error)))
+
+(ert-deftest search-tests-re-search-backward ()
+  (with-temp-buffer
+    (insert "one two three two one")
+    (should (= (re-search-backward "t\\(wo\\)") 15))
+    (should (= (point) 15))
+    (should (equal (match-data t) '(15 18 16 18)))
+    (should (= (re-search-backward "o" nil nil 2) 1))
+    ;; A match found backward may not extend past the origin.
+    (goto-char 6)
+    (should (= (re-search-backward "[a-z]+") 5))
+    (should (= (match-end 0) 6))
+    (goto-char (point-max))
+    (should (= (re-search-forward "one" nil nil -1) 19))))
+
+(ert-deftest search-tests-re-search-empty ()
+  (with-temp-buffer
+    (insert "abc")
+    (goto-char 2)
+    (should (= (re-search-forward "") 2))
+    (should (equal (match-data t) '(2 2)))
+    (should (= (re-search-forward "b" nil nil 0) 2))))
+
+(ert-deftest search-tests-posix-search ()
+  (with-temp-buffer
+    (insert "xabcd")
+    (goto-char 1)
+    (should (= (posix-search-forward "a\\|abc") 5))
+    (goto-char (point-max))
+    (should (= (posix-search-backward "b\\|bcd") 3))
+    (should (= (match-end 0) 6))))
+
+(ert-deftest search-tests-syntax-table-property ()
+  (with-temp-buffer
+    (insert "a-b")
+    (put-text-property 2 3 'syntax-table (string-to-syntax "w"))
+    (goto-char 1)
+    (should-not (looking-at "\\sw+\\'"))
+    (let ((parse-sexp-lookup-properties t))
+      (should (looking-at "\\sw+\\'")))))
+
+(ert-deftest search-tests-search-forward-any ()
+  (with-temp-buffer
+    (insert "TODO: fix the FIXME here, then todo more")
+    (goto-char 10)
+    ;; Put the gap in the middle of the text to be searched.
+    (insert "x")
+    (delete-char -1)
+    (goto-char 1)
+    (let ((case-fold-search nil))
+      (should (= (search-forward-any '("FIXME" "TODO")) 1))
+      (should (= (point) 5))
+      (should (equal (match-data t) '(1 5)))
+      (should (= (search-forward-any '("FIX" "FIXME" "TODO")) 1))
+      (should (equal (match-data t) '(15 20)))
+      (should-not (search-forward-any '("todo") 30 t))
+      (should (= (point) 20))
+      (should-not (search-forward-any '("nothing") nil 'move))
+      (should (= (point) (point-max)))
+      (goto-char 1)
+      (should-error (search-forward-any '("nothing")) :type 'search-failed)
+      (should-error (search-forward-any '("x") 0) :type 'error))
+    (goto-char 2)
+    (let ((case-fold-search t))
+      (should (= (search-forward-any '("xyz" "TODO")) 1))
+      (should (equal (match-data t) '(32 36))))))
+
+(ert-deftest search-tests-search-forward-any-multibyte ()
+  (with-temp-buffer
+    (insert "un été à Zürich")
+    (goto-char 1)
+    (let ((case-fold-search t))
+      (should (= (search-forward-any '("ZÜRICH" "ÉTÉ")) 1))
+      (should (equal (match-data t) '(4 7)))
+      (should (= (search-forward-any '("ZÜRICH" "ÉTÉ")) 0))
+      (should (= (point) (point-max))))))
+
+;; Match data.
+
+(ert-deftest search-tests-match-beginning-and-end ()
+  (string-match "\\(a\\)\\|\\(b\\)" "xb")
+  (should (= (match-beginning 0) 1))
+  (should (= (match-end 2) 2))
+  (should-not (match-beginning 1))
+  (should-not (match-end 100))
+  (should-error (match-beginning -1) :type 'args-out-of-range)
+  (should-error (match-end 'a) :type 'wrong-type-argument))
+
+(ert-deftest search-tests-match-data ()
+  (with-temp-buffer
+    (insert "foo bar")
+    (goto-char 1)
+    (re-search-forward "\\(x\\)?\\(bar\\)")
+    (let ((data (match-data)))
+      (should (equal (mapcar (lambda (m) (and m (marker-position m))) data)
+                     '(5 8 nil nil 5 8)))
+      (should (eq (marker-buffer (car data)) (current-buffer))))
+    (should (equal (match-data t) (list 5 8 nil nil 5 8 (current-buffer))))
+    (let ((reuse (list 'a 'b 'c 'd 'e 'f 'g 'h)))
+      (should (eq (match-data t reuse) reuse))
+      (should (equal reuse (list 5 8 nil nil 5 8 (current-buffer) nil))))
+    (let ((reuse (list 'a)))
+      (match-data t reuse)
+      (should (equal reuse (list 5 8 nil nil 5 8 (current-buffer)))))
+    (let ((marker (copy-marker 3)))
+      (match-data t (list marker) t)
+      (should-not (marker-buffer marker)))))
+
+(ert-deftest search-tests-set-match-data ()
+  (set-match-data '(1 3 nil nil 2 3))
+  (should (equal (match-data) '(1 3 nil nil 2 3)))
+  (should (= (match-beginning 2) 2))
+  (with-temp-buffer
+    (insert "abc")
+    (set-match-data (list 1 2 (current-buffer)))
+    (should (equal (match-data t) (list 1 2 (current-buffer))))
+    (let ((markers (list (copy-marker 2) (copy-marker 3))))
+      (set-match-data markers t)
+      (should (equal (match-data t) (list 2 3 (current-buffer))))
+      (should (equal markers '(nil nil)))))
+  (should-error (set-match-data '(1 a)) :type 'wrong-type-argument)
+  (should-error (set-match-data 'a) :type 'wrong-type-argument))
+
+(ert-deftest search-tests-save-match-data ()
+  (string-match "b" "abc")
+  (save-match-data
+    (should (string-match "c" "abc")))
+  (should (equal (match-data) '(1 2))))
+
+(ert-deftest search-tests-replace-match-string ()
+  (with-temp-buffer
+    (should (= (string-match "\\(o+\\)\\(x\\)?" "foo bar") 1))
+    (should (equal (replace-match "0" t t "foo bar") "f0 bar"))
+    (should (equal (replace-match "<\\&|\\1|\\2|\\\\|\\?>" t nil "foo bar")
+                   "f<oo|oo||\\|\\?> bar"))
+    (should (equal (replace-match "a" t t "foo bar" 1) "fa bar"))
+    (should (equal (replace-match "\\1" t t "foo bar") "f\\1 bar"))
+    (should-error (replace-match "\\x" t nil "foo bar"))
+    (should-error (replace-match "a" t t "foo bar" 2) :type 'args-out-of-range)
+    (should-error (replace-match "a" t t "foo bar" 100)
+                  :type 'args-out-of-range)
+    (should-error (replace-match "a" t t "fo") :type 'args-out-of-range)))
+
+(ert-deftest search-tests-replace-match-case ()
+  (with-temp-buffer
+    (let ((case-fold-search t))
+      (string-match "foo bar" "x FOO BAR")
+      (should (equal (replace-match "new text" nil nil "x FOO BAR")
+                     "x NEW TEXT"))
+      (string-match "foo bar" "x Foo Bar")
+      (should (equal (replace-match "new text" nil nil "x Foo Bar")
+                     "x New Text"))
+      (should (equal (replace-match "new text" t nil "x Foo Bar")
+                     "x new text"))
+      (string-match "foo bar" "x Foo bar")
+      (should (equal (replace-match "new text" nil nil "x Foo bar")
+                     "x new text"))
+      (string-match "x" "X")
+      (should (equal (replace-match "yz" nil nil "X") "YZ")))))
+
+(ert-deftest search-tests-replace-match-buffer ()
+  (with-temp-buffer
+    (insert "one Two THREE été")
+    (goto-char 6)
+    ;; Put the gap in the middle of the text to be replaced.
+    (insert "x")
+    (delete-char -1)
+    (goto-char 1)
+    (let ((case-fold-search t))
+      (re-search-forward "t\\(wo\\)")
+      (should-not (replace-match "six"))
+      (should (equal (buffer-string) "one Six THREE été"))
+      (should (= (point) 8))
+      (re-search-forward "three")
+      (replace-match "x-\\&" t)
+      (should (equal (buffer-string) "one Six x-THREE été"))
+      (should (= (point) 16))
+      (goto-char 1)
+      (re-search-forward "\\(o\\)ne")
+      (goto-char (point-max))
+      (replace-match "1" t t nil 1)
+      (should (equal (buffer-string) "1ne Six x-THREE été"))
+      (should (= (point) 2))
+      (should (equal (match-data t) (list 1 4 1 2 (current-buffer))))
+      (re-search-forward "\\(é\\)t\\(é\\)")
+      (replace-match "\\2\\\\\\1" t)
+      (should (equal (buffer-string) "1ne Six x-THREE é\\é"))
+      (goto-char 1)
+      (re-search-forward "six")
+      (should-error (replace-match "\\?"))
+      (should (equal (buffer-string) "1ne Six x-THREE é\\é")))))
+
+(ert-deftest search-tests-match-substitute-replacement ()
+  (with-temp-buffer
+    (insert "key=value")
+    (goto-char 1)
+    (re-search-forward "\\(\\w+\\)=\\(\\w+\\)")
+    (should (equal (match-substitute-replacement "\\2=\\1") "value=key"))
+    (should (equal (match-data t) (list 1 10 1 4 5 10 (current-buffer))))
+    (should (equal (buffer-string) "key=value"))
+    (string-match "b\\(.\\)" "abcd")
+    (should (equal (match-substitute-replacement "<\\1>" nil nil "abcd")
+                   "<c>"))
+    (should (equal (match-data) '(1 3 2 3)))))
+
+;; Patterns in the syntax of the regex crate.
+
+(ert-deftest search-tests-string-match-pcre ()
+  (let ((case-fold-search nil))
+    (should (= (string-match-pcre "(\\d+)-(\\d+)" "tel 555-1234") 4))
+    (should (equal (match-data) '(4 12 4 7 8 12)))
+    (should (equal (match-string 2 "tel 555-1234") "1234"))
+    (should (= (string-match-pcre "é+" "aéé") 1))
+    (should (= (match-end 0) 3))
+    (should (= (string-match-pcre "a" "abca" -1) 3))
+    (should-not (string-match-pcre "A" "abc"))
+    (should (= (string-match-pcre "(?i)A" "abc") 0))
+    ;; Raw bytes match as U+FFFD, and the match data stays in characters.
+    (let ((string (concat "a" (string (unibyte-char-to-multibyte #xff)) "é")))
+      (should (= (string-match-pcre "\uFFFD(.)" string) 1))
+      (should (equal (match-data) '(1 3 2 3))))
+    (should (= (string-match-pcre "(?-u:\\xff)" "a\xff") 1)))
+  (let ((case-fold-search t))
+    (should (= (string-match-pcre "B" "abc") 1))
+    (should-not (string-match-pcre "(?-i)B" "abc")))
+  (should-error (string-match-pcre "(" "a") :type 'invalid-regexp)
+  ;; A pattern that could match part of a character is refused.
+  (should-error (string-match-pcre "(?-u:\\xc3)" "é") :type 'invalid-regexp))
+
+(ert-deftest search-tests-re-search-forward-pcre ()
+  (with-temp-buffer
+    (insert "id=12 name=foo id=345")
+    (goto-char 8)
+    ;; Put the gap in the middle of the text to be searched.
+    (insert "x")
+    (delete-char -1)
+    (goto-char 1)
+    (let ((case-fold-search nil))
+      (should (= (re-search-forward-pcre "id=(\\d+)" nil nil 2) 22))
+      (should (equal (match-string 1) "345"))
+      (goto-char 1)
+      (should (= (re-search-forward-pcre "(?P<key>\\w+)=foo") 15))
+      (should (equal (match-data t) '(7 15 7 11)))
+      (replace-match "bar" t t nil 1)
+      (should (equal (buffer-string) "id=12 bar=foo id=345"))
+      (goto-char 1)
+      (should-not (re-search-forward-pcre "name" 5 t))
+      ;; Assertions see the text after the bound.
+      (should-not (re-search-forward-pcre "id$" 3 t))
+      (should-not (re-search-forward-pcre "i\\b" 2 t))
+      (should (= (re-search-forward-pcre "id" 3 t) 3))
+      (should-error (re-search-forward-pcre "zzz") :type 'search-failed)
+      (should-error (re-search-forward-pcre "id" nil nil -1)))))
+
+(provide 'search-tests)
+
+;;; search-tests.el ends here
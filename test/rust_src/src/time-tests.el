@@ -0,0 +1,232 @@
+;;; time-tests.el --- tests for time.rs functions
+
+;;; Code:
+
+(require 'ert)
+
+;; 2018-07-04 16:05:09.123456789 UTC, a Wednesday.
+(defconst time-tests--time '(23356 61493 123456 789000))
+
+(defvar time-tests--resources-dir
+  (concat (file-name-directory (or load-file-name buffer-file-name))
+          "time-resources/")
+  "Path to the time-resources directory next to \"time-tests.el\".")
+
+(defun time-tests--zone (name)
+  "Return the file name of the test zone Test/NAME."
+  (concat time-tests--resources-dir "Test/" name))
+
+(ert-deftest test-format-time-string-directives ()
+  (should (equal (format-time-string "%Y-%m-%d %H:%M:%S.%N" time-tests--time t)
+                 "2018-07-04 16:05:09.123456789"))
+  (should (equal (format-time-string "%3N|%6N|%1N|%-N" time-tests--time t)
+                 "123|123456|1|123456789"))
+  (should (equal (format-time-string "%C %y %G %g %V %U %W %j %u %w %q"
+                                     time-tests--time t)
+                 "20 18 2018 18 27 26 27 185 3 3 3"))
+  (should (equal (format-time-string "%D|%F|%R|%T|%s" time-tests--time t)
+                 "07/04/18|2018-07-04|16:05|16:05:09|1530720309"))
+  (should (equal (format-time-string "%e|%_m|%-m|%k|%l|%I|%5Y|%%|%Q|%n"
+                                     time-tests--time t)
+                 " 4| 7|7|16| 4|04|02018|%|%Q|\n"))
+  (let ((system-time-locale "C"))
+    (should (equal (format-time-string "%a %A %b %B %p %P|%^a|%#A|%#p|%10a"
+                                       time-tests--time t)
+                   "Wed Wednesday Jul July PM pm|WED|WEDNESDAY|pm|       Wed"))
+    (should (equal (format-time-string "%c" time-tests--time t)
+                   "Wed Jul  4 16:05:09 2018"))))
+
+(ert-deftest test-format-time-string-zones ()
+  (let ((format "%H:%M %z %:z %::z %:::z %Z"))
+    (should (equal (format-time-string format time-tests--time t)
+                   "16:05 +0000 +00:00 +00:00:00 +00 UTC"))
+    (should (equal (format-time-string format time-tests--time "UTC0")
+                   "16:05 +0000 +00:00 +00:00:00 +00 UTC"))
+    (should (equal (format-time-string format time-tests--time -12600)
+                   "12:35 -0330 -03:30 -03:30:00 -03:30 -0330"))
+    (should (equal (format-time-string format time-tests--time '(19800 "IST"))
+                   "21:35 +0530 +05:30 +05:30:00 +05:30 IST"))
+    (should (equal (format-time-string format time-tests--time "EST5EDT")
+                   "12:05 -0400 -04:00 -04:00:00 -04 EDT"))
+    (should (equal (format-time-string format 0 "EST5EDT")
+                   "19:00 -0500 -05:00 -05:00:00 -05 EST"))
+    (should (equal (format-time-string "%#Z" time-tests--time "EST5EDT")
+                   "edt"))
+    (should-error (format-time-string format time-tests--time 'no-such-zone))
+    (should-error (format-time-string format time-tests--time (* 25 3600)))))
+
+(ert-deftest test-decode-time ()
+  (should (equal (decode-time time-tests--time t)
+                 '(9 5 16 4 7 2018 3 nil 0)))
+  (should (equal (decode-time time-tests--time "EST5EDT")
+                 '(9 5 12 4 7 2018 3 t -14400)))
+  (should (equal (decode-time time-tests--time '(-28800 "PST"))
+                 '(9 5 8 4 7 2018 3 nil -28800)))
+  (should (equal (decode-time -1 t)
+                 '(59 59 23 31 12 1969 3 nil 0)))
+  (should (equal (decode-time '(0 0) 3600)
+                 '(0 0 1 1 1 1970 4 nil 3600))))
+
+(ert-deftest test-encode-time ()
+  (should (equal (encode-time 9 5 16 4 7 2018 t) '(23356 61493)))
+  (should (equal (encode-time 9 5 12 4 7 2018 "EST5EDT") '(23356 61493)))
+  (should (equal (encode-time 9 5 8 4 7 2018 -28800) '(23356 61493)))
+  (should (equal (encode-time 9 5 8 4 7 2018 '(-28800 "PST")) '(23356 61493)))
+  ;; Out-of-range fields are normalized.
+  (should (equal (encode-time 0 0 0 0 3 2016 t)
+                 (encode-time 0 0 0 29 2 2016 t)))
+  (should (equal (encode-time 0 0 0 1 13 2017 t)
+                 (encode-time 0 0 0 1 1 2018 t)))
+  ;; The last argument is the zone, so decoded times round-trip.
+  (should (equal (apply #'encode-time (decode-time time-tests--time "EST5EDT"))
+                 (encode-time 9 5 16 4 7 2018 t)))
+  (should-error (encode-time 0 0 0 1 1 (1+ most-positive-fixnum) t))
+  (should-error (encode-time 0 0 0 1 1 "2018" t)))
+
+(ert-deftest test-current-time-zone ()
+  (should (equal (current-time-zone time-tests--time t) '(0 "UTC")))
+  (should (equal (current-time-zone time-tests--time "EST5EDT") '(-14400 "EDT")))
+  (should (equal (current-time-zone time-tests--time -16200) '(-16200 "-0430")))
+  (should (equal (current-time-zone time-tests--time '(3600 "CET")) '(3600 "CET")))
+  (let ((zone (current-time-zone)))
+    (should (integerp (car zone)))
+    (should (stringp (cadr zone)))))
+
+(ert-deftest test-time-zone-files ()
+  (let ((format "%F %T %z %Z")
+        (berlin (time-tests--zone "Berlin"))
+        (tz (getenv "TZ")))
+    (should (equal (format-time-string format time-tests--time berlin)
+                   "2018-07-04 18:05:09 +0200 CEST"))
+    (should (equal (format-time-string format 0 berlin)
+                   "1970-01-01 01:00:00 +0100 CET"))
+    (should (equal (format-time-string format time-tests--time
+                                       (concat ":" berlin))
+                   "2018-07-04 18:05:09 +0200 CEST"))
+    ;; A slim file, whose rules come from its TZ string footer.
+    (should (equal (format-time-string format time-tests--time
+                                       (time-tests--zone "Sydney"))
+                   "2018-07-05 02:05:09 +1000 AEST"))
+    (should (equal (decode-time time-tests--time (time-tests--zone "Kolkata"))
+                   '(9 35 21 4 7 2018 3 nil 19800)))
+    (should (equal (decode-time time-tests--time berlin)
+                   '(9 5 18 4 7 2018 3 t 7200)))
+    (should (equal (current-time-zone time-tests--time berlin) '(7200 "CEST")))
+    (should (equal (encode-time 9 5 18 4 7 2018 berlin) '(23356 61493)))
+    ;; Times that occur twice are taken as the earlier, and times that
+    ;; are skipped use the offset in effect before the transition.
+    (should (equal (encode-time 0 30 2 28 10 2018 berlin) '(23509 776)))
+    (should (equal (encode-time 0 30 2 25 3 2018 berlin) '(23222 64408)))
+    (should (equal (getenv "TZ") tz))))
+
+(ert-deftest test-time-zone-names ()
+  (skip-unless (file-exists-p "/usr/share/zoneinfo/Europe/Berlin"))
+  (should (equal (format-time-string "%F %T %Z" time-tests--time "Europe/Berlin")
+                 "2018-07-04 18:05:09 CEST"))
+  (should (equal (decode-time time-tests--time "Europe/Berlin")
+                 (decode-time time-tests--time (time-tests--zone "Berlin")))))
+
+(ert-deftest test-iso8601-parse-to-time ()
+  (should (equal (iso8601-parse-to-time "2018-07-04T16:05:09.123456789Z")
+                 time-tests--time))
+  (should (equal (iso8601-parse-to-time "2018-07-04T18:05:09+02:00")
+                 '(23356 61493 0 0)))
+  (should (equal (iso8601-parse-to-time "20180704T1235,5-0330")
+                 '(23356 61514 0 0)))
+  (should (equal (iso8601-parse-to-time "2018-W27-3T16:05:09Z")
+                 '(23356 61493 0 0)))
+  (should (equal (iso8601-parse-to-time "2018-185T16:05:09Z")
+                 '(23356 61493 0 0)))
+  ;; Without an offset, the time is in ZONE.
+  (should (equal (iso8601-parse-to-time "2018-07-04 12:05:09" "EST5EDT")
+                 '(23356 61493 0 0)))
+  (should (equal (iso8601-parse-to-time "2018-07-04T18:05:09"
+                                        (time-tests--zone "Berlin"))
+                 '(23356 61493 0 0)))
+  (should (equal (iso8601-parse-to-time "1970-01-02" t) '(1 20864 0 0)))
+  ;; Durations.
+  (should (equal (iso8601-parse-to-time "P1DT12H") '(1 64064 0 0)))
+  (should (equal (iso8601-parse-to-time "PT0.25S") '(0 0 250000 0)))
+  (should (equal (cdr (should-error (iso8601-parse-to-time "2018-13-01")
+                                    :type 'iso8601-parse-error))
+                 '("Month out of range" "2018-13-01" 5)))
+  (should (equal (cdr (should-error (iso8601-parse-to-time "P1Y")
+                                    :type 'iso8601-parse-error))
+                 '("Years and months have no fixed length" "P1Y" 2)))
+  (should-error (iso8601-parse-to-time "2018-07-04T16:05:09Z junk")
+                :type 'iso8601-parse-error))
+
+(ert-deftest test-format-rfc3339 ()
+  (should (equal (format-rfc3339 time-tests--time t) "2018-07-04T16:05:09Z"))
+  (should (equal (format-rfc3339 time-tests--time (time-tests--zone "Berlin") 3)
+                 "2018-07-04T18:05:09.123+02:00"))
+  (should (equal (format-rfc3339 time-tests--time -12600 t)
+                 "2018-07-04T12:35:09.123456789-03:30"))
+  (should (equal (format-rfc3339 '(0 0 500000 0) t t)
+                 "1970-01-01T00:00:00.5Z"))
+  ;; Offsets with seconds cannot be written, so Universal Time is used.
+  (should (equal (format-rfc3339 0 '(3208 "LMT")) "1970-01-01T00:00:00Z"))
+  (should (equal (iso8601-parse-to-time
+                  (format-rfc3339 time-tests--time "EST5EDT" t))
+                 time-tests--time))
+  (should-error (format-rfc3339 nil t 10) :type 'args-out-of-range)
+  (should-error (format-rfc3339 (encode-time 0 0 0 1 1 10000 t) t)))
+
+(defun time-tests--ticks-p (time)
+  "Return non-nil if TIME has the form (TICKS . HZ)."
+  (and (consp time) (natnump (car time)) (integerp (cdr time))
+       (> (cdr time) 0)))
+
+(ert-deftest test-current-monotonic-time ()
+  (let ((before (current-monotonic-time))
+        (after (current-monotonic-time)))
+    (should (time-tests--ticks-p before))
+    (should (= (cdr before) (cdr after)))
+    (should (<= (car before) (car after)))))
+
+(ert-deftest test-current-cpu-time ()
+  (let ((process (current-cpu-time))
+        (thread (current-cpu-time t)))
+    (should (time-tests--ticks-p process))
+    (should (time-tests--ticks-p thread))
+    ;; The thread is part of the process.
+    (should (<= (car thread) (car (current-cpu-time))))))
+
+(ert-deftest test-benchmark-primitive ()
+  (let* ((calls 0)
+         (times (benchmark-primitive 5 (lambda () (setq calls (1+ calls))))))
+    (should (= calls 5))
+    (should (= (length times) 3))
+    (dolist (time times)
+      (should (time-tests--ticks-p time)))
+    (should (<= (car (nth 0 times)) (car (nth 1 times)) (car (nth 2 times)))))
+  (should-error (benchmark-primitive 0 #'ignore) :type 'args-out-of-range)
+  (should-error (benchmark-primitive 1 (lambda () (error "Oops"))))
+  ;; A huge count is not allocated for up front.
+  (should-error (benchmark-primitive most-positive-fixnum
+                                     (lambda () (error "Oops")))))
+
+(ert-deftest test-time-convert ()
+  (let ((time-ticks-hz-pairs t))
+    (should (equal (time-convert 5) '(5 . 1)))
+    (should (equal (time-convert 1.5) '(3 . 2)))
+    (should (equal (time-convert '(23356 61493)) '(1530720309 . 1)))
+    (should (equal (time-convert '(23356 61493 123456)) '(1530720309123456 . 1000000)))
+    (should (equal (time-convert time-tests--time 1000) '(1530720309123 . 1000)))
+    (should (equal (time-convert '(-1 . 3) 1) '(-1 . 1)))
+    (should (equal (time-convert time-tests--time 'integer) 1530720309))
+    (should (equal (time-convert '(3 . 2) 'list) '(0 1 500000 0)))
+    (should (equal (time-convert '(1530720309123456789 . 1000000000) 'list)
+                   time-tests--time))
+    (should (= (cdr (time-convert nil)) 1000000000))
+    ;; Other time functions accept (TICKS . HZ) too.
+    (should (equal (format-time-string "%F %T.%N" '(1530720309123456789 . 1000000000) t)
+                   "2018-07-04 16:05:09.123456789"))
+    (should (equal (decode-time '(3 . 2) t) '(1 0 0 1 1 1970 4 nil 0)))
+    (should (= (float-time '(1 . 4)) 0.25))
+    (should-error (time-convert 1 0))
+    (should-error (time-convert 1 'vector))))
+
+(provide 'time-tests)
+
+;;; time-tests.el ends here